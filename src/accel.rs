@@ -0,0 +1,261 @@
+//! Playing pointer moves as relative motion, for games and remote desktops
+//! that only follow that, undoing the acceleration the system applies to
+//! it.
+//!
+//! On Windows enigo already injects relative motion unaccelerated. Other
+//! systems accelerate it, by how fast it goes, along a curve measured once
+//! by `calibrate-pointer`; moves are scaled back along the same curve.
+
+use crate::backend::{EnigoBackend, InputBackend};
+use enigo::{Coordinate, Enigo, Mouse, Settings};
+use rdev::EventType;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Counts injected per step while calibrating, one speed each.
+const SPEEDS: [i32; 6] = [1, 2, 4, 8, 16, 32];
+/// Counts injected per speed, spread over as many steps as that takes.
+const TRAVEL: i32 = 200;
+const STEP_INTERVAL: Duration = Duration::from_millis(8);
+/// Time for the pointer to catch up before reading where it is.
+const SETTLE: Duration = Duration::from_millis(100);
+/// Shortest time counted between moves, so bursts don't look infinitely
+/// fast.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How far the pointer goes per count injected, at a speed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Point {
+    /// Counts per millisecond.
+    pub speed: f64,
+    pub gain: f64,
+}
+
+/// The system's pointer acceleration, as measured. Empty means none.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Curve {
+    pub points: Vec<Point>,
+}
+
+impl Curve {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text =
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, text)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    /// The gain at `speed`, interpolated between the measured points and
+    /// held beyond them.
+    fn gain(&self, speed: f64) -> f64 {
+        let (Some(first), Some(last)) =
+            (self.points.first(), self.points.last())
+        else {
+            return 1.0;
+        };
+        if speed <= first.speed {
+            return first.gain;
+        }
+        self.points
+            .windows(2)
+            .find(|pair| speed <= pair[1].speed)
+            .map_or(last.gain, |pair| {
+                let (a, b) = (pair[0], pair[1]);
+                let t = (speed - a.speed) / (b.speed - a.speed);
+                a.gain + (b.gain - a.gain) * t
+            })
+    }
+
+    /// Counts to inject over `interval` for the pointer to go `distance`.
+    /// The gain depends on the counts, but more counts always go further,
+    /// so this bisects for them.
+    fn counts(&self, distance: f64, interval: Duration) -> f64 {
+        let ms = interval.max(MIN_INTERVAL).as_secs_f64() * 1000.0;
+        let moved = |counts: f64| counts * self.gain(counts / ms);
+        let (mut low, mut high) = (0.0, distance);
+        // Gains below 1 need more counts than pixels.
+        for _ in 0..32 {
+            if moved(high) >= distance {
+                break;
+            }
+            high *= 2.0;
+        }
+        for _ in 0..48 {
+            let mid = (low + high) / 2.0;
+            if moved(mid) < distance {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        high
+    }
+}
+
+/// Measures the pointer acceleration by moving the pointer at each of
+/// [`SPEEDS`] and reading how far it went.
+pub fn calibrate() -> Result<Curve, String> {
+    let mut enigo =
+        Enigo::new(&Settings::default()).map_err(|e| e.to_string())?;
+    let fail = |e: enigo::InputError| e.to_string();
+    let (width, height) = enigo.main_display().map_err(fail)?;
+    let restore = enigo.location().map_err(fail)?;
+    let mut points = Vec::new();
+    for counts in SPEEDS {
+        // Room to the right for the fastest speeds, even doubled.
+        enigo
+            .move_mouse(width / 4, height / 2, Coordinate::Abs)
+            .map_err(fail)?;
+        thread::sleep(SETTLE);
+        let (start, _) = enigo.location().map_err(fail)?;
+        let steps = (TRAVEL / counts).max(4);
+        for _ in 0..steps {
+            enigo.move_mouse(counts, 0, Coordinate::Rel).map_err(fail)?;
+            thread::sleep(STEP_INTERVAL);
+        }
+        thread::sleep(SETTLE);
+        let (end, _) = enigo.location().map_err(fail)?;
+        let moved = (end - start) as f64;
+        if moved <= 0.0 {
+            return Err("the pointer didn't move; calibrating needs X11, \
+                        Windows or macOS"
+                .into());
+        }
+        points.push(Point {
+            speed: counts as f64 / (STEP_INTERVAL.as_secs_f64() * 1000.0),
+            gain: moved / (counts * steps) as f64,
+        });
+    }
+    let _ = enigo.move_mouse(restore.0, restore.1, Coordinate::Abs);
+    Ok(Curve { points })
+}
+
+/// Plays pointer moves through enigo as relative motion scaled back along
+/// `curve`, and everything else as [`EnigoBackend`] does. The first move
+/// places the pointer absolutely. How fast the pointer goes is worked out
+/// from when moves are due, as batched moves are sent back to back.
+pub struct RelativeMoves {
+    enigo: EnigoBackend,
+    curve: Curve,
+    // where the last move went, and when it was due
+    last: Option<((f64, f64), Instant)>,
+    // fractions of counts rounded off so far, carried to the next move
+    remainder: (f64, f64),
+}
+
+impl RelativeMoves {
+    pub fn new(curve: Curve) -> Result<Self, String> {
+        Ok(Self {
+            enigo: EnigoBackend::new()?,
+            curve,
+            last: None,
+            remainder: (0.0, 0.0),
+        })
+    }
+}
+
+impl InputBackend for RelativeMoves {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        self.send_due(evt, Instant::now())
+    }
+
+    fn send_due(
+        &mut self,
+        evt: &EventType,
+        due: Instant,
+    ) -> Result<(), String> {
+        let EventType::MouseMove { x, y } = *evt else {
+            return self.enigo.send(evt);
+        };
+        let Some(((last_x, last_y), at)) = self.last.replace(((x, y), due))
+        else {
+            return self.enigo.send(evt);
+        };
+        let (dx, dy) = (x - last_x, y - last_y);
+        let distance = dx.hypot(dy);
+        if distance == 0.0 {
+            return Ok(());
+        }
+        let interval = due.saturating_duration_since(at);
+        let scale = self.curve.counts(distance, interval) / distance;
+        let wanted =
+            (dx * scale + self.remainder.0, dy * scale + self.remainder.1);
+        let counts = (wanted.0.round(), wanted.1.round());
+        self.remainder = (wanted.0 - counts.0, wanted.1 - counts.1);
+        self.enigo.move_by(counts.0 as i32, counts.1 as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve() -> Curve {
+        Curve {
+            points: vec![
+                Point {
+                    speed: 0.5,
+                    gain: 1.0,
+                },
+                Point {
+                    speed: 2.0,
+                    gain: 2.5,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn gain_is_interpolated_and_held_beyond_the_points() {
+        let curve = curve();
+        assert_eq!(curve.gain(0.1), 1.0);
+        assert_eq!(curve.gain(0.5), 1.0);
+        assert!((curve.gain(1.25) - 1.75).abs() < 1e-9);
+        assert_eq!(curve.gain(2.0), 2.5);
+        assert_eq!(curve.gain(10.0), 2.5);
+    }
+
+    #[test]
+    fn no_points_means_no_acceleration() {
+        let curve = Curve::default();
+        assert_eq!(curve.gain(3.0), 1.0);
+        let counts = curve.counts(40.0, Duration::from_millis(8));
+        assert!((counts - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn counts_undo_the_gain_at_their_own_speed() {
+        let curve = curve();
+        for (distance, ms) in [(2.0, 8), (10.0, 8), (60.0, 8), (5.0, 1)] {
+            let interval = Duration::from_millis(ms);
+            let counts = curve.counts(distance, interval);
+            let moved = counts * curve.gain(counts / ms as f64);
+            assert!(
+                (moved - distance).abs() < 1e-6,
+                "{} counts over {} ms go {}, not {}",
+                counts,
+                ms,
+                moved,
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn faster_moves_need_fewer_counts_per_pixel() {
+        let curve = curve();
+        let slow = curve.counts(4.0, Duration::from_millis(8));
+        let fast = curve.counts(40.0, Duration::from_millis(8));
+        assert!(fast / 40.0 < slow / 4.0);
+    }
+}
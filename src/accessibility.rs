@@ -0,0 +1,604 @@
+//! Locating UI elements through the platform accessibility tree.
+//!
+//! Element-targeted clicks store the role and name of the element that was
+//! under the cursor at record time and look it up again at playback time, so
+//! the click lands on the element wherever it has moved to.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElementTarget {
+    pub role: String,
+    pub name: String,
+}
+
+impl std::fmt::Display for ElementTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} \"{}\"", self.role, self.name)
+    }
+}
+
+/// Returns the element at the given screen position.
+pub fn element_at(x: f64, y: f64) -> Result<ElementTarget, String> {
+    platform::element_at(x, y)
+}
+
+/// Finds the element matching `target` and returns the screen position of
+/// its center.
+pub fn locate(target: &ElementTarget) -> Result<(f64, f64), String> {
+    platform::locate(target)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::ElementTarget;
+    use windows::core::{BSTR, VARIANT};
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER,
+        COINIT_MULTITHREADED,
+    };
+    use windows::Win32::UI::Accessibility::*;
+
+    const ROLES: &[(&str, UIA_CONTROLTYPE_ID)] = &[
+        ("button", UIA_ButtonControlTypeId),
+        ("checkbox", UIA_CheckBoxControlTypeId),
+        ("combobox", UIA_ComboBoxControlTypeId),
+        ("edit", UIA_EditControlTypeId),
+        ("hyperlink", UIA_HyperlinkControlTypeId),
+        ("image", UIA_ImageControlTypeId),
+        ("listitem", UIA_ListItemControlTypeId),
+        ("list", UIA_ListControlTypeId),
+        ("menu", UIA_MenuControlTypeId),
+        ("menuitem", UIA_MenuItemControlTypeId),
+        ("radiobutton", UIA_RadioButtonControlTypeId),
+        ("tab", UIA_TabControlTypeId),
+        ("tabitem", UIA_TabItemControlTypeId),
+        ("text", UIA_TextControlTypeId),
+        ("treeitem", UIA_TreeItemControlTypeId),
+        ("document", UIA_DocumentControlTypeId),
+        ("pane", UIA_PaneControlTypeId),
+        ("window", UIA_WindowControlTypeId),
+    ];
+
+    fn automation() -> Result<IUIAutomation, String> {
+        unsafe {
+            // S_FALSE just means COM was already initialized on this thread.
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)
+                .map_err(|e| format!("UI Automation unavailable: {e}"))
+        }
+    }
+
+    fn role_name(id: UIA_CONTROLTYPE_ID) -> String {
+        ROLES
+            .iter()
+            .find(|(_, rid)| *rid == id)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| id.0.to_string())
+    }
+
+    fn role_id(role: &str) -> Option<UIA_CONTROLTYPE_ID> {
+        ROLES
+            .iter()
+            .find(|(name, _)| *name == role)
+            .map(|(_, id)| *id)
+            .or_else(|| role.parse().ok().map(UIA_CONTROLTYPE_ID))
+    }
+
+    pub fn element_at(x: f64, y: f64) -> Result<ElementTarget, String> {
+        let automation = automation()?;
+        unsafe {
+            let element = automation
+                .ElementFromPoint(POINT {
+                    x: x as i32,
+                    y: y as i32,
+                })
+                .map_err(|e| format!("no element at ({x}, {y}): {e}"))?;
+            let name = element.CurrentName().map_err(|e| e.to_string())?;
+            let control_type =
+                element.CurrentControlType().map_err(|e| e.to_string())?;
+            Ok(ElementTarget {
+                role: role_name(control_type),
+                name: name.to_string(),
+            })
+        }
+    }
+
+    pub fn locate(target: &ElementTarget) -> Result<(f64, f64), String> {
+        let control_type = role_id(&target.role)
+            .ok_or_else(|| format!("unknown role '{}'", target.role))?;
+        let automation = automation()?;
+        unsafe {
+            let by_name = automation
+                .CreatePropertyCondition(
+                    UIA_NamePropertyId,
+                    &VARIANT::from(BSTR::from(target.name.as_str())),
+                )
+                .map_err(|e| e.to_string())?;
+            let by_role = automation
+                .CreatePropertyCondition(
+                    UIA_ControlTypePropertyId,
+                    &VARIANT::from(control_type.0),
+                )
+                .map_err(|e| e.to_string())?;
+            let condition = automation
+                .CreateAndCondition(&by_name, &by_role)
+                .map_err(|e| e.to_string())?;
+            let element = automation
+                .GetRootElement()
+                .and_then(|root| root.FindFirst(TreeScope_Descendants, &condition))
+                .map_err(|_| format!("element {target} not found"))?;
+            let rect = element
+                .CurrentBoundingRectangle()
+                .map_err(|e| e.to_string())?;
+            Ok((
+                (rect.left + rect.right) as f64 / 2.0,
+                (rect.top + rect.bottom) as f64 / 2.0,
+            ))
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::ElementTarget;
+    use std::collections::VecDeque;
+    use std::ffi::{c_void, CStr, CString};
+
+    type CFTypeRef = *const c_void;
+    type CFStringRef = *const c_void;
+    type AXUIElementRef = *const c_void;
+
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const K_AX_VALUE_CG_POINT_TYPE: u32 = 1;
+    const K_AX_VALUE_CG_SIZE_TYPE: u32 = 2;
+    const MAX_VISITED: usize = 5000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateSystemWide() -> AXUIElementRef;
+        fn AXUIElementCopyElementAtPosition(
+            application: AXUIElementRef,
+            x: f32,
+            y: f32,
+            element: *mut AXUIElementRef,
+        ) -> i32;
+        fn AXUIElementCopyAttributeValue(
+            element: AXUIElementRef,
+            attribute: CFStringRef,
+            value: *mut CFTypeRef,
+        ) -> i32;
+        fn AXValueGetValue(
+            value: CFTypeRef,
+            value_type: u32,
+            value_ptr: *mut c_void,
+        ) -> bool;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(
+            alloc: CFTypeRef,
+            c_str: *const i8,
+            encoding: u32,
+        ) -> CFStringRef;
+        fn CFStringGetCString(
+            string: CFStringRef,
+            buffer: *mut i8,
+            buffer_size: isize,
+            encoding: u32,
+        ) -> bool;
+        fn CFGetTypeID(cf: CFTypeRef) -> usize;
+        fn CFStringGetTypeID() -> usize;
+        fn CFArrayGetTypeID() -> usize;
+        fn CFArrayGetCount(array: CFTypeRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CFTypeRef, index: isize) -> CFTypeRef;
+        fn CFRetain(cf: CFTypeRef) -> CFTypeRef;
+        fn CFRelease(cf: CFTypeRef);
+    }
+
+    /// Owned CoreFoundation reference, released on drop.
+    struct Cf(CFTypeRef);
+
+    impl Drop for Cf {
+        fn drop(&mut self) {
+            if !self.0.is_null() {
+                unsafe { CFRelease(self.0) }
+            }
+        }
+    }
+
+    fn attribute(element: AXUIElementRef, name: &str) -> Option<Cf> {
+        let c_name = CString::new(name).ok()?;
+        unsafe {
+            let key = Cf(CFStringCreateWithCString(
+                std::ptr::null(),
+                c_name.as_ptr(),
+                K_CF_STRING_ENCODING_UTF8,
+            ));
+            let mut value: CFTypeRef = std::ptr::null();
+            if AXUIElementCopyAttributeValue(element, key.0, &mut value) != 0
+                || value.is_null()
+            {
+                return None;
+            }
+            Some(Cf(value))
+        }
+    }
+
+    fn string_attribute(element: AXUIElementRef, name: &str) -> String {
+        let Some(value) = attribute(element, name) else {
+            return String::new();
+        };
+        unsafe {
+            if CFGetTypeID(value.0) != CFStringGetTypeID() {
+                return String::new();
+            }
+            let mut buffer = [0i8; 1024];
+            if !CFStringGetCString(
+                value.0,
+                buffer.as_mut_ptr(),
+                buffer.len() as isize,
+                K_CF_STRING_ENCODING_UTF8,
+            ) {
+                return String::new();
+            }
+            CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+        }
+    }
+
+    fn describe(element: AXUIElementRef) -> ElementTarget {
+        let mut name = string_attribute(element, "AXTitle");
+        if name.is_empty() {
+            name = string_attribute(element, "AXDescription");
+        }
+        ElementTarget {
+            role: string_attribute(element, "AXRole"),
+            name,
+        }
+    }
+
+    fn center(element: AXUIElementRef) -> Option<(f64, f64)> {
+        let position = attribute(element, "AXPosition")?;
+        let size = attribute(element, "AXSize")?;
+        let mut point = CGPoint::default();
+        let mut extent = CGSize::default();
+        unsafe {
+            if !AXValueGetValue(
+                position.0,
+                K_AX_VALUE_CG_POINT_TYPE,
+                &mut point as *mut CGPoint as *mut c_void,
+            ) || !AXValueGetValue(
+                size.0,
+                K_AX_VALUE_CG_SIZE_TYPE,
+                &mut extent as *mut CGSize as *mut c_void,
+            ) {
+                return None;
+            }
+        }
+        Some((point.x + extent.width / 2.0, point.y + extent.height / 2.0))
+    }
+
+    pub fn element_at(x: f64, y: f64) -> Result<ElementTarget, String> {
+        unsafe {
+            let system = Cf(AXUIElementCreateSystemWide());
+            let mut element: AXUIElementRef = std::ptr::null();
+            if AXUIElementCopyElementAtPosition(
+                system.0,
+                x as f32,
+                y as f32,
+                &mut element,
+            ) != 0
+            {
+                return Err(format!(
+                    "no element at ({x}, {y}); is Accessibility access granted?"
+                ));
+            }
+            let element = Cf(element);
+            Ok(describe(element.0))
+        }
+    }
+
+    pub fn locate(target: &ElementTarget) -> Result<(f64, f64), String> {
+        let system = Cf(unsafe { AXUIElementCreateSystemWide() });
+        let app = attribute(system.0, "AXFocusedApplication")
+            .ok_or("no focused application; is Accessibility access granted?")?;
+
+        // Breadth-first search of the focused application's element tree.
+        let mut queue = VecDeque::from([app]);
+        let mut visited = 0;
+        while let Some(element) = queue.pop_front() {
+            visited += 1;
+            if visited > MAX_VISITED {
+                break;
+            }
+            if describe(element.0) == *target {
+                return center(element.0)
+                    .ok_or_else(|| format!("element {target} has no frame"));
+            }
+            let Some(children) = attribute(element.0, "AXChildren") else {
+                continue;
+            };
+            unsafe {
+                if CFGetTypeID(children.0) != CFArrayGetTypeID() {
+                    continue;
+                }
+                for i in 0..CFArrayGetCount(children.0) {
+                    let child = CFArrayGetValueAtIndex(children.0, i);
+                    queue.push_back(Cf(CFRetain(child)));
+                }
+            }
+        }
+        Err(format!("element {target} not found"))
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    //! AT-SPI, reached over its own D-Bus bus with `gdbus`, which comes
+    //! with GLib wherever the accessibility stack is installed.
+
+    use super::ElementTarget;
+    use std::collections::VecDeque;
+    use std::process::Command;
+
+    const REGISTRY: (&str, &str) =
+        ("org.a11y.atspi.Registry", "/org/a11y/atspi/accessible/root");
+    const NULL: &str = "/org/a11y/atspi/null";
+    const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+    const COMPONENT: &str = "org.a11y.atspi.Component";
+    /// `ATSPI_COORD_TYPE_SCREEN`.
+    const SCREEN: &str = "uint32 0";
+    const MAX_VISITED: usize = 2000;
+
+    /// An element: the bus name of its application and its object path.
+    type Object = (String, String);
+
+    /// The accessibility bus, which AT-SPI keeps apart from the session
+    /// bus.
+    struct Bus {
+        address: String,
+    }
+
+    impl Bus {
+        fn open() -> Result<Self, String> {
+            let output = gdbus(&[
+                "--session",
+                "--dest",
+                "org.a11y.Bus",
+                "--object-path",
+                "/org/a11y/bus",
+                "--method",
+                "org.a11y.Bus.GetAddress",
+            ])
+            .map_err(|e| format!("AT-SPI unavailable: {e}"))?;
+            let address = strings(&output)
+                .into_iter()
+                .next()
+                .ok_or("AT-SPI unavailable: no bus address")?;
+            Ok(Self { address })
+        }
+
+        fn call(
+            &self,
+            (dest, path): (&str, &str),
+            method: &str,
+            args: &[&str],
+        ) -> Result<String, String> {
+            let mut all = vec![
+                "--address",
+                &self.address,
+                "--dest",
+                dest,
+                "--object-path",
+                path,
+                "--method",
+                method,
+            ];
+            all.extend(args);
+            gdbus(&all)
+        }
+
+        fn children(&self, (dest, path): (&str, &str)) -> Vec<Object> {
+            let method = format!("{ACCESSIBLE}.GetChildren");
+            let output = self.call((dest, path), &method, &[]);
+            objects(&output.unwrap_or_default())
+        }
+
+        fn role(&self, object: (&str, &str)) -> String {
+            let method = format!("{ACCESSIBLE}.GetRoleName");
+            let output = self.call(object, &method, &[]).unwrap_or_default();
+            strings(&output).into_iter().next().unwrap_or_default()
+        }
+
+        fn name(&self, object: (&str, &str)) -> String {
+            let output = self
+                .call(
+                    object,
+                    "org.freedesktop.DBus.Properties.Get",
+                    &[&format!("'{ACCESSIBLE}'"), "'Name'"],
+                )
+                .unwrap_or_default();
+            strings(&output).into_iter().next().unwrap_or_default()
+        }
+
+        fn describe(&self, object: (&str, &str)) -> ElementTarget {
+            ElementTarget {
+                role: self.role(object),
+                name: self.name(object),
+            }
+        }
+
+        /// The innermost element of `object` at `x`, `y`, if any.
+        fn at_point(
+            &self,
+            object: (&str, &str),
+            x: &str,
+            y: &str,
+        ) -> Option<Object> {
+            let method = format!("{COMPONENT}.GetAccessibleAtPoint");
+            let output = self.call(object, &method, &[x, y, SCREEN]).ok()?;
+            objects(&output).into_iter().find(|(_, path)| path != NULL)
+        }
+
+        fn center(&self, object: (&str, &str)) -> Option<(f64, f64)> {
+            let method = format!("{COMPONENT}.GetExtents");
+            let output = self.call(object, &method, &[SCREEN]).ok()?;
+            let [x, y, width, height] = numbers(&output)[..] else {
+                return None;
+            };
+            Some((x + width / 2.0, y + height / 2.0))
+        }
+    }
+
+    fn gdbus(args: &[&str]) -> Result<String, String> {
+        let output = Command::new("gdbus")
+            .arg("call")
+            .args(args)
+            .output()
+            .map_err(|e| format!("cannot run gdbus: {e}"))?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(error.trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// The strings in a reply as `gdbus` prints it, e.g. `('OK',)`.
+    fn strings(reply: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut chars = reply.chars();
+        while let Some(c) = chars.next() {
+            if c != '\'' && c != '"' {
+                continue;
+            }
+            let mut string = String::new();
+            while let Some(next) = chars.next() {
+                match next {
+                    '\\' => string.extend(chars.next()),
+                    _ if next == c => break,
+                    _ => string.push(next),
+                }
+            }
+            found.push(string);
+        }
+        found
+    }
+
+    /// The `(so)` references to elements in a reply.
+    fn objects(reply: &str) -> Vec<Object> {
+        let strings = strings(reply);
+        strings
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    /// The numbers in a reply, e.g. `((10, 20, 300, 40),)`.
+    fn numbers(reply: &str) -> Vec<f64> {
+        reply
+            .split(|c: char| !c.is_ascii_digit() && c != '-')
+            .filter_map(|n| n.parse().ok())
+            .collect()
+    }
+
+    fn parts(object: &Object) -> (&str, &str) {
+        (&object.0, &object.1)
+    }
+
+    pub fn element_at(x: f64, y: f64) -> Result<ElementTarget, String> {
+        let bus = Bus::open()?;
+        let (x, y) = ((x as i32).to_string(), (y as i32).to_string());
+        // Each application's windows know which of their elements is at a
+        // point, and those elements which of theirs.
+        let windows = bus
+            .children(REGISTRY)
+            .iter()
+            .flat_map(|app| bus.children(parts(app)))
+            .collect::<Vec<_>>();
+        let mut element = windows
+            .iter()
+            .find_map(|window| bus.at_point(parts(window), &x, &y))
+            .ok_or_else(|| format!("no element at ({x}, {y})"))?;
+        while let Some(inner) = bus.at_point(parts(&element), &x, &y) {
+            if inner == element {
+                break;
+            }
+            element = inner;
+        }
+        Ok(bus.describe(parts(&element)))
+    }
+
+    pub fn locate(target: &ElementTarget) -> Result<(f64, f64), String> {
+        let bus = Bus::open()?;
+
+        // Breadth-first search of every application's element tree.
+        let mut queue = VecDeque::from(bus.children(REGISTRY));
+        let mut visited = 0;
+        while let Some(element) = queue.pop_front() {
+            visited += 1;
+            if visited > MAX_VISITED {
+                break;
+            }
+            // Names are only asked for where the role matches.
+            if bus.role(parts(&element)) == target.role
+                && bus.name(parts(&element)) == target.name
+            {
+                return bus
+                    .center(parts(&element))
+                    .ok_or_else(|| format!("element {target} has no frame"));
+            }
+            queue.extend(bus.children(parts(&element)));
+        }
+        Err(format!("element {target} not found"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn reads_strings_as_gdbus_prints_them() {
+            assert_eq!(strings("(<'OK'>,)"), ["OK"]);
+            assert_eq!(strings(r#"("it's",)"#), ["it's"]);
+            assert_eq!(strings(r"('a \'b\' c',)"), ["a 'b' c"]);
+            assert!(strings("(uint32 3,)").is_empty());
+        }
+
+        #[test]
+        fn reads_element_references() {
+            let reply = "([(':1.5', '/org/a11y/atspi/accessible/1'), \
+                         (':1.5', '/org/a11y/atspi/accessible/2')],)";
+            assert_eq!(
+                objects(reply),
+                [
+                    (":1.5".into(), "/org/a11y/atspi/accessible/1".into()),
+                    (":1.5".into(), "/org/a11y/atspi/accessible/2".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn reads_extents() {
+            assert_eq!(
+                numbers("((10, -20, 300, 40),)"),
+                [10.0, -20.0, 300.0, 40.0]
+            );
+        }
+    }
+}
@@ -0,0 +1,567 @@
+//! Importing AutoHotkey scripts, v1 or v2, as recordings.
+//!
+//! Only the commands a recorded macro could have come from are understood:
+//! `Send` and its variants, `Click`, `MouseMove` and `Sleep`. Hotkey labels
+//! are dropped, so a script is imported top to bottom as one macro, and
+//! anything else, including variables and expressions, is skipped with a
+//! warning. A quoted argument is taken as v2 syntax, a bare one as v1.
+
+use crate::event::{MacroAction, RecordedEvent};
+use crate::preview::char_key;
+use rdev::{Button, EventType, Key};
+use std::time::Duration;
+
+/// Time between the events a command sends, about AutoHotkey's own key
+/// delay.
+const STEP: Duration = Duration::from_millis(10);
+
+/// Events taken from a script and what couldn't be taken.
+#[derive(Debug, Default)]
+pub struct Script {
+    pub events: Vec<RecordedEvent>,
+    pub warnings: Vec<String>,
+}
+
+/// Reads the supported commands of `script` into events.
+pub fn parse(script: &str) -> Script {
+    let mut builder = Builder::default();
+    let mut screen_coords = false;
+    let mut in_comment = false;
+    for (n, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if in_comment {
+            in_comment = !line.starts_with("*/");
+            continue;
+        }
+        if line.starts_with("/*") {
+            in_comment = true;
+            continue;
+        }
+        let mut line = strip_comment(line).trim();
+        // Hotstrings type on their own; nothing of them can be played.
+        if line.starts_with(':') {
+            builder.warn(n, line, "hotstrings aren't supported");
+            continue;
+        }
+        if let Some((label, rest)) = line.split_once("::") {
+            if !label.is_empty() && !label.contains(char::is_whitespace) {
+                line = rest.trim();
+            }
+        }
+        if line.is_empty()
+            || line.starts_with('#')
+            || matches!(line, "{" | "}")
+            || line.eq_ignore_ascii_case("return")
+        {
+            continue;
+        }
+        let (command, rest) = split_command(line);
+        let result = match command.to_lowercase().as_str() {
+            "send" | "sendinput" | "sendevent" | "sendplay" => {
+                send_text(rest).and_then(|text| builder.send(&text, false))
+            }
+            "sendraw" | "sendtext" => {
+                send_text(rest).and_then(|text| builder.send(&text, true))
+            }
+            "sleep" => arguments(rest).and_then(|args| builder.sleep(&args)),
+            "mousemove" => {
+                arguments(rest).and_then(|args| builder.mouse_move(&args))
+            }
+            "click" => arguments(rest).and_then(|args| builder.click(&args)),
+            "coordmode" => {
+                let args = arguments(rest).unwrap_or_default();
+                if args
+                    .first()
+                    .is_some_and(|a| a.eq_ignore_ascii_case("mouse"))
+                {
+                    screen_coords = args
+                        .get(1)
+                        .is_some_and(|a| a.eq_ignore_ascii_case("screen"));
+                }
+                Ok(())
+            }
+            _ => Err("not supported".to_string()),
+        };
+        if let Err(e) = result {
+            builder.warn(n, line, &e);
+        }
+    }
+    if builder.moved && !screen_coords {
+        builder.warnings.push(
+            "Positions are taken as screen coordinates, but without \
+             `CoordMode Mouse, Screen` AutoHotkey uses ones relative to the \
+             active window."
+                .to_string(),
+        );
+    }
+    Script {
+        events: builder.events,
+        warnings: builder.warnings,
+    }
+}
+
+/// The line without a `;` comment. One starts a line or follows
+/// whitespace; a backtick escapes it.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == ';' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = if previous == '`' { ' ' } else { c };
+    }
+    line
+}
+
+/// Splits a line into its command and the rest, without the comma or
+/// space in between; a v2 call keeps its parentheses.
+fn split_command(line: &str) -> (&str, &str) {
+    let end = line
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(line.len());
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    (&line[..end], rest)
+}
+
+/// The text of a `Send`: a v2 string, in parentheses or not, or the rest
+/// of a v1 line.
+fn send_text(rest: &str) -> Result<String, String> {
+    let rest = rest.trim();
+    let inner = match rest.strip_prefix('(') {
+        Some(call) => call.strip_suffix(')').ok_or("unclosed call")?.trim(),
+        None => rest,
+    };
+    match unquote(inner) {
+        Some(text) => text,
+        None if inner.starts_with(['"', '\'']) => {
+            Err("expressions aren't supported".into())
+        }
+        None if rest.starts_with('(') => Err("expected a string".into()),
+        None => unescape(rest, true),
+    }
+}
+
+/// Arguments separated by commas, v1 style or in a v2 call, with quotes
+/// removed. `Click` also takes them separated by spaces.
+fn arguments(rest: &str) -> Result<Vec<String>, String> {
+    let rest = rest.trim();
+    let rest = match rest.strip_prefix('(') {
+        Some(call) => call.strip_suffix(')').ok_or("unclosed call")?,
+        None => rest,
+    };
+    let mut args = Vec::new();
+    for arg in rest.split(',') {
+        let arg = arg.trim();
+        let arg = match unquote(arg) {
+            Some(text) => text?,
+            None if arg.contains(['"', '%']) => {
+                return Err("variables and expressions aren't supported".into())
+            }
+            None => arg.to_string(),
+        };
+        args.extend(arg.split_whitespace().map(str::to_string));
+    }
+    Ok(args)
+}
+
+/// The contents of a v2 string literal, if `s` is exactly one.
+fn unquote(s: &str) -> Option<Result<String, String>> {
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let inner = s[1..].strip_suffix(quote)?;
+    let mut escaped = false;
+    for c in inner.chars() {
+        // Another string, joined on by an expression.
+        if c == quote && !escaped {
+            return None;
+        }
+        escaped = c == '`' && !escaped;
+    }
+    Some(unescape(inner, false))
+}
+
+/// Resolves backtick escapes. In v1 text a `%` left over is a variable.
+fn unescape(s: &str, v1: bool) -> Result<String, String> {
+    let mut text = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('r') => {}
+                Some(c) => text.push(c),
+                None => return Err("dangling escape".into()),
+            },
+            '%' if v1 => return Err("variables aren't supported".into()),
+            c => text.push(c),
+        }
+    }
+    Ok(text)
+}
+
+/// Keys by their names in braces, as in `{Enter}`, and whether they need
+/// Shift.
+fn key_named(name: &str) -> Option<(Key, bool)> {
+    let key = match name.to_lowercase().as_str() {
+        "enter" => Key::Return,
+        "tab" => Key::Tab,
+        "esc" | "escape" => Key::Escape,
+        "space" => Key::Space,
+        "bs" | "backspace" => Key::Backspace,
+        "del" | "delete" => Key::Delete,
+        "ins" | "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pgup" => Key::PageUp,
+        "pgdn" => Key::PageDown,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "ctrl" | "control" | "lctrl" => Key::ControlLeft,
+        "rctrl" => Key::ControlRight,
+        "alt" | "lalt" => Key::Alt,
+        "ralt" => Key::AltGr,
+        "shift" | "lshift" => Key::ShiftLeft,
+        "rshift" => Key::ShiftRight,
+        "lwin" => Key::MetaLeft,
+        "rwin" => Key::MetaRight,
+        "capslock" => Key::CapsLock,
+        "numlock" => Key::NumLock,
+        "scrolllock" => Key::ScrollLock,
+        "printscreen" => Key::PrintScreen,
+        "pause" => Key::Pause,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        _ => {
+            let mut chars = name.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => char_key(c),
+                _ => None,
+            };
+        }
+    };
+    Some((key, false))
+}
+
+fn modifier(c: char) -> Option<Key> {
+    match c {
+        '^' => Some(Key::ControlLeft),
+        '!' => Some(Key::Alt),
+        '+' => Some(Key::ShiftLeft),
+        '#' => Some(Key::MetaLeft),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    events: Vec<RecordedEvent>,
+    warnings: Vec<String>,
+    last: Option<Duration>,
+    // time slept since the last event
+    sleep: Option<Duration>,
+    pointer: (f64, f64),
+    moved: bool,
+}
+
+impl Builder {
+    fn warn(&mut self, n: usize, line: &str, problem: &str) {
+        self.warnings.push(format!(
+            "Line {}: skipped `{}`: {}",
+            n + 1,
+            line,
+            problem
+        ));
+    }
+
+    fn push(&mut self, event_type: EventType) {
+        let gap = self.sleep.take();
+        let timestamp = match self.last {
+            Some(last) => last + gap.unwrap_or(STEP),
+            None => gap.unwrap_or_default(),
+        };
+        self.last = Some(timestamp);
+        self.events.push(RecordedEvent {
+            action: MacroAction::Input(event_type),
+            timestamp,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        });
+    }
+
+    /// Presses `key` with `modifiers` held, or only presses or releases it.
+    fn key(&mut self, key: Key, modifiers: &[Key], press: bool, release: bool) {
+        for m in modifiers {
+            self.push(EventType::KeyPress(*m));
+        }
+        if press {
+            self.push(EventType::KeyPress(key));
+        }
+        if release {
+            self.push(EventType::KeyRelease(key));
+        }
+        for m in modifiers.iter().rev() {
+            self.push(EventType::KeyRelease(*m));
+        }
+    }
+
+    fn send(&mut self, text: &str, raw: bool) -> Result<(), String> {
+        let (text, raw) = match text.strip_prefix("{Raw}") {
+            Some(text) => (text, true),
+            None => match text.strip_prefix("{Text}") {
+                Some(text) => (text, true),
+                None => (text, raw),
+            },
+        };
+        let mut modifiers = Vec::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            if let Some(m) = modifier(c).filter(|_| !raw) {
+                modifiers.push(m);
+                continue;
+            }
+            if c == '{' && !raw {
+                // `{}}` and `{{}` send the braces themselves.
+                let end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| c == '}')
+                    .map(|(i, _)| i);
+                let end = end.ok_or("unclosed brace")?;
+                let (inner, after) = (&rest[..end], &rest[end + 1..]);
+                rest = after;
+                self.send_braced(inner, &modifiers)?;
+                modifiers.clear();
+                continue;
+            }
+            self.send_char(c, &modifiers)?;
+            modifiers.clear();
+        }
+        Ok(())
+    }
+
+    /// Sends `{Name}`, `{Name down}`, `{Name up}` or `{Name N}`.
+    fn send_braced(&mut self, inner: &str, mods: &[Key]) -> Result<(), String> {
+        let (name, option) = match inner.rsplit_once(' ') {
+            Some((name, option)) if !name.is_empty() => (name, Some(option)),
+            _ => (inner, None),
+        };
+        let (key, shift) = key_named(name.trim())
+            .ok_or_else(|| format!("unknown key {{{}}}", name))?;
+        let mut mods = mods.to_vec();
+        if shift && !mods.contains(&Key::ShiftLeft) {
+            mods.push(Key::ShiftLeft);
+        }
+        let mods = &mods[..];
+        match option.map(str::to_lowercase).as_deref() {
+            None => self.key(key, mods, true, true),
+            Some("down") => self.key(key, mods, true, false),
+            Some("up") => self.key(key, mods, false, true),
+            Some(count) => {
+                let count: usize = count.parse().map_err(|_| {
+                    format!("invalid key option in {{{}}}", inner)
+                })?;
+                for _ in 0..count {
+                    self.key(key, mods, true, true);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send_char(&mut self, c: char, modifiers: &[Key]) -> Result<(), String> {
+        let (key, shift) = match c {
+            '\n' => (Key::Return, false),
+            '\t' => (Key::Tab, false),
+            c => {
+                char_key(c).ok_or_else(|| format!("'{}' can't be typed", c))?
+            }
+        };
+        let mut modifiers = modifiers.to_vec();
+        if shift && !modifiers.contains(&Key::ShiftLeft) {
+            modifiers.push(Key::ShiftLeft);
+        }
+        self.key(key, &modifiers, true, true);
+        Ok(())
+    }
+
+    fn sleep(&mut self, args: &[String]) -> Result<(), String> {
+        let [ms] = args else {
+            return Err("expected a time in milliseconds".into());
+        };
+        let ms: u64 =
+            ms.parse().map_err(|_| format!("invalid time '{}'", ms))?;
+        let slept = self.sleep.unwrap_or_default();
+        self.sleep = Some(slept + Duration::from_millis(ms));
+        Ok(())
+    }
+
+    /// `MouseMove X, Y [, Speed, R]`; the speed is ignored.
+    fn mouse_move(&mut self, args: &[String]) -> Result<(), String> {
+        let (x, y) = match args {
+            [x, y, ..] => (coordinate(x)?, coordinate(y)?),
+            _ => return Err("expected X and Y".into()),
+        };
+        // Left out arguments aren't kept, so R may come third.
+        let relative = args[2..].iter().any(|a| a.eq_ignore_ascii_case("r"));
+        self.move_to(x, y, relative);
+        Ok(())
+    }
+
+    fn move_to(&mut self, x: f64, y: f64, relative: bool) {
+        self.pointer = match relative {
+            true => (self.pointer.0 + x, self.pointer.1 + y),
+            false => (x, y),
+        };
+        self.moved = true;
+        let (x, y) = self.pointer;
+        self.push(EventType::MouseMove { x, y });
+    }
+
+    /// `Click [X, Y] [Button] [Count] [Down|Up] [Relative]`, in any order
+    /// as AutoHotkey allows.
+    fn click(&mut self, args: &[String]) -> Result<(), String> {
+        let mut numbers = Vec::new();
+        let mut button = Button::Left;
+        let mut wheel = None;
+        let (mut press, mut release, mut relative) = (true, true, false);
+        for arg in args {
+            match arg.to_lowercase().as_str() {
+                "left" | "l" => button = Button::Left,
+                "right" | "r" => button = Button::Right,
+                "middle" | "m" => button = Button::Middle,
+                "wheelup" | "wu" => wheel = Some(1),
+                "wheeldown" | "wd" => wheel = Some(-1),
+                "down" | "d" => release = false,
+                "up" | "u" => press = false,
+                "rel" | "relative" => relative = true,
+                _ => numbers.push(coordinate(arg)?),
+            }
+        }
+        let count = match numbers[..] {
+            [] => 1.0,
+            [count] => count,
+            [x, y, ref count @ ..] => {
+                self.move_to(x, y, relative);
+                count.first().copied().unwrap_or(1.0)
+            }
+        };
+        for _ in 0..count.max(0.0) as usize {
+            match wheel {
+                Some(delta_y) => self.push(EventType::Wheel {
+                    delta_x: 0,
+                    delta_y,
+                }),
+                None => {
+                    if press {
+                        self.push(EventType::ButtonPress(button));
+                    }
+                    if release {
+                        self.push(EventType::ButtonRelease(button));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn coordinate(s: &str) -> Result<f64, String> {
+    s.parse().map_err(|_| format!("unexpected '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(script: &str) -> Vec<EventType> {
+        let parsed = parse(script);
+        assert_eq!(parsed.warnings, Vec::<String>::new());
+        parsed
+            .events
+            .into_iter()
+            .map(|evt| match evt.action {
+                MacroAction::Input(input) => input,
+                action => panic!("unexpected {:?}", action),
+            })
+            .collect()
+    }
+
+    fn tap(key: Key) -> [EventType; 2] {
+        [EventType::KeyPress(key), EventType::KeyRelease(key)]
+    }
+
+    #[test]
+    fn sends_v1_and_v2_text_alike() {
+        let expected = [
+            vec![EventType::KeyPress(Key::ControlLeft)],
+            tap(Key::KeyC).to_vec(),
+            vec![EventType::KeyRelease(Key::ControlLeft)],
+            tap(Key::Return).to_vec(),
+        ]
+        .concat();
+        assert_eq!(inputs("Send, ^c{Enter}"), expected);
+        assert_eq!(inputs("Send \"^c{Enter}\""), expected);
+        assert_eq!(inputs("Send(\"^c{Enter}\")"), expected);
+    }
+
+    #[test]
+    fn sends_braces_and_multibyte_key_names() {
+        let down = inputs("SendInput {a down}");
+        assert_eq!(down, [EventType::KeyPress(Key::KeyA)]);
+        let braces = parse("Send {}}");
+        assert_eq!(braces.warnings, Vec::<String>::new());
+        assert_eq!(braces.events.len(), 4);
+        let accented = parse("Send {é}");
+        assert_eq!(accented.warnings.len(), 1);
+        assert!(
+            accented.warnings[0].ends_with("unknown key {é}"),
+            "{}",
+            accented.warnings[0]
+        );
+    }
+
+    #[test]
+    fn clicks_and_moves_in_both_versions() {
+        let expected = [
+            EventType::MouseMove { x: 100.0, y: 200.0 },
+            EventType::ButtonPress(Button::Right),
+            EventType::ButtonRelease(Button::Right),
+            EventType::MouseMove { x: 110.0, y: 190.0 },
+        ];
+        let v1 = "CoordMode, Mouse, Screen\n\
+                  Click, 100, 200, Right\n\
+                  MouseMove, 10, -10, 0, R";
+        let v2 = "CoordMode(\"Mouse\", \"Screen\")\n\
+                  Click(\"100 200 Right\")\n\
+                  MouseMove(10, -10, 0, \"R\")";
+        assert_eq!(inputs(v1), expected);
+        assert_eq!(inputs(v2), expected);
+    }
+
+    #[test]
+    fn sleeps_space_out_the_events() {
+        let parsed = parse("Click\nSleep, 500\nClick");
+        let times: Vec<u64> = parsed
+            .events
+            .iter()
+            .map(|evt| evt.timestamp.as_millis() as u64)
+            .collect();
+        assert_eq!(times, [0, 10, 510, 520]);
+    }
+}
@@ -0,0 +1,198 @@
+//! The replay engine on a tokio runtime, for embedding many playbacks in
+//! one process, e.g. against [`MockBackend`](crate::backend::MockBackend),
+//! without a thread for each.
+//!
+//! It is [`player::run`](crate::player::run) with its waits done on the
+//! runtime: the same scheduling, pause, stop, looping and wait steps, on
+//! the same [`Clock`]. Pause and stop wake it at once, but it sleeps on the
+//! runtime's timer instead of spinning, so events may be a millisecond or
+//! so late.
+//!
+//! Event sources are read as the playback goes, so its future isn't
+//! `Send`: run playbacks together on a `LocalSet` or by joining them.
+
+use crate::backend::InputBackend;
+use crate::player::{self, Domain, EventSource, PlaybackReport, Wait};
+use crate::state::SharedState;
+use crate::timing::Clock;
+use std::future::{self, Future};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Instant;
+use tokio::time;
+
+/// Plays the events from `source` into `backend` like
+/// [`player::run`](crate::player::run), honouring pause, stop and looping
+/// from the shared state.
+pub async fn run<B, S>(
+    backend: &mut B,
+    source: &S,
+    shared: &Arc<Mutex<SharedState>>,
+    domain: Option<Domain>,
+) -> PlaybackReport
+where
+    B: InputBackend,
+    S: EventSource + ?Sized,
+{
+    player::play(backend, source, shared, domain, &Runtime).await
+}
+
+/// Waits on the tokio runtime, woken through [`SharedState::wakers`].
+struct Runtime;
+
+impl Wait for Runtime {
+    async fn sleep_until(&self, clock: &Clock, deadline: Instant) {
+        match clock {
+            Clock::Real => time::sleep_until(deadline.into()).await,
+            Clock::Simulated(_) => clock.sleep_until(deadline),
+        }
+    }
+
+    async fn wait_while<F>(
+        &self,
+        shared: &Arc<Mutex<SharedState>>,
+        deadline: Option<Instant>,
+        waiting: F,
+    ) where
+        F: FnMut(&SharedState) -> bool,
+    {
+        let changed = changed(shared, waiting);
+        match deadline {
+            Some(deadline) => {
+                let _ = time::timeout_at(deadline.into(), changed).await;
+            }
+            None => changed.await,
+        }
+    }
+}
+
+/// Ready once `waiting` no longer holds for the shared state.
+fn changed<'a, F>(
+    shared: &'a Arc<Mutex<SharedState>>,
+    mut waiting: F,
+) -> impl Future<Output = ()> + 'a
+where
+    F: FnMut(&SharedState) -> bool + 'a,
+{
+    future::poll_fn(move |cx| {
+        let mut shared = shared.lock().unwrap();
+        if !waiting(&shared) {
+            return Poll::Ready(());
+        }
+        if !shared.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            shared.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::event::{MacroAction, RecordedEvent};
+    use crate::player::Outcome;
+    use crate::state::State;
+    use rdev::{EventType, Key};
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime;
+
+    fn input(ms: u64, evt: EventType) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Input(evt),
+            timestamp: Duration::from_millis(ms),
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        }
+    }
+
+    fn events() -> Vec<RecordedEvent> {
+        vec![
+            input(0, EventType::KeyPress(Key::KeyA)),
+            input(40, EventType::KeyRelease(Key::KeyA)),
+            input(100, EventType::MouseMove { x: 1.0, y: 2.0 }),
+            input(250, EventType::KeyPress(Key::KeyB)),
+            input(300, EventType::KeyRelease(Key::KeyB)),
+        ]
+    }
+
+    fn playing(clock: &Clock) -> Arc<Mutex<SharedState>> {
+        let mut state = SharedState::new();
+        state.clock = clock.clone();
+        state.set_state(State::Playing);
+        Arc::new(Mutex::new(state))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// What the mock was given, by time since `start`.
+    fn played(mock: MockBackend, start: Instant) -> Vec<(Duration, EventType)> {
+        mock.injected
+            .into_iter()
+            .map(|(at, evt)| (at - start, evt))
+            .collect()
+    }
+
+    #[test]
+    fn plays_like_the_threaded_player() {
+        let events = events();
+
+        let clock = Clock::simulated();
+        let start = clock.now();
+        let shared = playing(&clock);
+        let mut mock = MockBackend::with_clock(clock);
+        let report = player::run(&mut mock, &events, &shared, None);
+        let threaded = played(mock, start);
+        assert_eq!(report.outcome, Outcome::Finished);
+
+        let clock = Clock::simulated();
+        let start = clock.now();
+        let shared = playing(&clock);
+        let mut mock = MockBackend::with_clock(clock);
+        let report = block_on(run(&mut mock, &events, &shared, None));
+        assert_eq!(report.outcome, Outcome::Finished);
+        assert_eq!(report.events_injected, events.len());
+        assert_eq!(played(mock, start), threaded);
+    }
+
+    #[test]
+    fn follows_the_speed() {
+        let clock = Clock::simulated();
+        let start = clock.now();
+        let shared = playing(&clock);
+        shared.lock().unwrap().speed = 2.0;
+        let mut mock = MockBackend::with_clock(clock);
+        block_on(run(&mut mock, &events(), &shared, None));
+        let times: Vec<_> =
+            played(mock, start).into_iter().map(|(at, _)| at).collect();
+        let ms = Duration::from_millis;
+        assert_eq!(times, [ms(0), ms(20), ms(50), ms(125), ms(150)]);
+    }
+
+    #[test]
+    fn a_stop_wakes_a_paused_playback() {
+        let shared = playing(&Clock::Real);
+        shared.lock().unwrap().set_state(State::Paused);
+        let stopper = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                shared.lock().unwrap().set_state(State::Idle);
+            })
+        };
+        let mut mock = MockBackend::new();
+        let report = block_on(run(&mut mock, &events(), &shared, None));
+        stopper.join().unwrap();
+        assert_eq!(report.outcome, Outcome::Stopped);
+        assert!(mock.injected.is_empty());
+    }
+}
@@ -0,0 +1,445 @@
+//! Input injection backends used by the player.
+
+use crate::timing::Clock;
+use enigo::{
+    Axis, Button, Coordinate, Direction,
+    Enigo, Key, Keyboard, Mouse, Settings,
+};
+use rdev::{EventType, Key as RdevKey};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Which backend playback injects input through.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Enigo,
+    /// Linux uinput virtual device, see [`crate::uinput`].
+    Uinput,
+    /// Keys as hardware scan codes, for games ignoring synthesized virtual
+    /// keys: [`ScanCodeBackend`] on Windows, uinput on Linux. Recording with
+    /// it keeps keys by where they are, see [`crate::scancode`].
+    ScanCode,
+    /// Injects nothing, for checking macros play where there is no display.
+    Mock,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enigo" => Ok(BackendKind::Enigo),
+            "uinput" => Ok(BackendKind::Uinput),
+            "scancode" => Ok(BackendKind::ScanCode),
+            "mock" => Ok(BackendKind::Mock),
+            _ => Err(format!(
+                "unknown backend '{}' (enigo, uinput, scancode, mock)",
+                s
+            )),
+        }
+    }
+}
+
+/// Something the player can inject input events into.
+pub trait InputBackend {
+    fn send(&mut self, evt: &EventType) -> Result<(), String>;
+
+    /// Injects `evt`, due at `due` by the recording's timing. Backends
+    /// that depend on how fast input comes can go by that, as batched
+    /// events are sent back to back.
+    fn send_due(
+        &mut self,
+        evt: &EventType,
+        due: Instant,
+    ) -> Result<(), String> {
+        let _ = due;
+        self.send(evt)
+    }
+
+    /// Injects several events that are due at (nearly) the same time, each
+    /// at its `due` instant. Backends with per-call overhead can override
+    /// this to inject them in one go.
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        due: &[Instant],
+    ) -> Result<(), String> {
+        evts.iter()
+            .zip(due)
+            .try_for_each(|(evt, due)| self.send_due(evt, *due))
+    }
+
+    /// Whether the backend can still deliver input; playback stops once
+    /// this turns false.
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+impl<B: InputBackend + ?Sized> InputBackend for &mut B {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        (**self).send(evt)
+    }
+
+    fn send_due(
+        &mut self,
+        evt: &EventType,
+        due: Instant,
+    ) -> Result<(), String> {
+        (**self).send_due(evt, due)
+    }
+
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        due: &[Instant],
+    ) -> Result<(), String> {
+        (**self).send_batch(evts, due)
+    }
+
+    fn is_available(&self) -> bool {
+        (**self).is_available()
+    }
+}
+
+/// Global input injection through enigo.
+pub struct EnigoBackend {
+    enigo: Enigo,
+    /// What batched moves are mapped to, looked up once per playback.
+    #[cfg(target_os = "windows")]
+    desktop: sendinput::Desktop,
+}
+
+impl EnigoBackend {
+    pub fn new() -> Result<Self, String> {
+        let enigo =
+            Enigo::new(&Settings::default()).map_err(|e| e.to_string())?;
+        Ok(Self {
+            enigo,
+            #[cfg(target_os = "windows")]
+            desktop: sendinput::Desktop::current(),
+        })
+    }
+
+    /// Moves the pointer by `dx`, `dy` as relative motion.
+    pub fn move_by(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        self.enigo
+            .move_mouse(dx, dy, Coordinate::Rel)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl InputBackend for EnigoBackend {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        let enigo = &mut self.enigo;
+        let result = match evt {
+            EventType::MouseMove { x, y } => {
+                enigo.move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+            }
+            EventType::ButtonPress(button) => match enigo_button(*button) {
+                Some(b) => enigo.button(b, Direction::Press),
+                None => Ok(()),
+            },
+            EventType::ButtonRelease(button) => match enigo_button(*button) {
+                Some(b) => enigo.button(b, Direction::Release),
+                None => Ok(()),
+            },
+            EventType::Wheel { delta_x, delta_y } => {
+                let mut result = Ok(());
+                if *delta_y != 0 {
+                    result = enigo.scroll(*delta_y as i32, Axis::Vertical);
+                }
+                if *delta_x != 0 && result.is_ok() {
+                    result = enigo.scroll(*delta_x as i32, Axis::Horizontal);
+                }
+                result
+            }
+            EventType::KeyPress(key) => match rdev_key_to_enigo_key(*key) {
+                Some(k) => enigo.key(k, Direction::Press),
+                None => Ok(()),
+            },
+            EventType::KeyRelease(key) => match rdev_key_to_enigo_key(*key) {
+                Some(k) => enigo.key(k, Direction::Release),
+                None => Ok(()),
+            },
+        };
+        result.map_err(|e| e.to_string())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        _due: &[Instant],
+    ) -> Result<(), String> {
+        let all_moves = evts
+            .iter()
+            .all(|evt| matches!(evt, EventType::MouseMove { .. }));
+        if all_moves {
+            sendinput::move_mouse_batch(&self.desktop, evts)
+        } else {
+            evts.iter().try_for_each(|evt| self.send(evt))
+        }
+    }
+}
+
+/// Keys injected as scan codes, which games reading DirectInput or raw
+/// input register where they ignore virtual keys; the mouse goes through
+/// enigo. Keys are pressed where they are on a US keyboard, as recorded
+/// with this backend; keys rdev doesn't know are recorded with their
+/// virtual key code and injected by the scan code the layout gives it.
+#[cfg(target_os = "windows")]
+pub struct ScanCodeBackend {
+    enigo: EnigoBackend,
+}
+
+#[cfg(target_os = "windows")]
+impl ScanCodeBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            enigo: EnigoBackend::new()?,
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl InputBackend for ScanCodeBackend {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        match evt {
+            EventType::KeyPress(key) => sendinput::scan_key(*key, false),
+            EventType::KeyRelease(key) => sendinput::scan_key(*key, true),
+            _ => self.enigo.send(evt),
+        }
+    }
+
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        _due: &[Instant],
+    ) -> Result<(), String> {
+        evts.iter().try_for_each(|evt| self.send(evt))
+    }
+}
+
+/// Direct `SendInput` injection, bypassing enigo's one-call-per-event path.
+#[cfg(target_os = "windows")]
+mod sendinput {
+    use rdev::{EventType, Key};
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD,
+        INPUT_MOUSE, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP,
+        KEYEVENTF_SCANCODE, MAPVK_VK_TO_VSC_EX, MOUSEEVENTF_ABSOLUTE,
+        MOUSEEVENTF_MOVE, MOUSEEVENTF_VIRTUALDESK, MOUSEINPUT, VIRTUAL_KEY,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
+        SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+    };
+
+    /// The virtual desktop spanning every monitor.
+    pub struct Desktop {
+        left: i32,
+        top: i32,
+        width: i32,
+        height: i32,
+    }
+
+    impl Desktop {
+        pub fn current() -> Self {
+            unsafe {
+                Self {
+                    left: GetSystemMetrics(SM_XVIRTUALSCREEN),
+                    top: GetSystemMetrics(SM_YVIRTUALSCREEN),
+                    width: GetSystemMetrics(SM_CXVIRTUALSCREEN).max(2),
+                    height: GetSystemMetrics(SM_CYVIRTUALSCREEN).max(2),
+                }
+            }
+        }
+    }
+
+    pub fn move_mouse_batch(
+        desktop: &Desktop,
+        evts: &[EventType],
+    ) -> Result<(), String> {
+        let Desktop {
+            left,
+            top,
+            width,
+            height,
+        } = *desktop;
+        // Absolute coordinates are normalized to 0..=65535 across the
+        // virtual desktop.
+        let normalize = |v: f64, origin: i32, extent: i32| {
+            ((v as i32 - origin) * 65535 / (extent - 1)).clamp(0, 65535)
+        };
+        let inputs: Vec<INPUT> = evts
+            .iter()
+            .filter_map(|evt| match evt {
+                EventType::MouseMove { x, y } => Some(INPUT {
+                    r#type: INPUT_MOUSE,
+                    Anonymous: INPUT_0 {
+                        mi: MOUSEINPUT {
+                            dx: normalize(*x, left, width),
+                            dy: normalize(*y, top, height),
+                            mouseData: 0,
+                            dwFlags: MOUSEEVENTF_MOVE
+                                | MOUSEEVENTF_ABSOLUTE
+                                | MOUSEEVENTF_VIRTUALDESK,
+                            time: 0,
+                            dwExtraInfo: 0,
+                        },
+                    },
+                }),
+                _ => None,
+            })
+            .collect();
+        let sent = unsafe {
+            SendInput(&inputs, std::mem::size_of::<INPUT>() as i32)
+        };
+        if sent as usize != inputs.len() {
+            return Err(format!(
+                "SendInput injected {} of {} moves",
+                sent,
+                inputs.len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn scan_key(key: Key, release: bool) -> Result<(), String> {
+        let scan = match crate::scancode::code(key) {
+            Some(scan) => scan as u32,
+            None => {
+                let vk = match key {
+                    Key::Unknown(code) => u16::try_from(code).ok(),
+                    _ => None,
+                }
+                .ok_or_else(|| format!("no scan code for {:?}", key))?;
+                unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC_EX) }
+            }
+        };
+        if scan == 0 {
+            return Err(format!("no scan code for {:?}", key));
+        }
+        let mut flags = KEYEVENTF_SCANCODE;
+        // Extended keys, e.g. the arrows, come with an 0xe0 prefix.
+        if scan >> 8 == 0xe0 {
+            flags |= KEYEVENTF_EXTENDEDKEY;
+        }
+        if release {
+            flags |= KEYEVENTF_KEYUP;
+        }
+        let input = INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: VIRTUAL_KEY(0),
+                    wScan: (scan & 0xff) as u16,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        };
+        let sent = unsafe {
+            SendInput(&[input], std::mem::size_of::<INPUT>() as i32)
+        };
+        if sent != 1 {
+            return Err(format!("SendInput didn't inject {:?}", key));
+        }
+        Ok(())
+    }
+}
+
+/// Backend that injects nothing and remembers every event it was given,
+/// along with when it was given.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    pub injected: Vec<(Instant, EventType)>,
+    clock: Clock,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A mock noting times from `clock`, e.g. the simulated one playback
+    /// uses.
+    pub fn with_clock(clock: Clock) -> Self {
+        Self {
+            injected: Vec::new(),
+            clock,
+        }
+    }
+}
+
+impl InputBackend for MockBackend {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        self.injected.push((self.clock.now(), *evt));
+        Ok(())
+    }
+}
+
+fn enigo_button(button: rdev::Button) -> Option<Button> {
+    match button {
+        rdev::Button::Left => Some(Button::Left),
+        rdev::Button::Right => Some(Button::Right),
+        rdev::Button::Middle => Some(Button::Middle),
+        _ => None,
+    }
+}
+
+fn rdev_key_to_enigo_key(rkey: RdevKey) -> Option<Key> {
+    use RdevKey::*;
+    match rkey {
+        Num0 => Some(Key::Num0),
+        Num1 => Some(Key::Num1),
+        Num2 => Some(Key::Num2),
+        Num3 => Some(Key::Num3),
+        Num4 => Some(Key::Num4),
+        Num5 => Some(Key::Num5),
+        Num6 => Some(Key::Num6),
+        Num7 => Some(Key::Num7),
+        Num8 => Some(Key::Num8),
+        Num9 => Some(Key::Num9),
+        KeyA => Some(Key::A),
+        KeyB => Some(Key::B),
+        KeyC => Some(Key::C),
+        KeyD => Some(Key::D),
+        KeyE => Some(Key::E),
+        KeyF => Some(Key::F),
+        KeyG => Some(Key::G),
+        KeyH => Some(Key::H),
+        KeyI => Some(Key::I),
+        KeyJ => Some(Key::J),
+        KeyK => Some(Key::K),
+        KeyL => Some(Key::L),
+        KeyM => Some(Key::M),
+        KeyN => Some(Key::N),
+        KeyO => Some(Key::O),
+        KeyP => Some(Key::P),
+        KeyQ => Some(Key::Q),
+        KeyR => Some(Key::R),
+        KeyS => Some(Key::S),
+        KeyT => Some(Key::T),
+        KeyU => Some(Key::U),
+        KeyV => Some(Key::V),
+        KeyW => Some(Key::W),
+        KeyX => Some(Key::X),
+        KeyY => Some(Key::Y),
+        KeyZ => Some(Key::Z),
+        ShiftLeft => Some(Key::LShift),
+        ShiftRight => Some(Key::RShift),
+        ControlLeft => Some(Key::LControl),
+        ControlRight => Some(Key::RControl),
+        Space => Some(Key::Space),
+        Return => Some(Key::Return),
+        Backspace => Some(Key::Backspace),
+        Tab => Some(Key::Tab),
+        Escape => Some(Key::Escape),
+        _ => None,
+    }
+}
@@ -0,0 +1,245 @@
+//! Background playback: posting synthesized window messages straight to a
+//! chosen window instead of injecting global input, so the macro keeps
+//! running while another window has focus.
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use crate::backend::InputBackend;
+    use rdev::{Button, EventType, Key};
+    use windows::Win32::Foundation::{HWND, LPARAM, POINT, WPARAM};
+    use windows::Win32::Graphics::Gdi::ScreenToClient;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        MapVirtualKeyW, MAPVK_VK_TO_VSC,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::*;
+
+    const MK_LBUTTON: usize = 0x0001;
+    const MK_RBUTTON: usize = 0x0002;
+    const MK_MBUTTON: usize = 0x0010;
+    const WHEEL_DELTA: i64 = 120;
+
+    /// A top-level window that receives posted input messages.
+    #[derive(Debug, Clone)]
+    pub struct WindowTarget {
+        // HWND isn't Send, so the raw handle value is kept instead.
+        hwnd: isize,
+        buttons: usize,
+        cursor: (i32, i32),
+    }
+
+    impl WindowTarget {
+        pub fn foreground() -> Option<Self> {
+            let hwnd = unsafe { GetForegroundWindow() };
+            if hwnd.0.is_null() {
+                return None;
+            }
+            Some(Self {
+                hwnd: hwnd.0 as isize,
+                buttons: 0,
+                cursor: (0, 0),
+            })
+        }
+
+        fn hwnd(&self) -> HWND {
+            HWND(self.hwnd as *mut _)
+        }
+
+        pub fn title(&self) -> String {
+            let mut buffer = [0u16; 256];
+            let len = unsafe { GetWindowTextW(self.hwnd(), &mut buffer) };
+            String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+        }
+
+        fn post(&self, msg: u32, wparam: usize, lparam: isize) {
+            let _ = unsafe {
+                PostMessageW(self.hwnd(), msg, WPARAM(wparam), LPARAM(lparam))
+            };
+        }
+
+        fn client_lparam(&self) -> isize {
+            let mut point = POINT {
+                x: self.cursor.0,
+                y: self.cursor.1,
+            };
+            let _ = unsafe { ScreenToClient(self.hwnd(), &mut point) };
+            make_lparam(point.x, point.y)
+        }
+    }
+
+    impl InputBackend for WindowTarget {
+        fn is_available(&self) -> bool {
+            unsafe { IsWindow(self.hwnd()).as_bool() }
+        }
+
+        fn send(&mut self, evt: &EventType) -> Result<(), String> {
+            match evt {
+                EventType::MouseMove { x, y } => {
+                    self.cursor = (*x as i32, *y as i32);
+                    self.post(WM_MOUSEMOVE, self.buttons, self.client_lparam());
+                }
+                EventType::ButtonPress(button) => {
+                    let Some((down, _, flag)) = button_messages(*button) else {
+                        return Ok(());
+                    };
+                    self.buttons |= flag;
+                    self.post(down, self.buttons, self.client_lparam());
+                }
+                EventType::ButtonRelease(button) => {
+                    let Some((_, up, flag)) = button_messages(*button) else {
+                        return Ok(());
+                    };
+                    self.buttons &= !flag;
+                    self.post(up, self.buttons, self.client_lparam());
+                }
+                EventType::Wheel { delta_x, delta_y } => {
+                    // Wheel messages carry screen coordinates.
+                    let lparam = make_lparam(self.cursor.0, self.cursor.1);
+                    if *delta_y != 0 {
+                        let wparam = wheel_wparam(*delta_y, self.buttons);
+                        self.post(WM_MOUSEWHEEL, wparam, lparam);
+                    }
+                    if *delta_x != 0 {
+                        let wparam = wheel_wparam(*delta_x, self.buttons);
+                        self.post(WM_MOUSEHWHEEL, wparam, lparam);
+                    }
+                }
+                EventType::KeyPress(key) => {
+                    if let Some(vk) = rdev_key_to_vk(*key) {
+                        self.post(WM_KEYDOWN, vk as usize, key_lparam(vk, false));
+                    }
+                }
+                EventType::KeyRelease(key) => {
+                    if let Some(vk) = rdev_key_to_vk(*key) {
+                        self.post(WM_KEYUP, vk as usize, key_lparam(vk, true));
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn make_lparam(x: i32, y: i32) -> isize {
+        ((y as u16 as isize) << 16) | (x as u16 as isize)
+    }
+
+    fn wheel_wparam(delta: i64, buttons: usize) -> usize {
+        let amount = (delta * WHEEL_DELTA) as i16 as u16 as usize;
+        (amount << 16) | buttons
+    }
+
+    fn key_lparam(vk: u16, release: bool) -> isize {
+        let scan = unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC) };
+        let mut lparam = 1 | ((scan as isize & 0xff) << 16);
+        if release {
+            // previous key state and transition state bits
+            lparam |= (1 << 30) | (1 << 31);
+        }
+        lparam
+    }
+
+    fn button_messages(button: Button) -> Option<(u32, u32, usize)> {
+        match button {
+            Button::Left => Some((WM_LBUTTONDOWN, WM_LBUTTONUP, MK_LBUTTON)),
+            Button::Right => Some((WM_RBUTTONDOWN, WM_RBUTTONUP, MK_RBUTTON)),
+            Button::Middle => Some((WM_MBUTTONDOWN, WM_MBUTTONUP, MK_MBUTTON)),
+            Button::Unknown(_) => None,
+        }
+    }
+
+    pub fn rdev_key_to_vk(key: Key) -> Option<u16> {
+        use Key::*;
+        let vk = match key {
+            Num0 => 0x30,
+            Num1 => 0x31,
+            Num2 => 0x32,
+            Num3 => 0x33,
+            Num4 => 0x34,
+            Num5 => 0x35,
+            Num6 => 0x36,
+            Num7 => 0x37,
+            Num8 => 0x38,
+            Num9 => 0x39,
+            KeyA => 0x41,
+            KeyB => 0x42,
+            KeyC => 0x43,
+            KeyD => 0x44,
+            KeyE => 0x45,
+            KeyF => 0x46,
+            KeyG => 0x47,
+            KeyH => 0x48,
+            KeyI => 0x49,
+            KeyJ => 0x4A,
+            KeyK => 0x4B,
+            KeyL => 0x4C,
+            KeyM => 0x4D,
+            KeyN => 0x4E,
+            KeyO => 0x4F,
+            KeyP => 0x50,
+            KeyQ => 0x51,
+            KeyR => 0x52,
+            KeyS => 0x53,
+            KeyT => 0x54,
+            KeyU => 0x55,
+            KeyV => 0x56,
+            KeyW => 0x57,
+            KeyX => 0x58,
+            KeyY => 0x59,
+            KeyZ => 0x5A,
+            ShiftLeft => 0xA0,
+            ShiftRight => 0xA1,
+            ControlLeft => 0xA2,
+            ControlRight => 0xA3,
+            Space => 0x20,
+            Return => 0x0D,
+            Backspace => 0x08,
+            Tab => 0x09,
+            Escape => 0x1B,
+            LeftArrow => 0x25,
+            UpArrow => 0x26,
+            RightArrow => 0x27,
+            DownArrow => 0x28,
+            Delete => 0x2E,
+            Home => 0x24,
+            End => 0x23,
+            PageUp => 0x21,
+            PageDown => 0x22,
+            _ => return None,
+        };
+        Some(vk)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use crate::backend::InputBackend;
+    use rdev::EventType;
+
+    /// Background playback relies on window messages, which only exist on
+    /// Windows; elsewhere no target can ever be selected.
+    #[derive(Debug, Clone)]
+    pub struct WindowTarget;
+
+    impl WindowTarget {
+        pub fn foreground() -> Option<Self> {
+            None
+        }
+
+        pub fn title(&self) -> String {
+            String::new()
+        }
+    }
+
+    impl InputBackend for WindowTarget {
+        fn is_available(&self) -> bool {
+            false
+        }
+
+        fn send(&mut self, _evt: &EventType) -> Result<(), String> {
+            Err("background playback requires Windows".into())
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) use platform::rdev_key_to_vk;
+pub use platform::WindowTarget;
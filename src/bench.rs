@@ -0,0 +1,127 @@
+//! Playback timing benchmark: replays a recording into the mock backend and
+//! measures how far each injection landed from its scheduled time.
+
+use crate::backend::MockBackend;
+use crate::event::{MacroAction, RecordedEvent};
+use crate::player::{self, EventSource};
+use crate::state::{SharedState, State};
+use crate::stream::EventLog;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds (in ms) of the histogram buckets; the last bucket is open.
+const BUCKETS_MS: &[f64] = &[-1.0, 0.0, 0.1, 0.5, 1.0, 2.0, 5.0];
+
+pub struct BenchReport {
+    /// Scheduling error per event in milliseconds; positive means late.
+    pub errors_ms: Vec<f64>,
+    pub recorded: Duration,
+    pub wall: Duration,
+}
+
+/// Plays the input events of `events` into a [`MockBackend`].
+///
+/// Steps that don't map to a single injection (e.g. element clicks) are left
+/// out so every injection lines up with one recorded event.
+pub fn run(events: Vec<RecordedEvent>) -> BenchReport {
+    let events: Vec<RecordedEvent> = events
+        .into_iter()
+        .filter(|e| matches!(e.action, MacroAction::Input(_)))
+        .collect();
+
+    let mut state = SharedState::new();
+    state.set_state(State::Playing);
+    let shared = Arc::new(Mutex::new(state));
+    let mut mock = MockBackend::new();
+
+    let start = Instant::now();
+    player::run(&mut mock, &events, &shared, None);
+    let wall = start.elapsed();
+
+    let errors_ms = events
+        .iter()
+        .zip(&mock.injected)
+        .map(|(evt, (at, _))| {
+            let target = start + evt.timestamp;
+            if *at >= target {
+                (*at - target).as_secs_f64() * 1000.0
+            } else {
+                -(target - *at).as_secs_f64() * 1000.0
+            }
+        })
+        .collect();
+
+    BenchReport {
+        errors_ms,
+        recorded: events.last().map(|e| e.timestamp).unwrap_or_default(),
+        wall,
+    }
+}
+
+pub fn run_file(path: &Path) -> Result<BenchReport, String> {
+    let events = EventLog::new(path).open()?.collect();
+    Ok(run(events))
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} events, recorded {:.3} s, played in {:.3} s",
+            self.errors_ms.len(),
+            self.recorded.as_secs_f64(),
+            self.wall.as_secs_f64()
+        )?;
+        if self.errors_ms.is_empty() {
+            return Ok(());
+        }
+
+        let mut sorted = self.errors_ms.clone();
+        sorted.sort_by(f64::total_cmp);
+        let mean = sorted.iter().sum::<f64>() / sorted.len() as f64;
+        writeln!(
+            f,
+            "scheduling error (ms): mean {:+.3}, p50 {:+.3}, p95 {:+.3}, \
+             p99 {:+.3}, max {:+.3}",
+            mean,
+            percentile(&sorted, 0.50),
+            percentile(&sorted, 0.95),
+            percentile(&sorted, 0.99),
+            sorted[sorted.len() - 1]
+        )?;
+
+        let mut counts = vec![0usize; BUCKETS_MS.len() + 1];
+        for e in &sorted {
+            let bucket = BUCKETS_MS
+                .iter()
+                .position(|upper| e < upper)
+                .unwrap_or(BUCKETS_MS.len());
+            counts[bucket] += 1;
+        }
+        let widest = counts.iter().copied().max().unwrap_or(1).max(1);
+        for (i, count) in counts.iter().enumerate() {
+            let label = match i {
+                0 => format!("< {}", BUCKETS_MS[0]),
+                i if i == BUCKETS_MS.len() => {
+                    format!(">= {}", BUCKETS_MS[i - 1])
+                }
+                i => format!("{}..{}", BUCKETS_MS[i - 1], BUCKETS_MS[i]),
+            };
+            writeln!(
+                f,
+                "{:>10} ms | {:>7} {}",
+                label,
+                count,
+                "#".repeat(count * 40 / widest)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+//! A compact binary form of recordings, a fraction of the size of JSON
+//! Lines for dense ones.
+//!
+//! After a magic header, each event is a tag byte and the time since the
+//! previous event in nanoseconds. Pointer moves to whole pixels store how
+//! far the pointer moved; keys are named once and then referred to by
+//! number, and key presses can also store the text they typed. Numbers
+//! are LEB128 varints, zigzag encoded where they can be negative. Anything
+//! else, e.g. clipboard text or wait steps, is stored as its JSON.
+
+use crate::event::{MacroAction, RecordedEvent};
+use rdev::{Button, EventType, Key};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Extension of recordings in this format.
+pub const EXTENSION: &str = "rmb";
+
+const MAGIC: &[u8; 4] = b"RMB1";
+
+const MOVE: u8 = 0;
+/// A move to a position that isn't a whole pixel, stored as is.
+const MOVE_EXACT: u8 = 1;
+const KEY_PRESS: u8 = 2;
+const KEY_RELEASE: u8 = 3;
+const BUTTON_PRESS: u8 = 4;
+const BUTTON_RELEASE: u8 = 5;
+const WHEEL: u8 = 6;
+const JSON: u8 = 7;
+/// A key press with the text it typed.
+const KEY_TEXT: u8 = 8;
+
+/// Whether `bytes` start like a recording in this format.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(events: &[RecordedEvent]) -> Result<Vec<u8>, String> {
+    let mut out = MAGIC.to_vec();
+    let mut last = Duration::ZERO;
+    let mut pointer = (0i64, 0i64);
+    let mut keys: Vec<Key> = Vec::new();
+    for evt in events {
+        let plain = evt.device.is_none()
+            && evt.wait.is_none()
+            && evt.per_pass.is_none();
+        let input = match &evt.action {
+            // Only presses have a tag storing their text.
+            MacroAction::Input(input @ EventType::KeyPress(_)) if plain => {
+                Some(*input)
+            }
+            MacroAction::Input(input) if plain && evt.text.is_none() => {
+                Some(*input)
+            }
+            _ => None,
+        };
+        let tag = match input {
+            Some(EventType::MouseMove { x, y }) => match (whole(x), whole(y)) {
+                (Some(_), Some(_)) => MOVE,
+                _ => MOVE_EXACT,
+            },
+            Some(EventType::KeyPress(_)) if evt.text.is_some() => KEY_TEXT,
+            Some(EventType::KeyPress(_)) => KEY_PRESS,
+            Some(EventType::KeyRelease(_)) => KEY_RELEASE,
+            Some(EventType::ButtonPress(_)) => BUTTON_PRESS,
+            Some(EventType::ButtonRelease(_)) => BUTTON_RELEASE,
+            Some(EventType::Wheel { .. }) => WHEEL,
+            None => JSON,
+        };
+        out.push(tag);
+        // Timestamps that go backwards are kept as they are.
+        let delta = evt.timestamp.as_nanos() as i128 - last.as_nanos() as i128;
+        write_signed(&mut out, delta as i64);
+        last = evt.timestamp;
+
+        match input {
+            Some(EventType::MouseMove { x, y }) => match (whole(x), whole(y)) {
+                (Some(x), Some(y)) => {
+                    write_signed(&mut out, x - pointer.0);
+                    write_signed(&mut out, y - pointer.1);
+                    pointer = (x, y);
+                }
+                _ => {
+                    out.extend_from_slice(&x.to_le_bytes());
+                    out.extend_from_slice(&y.to_le_bytes());
+                }
+            },
+            Some(EventType::KeyPress(key) | EventType::KeyRelease(key)) => {
+                match keys.iter().position(|k| *k == key) {
+                    Some(i) => write_unsigned(&mut out, i as u64),
+                    None => {
+                        write_unsigned(&mut out, keys.len() as u64);
+                        let name = serde_json::to_vec(&key)
+                            .map_err(|e| e.to_string())?;
+                        write_bytes(&mut out, &name);
+                        keys.push(key);
+                    }
+                }
+                if let Some(text) = &evt.text {
+                    write_bytes(&mut out, text.as_bytes());
+                }
+            }
+            Some(
+                EventType::ButtonPress(button)
+                | EventType::ButtonRelease(button),
+            ) => write_unsigned(&mut out, button_code(button)),
+            Some(EventType::Wheel { delta_x, delta_y }) => {
+                write_signed(&mut out, delta_x);
+                write_signed(&mut out, delta_y);
+            }
+            None => {
+                let json =
+                    serde_json::to_vec(evt).map_err(|e| e.to_string())?;
+                write_bytes(&mut out, &json);
+            }
+        }
+    }
+    Ok(out)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<RecordedEvent>, String> {
+    if !is_binary(bytes) {
+        return Err("not a binary recording".into());
+    }
+    let mut reader = Reader {
+        bytes,
+        at: MAGIC.len(),
+    };
+    let mut events = Vec::new();
+    let mut last: i128 = 0;
+    let mut pointer = (0i64, 0i64);
+    let mut keys: Vec<Key> = Vec::new();
+    while let Some(tag) = reader.byte() {
+        last += reader.signed()? as i128;
+        let timestamp = Duration::from_nanos(
+            u64::try_from(last).map_err(|_| reader.error("negative time"))?,
+        );
+        let mut text = None;
+        let input = match tag {
+            MOVE => {
+                pointer.0 += reader.signed()?;
+                pointer.1 += reader.signed()?;
+                EventType::MouseMove {
+                    x: pointer.0 as f64,
+                    y: pointer.1 as f64,
+                }
+            }
+            MOVE_EXACT => EventType::MouseMove {
+                x: reader.float()?,
+                y: reader.float()?,
+            },
+            KEY_PRESS | KEY_RELEASE | KEY_TEXT => {
+                let i = reader.unsigned()? as usize;
+                if i == keys.len() {
+                    let name = reader.bytes()?;
+                    let key = serde_json::from_slice(name)
+                        .map_err(|e| reader.error(&e.to_string()))?;
+                    keys.push(key);
+                }
+                let key = *keys.get(i).ok_or_else(|| reader.error("key"))?;
+                if tag == KEY_TEXT {
+                    let bytes = reader.bytes()?.to_vec();
+                    text = Some(
+                        String::from_utf8(bytes)
+                            .map_err(|_| reader.error("text"))?,
+                    );
+                }
+                if tag != KEY_RELEASE {
+                    EventType::KeyPress(key)
+                } else {
+                    EventType::KeyRelease(key)
+                }
+            }
+            BUTTON_PRESS => EventType::ButtonPress(button(reader.unsigned()?)),
+            BUTTON_RELEASE => {
+                EventType::ButtonRelease(button(reader.unsigned()?))
+            }
+            WHEEL => EventType::Wheel {
+                delta_x: reader.signed()?,
+                delta_y: reader.signed()?,
+            },
+            JSON => {
+                let json = reader.bytes()?;
+                let evt = serde_json::from_slice(json)
+                    .map_err(|e| reader.error(&e.to_string()))?;
+                events.push(evt);
+                continue;
+            }
+            _ => return Err(reader.error("unknown event")),
+        };
+        events.push(RecordedEvent {
+            action: MacroAction::Input(input),
+            timestamp,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text,
+        });
+    }
+    Ok(events)
+}
+
+pub fn write(path: &Path, events: &[RecordedEvent]) -> Result<(), String> {
+    fs::write(path, encode(events)?)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+pub fn read(path: &Path) -> Result<Vec<RecordedEvent>, String> {
+    let bytes = fs::read(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    decode(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// `value` as a whole number of pixels, if it is one. -0 isn't, so it
+/// comes back the same.
+fn whole(value: f64) -> Option<i64> {
+    let rounded = value as i64;
+    let exact = (rounded as f64).to_bits() == value.to_bits();
+    (exact && value.abs() < 1e15).then_some(rounded)
+}
+
+fn button_code(button: Button) -> u64 {
+    match button {
+        Button::Left => 0,
+        Button::Right => 1,
+        Button::Middle => 2,
+        Button::Unknown(n) => 3 + n as u64,
+    }
+}
+
+fn button(code: u64) -> Button {
+    match code {
+        0 => Button::Left,
+        1 => Button::Right,
+        2 => Button::Middle,
+        n => Button::Unknown((n - 3) as u8),
+    }
+}
+
+fn write_unsigned(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_unsigned(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_unsigned(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, what: &str) -> String {
+        format!("invalid recording at byte {}: {}", self.at, what)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.at)?;
+        self.at += 1;
+        Some(byte)
+    }
+
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte().ok_or_else(|| self.error("cut off"))?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("number too long"))
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn float(&mut self) -> Result<f64, String> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + 8)
+            .ok_or_else(|| self.error("cut off"))?;
+        self.at += 8;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.unsigned()? as usize;
+        let bytes = self
+            .bytes
+            .get(self.at..self.at.saturating_add(len))
+            .ok_or_else(|| self.error("cut off"))?;
+        self.at += len;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::WaitRange;
+
+    fn input(ms: u64, input: EventType) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Input(input),
+            timestamp: Duration::from_millis(ms),
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        }
+    }
+
+    /// Events as JSON Lines, the form they have to survive unchanged.
+    fn json(events: &[RecordedEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|evt| serde_json::to_string(evt).unwrap())
+            .collect()
+    }
+
+    fn round_trip(events: &[RecordedEvent]) {
+        let decoded = decode(&encode(events).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(events));
+    }
+
+    #[test]
+    fn plain_input_round_trips() {
+        round_trip(&[
+            input(0, EventType::MouseMove { x: 100.0, y: 200.0 }),
+            input(8, EventType::MouseMove { x: 96.0, y: 215.0 }),
+            input(16, EventType::ButtonPress(Button::Left)),
+            input(90, EventType::ButtonRelease(Button::Left)),
+            input(120, EventType::KeyPress(Key::KeyA)),
+            input(180, EventType::KeyRelease(Key::KeyA)),
+            input(200, EventType::KeyPress(Key::Unknown(300))),
+            input(210, EventType::KeyPress(Key::KeyA)),
+            input(250, EventType::ButtonPress(Button::Unknown(7))),
+            input(
+                300,
+                EventType::Wheel {
+                    delta_x: 0,
+                    delta_y: -3,
+                },
+            ),
+        ]);
+    }
+
+    #[test]
+    fn exact_values_round_trip() {
+        let mut late = input(5, EventType::KeyPress(Key::KeyB));
+        late.timestamp = Duration::new(3, 123_456_789);
+        round_trip(&[
+            input(0, EventType::MouseMove { x: 10.25, y: -0.5 }),
+            input(4, EventType::MouseMove { x: -20.0, y: 1e9 }),
+            late,
+            // Out of order, as sanitize would fix.
+            input(1, EventType::KeyRelease(Key::KeyB)),
+        ]);
+    }
+
+    #[test]
+    fn typed_text_round_trips() {
+        let mut typed = input(0, EventType::KeyPress(Key::Num2));
+        typed.text = Some("é".into());
+        let mut shortcut = input(30, EventType::KeyPress(Key::KeyQ));
+        shortcut.text = Some("\u{1}".into());
+        round_trip(&[
+            typed,
+            input(20, EventType::KeyRelease(Key::Num2)),
+            shortcut,
+            input(40, EventType::KeyPress(Key::Num2)),
+        ]);
+    }
+
+    #[test]
+    fn other_events_round_trip_as_json() {
+        let mut waited = input(40, EventType::ButtonPress(Button::Right));
+        waited.wait = Some(WaitRange {
+            min: Duration::from_millis(200),
+            max: Duration::from_millis(900),
+        });
+        let mut shifted = input(50, EventType::MouseMove { x: 5.0, y: 5.0 });
+        shifted.per_pass = Some((0.0, 24.0));
+        let mut device = input(60, EventType::KeyPress(Key::Return));
+        device.device = Some("USB Keyboard".into());
+        round_trip(&[
+            RecordedEvent {
+                action: MacroAction::Clipboard("hello\nworld".into()),
+                ..input(0, EventType::KeyPress(Key::KeyA))
+            },
+            waited,
+            shifted,
+            device,
+            RecordedEvent {
+                action: MacroAction::Type("row {i}".into()),
+                ..input(70, EventType::KeyPress(Key::KeyA))
+            },
+        ]);
+    }
+
+    #[test]
+    fn dense_moves_are_much_smaller_than_json() {
+        let events: Vec<RecordedEvent> = (0..1000)
+            .map(|i| {
+                let x = 500.0 + (i as f64 / 10.0).sin() * 200.0;
+                let y = 300.0 + i as f64 / 4.0;
+                input(
+                    i * 8,
+                    EventType::MouseMove {
+                        x: x.round(),
+                        y: y.round(),
+                    },
+                )
+            })
+            .collect();
+        round_trip(&events);
+        let binary = encode(&events).unwrap().len();
+        let json: usize = json(&events).iter().map(|l| l.len() + 1).sum();
+        assert!(binary * 5 < json, "{} bytes vs {} as JSON", binary, json);
+    }
+
+    #[test]
+    fn empty_and_invalid_input() {
+        round_trip(&[]);
+        assert!(decode(b"{\"action\":").is_err());
+        let mut cut =
+            encode(&[input(1000, EventType::MouseMove { x: 1.5, y: 2.5 })])
+                .unwrap();
+        cut.truncate(cut.len() - 3);
+        assert!(decode(&cut).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Steps that play one of two variants depending on the keys held when
+//! playback was triggered.
+
+use crate::event::{MacroAction, RecordedEvent};
+use crate::player::EventSource;
+use rdev::Key;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// An event source with its branches decided by the keys held.
+pub struct Branched<S> {
+    source: S,
+    held: Vec<Key>,
+}
+
+impl<S> Branched<S> {
+    pub fn new(source: S, held: Vec<Key>) -> Self {
+        Self { source, held }
+    }
+}
+
+impl<S: EventSource> EventSource for Branched<S> {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        Ok(Box::new(Flatten {
+            events: self.source.open()?,
+            held: &self.held,
+            pending: VecDeque::new(),
+            shift: Duration::ZERO,
+        }))
+    }
+
+    fn passes(&self) -> Option<usize> {
+        self.source.passes()
+    }
+}
+
+/// Left and right modifiers count as the same key.
+fn either_side(key: Key) -> Key {
+    match key {
+        Key::ShiftRight => Key::ShiftLeft,
+        Key::ControlRight => Key::ControlLeft,
+        Key::MetaRight => Key::MetaLeft,
+        Key::AltGr => Key::Alt,
+        key => key,
+    }
+}
+
+/// Replaces each branch by the events of the variant taken, moving the
+/// events after it back by the variant's length.
+struct Flatten<'a, I> {
+    events: I,
+    held: &'a [Key],
+    pending: VecDeque<RecordedEvent>,
+    shift: Duration,
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Flatten<'_, I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        loop {
+            if let Some(evt) = self.pending.pop_front() {
+                return Some(evt);
+            }
+            let mut evt = self.events.next()?;
+            evt.timestamp += self.shift;
+            let MacroAction::IfKeyHeld {
+                key,
+                then,
+                otherwise,
+            } = evt.action
+            else {
+                return Some(evt);
+            };
+            let held = self
+                .held
+                .iter()
+                .any(|k| either_side(*k) == either_side(key));
+            let taken = if held {
+                then
+            } else {
+                otherwise
+            };
+            // Variants may branch again.
+            let taken: Vec<RecordedEvent> = Flatten {
+                events: taken.into_iter(),
+                held: self.held,
+                pending: VecDeque::new(),
+                shift: Duration::ZERO,
+            }
+            .collect();
+            self.shift += taken.last().map_or(Duration::ZERO, |e| e.timestamp);
+            self.pending.extend(taken.into_iter().map(|mut e| {
+                e.timestamp += evt.timestamp;
+                e
+            }));
+        }
+    }
+}
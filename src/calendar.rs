@@ -0,0 +1,258 @@
+//! Weekdays and times of day, for triggers that only act at certain
+//! times, e.g. an auto-clicker allowed 9:00 to 17:00 on weekdays.
+
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A moment in local time, to the second.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct LocalTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    /// Counting from Monday as 0.
+    pub weekday: usize,
+    /// Minutes since midnight.
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.minute / 60,
+            self.minute % 60,
+            self.second
+        )
+    }
+}
+
+/// Weekdays and a time of day range, e.g. `mon-fri 9:00-17:00`. Either
+/// part can be left out; a range ending before it starts runs past
+/// midnight.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeWindow {
+    /// Bit n set for weekday n.
+    days: u8,
+    hours: Option<(u32, u32)>,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: LocalTime) -> bool {
+        let day = |weekday: usize| self.days & (1 << weekday) != 0;
+        match self.hours {
+            None => day(time.weekday),
+            Some((from, to)) if from < to => {
+                day(time.weekday) && (from..to).contains(&time.minute)
+            }
+            // The part after midnight belongs to the day before.
+            Some((from, to)) => {
+                let yesterday = (time.weekday + 6) % 7;
+                (day(time.weekday) && time.minute >= from)
+                    || (day(yesterday) && time.minute < to)
+            }
+        }
+    }
+
+    /// Whether the window contains the current local time.
+    pub fn is_open(&self) -> bool {
+        self.contains(now())
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days: Vec<&str> = DAYS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.days & (1 << i) != 0)
+            .map(|(_, day)| *day)
+            .collect();
+        if days.len() < 7 {
+            write!(f, "{}", days.join("+"))?;
+            if self.hours.is_some() {
+                f.write_str(" ")?;
+            }
+        }
+        match self.hours {
+            Some((from, to)) => write!(
+                f,
+                "{}:{:02}-{}:{:02}",
+                from / 60,
+                from % 60,
+                to / 60,
+                to % 60
+            ),
+            None if days.len() == 7 => f.write_str("every day"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid time window '{}', expected e.g. 'mon-fri 9:00-17:00'",
+                s
+            )
+        };
+        if s.trim().is_empty() {
+            return Err(invalid());
+        }
+        let mut window = TimeWindow {
+            days: 0,
+            hours: None,
+        };
+        for part in s.split_whitespace() {
+            if part.contains(':') {
+                let (from, to) = part.split_once('-').ok_or_else(invalid)?;
+                let from = parse_time(from).ok_or_else(invalid)?;
+                let to = parse_time(to).ok_or_else(invalid)?;
+                if window.hours.replace((from, to)).is_some() || from == to {
+                    return Err(invalid());
+                }
+                continue;
+            }
+            for days in part.split('+') {
+                let (first, last) =
+                    days.split_once('-').unwrap_or((days, days));
+                let first = parse_day(first).ok_or_else(invalid)?;
+                let last = parse_day(last).ok_or_else(invalid)?;
+                // mon-fri, or sat-mon over the weekend.
+                let mut day = first;
+                loop {
+                    window.days |= 1 << day;
+                    if day == last {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+        }
+        if window.days == 0 {
+            window.days = 0x7f;
+        }
+        Ok(window)
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+fn parse_day(s: &str) -> Option<usize> {
+    let s = s.to_lowercase();
+    DAYS.iter().position(|day| s.starts_with(day))
+}
+
+/// Minutes since midnight of `H:MM`; `24:00` is the end of the day.
+fn parse_time(s: &str) -> Option<u32> {
+    let (hours, minutes) = s.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let minute = hours * 60 + minutes;
+    (minutes < 60 && minute <= 24 * 60).then_some(minute)
+}
+
+/// The current local time.
+pub fn now() -> LocalTime {
+    platform::now()
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::LocalTime;
+    use windows::Win32::System::SystemInformation::GetLocalTime;
+
+    pub fn now() -> LocalTime {
+        let time = unsafe { GetLocalTime() };
+        LocalTime {
+            year: time.wYear as i32,
+            month: time.wMonth as u32,
+            day: time.wDay as u32,
+            // Counted from Sunday.
+            weekday: (time.wDayOfWeek as usize + 6) % 7,
+            minute: time.wHour as u32 * 60 + time.wMinute as u32,
+            second: time.wSecond as u32,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::LocalTime;
+
+    pub fn now() -> LocalTime {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            let t = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&t, &mut tm);
+        }
+        LocalTime {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            // Counted from Sunday.
+            weekday: (tm.tm_wday as usize + 6) % 7,
+            minute: tm.tm_hour as u32 * 60 + tm.tm_min as u32,
+            // Up to 60 for a leap second.
+            second: (tm.tm_sec as u32).min(59),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::LocalTime;
+    use std::process::Command;
+
+    /// Asks `date`, falling back to the start of 1970 if it can't be run.
+    pub fn now() -> LocalTime {
+        let output = Command::new("date").arg("+%Y %m %d %u %H %M %S").output();
+        let text = output
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+            .unwrap_or_default();
+        let fields: Vec<u32> = text
+            .split_whitespace()
+            .filter_map(|f| f.parse().ok())
+            .collect();
+        match fields[..] {
+            [year, month, day, weekday @ 1..=7, hour, minute, second] => {
+                LocalTime {
+                    year: year as i32,
+                    month,
+                    day,
+                    weekday: weekday as usize - 1,
+                    minute: hour * 60 + minute,
+                    second: second.min(59),
+                }
+            }
+            // A Thursday.
+            _ => LocalTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                weekday: 3,
+                minute: 0,
+                second: 0,
+            },
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Chooses how global input is captured for the current session.
+
+use rdev::{listen, Event};
+use std::env;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Backoff between listener restarts, doubling up to the maximum while the
+/// listener keeps dying right away.
+const RESTART_DELAY_MIN: Duration = Duration::from_millis(500);
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(30);
+/// A listener that ran at least this long resets the backoff.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// Whether we are running inside a Wayland session.
+pub fn is_wayland() -> bool {
+    env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland")
+        || env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// Starts delivering global input to `callback` on a background thread,
+/// along with the name of the device it came from where that is known.
+///
+/// `per_device` asks for a capture method that knows the device, even if it
+/// is less accurate otherwise.
+///
+/// Returns false when the session can't be captured reliably. Hotkeys may
+/// still partly work then, but recording should be refused rather than
+/// silently capturing nothing.
+pub fn spawn<F>(screen: (i32, i32), per_device: bool, mut callback: F) -> bool
+where
+    F: FnMut(Event, Option<Arc<str>>) + Send + 'static,
+{
+    let wayland = is_wayland();
+
+    #[cfg(target_os = "linux")]
+    if wayland || per_device {
+        let devices = crate::evdev::open_devices();
+        if !devices.is_empty() {
+            println!("Capturing from {} input devices:", devices.len());
+            for device in &devices {
+                println!("  - {}", device.name);
+            }
+            if !wayland {
+                println!(
+                    "Pointer positions are approximate when capturing per \
+                     device."
+                );
+            }
+            crate::evdev::spawn(devices, screen, callback);
+            return true;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = screen;
+
+    if per_device {
+        println!(
+            "Input devices can't be told apart here (this needs read access \
+             to /dev/input on Linux); device filters are ignored."
+        );
+    }
+
+    if !wayland {
+        spawn_rdev(move |event| callback(event, None));
+        return true;
+    }
+
+    println!("Wayland session detected, but global input can't be captured.");
+    println!("  - add yourself to the 'input' group and log in again:");
+    println!("      sudo usermod -aG input $USER");
+    println!("  - or log into an X11 session.");
+    println!(
+        "Recording is disabled; hotkeys only work while an XWayland window \
+         has focus."
+    );
+    spawn_rdev(move |event| callback(event, None));
+    false
+}
+
+/// Runs `rdev::listen` under a supervisor that restarts it whenever it
+/// returns an error or panics, so hotkeys don't silently stop working.
+fn spawn_rdev<F>(callback: F)
+where
+    F: FnMut(Event) + Send + 'static,
+{
+    // Shared so each restarted listener keeps feeding the same callback.
+    let callback = Arc::new(Mutex::new(callback));
+
+    thread::spawn(move || {
+        let mut delay = RESTART_DELAY_MIN;
+        loop {
+            let started = Instant::now();
+            let callback = Arc::clone(&callback);
+            let listener = thread::spawn(move || {
+                listen(move |event| {
+                    // A panic in an earlier listener may have poisoned it.
+                    let mut callback =
+                        callback.lock().unwrap_or_else(PoisonError::into_inner);
+                    callback(event)
+                })
+            });
+
+            match listener.join() {
+                Ok(Ok(())) => println!("Input capture stopped."),
+                Ok(Err(e)) => println!("Input capture failed: {:?}", e),
+                Err(_) => println!("Input capture thread panicked."),
+            }
+
+            if started.elapsed() >= HEALTHY_RUN {
+                delay = RESTART_DELAY_MIN;
+            }
+            println!(
+                "Restarting input capture in {:.1} s...",
+                delay.as_secs_f64()
+            );
+            thread::sleep(delay);
+            delay = (delay * 2).min(RESTART_DELAY_MAX);
+        }
+    });
+}
@@ -0,0 +1,34 @@
+//! Text on the system clipboard, recorded and restored along with input.
+//!
+//! Each thread keeps its own handle open: on X11 text we put on the
+//! clipboard is only served while the handle that set it is alive.
+
+use arboard::Clipboard;
+use std::cell::RefCell;
+
+thread_local! {
+    static CLIPBOARD: RefCell<Option<Clipboard>> = const { RefCell::new(None) };
+}
+
+fn with<T>(
+    f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+) -> Result<T, String> {
+    CLIPBOARD.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let clipboard = match &mut *cell {
+            Some(clipboard) => clipboard,
+            None => cell.insert(Clipboard::new().map_err(|e| e.to_string())?),
+        };
+        f(clipboard).map_err(|e| e.to_string())
+    })
+}
+
+/// The clipboard text, or `None` if it holds something else or can't be
+/// read.
+pub fn text() -> Option<String> {
+    with(|clipboard| clipboard.get_text()).ok()
+}
+
+pub fn set_text(text: &str) -> Result<(), String> {
+    with(|clipboard| clipboard.set_text(text))
+}
@@ -0,0 +1,809 @@
+//! Settings from, in increasing priority: the JSON config file (`--config`
+//! or the per-user default), the profile selected by `--profile`,
+//! `MACRO_RECORDER_*` environment variables and command-line flags.
+//!
+//! Each layer replaces the settings it gives, except that device lists and
+//! remaps are combined (higher layers' remaps take priority) and skip
+//! switches can only be turned on. The file is watched while running so
+//! edits apply without a restart.
+
+use crate::demo;
+use crate::filter::{
+    ButtonTarget, DelayRule, DeviceFilter, PlaybackFilter, Randomize,
+    RecordFilter, Remap,
+};
+use crate::hotkeys::Hotkey;
+use crate::keynav::Control;
+use crate::player::{UserOverride, WhenBusy};
+use crate::snippets::Snippet;
+use crate::timing::Priority;
+use crate::triggers::{
+    Gesture, HotCorner, IdleTrigger, Sequence, TimeRestriction,
+    DEFAULT_DWELL_MS,
+};
+use rdev::{Button, Key};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Prefix of the environment variables overriding settings.
+pub const ENV_PREFIX: &str = "MACRO_RECORDER_";
+
+/// Command-line flags for settings that take a value.
+pub const VALUE_FLAGS: &[&str] = &[
+    "--record-filter",
+    "--device",
+    "--ignore-device",
+    "--library",
+    "--speed",
+    "--slot-keys",
+    "--hotkeys",
+    "--remap-key",
+    "--remap-button",
+    "--typing-wpm",
+    "--delay-before",
+    "--randomize-delays",
+    "--when-busy",
+    "--priority-slots",
+    "--hold-slots",
+    "--toggle-slots",
+    "--user-override",
+    "--sequence",
+    "--sequence-timeout-ms",
+    "--gesture",
+    "--hot-corner",
+    "--idle",
+    "--time-window",
+    "--snippet",
+    "--csv",
+    "--env-file",
+    "--loop-from",
+    "--layout",
+    "--click-delay-ms",
+    "--trigger-port",
+    "--thread-priority",
+    "--run-as",
+    "--inject-group",
+];
+
+/// Command-line flags for settings that are switched on by being given.
+pub const SWITCH_FLAGS: &[&str] = &[
+    "--skip-mouse-moves",
+    "--skip-scroll",
+    "--ease-scroll",
+    "--record-clipboard",
+    "--demo",
+    "--relative-moves",
+    "--record-hotkeys",
+    "--randomize",
+    "--drop-privileges",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub record_filter: Option<RecordFilter>,
+    pub devices: Option<DeviceFilter>,
+    pub playback: Option<PlaybackFilter>,
+    /// Hotkeys replacing the default F1 to F4, F9 to F11, Scroll Lock and
+    /// Pause.
+    pub hotkeys: Option<Vec<Hotkey>>,
+    /// Whether hotkey presses are recorded too.
+    pub record_hotkeys: Option<bool>,
+    /// Hotkeys for the recording slots, F5 to F8 by default.
+    pub slot_keys: Option<Vec<Key>>,
+    pub library: Option<PathBuf>,
+    /// Playback speed factor.
+    pub speed: Option<f64>,
+    /// Key sequences triggering actions.
+    pub sequences: Option<Vec<Sequence>>,
+    /// Longest gap between the keys of a sequence.
+    pub sequence_timeout_ms: Option<u64>,
+    /// Mouse gestures triggering actions.
+    pub gestures: Option<Vec<Gesture>>,
+    /// Screen corners triggering actions.
+    pub hot_corners: Option<Vec<HotCorner>>,
+    /// Macro played when there has been no input for a while.
+    pub idle: Option<IdleTrigger>,
+    /// Actions only performed at certain times.
+    pub time_windows: Option<Vec<TimeRestriction>>,
+    /// Abbreviations expanded as they are typed.
+    pub snippets: Option<Vec<Snippet>>,
+    /// Whether clipboard text is recorded and restored on playback.
+    pub record_clipboard: Option<bool>,
+    /// Whether playback shows its clicks and keys on screen.
+    pub demo: Option<bool>,
+    /// Whether the pointer is moved by relative motion, as games reading
+    /// raw input need, instead of placed.
+    pub relative_moves: Option<bool>,
+    /// What happens to playbacks requested while one is running.
+    pub when_busy: Option<WhenBusy>,
+    /// Slots, counted from 1, whose playback interrupts any other.
+    pub priority_slots: Option<Vec<usize>>,
+    /// Slots, counted from 1, played over and over while their key is held.
+    pub hold_slots: Option<Vec<usize>>,
+    /// Slots, counted from 1, whose key turns playing them over and over on
+    /// and off.
+    pub toggle_slots: Option<Vec<usize>>,
+    /// Pausing or stopping playback when the user takes over.
+    pub user_override: Option<UserOverride>,
+    /// Wait before clicks added by hotkey.
+    pub click_delay_ms: Option<u64>,
+    /// Port trigger messages are taken on, if any.
+    pub trigger_port: Option<u16>,
+    /// Priority of playback threads.
+    pub thread_priority: Option<Priority>,
+    /// Whether to stop running as root once the input devices are open.
+    pub drop_privileges: Option<bool>,
+    /// User to run as after dropping root, the one who ran `sudo` if not
+    /// set. Setting it drops root too.
+    pub run_as: Option<String>,
+    /// Group kept after dropping root, for access to `/dev/uinput`.
+    pub inject_group: Option<String>,
+    /// Window controls whose clicks `keyboard-nav` replaces with keys.
+    pub controls: Option<Vec<Control>>,
+    /// Named sets of settings layered over the ones above when selected.
+    pub profiles: HashMap<String, Config>,
+}
+
+impl Config {
+    /// Loads `path`, or the default config file if there is one, and
+    /// layers `profile` and the environment on top.
+    pub fn load(
+        path: Option<&Path>,
+        profile: Option<&str>,
+    ) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => default_path().filter(|p| p.exists()),
+        };
+        let mut config = match &path {
+            Some(path) => Self::read(path)?,
+            None => Self::default(),
+        };
+        if let Some(name) = profile {
+            let selected = config.profiles.remove(name).ok_or_else(|| {
+                let mut names: Vec<&String> = config.profiles.keys().collect();
+                names.sort();
+                format!("no profile '{}' in the config ({:?})", name, names)
+            })?;
+            config = config.overlay(selected);
+        }
+        if let Some(speed) = config.speed {
+            check_speed(speed)?;
+        }
+        if let Some(demo) = config.demo {
+            check_demo(demo)?;
+        }
+        for snippet in config.snippets.iter().flatten() {
+            snippet.validate()?;
+        }
+        let wpm = config.playback.as_ref().and_then(|p| p.typing_wpm);
+        if let Some(wpm) = wpm {
+            check_wpm(wpm)?;
+        }
+        for rule in config.playback.iter().flat_map(|p| &p.delays) {
+            check_scale(rule.scale)?;
+        }
+        let pct = config.playback.as_ref().and_then(|p| p.randomize_pct);
+        if let Some(pct) = pct {
+            check_pct(pct)?;
+        }
+        let playback = config.playback.as_ref();
+        if let Some(randomize) = playback.and_then(|p| p.randomize.as_ref()) {
+            randomize.validate()?;
+        }
+        Ok(config.overlay(Self::from_env()?))
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    /// Settings given as `MACRO_RECORDER_*` environment variables, named
+    /// after the matching command-line flags.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = Self::default();
+        for (name, value) in env::vars() {
+            let Some(setting) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let flag =
+                format!("--{}", setting.to_lowercase().replace('_', "-"));
+            let flag = flag.as_str();
+            let result = if VALUE_FLAGS.contains(&flag) {
+                config.set(flag, Some(&value))
+            } else if SWITCH_FLAGS.contains(&flag) {
+                match parse_switch(&value) {
+                    Ok(true) => config.set(flag, None),
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            } else {
+                // CONFIG and PROFILE are read by the caller to pick what
+                // to load.
+                if !matches!(flag, "--config" | "--profile") {
+                    println!("Ignoring unknown setting {}.", name);
+                }
+                Ok(())
+            };
+            result.map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Applies the command-line flag `flag`, with its `value` for
+    /// [`VALUE_FLAGS`]. List flags accept comma-separated values.
+    pub fn set(
+        &mut self,
+        flag: &str,
+        value: Option<&str>,
+    ) -> Result<(), String> {
+        let value = || value.ok_or_else(|| format!("{} needs a value", flag));
+        match flag {
+            "--record-filter" => self.record_filter = Some(value()?.parse()?),
+            "--device" => self.devices().include.extend(split(value()?)),
+            "--ignore-device" => {
+                self.devices().exclude.extend(split(value()?))
+            }
+            "--library" => self.library = Some(value()?.into()),
+            "--speed" => self.speed = Some(parse_speed(value()?)?),
+            "--slot-keys" => self.slot_keys = Some(parse_names(value()?)?),
+            "--hotkeys" => self.hotkeys = Some(parse_hotkeys(value()?)?),
+            "--record-hotkeys" => self.record_hotkeys = Some(true),
+            "--skip-mouse-moves" => self.playback().skip_mouse_moves = true,
+            "--skip-scroll" => self.playback().skip_scroll = true,
+            "--ease-scroll" => self.playback().ease_scroll = true,
+            "--record-clipboard" => self.record_clipboard = Some(true),
+            "--demo" => {
+                check_demo(true)?;
+                self.demo = Some(true)
+            }
+            "--relative-moves" => self.relative_moves = Some(true),
+            "--drop-privileges" => self.drop_privileges = Some(true),
+            "--run-as" => self.run_as = Some(value()?.to_string()),
+            "--inject-group" => {
+                self.inject_group = Some(value()?.to_string())
+            }
+            "--randomize" => {
+                self.playback().randomize = Some(Randomize::default())
+            }
+            "--when-busy" => self.when_busy = Some(value()?.parse()?),
+            "--user-override" => {
+                self.user_override = Some(value()?.parse()?)
+            }
+            "--priority-slots" => {
+                self.priority_slots = Some(parse_slots(value()?)?)
+            }
+            "--hold-slots" => self.hold_slots = Some(parse_slots(value()?)?),
+            "--toggle-slots" => {
+                self.toggle_slots = Some(parse_slots(value()?)?)
+            }
+            "--remap-key" => {
+                let remaps = parse_remaps::<Key>(value()?)?;
+                self.playback().remap_keys.extend(remaps);
+            }
+            "--sequence" => {
+                let sequences = parse_sequences(value()?)?;
+                self.sequences.get_or_insert_with(Vec::new).extend(sequences);
+            }
+            "--sequence-timeout-ms" => {
+                let ms = value()?;
+                let ms = ms
+                    .parse()
+                    .map_err(|_| format!("invalid timeout '{}'", ms))?;
+                self.sequence_timeout_ms = Some(ms);
+            }
+            "--click-delay-ms" => {
+                let ms = value()?;
+                let ms = ms
+                    .parse()
+                    .map_err(|_| format!("invalid delay '{}'", ms))?;
+                self.click_delay_ms = Some(ms);
+            }
+            "--trigger-port" => {
+                let port = value()?;
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port '{}'", port))?;
+                self.trigger_port = Some(port);
+            }
+            "--thread-priority" => {
+                self.thread_priority = Some(value()?.parse()?)
+            }
+            "--gesture" => {
+                let gestures = parse_gestures(value()?)?;
+                self.gestures.get_or_insert_with(Vec::new).extend(gestures);
+            }
+            "--hot-corner" => {
+                let corners = parse_hot_corners(value()?)?;
+                self.hot_corners.get_or_insert_with(Vec::new).extend(corners);
+            }
+            "--idle" => self.idle = Some(parse_idle(value()?)?),
+            "--time-window" => {
+                let windows = parse_time_windows(value()?)?;
+                self.time_windows.get_or_insert_with(Vec::new).extend(windows);
+            }
+            "--snippet" => {
+                let snippets = parse_snippets(value()?)?;
+                self.snippets.get_or_insert_with(Vec::new).extend(snippets);
+            }
+            "--typing-wpm" => {
+                self.playback().typing_wpm = Some(parse_wpm(value()?)?)
+            }
+            "--remap-button" => {
+                let remaps = parse_button_remaps(value()?)?;
+                self.playback().remap_buttons.extend(remaps);
+            }
+            "--randomize-delays" => {
+                let pct = parse_pct(value()?)?;
+                self.playback().randomize_pct = Some(pct);
+            }
+            "--csv" => self.playback().csv = Some(value()?.into()),
+            "--env-file" => {
+                self.playback().env_file = Some(value()?.into())
+            }
+            "--loop-from" => {
+                self.playback().loop_from = Some(value()?.to_string())
+            }
+            "--layout" => self.playback().layout = Some(value()?.parse()?),
+            "--delay-before" => {
+                let rules = parse_delays(value()?)?;
+                self.playback().delays.extend(rules);
+            }
+            _ => return Err(format!("unknown setting '{}'", flag)),
+        }
+        Ok(())
+    }
+
+    fn devices(&mut self) -> &mut DeviceFilter {
+        self.devices.get_or_insert_with(Default::default)
+    }
+
+    fn playback(&mut self) -> &mut PlaybackFilter {
+        self.playback.get_or_insert_with(Default::default)
+    }
+
+    /// Layers `top` over these settings.
+    pub fn overlay(self, top: Config) -> Self {
+        Self {
+            record_filter: top.record_filter.or(self.record_filter),
+            devices: merge(self.devices, top.devices, DeviceFilter::overlay),
+            playback: merge(
+                self.playback,
+                top.playback,
+                PlaybackFilter::overlay,
+            ),
+            hotkeys: top.hotkeys.or(self.hotkeys),
+            record_hotkeys: top.record_hotkeys.or(self.record_hotkeys),
+            slot_keys: top.slot_keys.or(self.slot_keys),
+            library: top.library.or(self.library),
+            speed: top.speed.or(self.speed),
+            sequences: top.sequences.or(self.sequences),
+            sequence_timeout_ms: top
+                .sequence_timeout_ms
+                .or(self.sequence_timeout_ms),
+            gestures: top.gestures.or(self.gestures),
+            hot_corners: top.hot_corners.or(self.hot_corners),
+            idle: top.idle.or(self.idle),
+            time_windows: top.time_windows.or(self.time_windows),
+            snippets: top.snippets.or(self.snippets),
+            record_clipboard: top.record_clipboard.or(self.record_clipboard),
+            demo: top.demo.or(self.demo),
+            relative_moves: top.relative_moves.or(self.relative_moves),
+            when_busy: top.when_busy.or(self.when_busy),
+            priority_slots: top.priority_slots.or(self.priority_slots),
+            hold_slots: top.hold_slots.or(self.hold_slots),
+            toggle_slots: top.toggle_slots.or(self.toggle_slots),
+            user_override: top.user_override.or(self.user_override),
+            click_delay_ms: top.click_delay_ms.or(self.click_delay_ms),
+            trigger_port: top.trigger_port.or(self.trigger_port),
+            thread_priority: top.thread_priority.or(self.thread_priority),
+            drop_privileges: top.drop_privileges.or(self.drop_privileges),
+            run_as: top.run_as.or(self.run_as),
+            inject_group: top.inject_group.or(self.inject_group),
+            controls: top.controls.or(self.controls),
+            profiles: self.profiles,
+        }
+    }
+}
+
+fn merge<T>(base: Option<T>, top: Option<T>, f: fn(T, T) -> T) -> Option<T> {
+    match (base, top) {
+        (Some(base), Some(top)) => Some(f(base, top)),
+        (base, top) => top.or(base),
+    }
+}
+
+fn check_speed(speed: f64) -> Result<(), String> {
+    if speed.is_finite() && speed > 0.0 {
+        Ok(())
+    } else {
+        Err("invalid speed, expected a factor like 1.5".into())
+    }
+}
+
+/// Refuses demo mode where the overlay can't be drawn, rather than
+/// silently playing without it.
+fn check_demo(demo: bool) -> Result<(), String> {
+    if demo && !demo::SUPPORTED {
+        Err("demo mode is only available on Windows".into())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed = s.parse().unwrap_or(f64::NAN);
+    check_speed(speed)?;
+    Ok(speed)
+}
+
+fn check_wpm(wpm: f64) -> Result<(), String> {
+    if wpm.is_finite() && wpm > 0.0 {
+        Ok(())
+    } else {
+        Err("invalid typing speed, expected words per minute".into())
+    }
+}
+
+fn parse_wpm(s: &str) -> Result<f64, String> {
+    let wpm = s.parse().unwrap_or(f64::NAN);
+    check_wpm(wpm)?;
+    Ok(wpm)
+}
+
+fn check_pct(pct: f64) -> Result<(), String> {
+    if (0.0..=100.0).contains(&pct) {
+        Ok(())
+    } else {
+        Err("invalid randomization, expected a percentage up to 100".into())
+    }
+}
+
+fn parse_pct(s: &str) -> Result<f64, String> {
+    let pct = s.trim_end_matches('%').parse().unwrap_or(f64::NAN);
+    check_pct(pct)?;
+    Ok(pct)
+}
+
+fn parse_switch(s: &str) -> Result<bool, String> {
+    match s {
+        "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" | "" => Ok(false),
+        _ => Err(format!("invalid switch '{}', expected true or false", s)),
+    }
+}
+
+fn split(s: &str) -> impl Iterator<Item = String> + '_ {
+    s.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from)
+}
+
+/// Parses a key or button by the name used in config files, e.g. `KeyA`,
+/// `F5` or `Left`.
+pub fn parse_name<T: DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("unknown key or button '{}'", s))
+}
+
+fn parse_names<T: DeserializeOwned>(s: &str) -> Result<Vec<T>, String> {
+    split(s).map(|name| parse_name(&name)).collect()
+}
+
+/// Parses slot numbers counted from 1, e.g. `1,3`.
+fn parse_slots(s: &str) -> Result<Vec<usize>, String> {
+    split(s)
+        .map(|n| match n.parse() {
+            Ok(slot) if slot > 0 => Ok(slot),
+            _ => Err(format!("invalid slot '{}'", n)),
+        })
+        .collect()
+}
+
+/// Parses `FROM=TO` pairs, e.g. `KeyA=KeyB,Left=Right`.
+fn parse_remaps<T: DeserializeOwned>(s: &str) -> Result<Vec<Remap<T>>, String> {
+    split(s)
+        .map(|pair| {
+            let (from, to) = pair.split_once('=').ok_or_else(|| {
+                format!("invalid remap '{}', expected FROM=TO", pair)
+            })?;
+            Ok(Remap {
+                from: parse_name(from.trim())?,
+                to: parse_name(to.trim())?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `FROM=TO` button remaps, where TO is a button or keys joined by
+/// `+`, e.g. `Left=Right,Right=Left,Middle=ControlLeft+KeyW`.
+pub fn parse_button_remaps(
+    s: &str,
+) -> Result<Vec<Remap<Button, ButtonTarget>>, String> {
+    split(s)
+        .map(|pair| {
+            let (from, to) = pair.split_once('=').ok_or_else(|| {
+                format!("invalid remap '{}', expected FROM=TO", pair)
+            })?;
+            let to = match parse_name::<Button>(to.trim()) {
+                Ok(button) => ButtonTarget::Button(button),
+                Err(_) => ButtonTarget::Keys(
+                    to.split('+')
+                        .map(|key| parse_name(key.trim()))
+                        .collect::<Result<_, _>>()?,
+                ),
+            };
+            Ok(Remap {
+                from: parse_name(from.trim())?,
+                to,
+            })
+        })
+        .collect()
+}
+
+/// Parses `KEY=ACTION` hotkeys, e.g. `F1=play-pause`. An empty list leaves
+/// only the slot hotkeys.
+fn parse_hotkeys(s: &str) -> Result<Vec<Hotkey>, String> {
+    split(s)
+        .map(|hotkey| {
+            let (key, action) = hotkey.split_once('=').ok_or_else(|| {
+                format!("invalid hotkey '{}', expected KEY=ACTION", hotkey)
+            })?;
+            Ok(Hotkey {
+                key: parse_name(key.trim())?,
+                action: action.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `KEY+KEY=ACTION` sequences, e.g.
+/// `ControlRight+ControlRight=play-pause,F9+KeyP=record`.
+fn parse_sequences(s: &str) -> Result<Vec<Sequence>, String> {
+    split(s)
+        .map(|sequence| {
+            let (keys, action) = sequence.split_once('=').ok_or_else(|| {
+                format!(
+                    "invalid sequence '{}', expected KEY+KEY=ACTION",
+                    sequence
+                )
+            })?;
+            Ok(Sequence {
+                keys: keys
+                    .split('+')
+                    .map(|key| parse_name(key.trim()))
+                    .collect::<Result<_, _>>()?,
+                action: action.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `BUTTON:STROKE-STROKE=ACTION` gestures, e.g.
+/// `8:down-right=record`. Buttons other than Left, Right and Middle are
+/// given by number.
+fn parse_gestures(s: &str) -> Result<Vec<Gesture>, String> {
+    split(s)
+        .map(|gesture| {
+            let invalid = || {
+                format!(
+                    "invalid gesture '{}', expected BUTTON:STROKES=ACTION",
+                    gesture
+                )
+            };
+            let (button, rest) = gesture.split_once(':').ok_or_else(invalid)?;
+            let (strokes, action) = rest.split_once('=').ok_or_else(invalid)?;
+            let button = match button.trim().parse() {
+                Ok(n) => Button::Unknown(n),
+                Err(_) => parse_name(button.trim())?,
+            };
+            Ok(Gesture {
+                button,
+                strokes: strokes
+                    .split('-')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty() && *s != "click")
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+                action: action.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `MINUTES=NAME[@WHEN]`, e.g. `10=keep-alive@mon-fri 9:00-17:00`.
+fn parse_idle(s: &str) -> Result<IdleTrigger, String> {
+    let (minutes, name) = s.split_once('=').ok_or_else(|| {
+        format!("invalid idle trigger '{}', expected MINUTES=NAME[@WHEN]", s)
+    })?;
+    let (name, when) = match name.split_once('@') {
+        Some((name, when)) => (name, Some(when.parse()?)),
+        None => (name, None),
+    };
+    let minutes = minutes
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|m| *m > 0.0)
+        .ok_or_else(|| format!("invalid idle time '{}'", minutes))?;
+    Ok(IdleTrigger {
+        minutes,
+        macro_name: name.trim().to_string(),
+        when,
+    })
+}
+
+/// Parses `ACTION=WHEN` time windows, e.g. `play-slot-1=mon-fri 9:00-17:00`.
+fn parse_time_windows(s: &str) -> Result<Vec<TimeRestriction>, String> {
+    split(s)
+        .map(|restriction| {
+            let (action, when) =
+                restriction.split_once('=').ok_or_else(|| {
+                    format!(
+                        "invalid time window '{}', expected ACTION=WHEN",
+                        restriction
+                    )
+                })?;
+            Ok(TimeRestriction {
+                action: action.trim().parse()?,
+                when: when.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `CORNER[:MS]=ACTION` hot corners, e.g. `top-left:300=play-pause`.
+fn parse_hot_corners(s: &str) -> Result<Vec<HotCorner>, String> {
+    split(s)
+        .map(|hot| {
+            let (corner, action) = hot.split_once('=').ok_or_else(|| {
+                format!(
+                    "invalid hot corner '{}', expected CORNER[:MS]=ACTION",
+                    hot
+                )
+            })?;
+            let (corner, dwell_ms) = match corner.split_once(':') {
+                Some((corner, ms)) => (
+                    corner,
+                    ms.trim()
+                        .parse()
+                        .map_err(|_| format!("invalid dwell time '{}'", ms))?,
+                ),
+                None => (corner, DEFAULT_DWELL_MS),
+            };
+            Ok(HotCorner {
+                corner: corner.trim().parse()?,
+                dwell_ms,
+                action: action.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `KIND=[xFACTOR][+MS]` delay rules, e.g. `click=+200` to wait
+/// 200 ms longer before clicks or `key=x0.5` to halve the wait before keys.
+fn parse_delays(s: &str) -> Result<Vec<DelayRule>, String> {
+    split(s)
+        .map(|rule| {
+            let invalid = || {
+                format!(
+                    "invalid delay '{}', expected KIND=[xFACTOR][+MS]",
+                    rule
+                )
+            };
+            let (kind, change) = rule.split_once('=').ok_or_else(invalid)?;
+            let (scale, add) = match change.trim().split_once('+') {
+                Some((scale, add)) => (scale, Some(add)),
+                None => (change.trim(), None),
+            };
+            let scale = match scale.strip_prefix('x') {
+                Some(factor) => factor.parse().map_err(|_| invalid())?,
+                None if scale.is_empty() => 1.0,
+                None => return Err(invalid()),
+            };
+            check_scale(scale)?;
+            let add_ms = match add {
+                Some(ms) => ms
+                    .trim_end_matches("ms")
+                    .parse()
+                    .map_err(|_| invalid())?,
+                None => 0,
+            };
+            Ok(DelayRule {
+                before: kind.trim().parse()?,
+                scale,
+                add_ms,
+            })
+        })
+        .collect()
+}
+
+fn check_scale(scale: f64) -> Result<(), String> {
+    if scale.is_finite() && scale >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("invalid delay factor {}", scale))
+    }
+}
+
+/// Parses `ABBR=TEXT` snippets, or `ABBR=@NAME` to play the library macro
+/// NAME, e.g. `addr=221B Baker Street`.
+fn parse_snippets(s: &str) -> Result<Vec<Snippet>, String> {
+    split(s)
+        .map(|snippet| {
+            let (abbreviation, expansion) =
+                snippet.split_once('=').ok_or_else(|| {
+                    format!("invalid snippet '{}', expected ABBR=TEXT", snippet)
+                })?;
+            let (text, macro_name) = match expansion.strip_prefix('@') {
+                Some(name) => (None, Some(name.to_string())),
+                None => (Some(expansion.to_string()), None),
+            };
+            let snippet = Snippet {
+                abbreviation: abbreviation.trim().to_string(),
+                text,
+                macro_name,
+            };
+            snippet.validate()?;
+            Ok(snippet)
+        })
+        .collect()
+}
+
+/// `realtime-macro/config.json` in the per-user config directory.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME")
+            .map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+            })
+    };
+    dir.map(|d| d.join("realtime-macro").join("config.json"))
+}
+
+/// Reloads the config at `path` whenever it is modified and passes it to
+/// `on_change`. A config that fails to load is reported and skipped, so the
+/// previous settings stay in effect.
+pub fn watch<F>(path: PathBuf, profile: Option<String>, mut on_change: F)
+where
+    F: FnMut(Config) + Send + 'static,
+{
+    thread::spawn(move || {
+        let modified = |path: &Path| -> Option<SystemTime> {
+            fs::metadata(path).and_then(|m| m.modified()).ok()
+        };
+        let mut last = modified(&path);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let current = modified(&path);
+            if current.is_none() || current == last {
+                continue;
+            }
+            last = current;
+            match Config::load(Some(&path), profile.as_deref()) {
+                Ok(config) => {
+                    on_change(config);
+                    println!("Reloaded {}.", path.display());
+                }
+                Err(e) => println!("Keeping the previous settings: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,330 @@
+//! Demo mode: playback shows a ripple where it clicks and the keys it
+//! presses, so a recording can double as a product demo.
+
+use crate::backend::InputBackend;
+use crate::preview::Modifiers;
+use rdev::EventType;
+use std::time::Instant;
+
+/// Whether this platform can draw the overlay.
+pub const SUPPORTED: bool = cfg!(target_os = "windows");
+
+/// Wraps a backend to show what it injects on screen.
+pub struct Demo<B> {
+    backend: B,
+    overlay: Overlay,
+    modifiers: Modifiers,
+    cursor: (f64, f64),
+}
+
+impl<B: InputBackend> Demo<B> {
+    pub fn new(backend: B, overlay: Overlay) -> Self {
+        Self {
+            backend,
+            overlay,
+            modifiers: Modifiers::default(),
+            cursor: (0.0, 0.0),
+        }
+    }
+
+    fn show(&mut self, evt: &EventType) {
+        match *evt {
+            EventType::MouseMove { x, y } => self.cursor = (x, y),
+            EventType::ButtonPress(_) => {
+                self.overlay.click(self.cursor.0 as i32, self.cursor.1 as i32)
+            }
+            // Modifiers are only shown along with the key they modify.
+            EventType::KeyPress(key) if !self.modifiers.update(key, true) => {
+                self.overlay.key(self.modifiers.combo(key));
+            }
+            EventType::KeyRelease(key) => {
+                self.modifiers.update(key, false);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<B: InputBackend> InputBackend for Demo<B> {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        let result = self.backend.send(evt);
+        self.show(evt);
+        result
+    }
+
+    fn send_due(
+        &mut self,
+        evt: &EventType,
+        due: Instant,
+    ) -> Result<(), String> {
+        let result = self.backend.send_due(evt, due);
+        self.show(evt);
+        result
+    }
+
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        due: &[Instant],
+    ) -> Result<(), String> {
+        let result = self.backend.send_batch(evts, due);
+        evts.iter().for_each(|evt| self.show(evt));
+        result
+    }
+
+    fn is_available(&self) -> bool {
+        self.backend.is_available()
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::sync::mpsc;
+    use std::sync::{Mutex, OnceLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use windows::core::w;
+    use windows::Win32::Foundation::{
+        COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM,
+    };
+    use windows::Win32::Graphics::Gdi::*;
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::UI::WindowsAndMessaging::*;
+
+    /// How long a click ripple stays on screen.
+    const RIPPLE_TIME: Duration = Duration::from_millis(400);
+    /// How long a pressed key stays on screen.
+    const KEY_TIME: Duration = Duration::from_millis(1500);
+    /// Most keys shown at once; older ones scroll off.
+    const MAX_KEYS: usize = 12;
+    /// Painted as fully transparent.
+    const CLEAR: COLORREF = COLORREF(0x00FF00FF);
+    const RIPPLE: COLORREF = COLORREF(0x000080FF);
+    const KEY_TEXT: COLORREF = COLORREF(0x00FFFFFF);
+    const KEY_BACKGROUND: COLORREF = COLORREF(0x00303030);
+    const FRAME_MS: u32 = 16;
+
+    #[derive(Default)]
+    struct Marks {
+        ripples: Vec<(i32, i32, Instant)>,
+        keys: Vec<(String, Instant)>,
+    }
+
+    impl Marks {
+        fn is_empty(&self) -> bool {
+            self.ripples.is_empty() && self.keys.is_empty()
+        }
+    }
+
+    // The window procedure has no way to reach a context of ours, and
+    // there is only ever one overlay.
+    static MARKS: Mutex<Marks> = Mutex::new(Marks {
+        ripples: Vec::new(),
+        keys: Vec::new(),
+    });
+    static STARTED: OnceLock<Result<(), String>> = OnceLock::new();
+
+    /// A transparent, click-through window over the whole desktop. It is
+    /// created on first use and lives as long as the process.
+    pub struct Overlay;
+
+    impl Overlay {
+        pub fn start() -> Result<Self, String> {
+            STARTED
+                .get_or_init(|| {
+                    let (tx, rx) = mpsc::channel();
+                    thread::spawn(move || unsafe { run(tx) });
+                    rx.recv()
+                        .unwrap_or_else(|_| Err("overlay thread died".into()))
+                })
+                .clone()
+                .map(|_| Overlay)
+        }
+
+        pub fn click(&self, x: i32, y: i32) {
+            MARKS.lock().unwrap().ripples.push((x, y, Instant::now()));
+        }
+
+        pub fn key(&self, name: String) {
+            let mut marks = MARKS.lock().unwrap();
+            marks.keys.push((name, Instant::now()));
+            if marks.keys.len() > MAX_KEYS {
+                marks.keys.remove(0);
+            }
+        }
+    }
+
+    unsafe fn run(started: mpsc::Sender<Result<(), String>>) {
+        let hwnd = match create() {
+            Ok(hwnd) => hwnd,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return;
+            }
+        };
+        let _ = ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+        SetTimer(hwnd, 1, FRAME_MS, None);
+        let _ = started.send(Ok(()));
+
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    unsafe fn create() -> Result<HWND, String> {
+        let instance = GetModuleHandleW(None).map_err(|e| e.to_string())?;
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance.into(),
+            lpszClassName: w!("RealtimeMacroDemo"),
+            hbrBackground: CreateSolidBrush(CLEAR),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err("cannot register the overlay window class".into());
+        }
+        let hwnd = CreateWindowExW(
+            WS_EX_LAYERED
+                | WS_EX_TRANSPARENT
+                | WS_EX_TOPMOST
+                | WS_EX_TOOLWINDOW
+                | WS_EX_NOACTIVATE,
+            w!("RealtimeMacroDemo"),
+            w!(""),
+            WS_POPUP,
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            None,
+            None,
+            instance,
+            None,
+        )
+        .map_err(|e| format!("cannot create the overlay window: {}", e))?;
+        SetLayeredWindowAttributes(hwnd, CLEAR, 0, LWA_COLORKEY)
+            .map_err(|e| e.to_string())?;
+        Ok(hwnd)
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_TIMER => {
+                let mut marks = MARKS.lock().unwrap();
+                let was_empty = marks.is_empty();
+                marks.ripples.retain(|(_, _, at)| at.elapsed() < RIPPLE_TIME);
+                marks.keys.retain(|(_, at)| at.elapsed() < KEY_TIME);
+                // Repaint once more after the last mark expires.
+                if !was_empty {
+                    let _ = InvalidateRect(hwnd, None, true);
+                }
+                LRESULT(0)
+            }
+            WM_PAINT => {
+                let mut ps = PAINTSTRUCT::default();
+                let hdc = BeginPaint(hwnd, &mut ps);
+                paint(hdc, &MARKS.lock().unwrap());
+                let _ = EndPaint(hwnd, &ps);
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+    }
+
+    unsafe fn paint(hdc: HDC, marks: &Marks) {
+        let origin = (
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+        );
+
+        let pen = CreatePen(PS_SOLID, 4, RIPPLE);
+        let old_pen = SelectObject(hdc, pen);
+        let old_brush = SelectObject(hdc, GetStockObject(NULL_BRUSH));
+        for (x, y, at) in &marks.ripples {
+            let progress =
+                at.elapsed().as_secs_f64() / RIPPLE_TIME.as_secs_f64();
+            let r = 8 + (progress.min(1.0) * 32.0) as i32;
+            let (x, y) = (x - origin.0, y - origin.1);
+            let _ = Ellipse(hdc, x - r, y - r, x + r, y + r);
+        }
+        SelectObject(hdc, old_brush);
+        SelectObject(hdc, old_pen);
+        let _ = DeleteObject(pen);
+
+        if marks.keys.is_empty() {
+            return;
+        }
+        let keys: Vec<&str> =
+            marks.keys.iter().map(|(key, _)| key.as_str()).collect();
+        let mut text: Vec<u16> = keys.join("  ").encode_utf16().collect();
+        // Color keying leaves fringes around antialiased text.
+        let font = CreateFontW(
+            36,
+            0,
+            0,
+            0,
+            FW_BOLD.0 as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET,
+            OUT_DEFAULT_PRECIS,
+            CLIP_DEFAULT_PRECIS,
+            NONANTIALIASED_QUALITY,
+            0,
+            w!("Segoe UI"),
+        );
+        let old_font = SelectObject(hdc, font);
+        let mut bounds = RECT::default();
+        DrawTextW(hdc, &mut text, &mut bounds, DT_CALCRECT | DT_SINGLELINE);
+        let (width, height) = (
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        );
+        let (w, h) = (bounds.right + 32, bounds.bottom + 16);
+        let mut rect = RECT {
+            left: (width - w) / 2,
+            top: height - h - 64,
+            right: (width + w) / 2,
+            bottom: height - 64,
+        };
+        let background = CreateSolidBrush(KEY_BACKGROUND);
+        FillRect(hdc, &rect, background);
+        let _ = DeleteObject(background);
+        SetBkMode(hdc, TRANSPARENT);
+        SetTextColor(hdc, KEY_TEXT);
+        DrawTextW(
+            hdc,
+            &mut text,
+            &mut rect,
+            DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+        );
+        SelectObject(hdc, old_font);
+        let _ = DeleteObject(font);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    /// Drawing over other windows is only implemented for Windows.
+    pub struct Overlay;
+
+    impl Overlay {
+        pub fn start() -> Result<Self, String> {
+            Err("demo mode is only available on Windows".into())
+        }
+
+        pub fn click(&self, _x: i32, _y: i32) {}
+
+        pub fn key(&self, _name: String) {}
+    }
+}
+
+pub use platform::Overlay;
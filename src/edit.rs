@@ -0,0 +1,430 @@
+//! Changes to a recording that keep the timing around them intact.
+
+use crate::config::parse_name;
+use crate::event::{MacroAction, RecordedEvent, WaitRange};
+use crate::snippets;
+use crate::vars;
+use crate::wait::Condition;
+use rdev::{Button, EventType, Key};
+use std::time::Duration;
+
+/// Parses a time into a recording, e.g. `12.5s`, `800ms` or `12.5`
+/// (seconds).
+pub fn parse_time(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid time '{}', expected e.g. 12.5s", s);
+    let (number, unit) = match s.strip_suffix("ms") {
+        Some(ms) => (ms, 0.001),
+        None => (s.strip_suffix('s').unwrap_or(s), 1.0),
+    };
+    let value: f64 = number.trim().parse().map_err(|_| invalid())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(value * unit))
+}
+
+/// How long a wait step added by `edit insert` waits unless told.
+pub const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Parses a `MIN-MAX` wait range, e.g. `200ms-1.5s`.
+pub fn parse_range(s: &str) -> Result<WaitRange, String> {
+    let (min, max) = s
+        .split_once('-')
+        .ok_or_else(|| format!("invalid range '{}', expected MIN-MAX", s))?;
+    let (min, max) = (parse_time(min)?, parse_time(max)?);
+    if max < min {
+        return Err(format!("invalid range '{}', MAX is below MIN", s));
+    }
+    Ok(WaitRange { min, max })
+}
+
+/// Parses a `DX,DY` offset, e.g. `0,24`.
+pub fn parse_offset(s: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid offset '{}', expected DX,DY", s);
+    let (dx, dy) = s.split_once(',').ok_or_else(invalid)?;
+    let dx = dx.trim().parse().map_err(|_| invalid())?;
+    let dy = dy.trim().parse().map_err(|_| invalid())?;
+    Ok((dx, dy))
+}
+
+/// Replaces the events from `from` up to `to` with `segment`, a recording
+/// of its own starting at zero. Events after `to` move by however much
+/// longer or shorter the segment is than what it replaces.
+pub fn splice(
+    mut events: Vec<RecordedEvent>,
+    from: Duration,
+    to: Duration,
+    segment: Vec<RecordedEvent>,
+) -> Result<Vec<RecordedEvent>, String> {
+    if to < from {
+        return Err("the end of the segment is before its start".into());
+    }
+    let length = segment.last().map_or(Duration::ZERO, |e| e.timestamp);
+    let end = from + length;
+    let after = events.split_off(events.partition_point(|e| e.timestamp < to));
+    events.truncate(events.partition_point(|e| e.timestamp < from));
+    events.extend(segment.into_iter().map(|mut e| {
+        e.timestamp += from;
+        e
+    }));
+    events.extend(after.into_iter().map(|mut e| {
+        e.timestamp = e.timestamp - to + end;
+        e
+    }));
+    Ok(events)
+}
+
+/// A change made by the `edit` command.
+pub enum Edit {
+    /// Adds `events`, a recording of their own starting at zero, at `at`.
+    /// Later events move back by the length of what is added.
+    Insert {
+        at: Duration,
+        events: Vec<RecordedEvent>,
+    },
+    /// Removes the events from `at` to `to`, or the first one from `at` on.
+    /// Other events keep their times.
+    Delete { at: Duration, to: Option<Duration> },
+    /// Moves the first event from `at` on to `to`.
+    Move { at: Duration, to: Duration },
+    /// Makes the wait before the first event from `at` on random within
+    /// `range`, or as recorded again without one.
+    Wait {
+        at: Duration,
+        range: Option<WaitRange>,
+    },
+    /// Makes the events from `at` up to `to` play only when `key` is held
+    /// as playback starts, and those from `to` up to `otherwise`, if given,
+    /// only when it isn't.
+    Branch {
+        at: Duration,
+        to: Duration,
+        otherwise: Option<Duration>,
+        key: Key,
+    },
+    /// Makes the events from `at` on the cleanup section, replacing any
+    /// earlier one.
+    Cleanup { at: Duration },
+    /// Moves the pointer moves from `at` to `to`, or the first one from
+    /// `at` on, by `by` more on each loop pass.
+    Offset {
+        at: Duration,
+        to: Option<Duration>,
+        by: (f64, f64),
+    },
+}
+
+impl Edit {
+    /// Applies the change, returning how many events it affected.
+    pub fn apply(
+        &self,
+        events: &mut Vec<RecordedEvent>,
+    ) -> Result<usize, String> {
+        let first_from = |events: &[RecordedEvent], at: Duration| {
+            let i = events.partition_point(|e| e.timestamp < at);
+            if i < events.len() {
+                Ok(i)
+            } else {
+                Err(format!("no event at or after {}", secs(at)))
+            }
+        };
+        match self {
+            Edit::Insert { at, events: added } => {
+                let taken = std::mem::take(events);
+                *events = splice(taken, *at, *at, added.clone())?;
+                Ok(added.len())
+            }
+            Edit::Delete { at, to: None } => {
+                events.remove(first_from(events, *at)?);
+                Ok(1)
+            }
+            Edit::Delete { at, to: Some(to) } => {
+                let before = events.len();
+                events.retain(|e| e.timestamp < *at || e.timestamp > *to);
+                Ok(before - events.len())
+            }
+            Edit::Move { at, to } => {
+                let mut moved = events.remove(first_from(events, *at)?);
+                moved.timestamp = *to;
+                // After any events already at `to`.
+                let i = events.partition_point(|e| e.timestamp <= *to);
+                events.insert(i, moved);
+                Ok(1)
+            }
+            Edit::Wait { at, range } => {
+                let i = first_from(events, *at)?;
+                events[i].wait = *range;
+                Ok(1)
+            }
+            Edit::Branch {
+                at,
+                to,
+                otherwise,
+                key,
+            } => {
+                let end = otherwise.unwrap_or(*to);
+                if to < at || end < *to {
+                    return Err("the branch ends before it starts".into());
+                }
+                let mut after = events.split_off(
+                    events.partition_point(|e| e.timestamp < end),
+                );
+                let mut otherwise = events.split_off(
+                    events.partition_point(|e| e.timestamp < *to),
+                );
+                let mut then = events.split_off(
+                    events.partition_point(|e| e.timestamp < *at),
+                );
+                let count = then.len() + otherwise.len();
+                rebase(&mut then, *at);
+                rebase(&mut otherwise, *to);
+                events.push(RecordedEvent {
+                    action: MacroAction::IfKeyHeld {
+                        key: *key,
+                        then,
+                        otherwise,
+                    },
+                    timestamp: *at,
+                    device: None,
+                    wait: None,
+                    per_pass: None,
+                    text: None,
+                });
+                // Playback adds the length of the variant taken.
+                rebase(&mut after, end - *at);
+                events.append(&mut after);
+                Ok(count)
+            }
+            Edit::Cleanup { at } => {
+                events.retain(|e| !matches!(e.action, MacroAction::Cleanup));
+                let i = first_from(events, *at)?;
+                events.insert(
+                    i,
+                    RecordedEvent {
+                        action: MacroAction::Cleanup,
+                        timestamp: *at,
+                        device: None,
+                        wait: None,
+                        per_pass: None,
+                        text: None,
+                    },
+                );
+                Ok(events.len() - i - 1)
+            }
+            Edit::Offset { at, to, by } => {
+                let is_move = |e: &RecordedEvent| {
+                    matches!(
+                        e.action,
+                        MacroAction::Input(EventType::MouseMove { .. })
+                    )
+                };
+                let from = events.partition_point(|e| e.timestamp < *at);
+                let end = match to {
+                    Some(to) => events.partition_point(|e| e.timestamp <= *to),
+                    None => events.len(),
+                };
+                let mut count = 0;
+                for evt in events[from..end.max(from)].iter_mut() {
+                    if is_move(evt) {
+                        evt.per_pass = Some(*by);
+                        count += 1;
+                        if to.is_none() {
+                            break;
+                        }
+                    }
+                }
+                if count == 0 {
+                    return Err(format!("no pointer move at {}", secs(*at)));
+                }
+                Ok(count)
+            }
+        }
+    }
+}
+
+/// Moves `events` earlier by `by`.
+fn rebase(events: &mut [RecordedEvent], by: Duration) {
+    for e in events {
+        e.timestamp -= by;
+    }
+}
+
+fn secs(t: Duration) -> String {
+    format!("{:.3} s", t.as_secs_f64())
+}
+
+/// Parses the events given to `edit insert --event`:
+///
+/// - `click BUTTON [X Y]`, moving to X, Y first if given
+/// - `press NAME` and `release NAME` for a key or button
+/// - `key NAME`, pressing and releasing a key
+/// - `move X Y`
+/// - `scroll DX DY`
+/// - `type TEXT`, where placeholders like `{i}` are filled in as it is
+///   played
+/// - `wait [TIMEOUT] window PATTERN` and `wait [TIMEOUT] focus PATTERN`,
+///   waiting for a window whose title matches to appear or get focus
+/// - `wait [TIMEOUT] file PATH`, waiting for the file to exist
+/// - `wait [TIMEOUT] start NAME` and `wait [TIMEOUT] exit NAME`, waiting
+///   for a process to start or exit
+/// - `screenshot X,Y,WxH PATH`, saving that part of the screen, where the
+///   path, inside the screenshots folder, can have placeholders and
+///   `{date}` and `{time}`
+/// - `checkpoint NAME`, where `--loop-from NAME` starts later loop passes
+pub fn parse_events(spec: &str) -> Result<Vec<RecordedEvent>, String> {
+    let invalid = || format!("invalid event '{}'", spec);
+    let spec = spec.trim();
+    let (verb, rest) = spec.split_once(' ').unwrap_or((spec, ""));
+    if verb == "wait" {
+        return Ok(vec![parse_wait(rest).map_err(|e| {
+            format!("invalid event '{}': {}", spec, e)
+        })?]);
+    }
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    let number = |s: &str| s.parse::<f64>().map_err(|_| invalid());
+    let delta = |s: &str| s.parse::<i64>().map_err(|_| invalid());
+    let key = |name: &str| parse_name::<Key>(name);
+    let button = |name: &str| {
+        // Buttons are written in lower case in event specs.
+        let mut chars = name.chars();
+        let capitalized: String = chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+        parse_name::<Button>(&capitalized)
+    };
+    let types = match (verb, &args[..]) {
+        ("click", [b]) => {
+            let b = button(b)?;
+            vec![EventType::ButtonPress(b), EventType::ButtonRelease(b)]
+        }
+        ("click", [b, x, y]) => {
+            let b = button(b)?;
+            vec![
+                EventType::MouseMove {
+                    x: number(x)?,
+                    y: number(y)?,
+                },
+                EventType::ButtonPress(b),
+                EventType::ButtonRelease(b),
+            ]
+        }
+        ("press", [name]) => match button(name) {
+            Ok(b) => vec![EventType::ButtonPress(b)],
+            Err(_) => vec![EventType::KeyPress(key(name)?)],
+        },
+        ("release", [name]) => match button(name) {
+            Ok(b) => vec![EventType::ButtonRelease(b)],
+            Err(_) => vec![EventType::KeyRelease(key(name)?)],
+        },
+        ("key", [name]) => {
+            let k = key(name)?;
+            vec![EventType::KeyPress(k), EventType::KeyRelease(k)]
+        }
+        ("move", [x, y]) => vec![EventType::MouseMove {
+            x: number(x)?,
+            y: number(y)?,
+        }],
+        ("scroll", [dx, dy]) => vec![EventType::Wheel {
+            delta_x: delta(dx)?,
+            delta_y: delta(dy)?,
+        }],
+        ("screenshot", [region, ..]) => {
+            let path = rest.trim_start()[region.len()..].trim();
+            if path.is_empty() {
+                return Err(format!("{}: no file to save to", invalid()));
+            }
+            return Ok(vec![RecordedEvent {
+                action: MacroAction::Screenshot {
+                    region: region.parse()?,
+                    path: path.to_string(),
+                },
+                timestamp: Duration::ZERO,
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            }]);
+        }
+        ("checkpoint", [name]) => {
+            return Ok(vec![RecordedEvent {
+                action: MacroAction::Checkpoint(name.to_string()),
+                timestamp: Duration::ZERO,
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            }]);
+        }
+        ("type", _) if vars::placeholders(rest).next().is_some() => {
+            return Ok(vec![RecordedEvent {
+                action: MacroAction::Type(rest.to_string()),
+                timestamp: Duration::ZERO,
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            }]);
+        }
+        ("type", _) if !rest.is_empty() => {
+            let (events, untypable) = snippets::typing(0, rest);
+            if !untypable.is_empty() {
+                return Err(format!(
+                    "can't type {:?} with a US layout",
+                    untypable
+                ));
+            }
+            return Ok(events);
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(types
+        .into_iter()
+        .map(|event_type| RecordedEvent {
+            action: MacroAction::Input(event_type),
+            timestamp: Duration::ZERO,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        })
+        .collect())
+}
+
+/// Parses what follows `wait` in an event spec.
+fn parse_wait(spec: &str) -> Result<RecordedEvent, String> {
+    let expected = "expected [TIMEOUT] window|focus|file|start|exit ...";
+    let (first, rest) = spec.trim().split_once(' ').ok_or(expected)?;
+    let (timeout, what, arg) = match first {
+        "window" | "focus" | "file" | "start" | "exit" => {
+            (DEFAULT_WAIT_TIMEOUT, first, rest)
+        }
+        _ => {
+            let (what, arg) =
+                rest.trim_start().split_once(' ').ok_or(expected)?;
+            (parse_time(first)?, what, arg)
+        }
+    };
+    let arg = arg.trim();
+    let until = match what {
+        "window" => Condition::window(arg, false)?,
+        "focus" => Condition::window(arg, true)?,
+        "file" => Condition::File { path: arg.into() },
+        "start" | "exit" => Condition::Process {
+            name: arg.to_string(),
+            running: what == "start",
+        },
+        _ => return Err(expected.into()),
+    };
+    Ok(RecordedEvent {
+        action: MacroAction::Wait {
+            until,
+            timeout_ms: timeout.as_millis() as u64,
+        },
+        timestamp: Duration::ZERO,
+        device: None,
+        wait: None,
+        per_pass: None,
+        text: None,
+    })
+}
@@ -0,0 +1,171 @@
+//! Global capture straight from `/dev/input/event*`.
+//!
+//! Used on Wayland, where rdev only sees input aimed at XWayland windows.
+//! Needs read access to the devices, normally via the `input` group.
+//! Pointer motion is reported by mice as relative deltas, so positions are
+//! reconstructed by accumulating them from the screen center; pointer
+//! acceleration and touchpads make them approximate.
+//!
+//! Unlike the platform hooks, every event here is known to come from a
+//! particular device, so this is also used when recording is limited to
+//! some devices.
+
+use crate::uinput::{
+    key_from_code, InputEvent, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, DEVICE_NAME,
+    EV_KEY, EV_REL, EV_SYN, REL_HWHEEL, REL_WHEEL, SYN_REPORT,
+};
+use rdev::{Button, Event, EventType};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const EVIOCGNAME_256: u32 = 0x8100_4506;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+
+/// An open event device and the name the kernel reports for it.
+pub struct Device {
+    file: File,
+    pub name: Arc<str>,
+}
+
+/// Opens every readable event device except our own virtual one.
+pub fn open_devices() -> Vec<Device> {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("event"))
+        .filter_map(|e| File::open(e.path()).ok())
+        .map(|file| Device {
+            name: device_name(&file).into(),
+            file,
+        })
+        .filter(|device| &*device.name != DEVICE_NAME)
+        .collect()
+}
+
+fn device_name(device: &File) -> String {
+    let mut buffer = [0u8; 256];
+    let len = unsafe {
+        libc::ioctl(
+            device.as_raw_fd(),
+            EVIOCGNAME_256 as _,
+            buffer.as_mut_ptr(),
+        )
+    };
+    if len <= 0 {
+        return String::new();
+    }
+    let name = &buffer[..len as usize];
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..end]).into_owned()
+}
+
+/// Reads `devices` on background threads and feeds the translated events,
+/// with the name of the device they came from, to `callback`, one at a
+/// time, from a single dispatch thread.
+pub fn spawn<F>(devices: Vec<Device>, screen: (i32, i32), mut callback: F)
+where
+    F: FnMut(Event, Option<Arc<str>>) + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut names = Vec::new();
+    for (index, device) in devices.into_iter().enumerate() {
+        let tx = tx.clone();
+        names.push(device.name);
+        thread::spawn(move || read_device(device.file, index, tx));
+    }
+
+    thread::spawn(move || {
+        let mut pointer = (screen.0 as f64 / 2.0, screen.1 as f64 / 2.0);
+        let mut moved = false;
+        for raw in rx {
+            let (device, time, type_, code, value): RawEvent = raw;
+            let event_type = match (type_, code) {
+                (EV_REL, REL_X) => {
+                    pointer.0 = (pointer.0 + value as f64)
+                        .clamp(0.0, (screen.0 - 1) as f64);
+                    moved = true;
+                    None
+                }
+                (EV_REL, REL_Y) => {
+                    pointer.1 = (pointer.1 + value as f64)
+                        .clamp(0.0, (screen.1 - 1) as f64);
+                    moved = true;
+                    None
+                }
+                (EV_SYN, SYN_REPORT) if moved => {
+                    moved = false;
+                    Some(EventType::MouseMove {
+                        x: pointer.0,
+                        y: pointer.1,
+                    })
+                }
+                (EV_REL, REL_WHEEL) => Some(EventType::Wheel {
+                    delta_x: 0,
+                    delta_y: value as i64,
+                }),
+                (EV_REL, REL_HWHEEL) => Some(EventType::Wheel {
+                    delta_x: value as i64,
+                    delta_y: 0,
+                }),
+                (EV_KEY, code) => key_event(code, value),
+                _ => None,
+            };
+            if let Some(event_type) = event_type {
+                callback(
+                    Event {
+                        time,
+                        name: None,
+                        event_type,
+                    },
+                    Some(Arc::clone(&names[device])),
+                );
+            }
+        }
+    });
+}
+
+fn key_event(code: u16, value: i32) -> Option<EventType> {
+    let button = match code {
+        BTN_LEFT => Some(Button::Left),
+        BTN_RIGHT => Some(Button::Right),
+        BTN_MIDDLE => Some(Button::Middle),
+        _ => None,
+    };
+    // value is 0 for release, 1 for press and 2 for autorepeat
+    match (button, value) {
+        (Some(b), 0) => Some(EventType::ButtonRelease(b)),
+        (Some(b), 1) => Some(EventType::ButtonPress(b)),
+        (Some(_), _) => None,
+        (None, 0) => key_from_code(code).map(EventType::KeyRelease),
+        (None, _) => key_from_code(code).map(EventType::KeyPress),
+    }
+}
+
+/// Device index, time, type, code and value of a kernel input event.
+type RawEvent = (usize, SystemTime, u16, u16, i32);
+
+fn read_device(mut device: File, index: usize, tx: mpsc::Sender<RawEvent>) {
+    let mut buffer = [0u8; std::mem::size_of::<InputEvent>()];
+    while device.read_exact(&mut buffer).is_ok() {
+        let event: InputEvent =
+            unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const _) };
+        // Kernel timestamps use the realtime clock unless a device was
+        // switched to another one.
+        let time = UNIX_EPOCH
+            + Duration::new(
+                event.time.tv_sec as u64,
+                event.time.tv_usec as u32 * 1000,
+            );
+        let raw = (index, time, event.type_, event.code, event.value);
+        if tx.send(raw).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::accessibility::ElementTarget;
+use crate::screenshot::Region;
+use crate::wait::Condition;
+use rdev::{EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MacroAction {
+    Input(EventType),
+    /// Click on a UI element found through the accessibility tree.
+    ClickElement {
+        target: ElementTarget,
+        button: rdev::Button,
+    },
+    /// Text put on the clipboard.
+    Clipboard(String),
+    /// Holds playback until `until` holds, stopping it after the timeout.
+    Wait { until: Condition, timeout_ms: u64 },
+    /// Text typed as it is played, with placeholders filled in: `{i}` is
+    /// the loop pass counting from 0, others come from `--csv` columns or
+    /// the environment.
+    Type(String),
+    /// Starts the cleanup section, which is also played when playback ends
+    /// early, before reaching it.
+    Cleanup,
+    /// Plays `then` if `key` was held when playback started, `otherwise`
+    /// if not. Both are recordings of their own starting at zero.
+    IfKeyHeld {
+        key: Key,
+        then: Vec<RecordedEvent>,
+        otherwise: Vec<RecordedEvent>,
+    },
+    /// Saves `region` of the screen as a PNG file. The path's placeholders
+    /// are filled in as for `Type`, plus `{date}` and `{time}` when the
+    /// screenshot is taken.
+    Screenshot { region: Region, path: String },
+    /// Marks a point loop passes after the first can start from, so a
+    /// setup section before it plays once. Does nothing itself.
+    Checkpoint(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub action: MacroAction,
+    pub timestamp: Duration, // time since start of recording
+    /// Name of the device the input came from, where the capture method
+    /// knows it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Waits a random time in this range before the event instead of the
+    /// recorded one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait: Option<WaitRange>,
+    /// Moves a pointer move by this much more on each loop pass, e.g. down
+    /// one row of a table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_pass: Option<(f64, f64)>,
+    /// What a key press typed in the layout it was recorded with, e.g. `é`,
+    /// so it can be typed again with another layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Bounds of a random wait.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WaitRange {
+    pub min: Duration,
+    pub max: Duration,
+}
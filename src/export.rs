@@ -0,0 +1,497 @@
+//! Renders a recording as an animated GIF of the pointer's path and its
+//! clicks, for sharing what a macro does without running it, or several
+//! recordings as a heatmap of where the pointer went and clicked.
+//!
+//! The pointer is drawn over a screenshot when one is given, so the
+//! images show where on screen things happen; keys aren't shown.
+
+use crate::event::{MacroAction, RecordedEvent};
+use rdev::EventType;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+const FPS: u32 = 15;
+/// Frames are scaled down to at most this width.
+const MAX_WIDTH: u32 = 960;
+/// Longest recording exported; the rest is cut off.
+const MAX_LENGTH: Duration = Duration::from_secs(120);
+/// How long the pointer leaves a trail behind it.
+const TRAIL: Duration = Duration::from_secs(1);
+/// How long a click is highlighted.
+const CLICK: Duration = Duration::from_millis(400);
+
+const BLANK: [u8; 3] = [40, 40, 40];
+const TRAIL_COLOR: [u8; 3] = [255, 200, 0];
+const CLICK_COLOR: [u8; 3] = [255, 80, 0];
+const POINTER: [u8; 3] = [255, 255, 255];
+const OUTLINE: [u8; 3] = [0, 0, 0];
+
+/// Heat a click adds, against 1 for each pixel the pointer moves over.
+const CLICK_HEAT: f32 = 40.0;
+/// Radius in pixels heat is spread over, before scaling down.
+const HEAT_RADIUS: f64 = 24.0;
+/// Strongest the heat colours get over the background.
+const HEAT_OPACITY: f32 = 0.75;
+
+/// An RGB image.
+#[derive(Clone, PartialEq)]
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn filled(width: u32, height: u32, color: [u8; 3]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: color.repeat((width * height) as usize),
+        }
+    }
+
+    fn set(&mut self, x: i32, y: i32, color: [u8; 3]) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height
+        {
+            return;
+        }
+        let i = (y as u32 * self.width + x as u32) as usize * 3;
+        self.pixels[i..i + 3].copy_from_slice(&color);
+    }
+
+    fn disc(&mut self, (cx, cy): (i32, i32), r: i32, color: [u8; 3]) {
+        for y in -r..=r {
+            for x in -r..=r {
+                if x * x + y * y <= r * r {
+                    self.set(cx + x, cy + y, color);
+                }
+            }
+        }
+    }
+
+    fn ring(&mut self, (cx, cy): (i32, i32), r: i32, color: [u8; 3]) {
+        let (inner, outer) = ((r - 2) * (r - 2), r * r);
+        for y in -r..=r {
+            for x in -r..=r {
+                let d = x * x + y * y;
+                if d <= outer && d >= inner {
+                    self.set(cx + x, cy + y, color);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, from: (i32, i32), to: (i32, i32), color: [u8; 3]) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).max(1);
+        for i in 0..=steps {
+            let x = from.0 + (to.0 - from.0) * i / steps;
+            let y = from.1 + (to.1 - from.1) * i / steps;
+            self.disc((x, y), 1, color);
+        }
+    }
+
+    /// This image resized to `width` x `height`, nearest neighbour.
+    fn scaled(&self, width: u32, height: u32) -> Self {
+        let mut scaled = Self::filled(width, height, BLANK);
+        for y in 0..height {
+            let sy = y * self.height / height;
+            for x in 0..width {
+                let sx = x * self.width / width;
+                let from = ((sy * self.width + sx) * 3) as usize;
+                let to = ((y * width + x) * 3) as usize;
+                scaled.pixels[to..to + 3]
+                    .copy_from_slice(&self.pixels[from..from + 3]);
+            }
+        }
+        scaled
+    }
+}
+
+/// Reads a PNG screenshot as an RGB image.
+fn read_png(path: &Path) -> Result<Canvas, String> {
+    let invalid = |e: png::DecodingError| format!("{}: {}", path.display(), e);
+    let file = File::open(path)
+        .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => {
+            return Err(format!("{}: unsupported PNG", path.display()))
+        }
+    };
+    let mut canvas = Canvas::filled(info.width, info.height, BLANK);
+    for y in 0..info.height as usize {
+        let row = &buffer[y * info.line_size..];
+        for x in 0..info.width as usize {
+            let px = &row[x * channels..];
+            let rgb = if channels < 3 {
+                [px[0]; 3]
+            } else {
+                [px[0], px[1], px[2]]
+            };
+            canvas.set(x as i32, y as i32, rgb);
+        }
+    }
+    Ok(canvas)
+}
+
+/// Where the pointer was and what it clicked, in screen coordinates.
+struct Track {
+    moves: Vec<(Duration, (f64, f64))>,
+    clicks: Vec<(Duration, (f64, f64))>,
+}
+
+impl Track {
+    fn new(events: &[RecordedEvent]) -> Self {
+        let mut track = Track {
+            moves: Vec::new(),
+            clicks: Vec::new(),
+        };
+        let mut pointer = (0.0, 0.0);
+        for evt in events {
+            match evt.action {
+                MacroAction::Input(EventType::MouseMove { x, y }) => {
+                    pointer = (x, y);
+                    track.moves.push((evt.timestamp, pointer));
+                }
+                MacroAction::Input(EventType::ButtonPress(_)) => {
+                    track.clicks.push((evt.timestamp, pointer));
+                }
+                _ => {}
+            }
+        }
+        track
+    }
+
+    fn pointer_at(&self, t: Duration) -> Option<(f64, f64)> {
+        let i = self.moves.partition_point(|(at, _)| *at <= t);
+        i.checked_sub(1).map(|i| self.moves[i].1)
+    }
+}
+
+/// Writes `events` as an animated GIF to `out`, drawn over `background`
+/// if given. Returns the number of frames written.
+pub fn gif(
+    events: &[RecordedEvent],
+    out: &Path,
+    background: Option<&Path>,
+    screen: (i32, i32),
+) -> Result<usize, String> {
+    let background = background.map(read_png).transpose()?;
+    let screen = match &background {
+        Some(shot) => (shot.width, shot.height),
+        None => (screen.0.max(1) as u32, screen.1.max(1) as u32),
+    };
+    let scale = (MAX_WIDTH as f64 / screen.0 as f64).min(1.0);
+    let width = ((screen.0 as f64 * scale) as u32).max(1);
+    let height = ((screen.1 as f64 * scale) as u32).max(1);
+    let base = match &background {
+        Some(shot) => shot.scaled(width, height),
+        None => Canvas::filled(width, height, BLANK),
+    };
+    let to_frame =
+        |(x, y): (f64, f64)| ((x * scale) as i32, (y * scale) as i32);
+
+    let track = Track::new(events);
+    let length = events.last().map_or(Duration::ZERO, |e| e.timestamp);
+    if length > MAX_LENGTH {
+        println!(
+            "Only the first {} s of the recording are exported.",
+            MAX_LENGTH.as_secs()
+        );
+    }
+    let length = length.min(MAX_LENGTH);
+
+    let file = File::create(out)
+        .map_err(|e| format!("cannot create {}: {}", out.display(), e))?;
+    let encode = |e: gif::EncodingError| format!("{}: {}", out.display(), e);
+    let (w, h) = (width as u16, height as u16);
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), w, h, &[])
+        .map_err(encode)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(encode)?;
+
+    let frame_time = Duration::from_secs(1) / FPS;
+    let frames = (length.as_nanos() / frame_time.as_nanos()) as u32 + 1;
+    // Identical frames are merged into one that is shown for longer.
+    let mut pending: Option<(Canvas, u32)> = None;
+    let mut written = 0;
+    // Frames already written, so delays can carry their rounding over.
+    let mut shown = 0;
+    for n in 0..frames {
+        let t = frame_time * n;
+        let mut canvas = base.clone();
+
+        let trail: Vec<(i32, i32)> = track
+            .moves
+            .iter()
+            .filter(|(at, _)| *at <= t && t - *at <= TRAIL)
+            .map(|(_, pos)| to_frame(*pos))
+            .collect();
+        for pair in trail.windows(2) {
+            canvas.line(pair[0], pair[1], TRAIL_COLOR);
+        }
+        for (at, pos) in &track.clicks {
+            if *at <= t && t - *at <= CLICK {
+                let grown = (t - *at).as_secs_f64() / CLICK.as_secs_f64();
+                let r = 6 + (grown * 18.0) as i32;
+                canvas.ring(to_frame(*pos), r, CLICK_COLOR);
+            }
+        }
+        if let Some(pos) = track.pointer_at(t) {
+            canvas.disc(to_frame(pos), 6, OUTLINE);
+            canvas.disc(to_frame(pos), 4, POINTER);
+        }
+
+        match &mut pending {
+            Some((last, count)) if *last == canvas => *count += 1,
+            _ => {
+                if let Some((last, count)) = pending.replace((canvas, 1)) {
+                    let delay = delay(shown, count);
+                    write_frame(&mut encoder, &last, delay).map_err(encode)?;
+                    shown += count;
+                    written += 1;
+                }
+            }
+        }
+    }
+    if let Some((last, count)) = pending {
+        let delay = delay(shown, count);
+        write_frame(&mut encoder, &last, delay).map_err(encode)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// How long to show `count` frames after the first `shown`, in the GIF's
+/// hundredths of a second. Rounding against the total time so far keeps
+/// the animation from drifting ahead of the recording.
+fn delay(shown: u32, count: u32) -> u16 {
+    let end = (shown + count) * 100 / FPS;
+    let start = shown * 100 / FPS;
+    (end - start).clamp(1, u16::MAX as u32) as u16
+}
+
+fn write_frame<W: std::io::Write>(
+    encoder: &mut gif::Encoder<W>,
+    canvas: &Canvas,
+    delay: u16,
+) -> Result<(), gif::EncodingError> {
+    let mut frame = gif::Frame::from_rgb_speed(
+        canvas.width as u16,
+        canvas.height as u16,
+        &canvas.pixels,
+        10,
+    );
+    frame.delay = delay;
+    encoder.write_frame(&frame)
+}
+
+/// What went into a heatmap.
+pub struct Heatmap {
+    pub recordings: usize,
+    pub moves: usize,
+    pub clicks: usize,
+    /// Smallest rectangle around all clicks, top left and bottom right.
+    pub clicked: Option<((f64, f64), (f64, f64))>,
+}
+
+impl fmt::Display for Heatmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} clicks and {} moves from {} recordings",
+            self.clicks, self.moves, self.recordings
+        )?;
+        if let Some(((left, top), (right, bottom))) = self.clicked {
+            write!(
+                f,
+                ", clicks between {:.0}, {:.0} and {:.0}, {:.0}",
+                left, top, right, bottom
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a PNG heatmap of the pointer's moves and clicks in all of
+/// `recordings` to `out`, drawn over `background` if given.
+pub fn heatmap(
+    recordings: &[Vec<RecordedEvent>],
+    out: &Path,
+    background: Option<&Path>,
+    screen: (i32, i32),
+) -> Result<Heatmap, String> {
+    let background = background.map(read_png).transpose()?;
+    let screen = match &background {
+        Some(shot) => (shot.width, shot.height),
+        None => (screen.0.max(1) as u32, screen.1.max(1) as u32),
+    };
+    let scale = (MAX_WIDTH as f64 / screen.0 as f64).min(1.0);
+    let width = ((screen.0 as f64 * scale) as u32).max(1);
+    let height = ((screen.1 as f64 * scale) as u32).max(1);
+    let mut canvas = match &background {
+        Some(shot) => shot.scaled(width, height),
+        None => Canvas::filled(width, height, BLANK),
+    };
+    let to_image =
+        |(x, y): (f64, f64)| ((x * scale) as i32, (y * scale) as i32);
+
+    let mut heat = vec![0.0f32; (width * height) as usize];
+    let mut add = |(x, y): (i32, i32), amount: f32| {
+        if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+            heat[(y as u32 * width + x as u32) as usize] += amount;
+        }
+    };
+    let mut summary = Heatmap {
+        recordings: recordings.len(),
+        moves: 0,
+        clicks: 0,
+        clicked: None,
+    };
+    for events in recordings {
+        let track = Track::new(events);
+        summary.moves += track.moves.len();
+        summary.clicks += track.clicks.len();
+        for pair in track.moves.windows(2) {
+            let (from, to) = (to_image(pair[0].1), to_image(pair[1].1));
+            let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs());
+            // The end is the start of the next segment.
+            for i in 0..steps.max(1) {
+                let x = from.0 + (to.0 - from.0) * i / steps.max(1);
+                let y = from.1 + (to.1 - from.1) * i / steps.max(1);
+                add((x, y), 1.0);
+            }
+        }
+        for (_, pos) in &track.clicks {
+            add(to_image(*pos), CLICK_HEAT);
+            summary.clicked = Some(match summary.clicked {
+                Some(((left, top), (right, bottom))) => (
+                    (left.min(pos.0), top.min(pos.1)),
+                    (right.max(pos.0), bottom.max(pos.1)),
+                ),
+                None => (*pos, *pos),
+            });
+        }
+    }
+
+    // Two box blurs come close enough to a gaussian.
+    let radius = ((HEAT_RADIUS * scale) as usize).max(1);
+    for _ in 0..2 {
+        heat = blur(&heat, width as usize, height as usize, radius);
+    }
+    // The square root keeps the pointer's path visible next to clicks.
+    let max = heat.iter().cloned().fold(0.0f32, f32::max).sqrt();
+    if max > 0.0 {
+        for (i, h) in heat.iter().enumerate() {
+            let level = h.sqrt() / max;
+            if level < 0.02 {
+                continue;
+            }
+            let color = heat_color(level);
+            let alpha = HEAT_OPACITY * level.min(0.25) * 4.0;
+            let px = &mut canvas.pixels[i * 3..i * 3 + 3];
+            for (c, target) in px.iter_mut().zip(color) {
+                let mixed = *c as f32 * (1.0 - alpha) + target as f32 * alpha;
+                *c = mixed.round() as u8;
+            }
+        }
+    }
+
+    write_png(&canvas, out)?;
+    Ok(summary)
+}
+
+/// Averages each value with its neighbours up to `radius` away, first
+/// along rows and then along columns.
+fn blur(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+) -> Vec<f32> {
+    let rows = box_blur(values, width, radius);
+    let columns = box_blur(&transpose(&rows, width, height), height, radius);
+    transpose(&columns, height, width)
+}
+
+/// Averages each value with its neighbours in rows of `width` values.
+fn box_blur(values: &[f32], width: usize, radius: usize) -> Vec<f32> {
+    let size = (2 * radius + 1) as f32;
+    let mut out = vec![0.0; values.len()];
+    for (row, blurred) in values.chunks(width).zip(out.chunks_mut(width)) {
+        let mut sum: f32 = row[..=radius.min(width - 1)].iter().sum();
+        for (i, value) in blurred.iter_mut().enumerate() {
+            *value = sum / size;
+            if let Some(next) = row.get(i + radius + 1) {
+                sum += next;
+            }
+            if i >= radius {
+                sum -= row[i - radius];
+            }
+        }
+    }
+    out
+}
+
+fn transpose(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut out = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[x * height + y] = values[y * width + x];
+        }
+    }
+    out
+}
+
+/// Blue through green and yellow to red as `level` goes from 0 to 1.
+fn heat_color(level: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 255.0],
+        [0.0, 255.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 0.0, 0.0],
+    ];
+    let at = level.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (at as usize).min(STOPS.len() - 2);
+    let t = at - i as f32;
+    let mut color = [0; 3];
+    for (c, (a, b)) in color.iter_mut().zip(STOPS[i].iter().zip(STOPS[i + 1]))
+    {
+        *c = (a + (b - a) * t).round() as u8;
+    }
+    color
+}
+
+fn write_png(canvas: &Canvas, out: &Path) -> Result<(), String> {
+    let file = File::create(out)
+        .map_err(|e| format!("cannot create {}: {}", out.display(), e))?;
+    let encode = |e: png::EncodingError| format!("{}: {}", out.display(), e);
+    let mut encoder =
+        png::Encoder::new(BufWriter::new(file), canvas.width, canvas.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(encode)?;
+    writer.write_image_data(&canvas.pixels).map_err(encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_delays_add_up_to_the_recording() {
+        let total: u32 = (0..FPS).map(|shown| delay(shown, 1) as u32).sum();
+        assert_eq!(total, 100);
+        assert_eq!(delay(0, FPS * 3), 300);
+    }
+}
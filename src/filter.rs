@@ -0,0 +1,1384 @@
+//! Filters selecting which input is kept while recording, and filters and
+//! remaps applied to a recording as it is played back.
+
+use crate::event::{MacroAction, RecordedEvent, WaitRange};
+use crate::layout::{Layout, Retarget};
+use crate::player::EventSource;
+use crate::snippets;
+use crate::vars::{self, Vars};
+use rdev::{Button, EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::cell::{Cell, OnceCell};
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Key events further apart than this are not one stretch of typing.
+const MAX_TYPING_GAP: Duration = Duration::from_secs(1);
+/// How far the time between key presses may stray from the target speed.
+const TYPING_VARIANCE: f64 = 0.25;
+/// Wheel events further apart than this are not one scroll gesture.
+const MAX_SCROLL_GAP: Duration = Duration::from_millis(100);
+
+/// Which class of input gets recorded.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RecordFilter {
+    All,
+    KeyboardOnly,
+    MouseOnly,
+    NoMouseMove,
+    /// Button presses and releases only.
+    ClicksOnly,
+}
+
+impl RecordFilter {
+    /// Filter order used when cycling through them with the hotkey.
+    const CYCLE: [RecordFilter; 5] = [
+        RecordFilter::All,
+        RecordFilter::KeyboardOnly,
+        RecordFilter::MouseOnly,
+        RecordFilter::NoMouseMove,
+        RecordFilter::ClicksOnly,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordFilter::All => "all",
+            RecordFilter::KeyboardOnly => "keyboard",
+            RecordFilter::MouseOnly => "mouse",
+            RecordFilter::NoMouseMove => "no-move",
+            RecordFilter::ClicksOnly => "clicks",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::CYCLE.iter().position(|f| *f == self).unwrap_or(0);
+        Self::CYCLE[(i + 1) % Self::CYCLE.len()]
+    }
+
+    pub fn allows(self, event_type: &EventType) -> bool {
+        let keyboard = matches!(
+            event_type,
+            EventType::KeyPress(_) | EventType::KeyRelease(_)
+        );
+        let click = matches!(
+            event_type,
+            EventType::ButtonPress(_) | EventType::ButtonRelease(_)
+        );
+        match self {
+            RecordFilter::All => true,
+            RecordFilter::KeyboardOnly => keyboard,
+            RecordFilter::MouseOnly => !keyboard,
+            RecordFilter::NoMouseMove => {
+                !matches!(event_type, EventType::MouseMove { .. })
+            }
+            RecordFilter::ClicksOnly => click,
+        }
+    }
+
+    /// Whether mouse moves are recorded. Without them a click has to carry
+    /// its own position to land in the right place.
+    pub fn records_moves(self) -> bool {
+        matches!(self, RecordFilter::All | RecordFilter::MouseOnly)
+    }
+}
+
+impl FromStr for RecordFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::CYCLE
+            .into_iter()
+            .find(|f| f.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown record filter '{}' (all, keyboard, mouse, \
+                     no-move, clicks)",
+                    s
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for RecordFilter {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Limits recording to input from particular devices. Devices are matched
+/// by a case-insensitive substring of their name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceFilter {
+    /// When non-empty, only these devices are recorded.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl DeviceFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Combines the device lists of both filters.
+    pub fn overlay(mut self, top: DeviceFilter) -> Self {
+        self.include.extend(top.include);
+        self.exclude.extend(top.exclude);
+        self
+    }
+
+    /// Input from an unknown device is always allowed, since not every
+    /// capture method can tell devices apart.
+    pub fn allows(&self, device: Option<&str>) -> bool {
+        let Some(device) = device else {
+            return true;
+        };
+        let device = device.to_lowercase();
+        let matches =
+            |pattern: &String| device.contains(&pattern.to_lowercase());
+        !self.exclude.iter().any(matches)
+            && (self.include.is_empty() || self.include.iter().any(matches))
+    }
+}
+
+/// Kinds of event a [`DelayRule`] can apply to.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum EventKind {
+    /// Button presses, including clicks on elements.
+    Click,
+    /// Key presses.
+    Key,
+    Move,
+    Scroll,
+}
+
+impl EventKind {
+    const ALL: [EventKind; 4] = [
+        EventKind::Click,
+        EventKind::Key,
+        EventKind::Move,
+        EventKind::Scroll,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Click => "click",
+            EventKind::Key => "key",
+            EventKind::Move => "move",
+            EventKind::Scroll => "scroll",
+        }
+    }
+
+    pub fn matches(self, action: &MacroAction) -> bool {
+        match (self, action) {
+            (EventKind::Click, MacroAction::ClickElement { .. }) => true,
+            (_, MacroAction::Input(evt)) => matches!(
+                (self, evt),
+                (EventKind::Click, EventType::ButtonPress(_))
+                    | (EventKind::Key, EventType::KeyPress(_))
+                    | (EventKind::Move, EventType::MouseMove { .. })
+                    | (EventKind::Scroll, EventType::Wheel { .. })
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|k| k.name() == s).ok_or_else(|| {
+            format!("unknown event kind '{}' (click, key, move, scroll)", s)
+        })
+    }
+}
+
+impl TryFrom<String> for EventKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Lengthens or shortens the wait before every event of one kind, e.g. to
+/// give a slower machine time to react to clicks.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DelayRule {
+    pub before: EventKind,
+    /// Factor the recorded wait is multiplied by.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Added to the wait after scaling.
+    #[serde(default)]
+    pub add_ms: u64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Makes every playback differ, for testing how a UI copes with input
+/// that is never quite the same.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Randomize {
+    /// Pointer positions move by up to this many pixels either way.
+    pub position_px: f64,
+    /// Waits vary by up to this many percent either way.
+    pub timing_pct: f64,
+    /// Chance of a small pointer movement before each event.
+    pub micro_move_chance: f64,
+    /// Longest random pause before each loop pass after the first.
+    pub cooldown_ms: u64,
+}
+
+impl Default for Randomize {
+    fn default() -> Self {
+        Self {
+            position_px: 3.0,
+            timing_pct: 10.0,
+            micro_move_chance: 0.05,
+            cooldown_ms: 2000,
+        }
+    }
+}
+
+impl Randomize {
+    const OFF: Randomize = Randomize {
+        position_px: 0.0,
+        timing_pct: 0.0,
+        micro_move_chance: 0.0,
+        cooldown_ms: 0,
+    };
+
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = self.position_px.is_finite()
+            && self.position_px >= 0.0
+            && (0.0..=100.0).contains(&self.timing_pct)
+            && (0.0..=1.0).contains(&self.micro_move_chance);
+        if valid {
+            Ok(())
+        } else {
+            Err("invalid randomize settings".into())
+        }
+    }
+}
+
+/// Replaces `from` with `to` during playback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remap<T, U = T> {
+    pub from: T,
+    pub to: U,
+}
+
+/// What a mouse button is played as: another button, e.g. to swap left
+/// and right, or keys pressed while the button would be held, e.g. a
+/// middle click closing a tab as `["ControlLeft", "KeyW"]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ButtonTarget {
+    Button(Button),
+    Keys(Vec<Key>),
+}
+
+impl From<Button> for ButtonTarget {
+    fn from(button: Button) -> Self {
+        ButtonTarget::Button(button)
+    }
+}
+
+/// Changes applied to recorded events as they are played back, to adapt a
+/// recording to a slightly different target without re-recording it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaybackFilter {
+    pub skip_mouse_moves: bool,
+    pub skip_scroll: bool,
+    /// Replays bursts of wheel events, as trackpads send, as one gesture
+    /// slowing down smoothly.
+    pub ease_scroll: bool,
+    pub remap_keys: Vec<Remap<Key>>,
+    pub remap_buttons: Vec<Remap<Button, ButtonTarget>>,
+    /// Replays stretches of typing at this many words per minute.
+    pub typing_wpm: Option<f64>,
+    pub delays: Vec<DelayRule>,
+    /// Varies every wait by up to this many percent either way.
+    pub randomize_pct: Option<f64>,
+    pub randomize: Option<Randomize>,
+    /// A CSV file to play a pass for each row of, with its columns filling
+    /// in the placeholders of typed text.
+    pub csv: Option<PathBuf>,
+    /// Placeholder values to read instead of `.env`.
+    pub env_file: Option<PathBuf>,
+    /// Checkpoint loop passes after the first start from.
+    pub loop_from: Option<String>,
+    /// Keyboard layout to play typing with, when it differs from the one
+    /// it was recorded with.
+    pub layout: Option<Layout>,
+}
+
+impl PlaybackFilter {
+    /// Layers `top` over this filter: its remaps take priority over these,
+    /// delay rules of both apply and it can turn skipping on, but not off.
+    pub fn overlay(self, mut top: PlaybackFilter) -> Self {
+        top.remap_keys.extend(self.remap_keys);
+        top.remap_buttons.extend(self.remap_buttons);
+        top.delays.extend(self.delays);
+        Self {
+            skip_mouse_moves: self.skip_mouse_moves || top.skip_mouse_moves,
+            skip_scroll: self.skip_scroll || top.skip_scroll,
+            ease_scroll: self.ease_scroll || top.ease_scroll,
+            remap_keys: top.remap_keys,
+            remap_buttons: top.remap_buttons,
+            typing_wpm: top.typing_wpm.or(self.typing_wpm),
+            delays: top.delays,
+            randomize_pct: top.randomize_pct.or(self.randomize_pct),
+            randomize: top.randomize.or(self.randomize),
+            csv: top.csv.or(self.csv),
+            env_file: top.env_file.or(self.env_file),
+            loop_from: top.loop_from.or(self.loop_from),
+            layout: top.layout.or(self.layout),
+        }
+    }
+
+    /// Takes the skipping and remapping out of the filter, for a source to
+    /// apply to each event as it reads it, ahead of the rest of the filter.
+    /// Nothing is taken where the rest needs events as recorded: to retarget
+    /// them to a layout, or to play buttons as shortcuts.
+    pub fn take_remaps(&mut self) -> Option<Plan> {
+        let shortcuts = self
+            .remap_buttons
+            .iter()
+            .any(|r| matches!(r.to, ButtonTarget::Keys(_)));
+        if self.layout.is_some() || shortcuts {
+            return None;
+        }
+        let remaps = PlaybackFilter {
+            skip_mouse_moves: std::mem::take(&mut self.skip_mouse_moves),
+            skip_scroll: std::mem::take(&mut self.skip_scroll),
+            remap_keys: std::mem::take(&mut self.remap_keys),
+            remap_buttons: std::mem::take(&mut self.remap_buttons),
+            ..Default::default()
+        };
+        Some(remaps.plan())
+    }
+
+    /// Resolves the filter into what playing each event needs.
+    pub fn plan(&self) -> Plan {
+        let mut delays = [None; EventKind::ALL.len()];
+        for rule in &self.delays {
+            let i = EventKind::ALL.iter().position(|k| *k == rule.before);
+            let Some(delay) = i.map(|i| &mut delays[i]) else {
+                continue;
+            };
+            // Rules apply one after the other, which folds into one.
+            let (scale, add) = delay.unwrap_or((1.0, Duration::ZERO));
+            *delay = Some((
+                scale * rule.scale,
+                add.mul_f64(rule.scale) + Duration::from_millis(rule.add_ms),
+            ));
+        }
+        let randomize = self.randomize.clone().unwrap_or(Randomize::OFF);
+        Plan {
+            skip_mouse_moves: self.skip_mouse_moves,
+            skip_scroll: self.skip_scroll,
+            keys: resolve(&self.remap_keys),
+            buttons: resolve(&self.remap_buttons),
+            delays,
+            timing_pct: randomize
+                .timing_pct
+                .max(self.randomize_pct.unwrap_or(0.0)),
+            randomize,
+        }
+    }
+}
+
+/// A [`PlaybackFilter`] resolved for playback: remaps without the ones
+/// earlier remaps shadow, and each kind's delay rules folded into a single
+/// scale and addition, so events cost a lookup rather than a walk over
+/// every rule.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    skip_mouse_moves: bool,
+    skip_scroll: bool,
+    keys: Vec<(Key, Key)>,
+    buttons: Vec<(Button, ButtonTarget)>,
+    /// By [`EventKind::ALL`] order.
+    delays: [Option<(f64, Duration)>; EventKind::ALL.len()],
+    timing_pct: f64,
+    randomize: Randomize,
+}
+
+impl Plan {
+    /// Returns the action to play instead of `action`, or `None` to skip it.
+    pub fn apply(&self, action: MacroAction) -> Option<MacroAction> {
+        let action = match action {
+            MacroAction::Input(event_type) => {
+                MacroAction::Input(self.apply_input(event_type)?)
+            }
+            MacroAction::ClickElement { target, button } => {
+                MacroAction::ClickElement {
+                    target,
+                    button: self.button(button),
+                }
+            }
+            other @ (MacroAction::Clipboard(_)
+            | MacroAction::Wait { .. }
+            | MacroAction::Type(_)
+            | MacroAction::Cleanup
+            | MacroAction::IfKeyHeld { .. }
+            | MacroAction::Screenshot { .. }
+            | MacroAction::Checkpoint(_)) => other,
+        };
+        Some(action)
+    }
+
+    /// Applies the plan to `evt` and the branches in it, or returns `None`
+    /// to skip it.
+    pub fn apply_event(
+        &self,
+        mut evt: RecordedEvent,
+    ) -> Option<RecordedEvent> {
+        if let MacroAction::IfKeyHeld {
+            then, otherwise, ..
+        } = &mut evt.action
+        {
+            for branch in [then, otherwise] {
+                *branch = std::mem::take(branch)
+                    .into_iter()
+                    .filter_map(|evt| self.apply_event(evt))
+                    .collect();
+            }
+        }
+        evt.action = self.apply(evt.action)?;
+        Some(evt)
+    }
+
+    fn apply_input(&self, event_type: EventType) -> Option<EventType> {
+        let event_type = match event_type {
+            EventType::MouseMove { .. } if self.skip_mouse_moves => {
+                return None
+            }
+            EventType::Wheel { .. } if self.skip_scroll => return None,
+            EventType::KeyPress(key) => EventType::KeyPress(self.key(key)),
+            EventType::KeyRelease(key) => EventType::KeyRelease(self.key(key)),
+            EventType::ButtonPress(button) => {
+                EventType::ButtonPress(self.button(button))
+            }
+            EventType::ButtonRelease(button) => {
+                EventType::ButtonRelease(self.button(button))
+            }
+            other => other,
+        };
+        Some(event_type)
+    }
+
+    fn key(&self, key: Key) -> Key {
+        remap(&self.keys, key)
+    }
+
+    fn button(&self, button: Button) -> Button {
+        match self.target(button) {
+            Some(ButtonTarget::Button(to)) => *to,
+            _ => button,
+        }
+    }
+
+    /// The keys `button` is played as, if it is mapped to a shortcut.
+    fn shortcut(&self, button: Button) -> Option<&[Key]> {
+        match self.target(button) {
+            Some(ButtonTarget::Keys(keys)) => Some(keys),
+            _ => None,
+        }
+    }
+
+    fn target(&self, button: Button) -> Option<&ButtonTarget> {
+        self.buttons
+            .iter()
+            .find(|(from, _)| *from == button)
+            .map(|(_, to)| to)
+    }
+
+    fn has_delays(&self) -> bool {
+        self.delays.iter().any(Option::is_some)
+    }
+
+    /// The scale and addition for the wait before `action`.
+    fn delay(&self, action: &MacroAction) -> Option<(f64, Duration)> {
+        let i = EventKind::ALL.iter().position(|k| k.matches(action))?;
+        self.delays[i]
+    }
+}
+
+/// The remaps that can apply: the first one for each value, unless it
+/// leaves the value as it is.
+fn resolve<T, U>(remaps: &[Remap<T, U>]) -> Vec<(T, U)>
+where
+    T: PartialEq + Copy,
+    U: PartialEq + Clone + From<T>,
+{
+    let mut resolved: Vec<(T, U)> = Vec::new();
+    let mut seen = Vec::new();
+    for r in remaps {
+        if seen.contains(&r.from) {
+            continue;
+        }
+        seen.push(r.from);
+        if r.to != U::from(r.from) {
+            resolved.push((r.from, r.to.clone()));
+        }
+    }
+    resolved
+}
+
+fn remap<T: PartialEq + Copy>(remaps: &[(T, T)], value: T) -> T {
+    remaps
+        .iter()
+        .find(|(from, _)| *from == value)
+        .map_or(value, |(_, to)| *to)
+}
+
+/// An event source with a [`PlaybackFilter`] applied to its events.
+pub struct Filtered<S> {
+    source: S,
+    filter: PlaybackFilter,
+    plan: Plan,
+    // how often the source was opened, i.e. the loop pass
+    passes: Cell<usize>,
+    // read from the CSV file on the first pass
+    rows: OnceCell<Vec<Vars>>,
+    // read from the environment when first needed
+    env: Env,
+    // asked for before playing
+    params: Vars,
+    // whether a pass went by the checkpoint looped from
+    checkpoint_seen: Cell<bool>,
+}
+
+impl<S> Filtered<S> {
+    pub fn new(source: S, filter: PlaybackFilter) -> Self {
+        Self {
+            source,
+            plan: filter.plan(),
+            env: Env {
+                file: filter.env_file.clone(),
+                vars: OnceCell::new(),
+            },
+            filter,
+            passes: Cell::new(0),
+            rows: OnceCell::new(),
+            params: Vars::new(),
+            checkpoint_seen: Cell::new(false),
+        }
+    }
+
+    /// Fills placeholders with the values of the macro's parameters.
+    pub fn with_params(mut self, params: Vars) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Fills placeholders from `env` instead of the environment and
+    /// `.env` file.
+    pub fn with_env(mut self, env: Vars) -> Self {
+        self.env = Env {
+            file: None,
+            vars: OnceCell::from(env),
+        };
+        self
+    }
+
+    /// The rows of the CSV file, if there is one.
+    fn rows(&self) -> Result<Option<&[Vars]>, String> {
+        let Some(path) = &self.filter.csv else {
+            return Ok(None);
+        };
+        if self.rows.get().is_none() {
+            let _ = self.rows.set(vars::read_csv(path)?);
+        }
+        Ok(self.rows.get().map(Vec::as_slice))
+    }
+}
+
+impl<S: EventSource> EventSource for Filtered<S> {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        let pass = self.passes.replace(self.passes.get() + 1);
+        if self.env.file.is_some() {
+            // A file asked for that can't be read stops playback at once.
+            self.env.vars()?;
+        }
+        let mut vars = self.params.clone();
+        if let Some(row) = self.rows()?.and_then(|rows| rows.get(pass)) {
+            vars.extend(row.clone());
+        }
+        vars.insert("i".to_string(), pass.to_string());
+        let events = self.source.open()?;
+        let events: Box<dyn Iterator<Item = RecordedEvent>> =
+            match &self.filter.loop_from {
+                Some(name) if pass > 0 => {
+                    if !self.checkpoint_seen.get() {
+                        return Err(format!("no checkpoint named '{}'", name));
+                    }
+                    Box::new(FromCheckpoint {
+                        events,
+                        name,
+                        at: None,
+                    })
+                }
+                Some(name) => Box::new(events.inspect(move |evt| {
+                    if is_checkpoint(evt, name) {
+                        self.checkpoint_seen.set(true);
+                    }
+                })),
+                None => events,
+            };
+        let events: Box<dyn Iterator<Item = RecordedEvent>> =
+            match self.filter.layout {
+                Some(layout) => Box::new(Retarget::new(events, layout)),
+                None => events,
+            };
+        let events = Passes {
+            events,
+            pass,
+            vars,
+            env: Some(&self.env),
+            shift: Duration::ZERO,
+            pending: VecDeque::new(),
+        };
+        let events = Shortcuts {
+            events,
+            plan: &self.plan,
+            pending: VecDeque::new(),
+        };
+        let events = events.filter_map(move |mut evt| {
+            evt.action = self.plan.apply(evt.action)?;
+            Some(evt)
+        });
+        let events: Box<dyn Iterator<Item = RecordedEvent>> =
+            if self.filter.ease_scroll {
+                Box::new(Scrolls {
+                    events: events.peekable(),
+                    pending: VecDeque::new(),
+                })
+            } else {
+                Box::new(events)
+            };
+        let randomize = self.plan.randomize.clone();
+        let mut rng = Rng::seeded();
+        let cooldown = match pass {
+            0 => Duration::ZERO,
+            _ => Duration::from_millis(randomize.cooldown_ms)
+                .mul_f64(rng.unit()),
+        };
+        let events = Random {
+            events,
+            pct: self.plan.timing_pct,
+            randomize,
+            last: None,
+            pointer: None,
+            pending: Vec::new(),
+            lead_in: cooldown,
+            rng,
+        };
+        let events: Box<dyn Iterator<Item = RecordedEvent>> =
+            match self.filter.typing_wpm {
+                Some(wpm) => Box::new(Typing::new(events, wpm)),
+                None => Box::new(events),
+            };
+        if !self.plan.has_delays() {
+            return Ok(events);
+        }
+        Ok(Box::new(Delays {
+            events,
+            plan: &self.plan,
+            last: None,
+        }))
+    }
+
+    fn passes(&self) -> Option<usize> {
+        match self.rows() {
+            Ok(Some(rows)) => Some(rows.len()),
+            Ok(None) => self.source.passes(),
+            // Reported when the source is opened.
+            Err(_) => None,
+        }
+    }
+}
+
+fn is_checkpoint(evt: &RecordedEvent, name: &str) -> bool {
+    matches!(&evt.action, MacroAction::Checkpoint(n) if n == name)
+}
+
+/// Whether `events` have the checkpoint `name`, in a branch or not, so one
+/// that's missing is reported before playback starts.
+pub fn has_checkpoint<E: Borrow<RecordedEvent>>(
+    events: impl IntoIterator<Item = E>,
+    name: &str,
+) -> bool {
+    events.into_iter().any(|evt| match &evt.borrow().action {
+        MacroAction::IfKeyHeld {
+            then, otherwise, ..
+        } => has_checkpoint(then.iter().chain(otherwise), name),
+        _ => is_checkpoint(evt.borrow(), name),
+    })
+}
+
+/// Starts a loop pass from the checkpoint `name`, moving it to the start.
+struct FromCheckpoint<'a, I> {
+    events: I,
+    name: &'a str,
+    // when the checkpoint was recorded, once it was reached
+    at: Option<Duration>,
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for FromCheckpoint<'_, I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        let Some(at) = self.at else {
+            let checkpoint = self
+                .events
+                .by_ref()
+                .find(|evt| is_checkpoint(evt, self.name))?;
+            self.at = Some(checkpoint.timestamp);
+            return Some(RecordedEvent {
+                timestamp: Duration::ZERO,
+                ..checkpoint
+            });
+        };
+        let mut evt = self.events.next()?;
+        evt.timestamp = evt.timestamp.saturating_sub(at);
+        Some(evt)
+    }
+}
+
+/// Placeholder values from the environment, read the first time they are
+/// needed, so macros without placeholders don't depend on a `.env` file.
+struct Env {
+    file: Option<PathBuf>,
+    vars: OnceCell<Vars>,
+}
+
+impl Env {
+    fn vars(&self) -> Result<&Vars, String> {
+        if self.vars.get().is_none() {
+            let _ = self.vars.set(vars::from_env(self.file.as_deref())?);
+        }
+        Ok(self.vars.get().unwrap())
+    }
+}
+
+/// Fills in the loop pass: types [`MacroAction::Type`] text with its
+/// placeholders filled from `vars`, moving later events back by how long
+/// that takes, fills in screenshot paths the same way and moves pointer
+/// moves by their offset per pass.
+struct Passes<'a, I> {
+    events: I,
+    pass: usize,
+    vars: Vars,
+    // filling placeholders `vars` doesn't, until added to it
+    env: Option<&'a Env>,
+    shift: Duration,
+    pending: VecDeque<RecordedEvent>,
+}
+
+impl<I> Passes<'_, I> {
+    /// `text` with its placeholders filled in, reading the environment if
+    /// one isn't filled otherwise.
+    fn fill(&mut self, text: &str) -> String {
+        let unfilled = vars::placeholders(text)
+            .any(|name| !self.vars.contains_key(name));
+        if let Some(env) = self.env.filter(|_| unfilled) {
+            self.env = None;
+            match env.vars() {
+                Ok(env) => {
+                    let vars = std::mem::take(&mut self.vars);
+                    self.vars = env.clone();
+                    self.vars.extend(vars);
+                }
+                Err(e) => println!("Cannot read placeholder values: {}", e),
+            }
+        }
+        vars::fill(text, &self.vars)
+    }
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Passes<'_, I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        loop {
+            if let Some(evt) = self.pending.pop_front() {
+                return Some(evt);
+            }
+            let mut evt = self.events.next()?;
+            evt.timestamp += self.shift;
+            let text = match &mut evt.action {
+                MacroAction::Type(text) => text,
+                MacroAction::Screenshot { path, .. } => {
+                    *path = self.fill(path);
+                    return Some(evt);
+                }
+                MacroAction::Input(EventType::MouseMove { x, y }) => {
+                    if let Some((dx, dy)) = evt.per_pass {
+                        *x += dx * self.pass as f64;
+                        *y += dy * self.pass as f64;
+                    }
+                    return Some(evt);
+                }
+                _ => return Some(evt),
+            };
+            let text = self.fill(text);
+            // Characters a US layout can't type are left out.
+            let (mut typed, _) = snippets::typing(0, &text);
+            self.shift += typed.last().map_or(Duration::ZERO, |e| e.timestamp);
+            if let Some(first) = typed.first_mut() {
+                first.wait = evt.wait;
+            }
+            self.pending.extend(typed.into_iter().map(|mut e| {
+                e.timestamp += evt.timestamp;
+                e
+            }));
+        }
+    }
+}
+
+/// Plays buttons mapped to a shortcut as its keys: pressed in order along
+/// with the button and released in reverse along with it.
+struct Shortcuts<'a, I> {
+    events: I,
+    plan: &'a Plan,
+    pending: VecDeque<RecordedEvent>,
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Shortcuts<'_, I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        loop {
+            if let Some(evt) = self.pending.pop_front() {
+                return Some(evt);
+            }
+            let evt = self.events.next()?;
+            let (button, press) = match evt.action {
+                MacroAction::Input(EventType::ButtonPress(b)) => (b, true),
+                MacroAction::Input(EventType::ButtonRelease(b)) => (b, false),
+                _ => return Some(evt),
+            };
+            let Some(keys) = self.plan.shortcut(button) else {
+                return Some(evt);
+            };
+            let keys: Vec<EventType> = if press {
+                keys.iter().map(|k| EventType::KeyPress(*k)).collect()
+            } else {
+                keys.iter().rev().map(|k| EventType::KeyRelease(*k)).collect()
+            };
+            // The first key takes the button's wait; the rest follow it.
+            self.pending.extend(keys.into_iter().enumerate().map(
+                |(i, event_type)| RecordedEvent {
+                    action: MacroAction::Input(event_type),
+                    timestamp: evt.timestamp,
+                    device: evt.device.clone(),
+                    wait: if i == 0 { evt.wait } else { None },
+                    per_pass: None,
+                    text: None,
+                },
+            ));
+        }
+    }
+}
+
+/// Changes the waits before the events [`DelayRule`]s apply to, moving
+/// everything after along with them.
+struct Delays<'a, I> {
+    events: I,
+    plan: &'a Plan,
+    // when the last event was recorded and when it is played
+    last: Option<(Duration, Duration)>,
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Delays<'_, I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        let mut evt = self.events.next()?;
+        let recorded = evt.timestamp;
+        let (last_recorded, last_retimed) =
+            self.last.unwrap_or((Duration::ZERO, Duration::ZERO));
+        let mut gap = recorded.saturating_sub(last_recorded);
+        if let Some((scale, add)) = self.plan.delay(&evt.action) {
+            gap = gap.mul_f64(scale) + add;
+        }
+        evt.timestamp = last_retimed + gap;
+        self.last = Some((recorded, evt.timestamp));
+        Some(evt)
+    }
+}
+
+/// A small xorshift generator; playback timing doesn't need more.
+struct Rng(u64);
+
+impl Rng {
+    /// Seeded from the clock, so each playback differs.
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.subsec_nanos() as u64);
+        // Zero would stay zero.
+        Self(nanos | 1)
+    }
+
+    /// A number in [0, 1).
+    fn unit(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Picks the waits of events with a [`WaitRange`] from that range and
+/// varies the others by up to `pct` percent, and applies [`Randomize`].
+struct Random<I> {
+    events: I,
+    pct: f64,
+    randomize: Randomize,
+    // when the last event was recorded and when it is played
+    last: Option<(Duration, Duration)>,
+    // where the last move put the pointer, after jitter
+    pointer: Option<(f64, f64)>,
+    // events held back while a micro move goes first, last one first
+    pending: Vec<RecordedEvent>,
+    // added to every time, pausing before the pass
+    lead_in: Duration,
+    rng: Rng,
+}
+
+impl<I> Random<I> {
+    /// A number in [-1, 1).
+    fn signed(&mut self) -> f64 {
+        2.0 * self.rng.unit() - 1.0
+    }
+
+    /// `pos` moved by up to `r` pixels either way.
+    fn jitter(&mut self, (x, y): (f64, f64), r: f64) -> (f64, f64) {
+        (x + r * self.signed(), y + r * self.signed())
+    }
+}
+
+fn pointer_move(at: Duration, (x, y): (f64, f64)) -> RecordedEvent {
+    RecordedEvent {
+        action: MacroAction::Input(EventType::MouseMove { x, y }),
+        timestamp: at,
+        device: None,
+        wait: None,
+        per_pass: None,
+        text: None,
+    }
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Random<I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        if let Some(evt) = self.pending.pop() {
+            return Some(evt);
+        }
+        let mut evt = self.events.next()?;
+        if let MacroAction::Input(EventType::MouseMove { x, y }) = evt.action {
+            let (x, y) = self.jitter((x, y), self.randomize.position_px);
+            evt.action = MacroAction::Input(EventType::MouseMove { x, y });
+            self.pointer = Some((x, y));
+        }
+        let recorded = evt.timestamp;
+        let (last_recorded, last_retimed) =
+            self.last.unwrap_or((Duration::ZERO, Duration::ZERO));
+        let gap = recorded.saturating_sub(last_recorded);
+        let gap = match evt.wait {
+            Some(WaitRange { min, max }) if max > min => {
+                min + (max - min).mul_f64(self.rng.unit())
+            }
+            Some(WaitRange { min, .. }) => min,
+            None if self.pct > 0.0 => {
+                let unit = self.signed();
+                gap.mul_f64((1.0 + unit * self.pct / 100.0).max(0.0))
+            }
+            None => gap,
+        };
+        let last_retimed = last_retimed.max(self.lead_in);
+        evt.timestamp = last_retimed + gap;
+        self.last = Some((recorded, evt.timestamp));
+        let nudge = self.rng.unit() < self.randomize.micro_move_chance;
+        if let (true, Some(pointer)) = (nudge, self.pointer) {
+            // A nudge halfway through the wait, and back before the event
+            // unless it moves the pointer itself.
+            let r = self.randomize.position_px.max(1.0);
+            let nudged = self.jitter(pointer, r);
+            let moves = matches!(
+                evt.action,
+                MacroAction::Input(EventType::MouseMove { .. })
+            );
+            let at = evt.timestamp;
+            self.pending.push(evt);
+            if !moves {
+                self.pending.push(pointer_move(at, pointer));
+            }
+            return Some(pointer_move(last_retimed + gap / 2, nudged));
+        }
+        Some(evt)
+    }
+}
+
+/// Replays each burst of wheel events as a gesture covering the same
+/// distance in the same time, easing out the way inertial scrolling does,
+/// instead of the uneven steps a trackpad reports.
+struct Scrolls<I: Iterator> {
+    events: Peekable<I>,
+    pending: VecDeque<RecordedEvent>,
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Scrolls<I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        if let Some(evt) = self.pending.pop_front() {
+            return Some(evt);
+        }
+        let first = self.events.next()?;
+        let MacroAction::Input(EventType::Wheel { delta_x, delta_y }) =
+            first.action
+        else {
+            return Some(first);
+        };
+        let (mut dx, mut dy) = (delta_x, delta_y);
+        let mut end = first.timestamp;
+        while let Some(evt) = self.events.peek() {
+            let MacroAction::Input(EventType::Wheel { delta_x, delta_y }) =
+                evt.action
+            else {
+                break;
+            };
+            if evt.timestamp.saturating_sub(end) > MAX_SCROLL_GAP {
+                break;
+            }
+            dx += delta_x;
+            dy += delta_y;
+            end = evt.timestamp;
+            self.events.next();
+        }
+        if end == first.timestamp {
+            // Nothing to smooth out.
+            return Some(RecordedEvent {
+                action: MacroAction::Input(EventType::Wheel {
+                    delta_x: dx,
+                    delta_y: dy,
+                }),
+                ..first
+            });
+        }
+        let steps = dx.abs().max(dy.abs());
+        let duration = end - first.timestamp;
+        let (mut sent_x, mut sent_y) = (0, 0);
+        for step in 1..=steps {
+            let progress = step as f64 / steps as f64;
+            // Cubic ease-out, solved for when it reaches `progress`.
+            let at = 1.0 - (1.0 - progress).cbrt();
+            let to_x = (dx as f64 * progress).round() as i64;
+            let to_y = (dy as f64 * progress).round() as i64;
+            self.pending.push_back(RecordedEvent {
+                action: MacroAction::Input(EventType::Wheel {
+                    delta_x: to_x - sent_x,
+                    delta_y: to_y - sent_y,
+                }),
+                timestamp: first.timestamp + duration.mul_f64(at),
+                device: first.device.clone(),
+                wait: if step == 1 { first.wait } else { None },
+                per_pass: None,
+                text: None,
+            });
+            (sent_x, sent_y) = (to_x, to_y);
+        }
+        // A burst scrolling back to where it started has nothing to play.
+        self.pending.pop_front().or_else(|| self.next())
+    }
+}
+
+/// Re-times stretches of typing to a steady speed with a little variance,
+/// leaving the time between other events as recorded.
+struct Typing<I> {
+    events: I,
+    // time between key presses at the target speed
+    interval: Duration,
+    // whether the last event was a key, when it was recorded and when it
+    // is played
+    last: Option<(bool, Duration, Duration)>,
+    rng: Rng,
+}
+
+impl<I> Typing<I> {
+    fn new(events: I, wpm: f64) -> Self {
+        // A word is five characters.
+        let interval = Duration::from_secs_f64(12.0 / wpm);
+        Self {
+            events,
+            interval,
+            last: None,
+            rng: Rng(0x2545_f491_4f6c_dd1d),
+        }
+    }
+
+    /// A factor around 1 to vary the typing speed by.
+    fn jitter(&mut self) -> f64 {
+        1.0 + TYPING_VARIANCE * (2.0 * self.rng.unit() - 1.0)
+    }
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Typing<I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        let mut evt = self.events.next()?;
+        let recorded = evt.timestamp;
+        if let Some((was_key, last_recorded, last_retimed)) = self.last {
+            let gap = recorded.saturating_sub(last_recorded);
+            let typing = was_key && is_key(&evt) && gap <= MAX_TYPING_GAP;
+            let gap = match evt.action {
+                MacroAction::Input(EventType::KeyPress(key))
+                    if typing && !is_modifier(key) =>
+                {
+                    self.interval.mul_f64(self.jitter())
+                }
+                // Releases and modifiers follow closely, but not later than
+                // the recording had them.
+                _ if typing => gap.min(self.interval / 2),
+                _ => gap,
+            };
+            evt.timestamp = last_retimed + gap;
+        }
+        self.last = Some((is_key(&evt), recorded, evt.timestamp));
+        Some(evt)
+    }
+}
+
+fn is_key(evt: &RecordedEvent) -> bool {
+    matches!(
+        evt.action,
+        MacroAction::Input(EventType::KeyPress(_) | EventType::KeyRelease(_))
+    )
+}
+
+fn is_modifier(key: Key) -> bool {
+    matches!(
+        key,
+        Key::ShiftLeft
+            | Key::ShiftRight
+            | Key::ControlLeft
+            | Key::ControlRight
+            | Key::Alt
+            | Key::AltGr
+            | Key::MetaLeft
+            | Key::MetaRight
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(ms: u64, input: EventType) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Input(input),
+            timestamp: Duration::from_millis(ms),
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        }
+    }
+
+    fn remapping() -> PlaybackFilter {
+        PlaybackFilter {
+            skip_scroll: true,
+            remap_keys: vec![Remap {
+                from: Key::KeyA,
+                to: Key::KeyB,
+            }],
+            typing_wpm: Some(60.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn remaps_are_taken_out_of_the_filter() {
+        let mut filter = remapping();
+        assert!(filter.take_remaps().is_some());
+        assert!(filter.remap_keys.is_empty());
+        assert!(!filter.skip_scroll);
+        assert_eq!(filter.typing_wpm, Some(60.0));
+    }
+
+    #[test]
+    fn remaps_stay_where_the_filter_needs_recorded_events() {
+        let mut filter = PlaybackFilter {
+            layout: Some(Layout::Azerty),
+            ..remapping()
+        };
+        assert!(filter.take_remaps().is_none());
+        assert_eq!(filter.remap_keys.len(), 1);
+
+        let mut filter = PlaybackFilter {
+            remap_buttons: vec![Remap {
+                from: Button::Middle,
+                to: ButtonTarget::Keys(vec![Key::ControlLeft, Key::KeyW]),
+            }],
+            ..remapping()
+        };
+        assert!(filter.take_remaps().is_none());
+        assert!(filter.skip_scroll);
+    }
+
+    #[test]
+    fn taken_remaps_apply_inside_branches() {
+        let plan = remapping().take_remaps().unwrap();
+        let branch = RecordedEvent {
+            action: MacroAction::IfKeyHeld {
+                key: Key::KeyA,
+                then: vec![
+                    input(0, EventType::KeyPress(Key::KeyA)),
+                    input(5, EventType::Wheel {
+                        delta_x: 0,
+                        delta_y: 1,
+                    }),
+                ],
+                otherwise: Vec::new(),
+            },
+            ..input(0, EventType::KeyPress(Key::KeyC))
+        };
+        let Some(RecordedEvent {
+            action: MacroAction::IfKeyHeld { key, then, .. },
+            ..
+        }) = plan.apply_event(branch)
+        else {
+            panic!("the branch was skipped");
+        };
+        // The key tested is the one held, not one played.
+        assert_eq!(key, Key::KeyA);
+        assert!(matches!(
+            then[..],
+            [RecordedEvent {
+                action: MacroAction::Input(EventType::KeyPress(Key::KeyB)),
+                ..
+            }]
+        ));
+        let scroll = input(0, EventType::Wheel {
+            delta_x: 0,
+            delta_y: 1,
+        });
+        assert!(plan.apply_event(scroll).is_none());
+    }
+
+    fn typing(ms: u64, text: &str) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Type(text.to_string()),
+            ..input(ms, EventType::KeyPress(Key::KeyA))
+        }
+    }
+
+    #[test]
+    fn reads_the_environment_only_for_unfilled_placeholders() {
+        let events = vec![
+            input(0, EventType::KeyPress(Key::KeyA)),
+            typing(10, "{who}"),
+        ];
+        let params = Vars::from([("who".to_string(), "ann".to_string())]);
+        let filtered = Filtered::new(events, PlaybackFilter::default())
+            .with_params(params);
+        filtered.open().unwrap().for_each(drop);
+        assert!(filtered.env.vars.get().is_none());
+
+        let events = vec![typing(0, "{nobody}")];
+        let filtered = Filtered::new(events, PlaybackFilter::default());
+        filtered.open().unwrap().for_each(drop);
+        assert!(filtered.env.vars.get().is_some());
+    }
+
+    #[test]
+    fn a_missing_env_file_stops_playback() {
+        let filter = PlaybackFilter {
+            env_file: Some("no/such/file.env".into()),
+            ..Default::default()
+        };
+        assert!(Filtered::new(Vec::new(), filter).open().is_err());
+    }
+
+    fn checkpoint(ms: u64, name: &str) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Checkpoint(name.to_string()),
+            ..input(ms, EventType::KeyPress(Key::KeyA))
+        }
+    }
+
+    fn looping_from(name: &str) -> Filtered<Vec<RecordedEvent>> {
+        let events = vec![
+            input(0, EventType::KeyPress(Key::KeyA)),
+            input(10, EventType::KeyRelease(Key::KeyA)),
+            checkpoint(100, "again"),
+            input(150, EventType::KeyPress(Key::KeyB)),
+            input(160, EventType::KeyRelease(Key::KeyB)),
+        ];
+        let filter = PlaybackFilter {
+            loop_from: Some(name.to_string()),
+            ..Default::default()
+        };
+        Filtered::new(events, filter)
+    }
+
+    #[test]
+    fn later_passes_start_from_the_checkpoint() {
+        let filtered = looping_from("again");
+        assert_eq!(filtered.open().unwrap().count(), 5);
+        let later: Vec<_> = filtered.open().unwrap().collect();
+        let times: Vec<_> = later.iter().map(|e| e.timestamp).collect();
+        let ms = Duration::from_millis;
+        assert_eq!(times, [ms(0), ms(50), ms(60)]);
+        assert!(is_checkpoint(&later[0], "again"));
+        assert!(matches!(
+            later[1].action,
+            MacroAction::Input(EventType::KeyPress(Key::KeyB))
+        ));
+    }
+
+    #[test]
+    fn a_missing_checkpoint_fails_the_next_pass() {
+        let filtered = looping_from("nowhere");
+        assert_eq!(filtered.open().unwrap().count(), 5);
+        assert!(filtered.open().is_err());
+    }
+
+    #[test]
+    fn finds_checkpoints_in_branches() {
+        let events = vec![RecordedEvent {
+            action: MacroAction::IfKeyHeld {
+                key: Key::ShiftLeft,
+                then: Vec::new(),
+                otherwise: vec![checkpoint(0, "again")],
+            },
+            ..input(0, EventType::KeyPress(Key::KeyA))
+        }];
+        assert!(has_checkpoint(&events, "again"));
+        assert!(!has_checkpoint(&events, "nowhere"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Playback for programs embedding the player, controlled through a
+//! handle instead of the shared state the recorder itself uses.
+
+use crate::backend::InputBackend;
+use crate::player::{self, EventSource, PlaybackReport, Progress};
+use crate::state::{SharedState, State};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A playback running on its own thread.
+pub struct PlaybackHandle {
+    shared: Arc<Mutex<SharedState>>,
+    thread: JoinHandle<PlaybackReport>,
+    progress: Receiver<Progress>,
+}
+
+impl PlaybackHandle {
+    /// Starts playing `source` into `backend` at `speed`, over and over if
+    /// `looping`.
+    pub fn spawn<B, S>(backend: B, source: S, speed: f64, looping: bool) -> Self
+    where
+        B: InputBackend + Send + 'static,
+        S: EventSource + Send + 'static,
+    {
+        let (tx, progress) = mpsc::channel();
+        let mut state = SharedState::new();
+        state.speed = speed;
+        state.looping = looping;
+        state.progress = Some(tx);
+        state.set_state(State::Playing);
+        let shared = Arc::new(Mutex::new(state));
+        let thread = thread::spawn({
+            let shared = Arc::clone(&shared);
+            move || {
+                let mut backend = backend;
+                let report = player::run(&mut backend, &source, &shared, None);
+                let mut shared = shared.lock().unwrap();
+                shared.set_state(State::Idle);
+                // Ends the progress channel.
+                shared.progress = None;
+                report
+            }
+        });
+        Self {
+            shared,
+            thread,
+            progress,
+        }
+    }
+
+    pub fn pause(&self) {
+        self.set_state(State::Playing, State::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.set_state(State::Paused, State::Playing);
+    }
+
+    /// Stops playback as soon as it can; [`join`](Self::join) waits for it.
+    pub fn stop(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.state != State::Idle {
+            shared.set_state(State::Idle);
+        }
+    }
+
+    /// Changes whether playback loops, e.g. to let it end after this pass.
+    pub fn set_looping(&self, looping: bool) {
+        self.shared.lock().unwrap().looping = looping;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.lock().unwrap().state == State::Paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Progress after each event, ending once playback has.
+    pub fn progress(&self) -> &Receiver<Progress> {
+        &self.progress
+    }
+
+    /// Waits for playback to end and returns its report.
+    pub fn join(self) -> Result<PlaybackReport, String> {
+        self.thread
+            .join()
+            .map_err(|_| "the playback thread panicked".to_string())
+    }
+
+    fn set_state(&self, from: State, to: State) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.state == from {
+            shared.set_state(to);
+        }
+    }
+}
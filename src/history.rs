@@ -0,0 +1,161 @@
+//! A log of every recording and playback, one JSON line each, kept so
+//! unattended runs can be accounted for afterwards.
+
+use crate::calendar;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Recording,
+    Playback,
+}
+
+/// One recording or playback session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub kind: Kind,
+    /// The macro, slot or snippet recorded or played.
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub started: u64,
+    /// The same in local time, as the user saw it.
+    pub local_time: String,
+    pub duration: Duration,
+    /// How it ended, e.g. `Finished` or `Stopped`.
+    pub outcome: String,
+    /// Events recorded or injected.
+    pub events: usize,
+    /// Loop passes completed by a playback.
+    #[serde(default)]
+    pub passes: usize,
+}
+
+impl Entry {
+    /// An entry for a session starting now, filled in as it ends.
+    pub fn start(kind: Kind, name: String) -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        Self {
+            kind,
+            name,
+            started,
+            local_time: calendar::now().to_string(),
+            duration: Duration::ZERO,
+            outcome: String::new(),
+            events: 0,
+            passes: 0,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Recording => "recorded",
+            Kind::Playback => "played",
+        };
+        write!(
+            f,
+            "{}  {} {} for {:.1} s: {}, {} events",
+            self.local_time,
+            kind,
+            self.name,
+            self.duration.as_secs_f64(),
+            self.outcome,
+            self.events
+        )?;
+        if self.passes > 1 {
+            write!(f, ", {} passes", self.passes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds `entry` to the log at `path`, creating it if needed.
+pub fn append(path: &Path, entry: &Entry) -> Result<(), String> {
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads the log at `path`, oldest first. A missing log is empty.
+pub fn read(path: &Path) -> Result<Vec<Entry>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let mut entries = Vec::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), n + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Which entries `history` shows.
+#[derive(Debug, Default)]
+pub struct Query {
+    /// Only entries whose name contains this.
+    pub name: Option<String>,
+    /// Only entries started within this long of now.
+    pub since: Option<Duration>,
+}
+
+impl Query {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let name = self.name.as_deref();
+        let started = UNIX_EPOCH + Duration::from_secs(entry.started);
+        name.is_none_or(|name| entry.name.contains(name))
+            && self.since.is_none_or(|since| {
+                SystemTime::now()
+                    .duration_since(started)
+                    .map_or(true, |age| age <= since)
+            })
+    }
+}
+
+/// Parses how far back to look, e.g. `30m`, `12h` or `7d`.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid age '{}', expected e.g. 12h", s);
+    let unit = match s.chars().last() {
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let value: f64 = s[..s.len() - 1].trim().parse().map_err(|_| invalid())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(value * unit as f64))
+}
+
+/// `age` in its largest whole unit, e.g. `3h`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
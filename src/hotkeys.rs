@@ -0,0 +1,253 @@
+//! Hotkey bindings, the actions they trigger and diagnostics for them.
+//!
+//! Capture doesn't grab keys, so a hotkey also reaches whatever has focus
+//! and a key the OS handles itself may never reach us at all.
+
+use crate::triggers::Sequence;
+use rdev::Key;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Something a hotkey or another trigger can do.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    PlayPause,
+    Stop,
+    /// Stops playback once the loop pass being played is done, as the
+    /// stop hotkey does with Shift held.
+    SoftStop,
+    ToggleLoop,
+    Record,
+    ToggleElements,
+    ToggleWindow,
+    CycleRecordFilter,
+    /// Prints what is playing and queued.
+    Status,
+    /// Turns pick mode on, or off, copying the pointer position or, with
+    /// Shift, adding a click there to the recording.
+    Pick,
+    /// Adds a click at the pointer to the end of the recording.
+    AddClick,
+    /// Marks where paused playback is as the start, or end, of a section
+    /// to play over and over. With Shift, clears both marks.
+    LoopStart,
+    LoopEnd,
+    /// Index into the slots, shown to users counting from 1.
+    PlaySlot(usize),
+    RecordSlot(usize),
+}
+
+impl Action {
+    const FIXED: [(Action, &'static str); 13] = [
+        (Action::PlayPause, "play-pause"),
+        (Action::Stop, "stop"),
+        (Action::SoftStop, "soft-stop"),
+        (Action::ToggleLoop, "toggle-loop"),
+        (Action::Record, "record"),
+        (Action::ToggleElements, "toggle-elements"),
+        (Action::ToggleWindow, "toggle-window"),
+        (Action::CycleRecordFilter, "cycle-record-filter"),
+        (Action::Status, "status"),
+        (Action::Pick, "pick"),
+        (Action::AddClick, "add-click"),
+        (Action::LoopStart, "loop-start"),
+        (Action::LoopEnd, "loop-end"),
+    ];
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::PlaySlot(slot) => write!(f, "play-slot-{}", slot + 1),
+            Action::RecordSlot(slot) => write!(f, "record-slot-{}", slot + 1),
+            fixed => {
+                let (_, name) = Self::FIXED
+                    .iter()
+                    .find(|(action, _)| action == fixed)
+                    .expect("every other action has a fixed name");
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((action, _)) = Self::FIXED.iter().find(|(_, n)| *n == s) {
+            return Ok(*action);
+        }
+        let slot = |n: &str| match n.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n - 1),
+            _ => Err(format!("invalid slot in action '{}'", s)),
+        };
+        if let Some(n) = s.strip_prefix("play-slot-") {
+            return Ok(Action::PlaySlot(slot(n)?));
+        }
+        if let Some(n) = s.strip_prefix("record-slot-") {
+            return Ok(Action::RecordSlot(slot(n)?));
+        }
+        Err(format!(
+            "unknown action '{}' (play-pause, stop, soft-stop, toggle-loop, \
+             record, toggle-elements, toggle-window, cycle-record-filter, \
+             status, pick, add-click, loop-start, loop-end, play-slot-N, \
+             record-slot-N)",
+            s
+        ))
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A key triggering an action whenever it is pressed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hotkey {
+    pub key: Key,
+    pub action: Action,
+}
+
+/// Hotkeys used unless others are configured.
+pub const DEFAULT: &[(Key, Action)] = &[
+    (Key::F1, Action::PlayPause),
+    (Key::F2, Action::Stop),
+    (Key::F3, Action::ToggleLoop),
+    (Key::F4, Action::Record),
+    (Key::F9, Action::ToggleElements),
+    (Key::F10, Action::ToggleWindow),
+    (Key::F11, Action::CycleRecordFilter),
+    (Key::ScrollLock, Action::Pick),
+    (Key::Pause, Action::AddClick),
+];
+
+/// Keys other software commonly takes for itself.
+const RESERVED: &[(Key, &str)] = &[
+    (Key::F1, "opens help in many applications"),
+    #[cfg(target_os = "macos")]
+    (Key::F3, "opens Mission Control"),
+    #[cfg(target_os = "macos")]
+    (Key::F4, "opens Launchpad"),
+    #[cfg(target_os = "windows")]
+    (Key::F10, "activates the menu bar"),
+    #[cfg(target_os = "macos")]
+    (Key::F11, "shows the desktop"),
+    #[cfg(not(target_os = "macos"))]
+    (Key::F11, "toggles full screen in browsers"),
+    (Key::F12, "opens developer tools in browsers"),
+    (Key::PrintScreen, "takes a screenshot"),
+];
+
+/// All hotkeys with their actions, slot hotkeys after the others.
+pub fn bindings(
+    hotkeys: &[(Key, Action)],
+    slot_keys: &[Key],
+) -> Vec<(Key, String)> {
+    let hotkeys = hotkeys
+        .iter()
+        .map(|(key, action)| (*key, action.to_string()));
+    let slots = slot_keys.iter().enumerate().map(|(i, key)| {
+        (*key, format!("play slot {0}, Shift to record into slot {0}", i + 1))
+    });
+    hotkeys.chain(slots).collect()
+}
+
+/// Warnings about keys bound twice or reserved by other software.
+pub fn conflicts(bindings: &[(Key, String)]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (i, (key, action)) in bindings.iter().enumerate() {
+        if let Some((_, first)) = bindings[..i].iter().find(|(k, _)| k == key) {
+            warnings.push(format!(
+                "{:?} is bound to both \"{}\" and \"{}\"; only the first works",
+                key, first, action
+            ));
+        }
+    }
+    for (key, use_) in RESERVED {
+        if bindings.iter().any(|(k, _)| k == key) {
+            warnings.push(format!(
+                "{:?} {}, so it may not reach the recorder or may also act \
+                 in the focused window",
+                key, use_
+            ));
+        }
+    }
+    warnings
+}
+
+/// Warnings about sequences containing keys that are hotkeys themselves,
+/// which then fire along the way.
+pub fn sequence_conflicts(
+    bindings: &[(Key, String)],
+    sequences: &[Sequence],
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for sequence in sequences {
+        for (key, action) in bindings {
+            if sequence.keys.contains(key) {
+                warnings.push(format!(
+                    "the sequence {} also triggers \"{}\" with {:?}",
+                    sequence, action, key
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Waits for each hotkey to arrive from `presses` and reports which were
+/// received, giving up after `timeout`. Returns whether all were received.
+pub fn check(
+    bindings: &[(Key, String)],
+    presses: Receiver<Key>,
+    timeout: Duration,
+) -> bool {
+    let mut pending: Vec<&(Key, String)> = Vec::new();
+    for binding in bindings {
+        if !pending.iter().any(|(key, _)| *key == binding.0) {
+            pending.push(binding);
+        }
+    }
+    println!("Press each hotkey once ({} s timeout):", timeout.as_secs());
+    for (key, action) in &pending {
+        println!("  {:?}: {}", key, action);
+    }
+
+    let deadline = Instant::now() + timeout;
+    while !pending.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match presses.recv_timeout(remaining) {
+            Ok(key) => {
+                if let Some(i) = pending.iter().position(|(k, _)| *k == key) {
+                    println!("Received {:?}.", key);
+                    pending.remove(i);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => {
+                println!("Input capture stopped.");
+                break;
+            }
+        }
+    }
+
+    if pending.is_empty() {
+        println!("All hotkeys were received.");
+        return true;
+    }
+    println!("Never received:");
+    for (key, action) in &pending {
+        println!("  {:?}: {}", key, action);
+    }
+    false
+}
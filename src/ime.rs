@@ -0,0 +1,74 @@
+//! Recording text a key press typed that pressing the key again wouldn't,
+//! e.g. an unfinished dead key followed by a letter, or the characters of
+//! a layout typing a script straight from its keys. Such presses are
+//! recorded as typed text instead.
+//!
+//! Input methods, e.g. for Chinese or Japanese, compose text inside the
+//! application, where the capture hooks don't see it: they only see the
+//! keys pressed, and nothing tells them what was committed. Those keys are
+//! recorded as they are, and play back the same text where the same input
+//! method is active.
+
+/// Scripts typed through an input method, unless a layout types them:
+/// Hangul jamo, CJK symbols, kana and ideographs, Hangul syllables,
+/// compatibility ideographs, full-width forms and the supplementary
+/// ideographs.
+const COMPOSED: &[(char, char)] = &[
+    ('\u{1100}', '\u{11FF}'),
+    ('\u{2E80}', '\u{9FFF}'),
+    ('\u{AC00}', '\u{D7AF}'),
+    ('\u{F900}', '\u{FAFF}'),
+    ('\u{FF00}', '\u{FFEF}'),
+    ('\u{20000}', '\u{3FFFF}'),
+];
+
+/// The text a key press committed, if `name`, what the capture hook says
+/// it typed, is more than a single key types: several characters at once,
+/// or a script otherwise typed through an input method.
+pub fn committed(name: Option<&str>) -> Option<&str> {
+    let text = name?;
+    if text.chars().any(char::is_control) {
+        return None;
+    }
+    let composed = text.chars().nth(1).is_some()
+        || text.chars().any(|c| {
+            COMPOSED
+                .iter()
+                .any(|(first, last)| (*first..=*last).contains(&c))
+        });
+    composed.then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_keys_are_not_committed_text() {
+        assert_eq!(committed(None), None);
+        assert_eq!(committed(Some("")), None);
+        assert_eq!(committed(Some("a")), None);
+        assert_eq!(committed(Some("é")), None);
+    }
+
+    #[test]
+    fn several_characters_are() {
+        assert_eq!(committed(Some("^a")), Some("^a"));
+        assert_eq!(committed(Some("日本")), Some("日本"));
+    }
+
+    #[test]
+    fn composed_scripts_are() {
+        assert_eq!(committed(Some("あ")), Some("あ"));
+        assert_eq!(committed(Some("한")), Some("한"));
+        assert_eq!(committed(Some("Ａ")), Some("Ａ"));
+    }
+
+    #[test]
+    fn control_characters_are_not() {
+        // Shortcuts, e.g. Ctrl+Q, and keys like Return and Backspace.
+        assert_eq!(committed(Some("\u{11}")), None);
+        assert_eq!(committed(Some("\r")), None);
+        assert_eq!(committed(Some("a\u{8}")), None);
+    }
+}
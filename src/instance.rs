@@ -0,0 +1,139 @@
+//! Keeping to one running recorder. Two would inject every event twice and
+//! fight over the hotkeys.
+//!
+//! The running recorder holds a port on the loopback interface. Another
+//! one finds it taken and either gives up or asks the first to shut down
+//! and waits for the port to come free. The port goes with the process
+//! however it ends, so a crash leaves nothing stale behind. The `status`
+//! command asks on the same port.
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Port on the loopback interface the running recorder holds.
+pub const PORT: u16 = 47601;
+
+const SHUTDOWN: &str = "shutdown";
+const STATUS: &str = "status";
+
+/// How long the running recorder gets to shut down when taken over.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+/// How long a status request is waited for, either way.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The claim on being the running recorder, held until the process exits.
+pub struct Instance {
+    listener: TcpListener,
+}
+
+impl Instance {
+    /// Answers on a background thread: status requests with the line
+    /// `status` gives, and another recorder taking over by calling
+    /// `shutdown`, which should end the process.
+    pub fn serve<F, S>(self, shutdown: F, status: S)
+    where
+        F: FnOnce() + Send + 'static,
+        S: Fn() -> String + Send + 'static,
+    {
+        thread::spawn(move || {
+            for stream in self.listener.incoming().flatten() {
+                // A client that sends nothing mustn't hold up the next.
+                let _ = stream.set_read_timeout(Some(STATUS_TIMEOUT));
+                let mut line = String::new();
+                let _ = BufReader::new(&stream).read_line(&mut line);
+                match line.trim() {
+                    SHUTDOWN => {
+                        let _ = writeln!(&stream, "ok");
+                        shutdown();
+                        return;
+                    }
+                    STATUS => {
+                        let _ = writeln!(&stream, "{}", status());
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+}
+
+/// Claims being the running recorder. If another one is running this fails,
+/// unless `takeover` is set, in which case that one is asked to shut down.
+pub fn claim(takeover: bool) -> Result<Instance, String> {
+    let bind = || TcpListener::bind((Ipv4Addr::LOCALHOST, PORT));
+    match bind() {
+        Ok(listener) => return Ok(Instance { listener }),
+        Err(e) if e.kind() != ErrorKind::AddrInUse => {
+            return Err(format!("cannot claim port {}: {}", PORT, e))
+        }
+        Err(_) if !takeover => {
+            return Err(
+                "another recorder is already running; pass --takeover to \
+                 replace it"
+                    .into(),
+            )
+        }
+        Err(_) => {}
+    }
+    println!("Asking the running recorder to shut down...");
+    request_shutdown()?;
+    let started = Instant::now();
+    loop {
+        match bind() {
+            Ok(listener) => return Ok(Instance { listener }),
+            Err(_) if started.elapsed() < TAKEOVER_TIMEOUT => {
+                thread::sleep(RETRY_DELAY)
+            }
+            Err(e) => {
+                return Err(format!(
+                    "the running recorder didn't shut down: {}",
+                    e
+                ))
+            }
+        }
+    }
+}
+
+/// Asks the running recorder for its status line.
+pub fn status() -> Result<String, String> {
+    let answer = request(STATUS, STATUS_TIMEOUT).map_err(|e| {
+        if e.kind() == ErrorKind::ConnectionRefused {
+            "no recorder is running".to_string()
+        } else {
+            format!("cannot reach the running recorder: {}", e)
+        }
+    })?;
+    if answer.is_empty() {
+        return Err(format!(
+            "port {} is taken by something other than a recorder",
+            PORT
+        ));
+    }
+    Ok(answer)
+}
+
+/// Sends `line` to the running recorder and reads its answer.
+fn request(line: &str, timeout: Duration) -> io::Result<String> {
+    let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PORT))?;
+    stream.set_read_timeout(Some(timeout))?;
+    writeln!(&stream, "{}", line)?;
+    let mut answer = String::new();
+    BufReader::new(&stream).read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+fn request_shutdown() -> Result<(), String> {
+    let answer = request(SHUTDOWN, TAKEOVER_TIMEOUT).map_err(|e| {
+        format!("cannot reach the running recorder on port {}: {}", PORT, e)
+    })?;
+    if answer != "ok" {
+        return Err(format!(
+            "port {} is taken by something other than a recorder",
+            PORT
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Replacing clicks on known window controls with the keys that reach them,
+//! so a recording can be played where screen positions can't be trusted,
+//! e.g. on another resolution or with a different theme.
+//!
+//! The controls come from the config, usually a profile per application,
+//! e.g. a dialog's OK button as `Tab Tab Return`.
+
+use crate::accessibility::ElementTarget;
+use crate::config;
+use crate::event::{MacroAction, RecordedEvent};
+use crate::screenshot::Region;
+use rdev::{Button, EventType, Key};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Time between the keys replacing a click.
+const STEP: Duration = Duration::from_millis(30);
+
+/// A control and the keys that activate it. Clicks on it are recognized by
+/// where they land or by the element they were recorded on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Control {
+    /// Where the control is on screen in the recordings.
+    #[serde(default)]
+    pub region: Option<Region>,
+    /// Name of the element, for clicks recorded on elements. Case doesn't
+    /// matter.
+    #[serde(default)]
+    pub element: Option<String>,
+    pub keys: Strokes,
+}
+
+impl Control {
+    fn contains(&self, (x, y): (f64, f64)) -> bool {
+        self.region.is_some_and(|r| {
+            let (left, top) = (r.x as f64, r.y as f64);
+            (left..left + r.width as f64).contains(&x)
+                && (top..top + r.height as f64).contains(&y)
+        })
+    }
+
+    fn is(&self, target: &ElementTarget) -> bool {
+        self.element
+            .as_ref()
+            .is_some_and(|name| name.eq_ignore_ascii_case(&target.name))
+    }
+}
+
+/// Keys pressed one after the other, with `+` joining keys held together,
+/// e.g. `Tab Tab Return` or `Alt+KeyO`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Strokes(Vec<Vec<Key>>);
+
+impl TryFrom<String> for Strokes {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let strokes = s
+            .split_whitespace()
+            .map(|stroke| stroke.split('+').map(config::parse_name).collect())
+            .collect::<Result<Vec<Vec<Key>>, String>>()?;
+        if strokes.is_empty() {
+            return Err("no keys given for a control".into());
+        }
+        Ok(Strokes(strokes))
+    }
+}
+
+impl Strokes {
+    /// Events pressing the keys from `at` on, a step apart but none later
+    /// than `until`, so the recording stays in order.
+    fn events(&self, at: Duration, until: Duration) -> Vec<RecordedEvent> {
+        let mut types = Vec::new();
+        for stroke in &self.0 {
+            types.extend(stroke.iter().map(|k| EventType::KeyPress(*k)));
+            types
+                .extend(stroke.iter().rev().map(|k| EventType::KeyRelease(*k)));
+        }
+        types
+            .into_iter()
+            .enumerate()
+            .map(|(n, event_type)| RecordedEvent {
+                action: MacroAction::Input(event_type),
+                timestamp: (at + STEP * n as u32).min(until),
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            })
+            .collect()
+    }
+}
+
+/// Replaces left clicks on `controls` with their keys. Pointer moves stay,
+/// as other clicks may need them. Returns how many clicks were replaced.
+pub fn keyboard_only(
+    events: &mut Vec<RecordedEvent>,
+    controls: &[Control],
+) -> usize {
+    let mut recorded = std::mem::take(events).into_iter().peekable();
+    let mut pointer = (0.0, 0.0);
+    // whether the release of a replaced click is still to come
+    let mut swallow_release = false;
+    let mut replaced = 0;
+    while let Some(evt) = recorded.next() {
+        let control = match &evt.action {
+            MacroAction::Input(EventType::MouseMove { x, y }) => {
+                pointer = (*x, *y);
+                None
+            }
+            MacroAction::Input(EventType::ButtonPress(Button::Left)) => {
+                controls.iter().find(|c| c.contains(pointer))
+            }
+            MacroAction::Input(EventType::ButtonRelease(Button::Left))
+                if swallow_release =>
+            {
+                swallow_release = false;
+                continue;
+            }
+            MacroAction::ClickElement {
+                target,
+                button: Button::Left,
+            } => controls.iter().find(|c| c.is(target)),
+            _ => None,
+        };
+        let Some(control) = control else {
+            events.push(evt);
+            continue;
+        };
+        swallow_release = matches!(evt.action, MacroAction::Input(_));
+        let until = recorded.peek().map_or(Duration::MAX, |e| e.timestamp);
+        let mut keys = control.keys.events(evt.timestamp, until);
+        keys[0].wait = evt.wait;
+        events.extend(keys);
+        replaced += 1;
+    }
+    replaced
+}
@@ -0,0 +1,438 @@
+//! Playing a recording with another keyboard layout than the one it was
+//! recorded with, e.g. recorded on QWERTY and played on AZERTY.
+//!
+//! Keys are recorded by where they are on the keyboard, and key presses
+//! also keep the text they typed. Playing with `--layout`, each press moves
+//! to the key typing the same text in that layout, with Shift pressed or
+//! let go around it where the two differ. Recordings made before presses
+//! kept their text are taken as typed on a US layout.
+//!
+//! This only holds where keys are played by position: through uinput or as
+//! scan codes. Other backends press virtual keys or what a key types, which
+//! the layout in use already puts in the right place.
+
+use crate::backend::BackendKind;
+use crate::event::{MacroAction, RecordedEvent};
+use crate::preview;
+use rdev::{EventType, Key};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// Keys typing something else on a French AZERTY layout than on US QWERTY,
+/// without and with Shift. Dead keys are left out.
+const AZERTY: &[(Key, char, char)] = &[
+    (Key::KeyQ, 'a', 'A'),
+    (Key::KeyW, 'z', 'Z'),
+    (Key::KeyA, 'q', 'Q'),
+    (Key::KeyZ, 'w', 'W'),
+    (Key::SemiColon, 'm', 'M'),
+    (Key::KeyM, ',', '?'),
+    (Key::Comma, ';', '.'),
+    (Key::Dot, ':', '/'),
+    (Key::Slash, '!', '§'),
+    (Key::Num1, '&', '1'),
+    (Key::Num2, 'é', '2'),
+    (Key::Num3, '"', '3'),
+    (Key::Num4, '\'', '4'),
+    (Key::Num5, '(', '5'),
+    (Key::Num6, '-', '6'),
+    (Key::Num7, 'è', '7'),
+    (Key::Num8, '_', '8'),
+    (Key::Num9, 'ç', '9'),
+    (Key::Num0, 'à', '0'),
+    (Key::Minus, ')', '°'),
+    (Key::Equal, '=', '+'),
+    (Key::RightBracket, '$', '£'),
+    (Key::Quote, 'ù', '%'),
+    (Key::BackSlash, '*', 'µ'),
+    (Key::IntlBackslash, '<', '>'),
+];
+
+/// The same for a German QWERTZ layout.
+const QWERTZ: &[(Key, char, char)] = &[
+    (Key::KeyY, 'z', 'Z'),
+    (Key::KeyZ, 'y', 'Y'),
+    (Key::Num2, '2', '"'),
+    (Key::Num3, '3', '§'),
+    (Key::Num6, '6', '&'),
+    (Key::Num7, '7', '/'),
+    (Key::Num8, '8', '('),
+    (Key::Num9, '9', ')'),
+    (Key::Num0, '0', '='),
+    (Key::Minus, 'ß', '?'),
+    (Key::LeftBracket, 'ü', 'Ü'),
+    (Key::RightBracket, '+', '*'),
+    (Key::SemiColon, 'ö', 'Ö'),
+    (Key::Quote, 'ä', 'Ä'),
+    (Key::BackSlash, '#', '\''),
+    (Key::Comma, ',', ';'),
+    (Key::Dot, '.', ':'),
+    (Key::Slash, '-', '_'),
+    (Key::IntlBackslash, '<', '>'),
+];
+
+/// A keyboard layout recordings can be played with.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Layout {
+    /// US QWERTY.
+    Qwerty,
+    /// French AZERTY.
+    Azerty,
+    /// German QWERTZ.
+    Qwertz,
+}
+
+impl Layout {
+    const ALL: [Layout; 3] = [Layout::Qwerty, Layout::Azerty, Layout::Qwertz];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Qwerty => "qwerty",
+            Layout::Azerty => "azerty",
+            Layout::Qwertz => "qwertz",
+        }
+    }
+
+    fn differences(self) -> &'static [(Key, char, char)] {
+        match self {
+            Layout::Qwerty => &[],
+            Layout::Azerty => AZERTY,
+            Layout::Qwertz => QWERTZ,
+        }
+    }
+
+    /// The key typing `c` in this layout and whether it needs Shift.
+    fn char_key(self, c: char) -> Option<(Key, bool)> {
+        let differences = self.differences();
+        let found = differences.iter().find_map(|(key, plain, shifted)| {
+            if c == *plain {
+                Some((*key, false))
+            } else if c == *shifted {
+                Some((*key, true))
+            } else {
+                None
+            }
+        });
+        found.or_else(|| {
+            preview::char_key(c)
+                .filter(|(key, _)| differences.iter().all(|(k, ..)| k != key))
+        })
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|l| l.name() == s)
+            .ok_or_else(|| {
+                format!("unknown layout '{}' (qwerty, azerty, qwertz)", s)
+            })
+    }
+}
+
+impl TryFrom<String> for Layout {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Whether `backend` presses keys by where they are on the keyboard, which
+/// is what retargeting them to a layout moves them by.
+pub fn is_positional(backend: BackendKind) -> bool {
+    matches!(backend, BackendKind::Uinput | BackendKind::ScanCode)
+}
+
+/// Moves key presses to the keys typing the same text in `layout`.
+/// Characters typed with AltGr and keys typing nothing stay where they
+/// are.
+pub(crate) struct Retarget<I> {
+    events: I,
+    layout: Layout,
+    /// The Shift key held as recorded.
+    shift: Option<Key>,
+    alt_gr: bool,
+    /// Keys down that were moved, and where to, so their release follows.
+    moved: Vec<(Key, Key)>,
+    pending: VecDeque<RecordedEvent>,
+}
+
+impl<I> Retarget<I> {
+    pub(crate) fn new(events: I, layout: Layout) -> Self {
+        Self {
+            events,
+            layout,
+            shift: None,
+            alt_gr: false,
+            moved: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = RecordedEvent>> Iterator for Retarget<I> {
+    type Item = RecordedEvent;
+
+    fn next(&mut self) -> Option<RecordedEvent> {
+        if let Some(evt) = self.pending.pop_front() {
+            return Some(evt);
+        }
+        let mut evt = self.events.next()?;
+        let MacroAction::Input(input) = evt.action else {
+            return Some(evt);
+        };
+        match input {
+            EventType::KeyPress(key @ (Key::ShiftLeft | Key::ShiftRight)) => {
+                self.shift = Some(key)
+            }
+            EventType::KeyRelease(Key::ShiftLeft | Key::ShiftRight) => {
+                self.shift = None
+            }
+            EventType::KeyPress(Key::AltGr) => self.alt_gr = true,
+            EventType::KeyRelease(Key::AltGr) => self.alt_gr = false,
+            EventType::KeyPress(key) if !self.alt_gr => {
+                let shift = self.shift.is_some();
+                let Some((target, wanted)) = typed(&evt, key, shift)
+                    .and_then(|c| self.layout.char_key(c))
+                else {
+                    return Some(evt);
+                };
+                // Held keys repeat their press; the release comes once.
+                self.moved.retain(|(k, _)| *k != key);
+                self.moved.push((key, target));
+                evt.action = MacroAction::Input(EventType::KeyPress(target));
+                if wanted == shift {
+                    return Some(evt);
+                }
+                let toggle = self.shift.unwrap_or(Key::ShiftLeft);
+                let (before, after) = if wanted {
+                    (EventType::KeyPress(toggle), EventType::KeyRelease(toggle))
+                } else {
+                    (EventType::KeyRelease(toggle), EventType::KeyPress(toggle))
+                };
+                let before = RecordedEvent {
+                    action: MacroAction::Input(before),
+                    text: None,
+                    ..evt.clone()
+                };
+                evt.wait = None;
+                self.pending.push_back(RecordedEvent {
+                    action: MacroAction::Input(after),
+                    text: None,
+                    ..evt.clone()
+                });
+                self.pending.push_front(evt);
+                return Some(before);
+            }
+            EventType::KeyRelease(key) => {
+                if let Some(i) = self.moved.iter().position(|(k, _)| *k == key)
+                {
+                    let (_, target) = self.moved.swap_remove(i);
+                    evt.action =
+                        MacroAction::Input(EventType::KeyRelease(target));
+                }
+            }
+            _ => {}
+        }
+        Some(evt)
+    }
+}
+
+/// The character a press of `key` typed: its recorded text, or what it
+/// types on a US layout if there is none. Shortcuts record a control
+/// character, which counts as its letter so they follow the letter too.
+fn typed(evt: &RecordedEvent, key: Key, shift: bool) -> Option<char> {
+    // Keys typing nothing, e.g. Return, have nowhere else to go.
+    let types = preview::key_char(key, false).is_some()
+        || Layout::ALL
+            .iter()
+            .any(|l| l.differences().iter().any(|(k, ..)| *k == key));
+    if !types {
+        return None;
+    }
+    let Some(text) = &evt.text else {
+        return preview::key_char(key, shift);
+    };
+    let mut chars = text.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    match c as u32 {
+        n @ 1..=26 => {
+            let letter = char::from(b'a' + n as u8 - 1);
+            Some(if shift {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            })
+        }
+        _ => Some(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn key(input: EventType, text: Option<&str>) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Input(input),
+            timestamp: Duration::ZERO,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: text.map(str::to_string),
+        }
+    }
+
+    fn press(k: Key, text: &str) -> RecordedEvent {
+        key(EventType::KeyPress(k), Some(text))
+    }
+
+    fn release(k: Key) -> RecordedEvent {
+        key(EventType::KeyRelease(k), None)
+    }
+
+    fn modifier(input: EventType) -> RecordedEvent {
+        key(input, None)
+    }
+
+    fn retarget(events: Vec<RecordedEvent>, layout: Layout) -> Vec<EventType> {
+        Retarget::new(events.into_iter(), layout)
+            .map(|evt| match evt.action {
+                MacroAction::Input(input) => input,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unshifted_keys_move_to_the_same_text() {
+        let played = retarget(
+            vec![press(Key::KeyQ, "q"), release(Key::KeyQ)],
+            Layout::Azerty,
+        );
+        assert_eq!(
+            played,
+            [EventType::KeyPress(Key::KeyA), EventType::KeyRelease(Key::KeyA)]
+        );
+    }
+
+    #[test]
+    fn shifted_keys_keep_shift_held() {
+        let played = retarget(
+            vec![
+                modifier(EventType::KeyPress(Key::ShiftLeft)),
+                press(Key::KeyQ, "Q"),
+                release(Key::KeyQ),
+                modifier(EventType::KeyRelease(Key::ShiftLeft)),
+            ],
+            Layout::Azerty,
+        );
+        assert_eq!(
+            played,
+            [
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::KeyA),
+                EventType::KeyRelease(Key::KeyA),
+                EventType::KeyRelease(Key::ShiftLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_is_pressed_or_let_go_where_the_layouts_differ() {
+        // A digit needs Shift on AZERTY.
+        let played = retarget(vec![press(Key::Num1, "1")], Layout::Azerty);
+        assert_eq!(
+            played,
+            [
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::Num1),
+                EventType::KeyRelease(Key::ShiftLeft),
+            ]
+        );
+        // `!` doesn't.
+        let played = retarget(
+            vec![
+                modifier(EventType::KeyPress(Key::ShiftRight)),
+                press(Key::Num1, "!"),
+            ],
+            Layout::Azerty,
+        );
+        assert_eq!(
+            played,
+            [
+                EventType::KeyPress(Key::ShiftRight),
+                EventType::KeyRelease(Key::ShiftRight),
+                EventType::KeyPress(Key::Slash),
+                EventType::KeyPress(Key::ShiftRight),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_typed_with_alt_gr_stay() {
+        let played = retarget(
+            vec![
+                modifier(EventType::KeyPress(Key::AltGr)),
+                press(Key::KeyQ, "@"),
+                release(Key::KeyQ),
+                modifier(EventType::KeyRelease(Key::AltGr)),
+            ],
+            Layout::Azerty,
+        );
+        assert_eq!(
+            played,
+            [
+                EventType::KeyPress(Key::AltGr),
+                EventType::KeyPress(Key::KeyQ),
+                EventType::KeyRelease(Key::KeyQ),
+                EventType::KeyRelease(Key::AltGr),
+            ]
+        );
+    }
+
+    #[test]
+    fn shortcuts_follow_their_letter() {
+        // Ctrl+Q records the control character it types.
+        let played = retarget(
+            vec![
+                modifier(EventType::KeyPress(Key::ControlLeft)),
+                press(Key::KeyQ, "\u{11}"),
+                release(Key::KeyQ),
+            ],
+            Layout::Azerty,
+        );
+        assert_eq!(
+            played,
+            [
+                EventType::KeyPress(Key::ControlLeft),
+                EventType::KeyPress(Key::KeyA),
+                EventType::KeyRelease(Key::KeyA),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_typing_nothing_stay() {
+        let played = retarget(vec![press(Key::Return, "\r")], Layout::Qwertz);
+        assert_eq!(played, [EventType::KeyPress(Key::Return)]);
+    }
+
+    #[test]
+    fn only_positional_backends_retarget() {
+        assert!(is_positional(BackendKind::Uinput));
+        assert!(is_positional(BackendKind::ScanCode));
+        assert!(!is_positional(BackendKind::Enigo));
+        assert!(!is_positional(BackendKind::Mock));
+    }
+}
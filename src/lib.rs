@@ -0,0 +1,58 @@
+pub mod accel;
+pub mod accessibility;
+pub mod ahk;
+#[cfg(feature = "async")]
+pub mod async_player;
+pub mod backend;
+pub mod bench;
+pub mod binary;
+pub mod branch;
+pub mod background;
+pub mod calendar;
+pub mod capture;
+pub mod clipboard;
+pub mod config;
+pub mod demo;
+pub mod edit;
+#[cfg(target_os = "linux")]
+pub mod evdev;
+pub mod event;
+pub mod export;
+pub mod filter;
+pub mod handle;
+pub mod history;
+pub mod hotkeys;
+pub mod ime;
+pub mod instance;
+pub mod keynav;
+pub mod layout;
+pub mod library;
+pub mod monitor;
+pub mod observe;
+pub mod params;
+pub mod permissions;
+pub mod pick;
+pub mod player;
+pub mod preview;
+pub mod privileges;
+pub mod remote;
+pub mod sandbox;
+pub mod sanitize;
+pub mod scancode;
+pub mod screenshot;
+pub mod service;
+pub mod session;
+pub mod simplify;
+pub mod snippets;
+pub mod state;
+pub mod status;
+pub mod stream;
+pub mod synthetic;
+pub mod timing;
+pub mod trace;
+pub mod triggers;
+#[cfg(target_os = "linux")]
+pub mod uinput;
+pub mod vars;
+pub mod wait;
+pub mod webtest;
@@ -0,0 +1,520 @@
+//! A directory of named macros with tags and descriptions.
+//!
+//! Each macro is one JSON file named after it. The interactive mode also
+//! keeps a copy of its latest in-memory recording here, so it can be saved
+//! under a name after the fact, and its session and the measured pointer
+//! acceleration in a folder of their own.
+//!
+//! A macro saved over is kept as a numbered version, so a bad re-record
+//! can be rolled back.
+
+use crate::backend::BackendKind;
+use crate::binary;
+use crate::event::RecordedEvent;
+use crate::filter::{ButtonTarget, Remap};
+use crate::params::Param;
+use crate::player::EventSource;
+use crate::stream::{EventLog, EventLogWriter};
+use rdev::Button;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const EXTENSION: &str = "json";
+const LAST_RECORDING: &str = "last-recording.jsonl";
+/// Folder of the files the library keeps for itself, out of the way of
+/// the macros.
+const STATE: &str = "state";
+const SESSION: &str = "session.json";
+const HISTORY: &str = "history.jsonl";
+const POINTER_CURVE: &str = "pointer-curve.json";
+/// Copies of macros as they were before being saved over, one folder per
+/// macro.
+const VERSIONS: &str = "versions";
+/// Versions kept per macro, the oldest going first.
+const MAX_VERSIONS: usize = 20;
+/// Names macros can't take, used by the library's own files and folders,
+/// now or before they moved to [`STATE`].
+const RESERVED: &[&str] = &[STATE, VERSIONS, "session", "pointer-curve"];
+/// Largest macro accepted by `import`.
+const MAX_IMPORT_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Backend to play it through instead of the `--backend` one, e.g.
+    /// scan codes for a game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    /// Buttons played as other buttons or as shortcuts, to adapt it to a
+    /// mouse set up differently from the one it was recorded with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buttons: Vec<Remap<Button, ButtonTarget>>,
+    /// Values asked for before it plays, filling its placeholders.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    /// Saving over it needs `--force`, to protect macros relied on.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Of the events as locked, to catch edits made to the file by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Macro {
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |e| e.timestamp)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Checks that the macro can be played as it is.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.events.is_empty() {
+            return Err("the macro has no events".into());
+        }
+        let ordered = self
+            .events
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp);
+        if !ordered {
+            return Err("the macro's events are out of order".into());
+        }
+        Ok(())
+    }
+
+    /// FNV-1a hash of the events, in hex.
+    pub fn events_checksum(&self) -> String {
+        let bytes = serde_json::to_vec(&self.events).unwrap_or_default();
+        let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+
+    /// Case-insensitive match against the name, description and tags.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_lowercase().contains(&query)
+            || self.description.to_lowercase().contains(&query)
+            || self.tags.iter().any(|t| t.to_lowercase().contains(&query))
+    }
+}
+
+impl std::fmt::Display for Macro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} events, {:.1} s{})",
+            self.name,
+            self.events.len(),
+            self.duration().as_secs_f64(),
+            if self.locked { ", locked" } else { "" }
+        )?;
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        if !self.description.is_empty() {
+            write!(f, " - {}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// A macro as it was before being saved over.
+pub struct Version {
+    /// Counting from 1, the oldest kept first.
+    pub number: usize,
+    /// When it was saved over.
+    pub replaced: SystemTime,
+    pub m: Macro,
+}
+
+pub struct Library {
+    dir: PathBuf,
+    // whether locked macros may be saved over
+    force: bool,
+}
+
+impl Library {
+    /// Opens the library in `dir`, or in the per-user data directory.
+    pub fn open(dir: Option<PathBuf>) -> Result<Self, String> {
+        let dir = dir
+            .or_else(default_dir)
+            .ok_or("cannot find a data directory, pass --library DIR")?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        Ok(Self { dir, force: false })
+    }
+
+    /// Lets saving replace locked macros.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, String> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\', ':']);
+        if !valid {
+            return Err(format!("invalid macro name '{}'", name));
+        }
+        if RESERVED.contains(&name) {
+            return Err(format!("'{}' is reserved for the library", name));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, EXTENSION)))
+    }
+
+    /// Saves `m`, unless that replaces a locked macro without `force`.
+    /// A macro saved over is kept as a version.
+    pub fn save(&self, m: &Macro) -> Result<PathBuf, String> {
+        // Whether the macro replaced is locked, if there is one.
+        let replaced = match self.find(&m.name) {
+            Ok(found) => found.map(|old| old.locked),
+            // It may be locked, which only --force may ignore.
+            Err(e) if !self.force => return Err(e),
+            Err(_) => Some(true),
+        };
+        if replaced == Some(true) && !self.force {
+            return Err(format!(
+                "'{}' is locked; unlock it or pass --force",
+                m.name
+            ));
+        }
+        if replaced.is_some() {
+            self.keep_version(&m.name)?;
+        }
+        // Saving over a locked macro keeps it locked; only `unlock`
+        // unlocks it.
+        if m.locked || replaced == Some(true) {
+            self.write(&Macro {
+                locked: true,
+                checksum: Some(m.events_checksum()),
+                ..m.clone()
+            })
+        } else {
+            self.write(m)
+        }
+    }
+
+    /// Locks or unlocks the macro `name`, recording the checksum of its
+    /// events as they are when locking.
+    pub fn set_locked(
+        &self,
+        name: &str,
+        locked: bool,
+    ) -> Result<Macro, String> {
+        let mut m = self.read(name)?;
+        m.locked = locked;
+        m.checksum = locked.then(|| m.events_checksum());
+        self.write(&m)?;
+        Ok(m)
+    }
+
+    fn versions_dir(&self, name: &str) -> Result<PathBuf, String> {
+        self.path(name)?;
+        Ok(self.dir.join(VERSIONS).join(name))
+    }
+
+    /// Copies the macro `name` as it is now to its next version, dropping
+    /// the oldest beyond `MAX_VERSIONS`.
+    fn keep_version(&self, name: &str) -> Result<(), String> {
+        let dir = self.versions_dir(name)?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        let numbers = self.version_numbers(name)?;
+        let next = numbers.last().map_or(1, |n| n + 1);
+        let to = dir.join(format!("{}.{}", next, EXTENSION));
+        fs::copy(self.path(name)?, &to)
+            .map_err(|e| format!("cannot create {}: {}", to.display(), e))?;
+        let excess = (numbers.len() + 1).saturating_sub(MAX_VERSIONS);
+        for n in &numbers[..excess] {
+            let _ = fs::remove_file(dir.join(format!("{}.{}", n, EXTENSION)));
+        }
+        Ok(())
+    }
+
+    /// Numbers of the kept versions of `name`, oldest first.
+    fn version_numbers(&self, name: &str) -> Result<Vec<usize>, String> {
+        let Ok(entries) = fs::read_dir(self.versions_dir(name)?) else {
+            return Ok(Vec::new());
+        };
+        let mut numbers: Vec<usize> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == EXTENSION))
+            .filter_map(|p| p.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    /// The kept versions of `name`, oldest first. Unreadable ones are
+    /// reported and skipped.
+    pub fn versions(&self, name: &str) -> Result<Vec<Version>, String> {
+        let dir = self.versions_dir(name)?;
+        let versions = self
+            .version_numbers(name)?
+            .into_iter()
+            .filter_map(|number| {
+                let path = dir.join(format!("{}.{}", number, EXTENSION));
+                let replaced = fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                read_macro(&path)
+                    .inspect_err(|e| println!("Skipping {}", e))
+                    .ok()
+                    .map(|m| Version {
+                        number,
+                        replaced,
+                        m,
+                    })
+            })
+            .collect();
+        Ok(versions)
+    }
+
+    /// Version `number` of `name`.
+    pub fn version(&self, name: &str, number: usize) -> Result<Macro, String> {
+        let path = self
+            .versions_dir(name)?
+            .join(format!("{}.{}", number, EXTENSION));
+        if !path.exists() {
+            return Err(format!("'{}' has no version {}", name, number));
+        }
+        read_macro(&path)
+    }
+
+    fn write(&self, m: &Macro) -> Result<PathBuf, String> {
+        let path = self.path(&m.name)?;
+        let file = File::create(&path)
+            .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, m).map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    /// Loads the macro `name`, failing if it is locked and its events
+    /// changed since.
+    pub fn load(&self, name: &str) -> Result<Macro, String> {
+        let m = self.read(name)?;
+        let checksum = m.checksum.as_ref().filter(|_| m.locked);
+        if checksum.is_some_and(|sum| *sum != m.events_checksum()) {
+            return Err(format!(
+                "'{}' changed since it was locked; check it and lock it again",
+                name
+            ));
+        }
+        Ok(m)
+    }
+
+    fn read(&self, name: &str) -> Result<Macro, String> {
+        self.find(name)?
+            .ok_or_else(|| format!("no macro named '{}'", name))
+    }
+
+    /// The macro `name`, or `None` if there is no file for it. A file that
+    /// can't be read is an error.
+    fn find(&self, name: &str) -> Result<Option<Macro>, String> {
+        let path = self.path(name)?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(format!("cannot read {}: {}", path.display(), e))
+            }
+        };
+        serde_json::from_reader(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+    }
+
+    /// All macros in the library, sorted by name. Unreadable files are
+    /// reported and skipped.
+    pub fn list(&self) -> Result<Vec<Macro>, String> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("cannot read {}: {}", self.dir.display(), e))?;
+        let mut macros: Vec<Macro> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == EXTENSION))
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                if RESERVED.contains(&name.as_str()) {
+                    return None;
+                }
+                self.load(&name)
+                    .inspect_err(|e| println!("Skipping {}", e))
+                    .ok()
+            })
+            .collect();
+        macros.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(macros)
+    }
+
+    /// Where the interactive mode keeps its latest recording.
+    pub fn last_recording(&self) -> PathBuf {
+        self.dir.join(LAST_RECORDING)
+    }
+
+    /// Where the interactive mode keeps its session across restarts.
+    pub fn session(&self) -> PathBuf {
+        self.state_path(SESSION)
+    }
+
+    /// Where recordings and playbacks are logged.
+    pub fn history(&self) -> PathBuf {
+        self.dir.join(HISTORY)
+    }
+
+    /// Where `calibrate-pointer` keeps the measured pointer acceleration.
+    pub fn pointer_curve(&self) -> PathBuf {
+        self.state_path(POINTER_CURVE)
+    }
+
+    /// Where the library keeps `file` for itself, moving it there from
+    /// beside the macros where older versions kept it.
+    fn state_path(&self, file: &str) -> PathBuf {
+        let dir = self.dir.join(STATE);
+        // Failing here shows up as failing to write the file.
+        let _ = fs::create_dir_all(&dir);
+        let path = dir.join(file);
+        let old = self.dir.join(file);
+        if old.exists() && !path.exists() {
+            let _ = fs::rename(&old, &path);
+        }
+        path
+    }
+
+    pub fn store_last_recording(
+        &self,
+        events: &[RecordedEvent],
+    ) -> Result<(), String> {
+        let mut writer = EventLogWriter::create(&self.last_recording())?;
+        events.iter().try_for_each(|evt| writer.append(evt))?;
+        writer.flush()
+    }
+}
+
+/// Downloads a macro shared as a library file and checks that it is valid.
+pub fn fetch(url: &str) -> Result<Macro, String> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("'{}' is not an http(s) URL", url));
+    }
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("cannot download {}: {}", url, e))?;
+    let reader = response.into_reader().take(MAX_IMPORT_SIZE);
+    let m: Macro = serde_json::from_reader(reader)
+        .map_err(|e| format!("{} is not a valid macro: {}", url, e))?;
+    m.validate()
+        .map_err(|e| format!("{} is not a valid macro: {}", url, e))?;
+    Ok(m)
+}
+
+fn read_macro(path: &Path) -> Result<Macro, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+}
+
+/// Macros shared together in one file, e.g. a team's set.
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    macros: Vec<Macro>,
+}
+
+/// Writes `macros` to a single file for `read_bundle`.
+pub fn write_bundle(path: &Path, macros: &[Macro]) -> Result<(), String> {
+    let file = File::create(path)
+        .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let bundle = Bundle {
+        macros: macros.to_vec(),
+    };
+    serde_json::to_writer(&mut writer, &bundle).map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// The macros of a bundle, each checked as an imported one is.
+pub fn read_bundle(path: &Path) -> Result<Vec<Macro>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let reader = BufReader::new(file).take(MAX_IMPORT_SIZE);
+    let bundle: Bundle = serde_json::from_reader(reader).map_err(|e| {
+        format!("{} is not a valid bundle: {}", path.display(), e)
+    })?;
+    for m in &bundle.macros {
+        m.validate().map_err(|e| {
+            format!("'{}' in {}: {}", m.name, path.display(), e)
+        })?;
+    }
+    Ok(bundle.macros)
+}
+
+/// Reads a recording saved by `--stream` or kept as the last recording,
+/// or converted to the binary format.
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedEvent>, String> {
+    if is_binary(path) {
+        return binary::read(path);
+    }
+    Ok(EventLog::new(path).open()?.collect())
+}
+
+/// Writes a recording as JSON Lines, or in the binary format if `path` has
+/// its extension.
+pub fn write_recording(
+    path: &Path,
+    events: &[RecordedEvent],
+) -> Result<(), String> {
+    if is_binary(path) {
+        return binary::write(path, events);
+    }
+    let mut writer = EventLogWriter::create(path)?;
+    events.iter().try_for_each(|evt| writer.append(evt))?;
+    writer.flush()
+}
+
+fn is_binary(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == binary::EXTENSION)
+}
+
+fn default_dir() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("library"))
+}
+
+/// Where the program keeps its data: the library by default, and the
+/// screenshots macros take.
+pub fn data_dir() -> Option<PathBuf> {
+    let data = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME")
+            .map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME")
+                    .map(|home| Path::new(&home).join(".local/share"))
+            })
+    };
+    data.map(|d| d.join("realtime-macro"))
+}
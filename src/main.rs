@@ -1,389 +1,3019 @@
-use enigo::{
-    Axis, Button, Coordinate, Direction,
-    Enigo, Key, Keyboard, Mouse, Settings,
+use rdev::{Button, Event, EventType, Key as RdevKey};
+use realtime_macro::accel::{self, Curve, RelativeMoves};
+use realtime_macro::accessibility;
+use realtime_macro::ahk;
+use realtime_macro::backend::{
+    BackendKind, EnigoBackend, InputBackend, MockBackend,
 };
-use rdev::{listen, Event, EventType, Key as RdevKey};
+use realtime_macro::background::WindowTarget;
+use realtime_macro::bench;
+use realtime_macro::branch::Branched;
+use realtime_macro::capture;
+use realtime_macro::clipboard;
+use realtime_macro::config::{self, Config};
+use realtime_macro::demo::{Demo, Overlay};
+use realtime_macro::edit::{self, Edit};
+use realtime_macro::event::{MacroAction, RecordedEvent, WaitRange};
+use realtime_macro::export;
+use realtime_macro::filter::{
+    ButtonTarget, Filtered, PlaybackFilter, RecordFilter, Remap,
+};
+use realtime_macro::history::{self, Entry, Kind};
+use realtime_macro::hotkeys::{self, Action};
+use realtime_macro::ime;
+use realtime_macro::instance;
+use realtime_macro::keynav;
+use realtime_macro::layout;
+use realtime_macro::library::{self, Library, Macro};
+use realtime_macro::monitor::Stats;
+use realtime_macro::params::{self, Param};
+use realtime_macro::permissions;
+use realtime_macro::pick;
+use realtime_macro::player::{
+    self, Domain, EventSource, LoopMarks, Outcome, PlaybackReport,
+    UserOverride, WhenBusy,
+};
+use realtime_macro::preview;
+use realtime_macro::privileges::{self, Target};
+use realtime_macro::remote::{self, Message};
+use realtime_macro::sandbox::Sandbox;
+use realtime_macro::sanitize;
+use realtime_macro::scancode;
+use realtime_macro::service;
+use realtime_macro::session::Session;
+use realtime_macro::simplify;
+use realtime_macro::snippets::{self, Snippet};
+use realtime_macro::state::{
+    self, Playback, Prompt, Running, SharedState, State,
+};
+use realtime_macro::status;
+use realtime_macro::stream::{EventLog, EventLogWriter};
+use realtime_macro::synthetic;
+use realtime_macro::timing;
+use realtime_macro::trace::{self, Golden};
+use realtime_macro::triggers::{
+    self, Corner, Matched, DEFAULT_SEQUENCE_TIMEOUT,
+};
+use realtime_macro::webtest;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Events buffered between the capture hook and the state thread.
+const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+/// How often the clipboard is checked for changes while recording.
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-enum State {
-    Idle,
-    Recording,
-    Playing,
-    Paused,
+/// Presses of play this soon after the one starting playback each add a
+/// pass instead of pausing.
+const MULTI_PRESS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Events lost because the state thread fell behind and the queue was full.
+static DROPPED_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+struct Options {
+    backend: Option<BackendKind>,
+    // button mapping saved with a macro
+    buttons: Vec<Remap<Button, ButtonTarget>>,
+    // values a saved macro asks for before playing
+    params: Vec<Param>,
+    screen: Option<(i32, i32)>,
+    stream: Option<PathBuf>,
+    start_at: Duration,
+    bench: Option<PathBuf>,
+    check_hotkeys: bool,
+    report: Option<PathBuf>,
+    config: Option<PathBuf>,
+    // settings given as flags, layered over the config and environment
+    settings: Config,
+    command: Option<Command>,
+    load: Option<String>,
+    tags: Vec<String>,
+    description: String,
+    from: Option<PathBuf>,
+    profile: Option<String>,
+    background: Option<PathBuf>,
+    no_session: bool,
+    // shut down a recorder already running instead of giving up
+    takeover: bool,
+    // no display: play into the mock backend and capture nothing
+    headless: bool,
+    // list the steps of the macro inspected
+    preview: bool,
+    // save over locked macros
+    force: bool,
+    // show the sandbox display in a window
+    nested: bool,
+    // run in the sandbox display before playing
+    launch: Option<String>,
+    // print the status or history as JSON
+    json: bool,
+    // how far back the history goes
+    since: Option<Duration>,
+    // pixels the simplified pointer path may stray
+    tolerance: Option<f64>,
+    // keep printing the status for a status bar
+    bar: Option<status::Bar>,
+    // arguments of the edit command
+    at: Option<Duration>,
+    to: Option<Duration>,
+    event: Option<String>,
+    range: Option<WaitRange>,
+    key: Option<RdevKey>,
+    otherwise: Option<Duration>,
+    by: Option<(f64, f64)>,
+}
+
+/// Macro library commands, run instead of the interactive mode.
+enum Command {
+    Save(String),
+    /// Keeps a library macro from being saved over without `--force`.
+    Lock(String),
+    Unlock(String),
+    /// Lists the kept versions of a library macro.
+    Versions(String),
+    /// Puts a kept version of a library macro back, keeping the current
+    /// one as a version in turn.
+    Rollback { name: String, version: usize },
+    List,
+    Search(String),
+    Import { url: String, name: Option<String> },
+    /// Writes library macros, the named ones or else those with the
+    /// `--tag`s, to a single file.
+    ExportBundle { names: Vec<String>, out: PathBuf },
+    /// Offers each macro of a bundle for the library.
+    ImportBundle(PathBuf),
+    /// Turns the supported commands of an AutoHotkey script into a macro.
+    ImportAhk { script: PathBuf, name: String },
+    /// Renders a library macro, or the last or `--from` recording.
+    ExportGif { name: Option<String>, out: PathBuf },
+    /// Writes the skeleton of a Selenium test doing what a library macro,
+    /// or the last or `--from` recording, does.
+    ExportSelenium { name: Option<String>, out: PathBuf },
+    /// Renders where the pointer went and clicked in library macros, or
+    /// the last or `--from` recording.
+    Heatmap { names: Vec<String>, out: PathBuf },
+    /// Starts the recorder on login with the other arguments given.
+    InstallService,
+    UninstallService,
+    /// Puts the last or `--from` recording in place of part of a macro.
+    Replace {
+        name: String,
+        from: Duration,
+        to: Duration,
+    },
+    /// Changes a library macro, or the last or `--from` recording.
+    Edit { name: Option<String>, edit: Edit },
+    /// Describes a library macro, or the last or `--from` recording.
+    Inspect { name: Option<String> },
+    /// Stores the trace of playing a library macro, or the last or `--from`
+    /// recording, as the expected one.
+    RecordTrace { name: Option<String>, out: PathBuf },
+    CompareTrace(PathBuf),
+    /// Compares every golden trace in a directory.
+    Verify(PathBuf),
+    /// Fixes defects in a library macro, or the last or `--from` recording.
+    Sanitize { name: Option<String> },
+    /// Drops pointer moves hardly changing the path from a library macro,
+    /// or the last or `--from` recording.
+    Simplify { name: Option<String> },
+    /// Replaces clicks on the configured window controls with their keys
+    /// in a library macro, or the last or `--from` recording.
+    KeyboardNav { name: Option<String> },
+    /// Plays a library macro, or the last or `--from` recording, into a
+    /// display of its own.
+    Sandbox { name: Option<String> },
+    /// Measures the pointer acceleration `--relative-moves` undoes.
+    CalibratePointer,
+    /// Asks the running recorder what it is doing.
+    Status,
+    /// Lists past recordings and playbacks, of macros whose name contains
+    /// the text given.
+    History(Option<String>),
+    /// Shows live input statistics, saving their summary as JSON if given
+    /// a file.
+    Monitor(Option<PathBuf>),
+    /// Rewrites a recording in the binary format, or back to JSON Lines,
+    /// going by the extension of the file written.
+    Convert { from: PathBuf, to: PathBuf },
+}
+
+/// Where `verify` looks for golden traces unless told otherwise.
+const TRACE_DIR: &str = "traces";
+
+/// How long `--check-hotkeys` waits for the hotkeys to be pressed.
+const HOTKEY_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Steps of an imported macro shown before asking to save it.
+const IMPORT_PREVIEW_STEPS: usize = 40;
+
+/// Size of the sandbox display unless `--screen` is given.
+const SANDBOX_SIZE: (i32, i32) = (1280, 800);
+
+/// How long an application launched in the sandbox gets to start.
+const LAUNCH_SETTLE: Duration = Duration::from_secs(2);
+
+/// How often `monitor` updates its live line.
+const MONITOR_REFRESH: Duration = Duration::from_secs(1);
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        backend: None,
+        buttons: Vec::new(),
+        params: Vec::new(),
+        screen: None,
+        stream: None,
+        start_at: Duration::ZERO,
+        bench: None,
+        check_hotkeys: false,
+        report: None,
+        config: env::var_os("MACRO_RECORDER_CONFIG").map(PathBuf::from),
+        settings: Config::default(),
+        command: None,
+        load: None,
+        tags: Vec::new(),
+        description: String::new(),
+        from: None,
+        profile: env::var("MACRO_RECORDER_PROFILE").ok(),
+        background: None,
+        no_session: false,
+        takeover: false,
+        headless: false,
+        preview: false,
+        force: false,
+        nested: false,
+        launch: None,
+        json: false,
+        since: None,
+        tolerance: None,
+        bar: None,
+        at: None,
+        to: None,
+        event: None,
+        range: None,
+        key: None,
+        otherwise: None,
+        by: None,
+    };
+    let mut positional = Vec::new();
+    // parsed once the command is known
+    let mut to = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--backend" => options.backend = Some(value()?.parse()?),
+            "--button-map" => {
+                let remaps = config::parse_button_remaps(&value()?)?;
+                options.buttons.extend(remaps);
+            }
+            "--param" => options.params.push(value()?.parse()?),
+            "--screen" => options.screen = Some(parse_size(&value()?)?),
+            "--stream" => options.stream = Some(value()?.into()),
+            "--start-at" => {
+                options.start_at = edit::parse_time(&value()?)?
+            }
+            "--bench" => options.bench = Some(value()?.into()),
+            "--check-hotkeys" => options.check_hotkeys = true,
+            "--report" => options.report = Some(value()?.into()),
+            "--config" => options.config = Some(value()?.into()),
+            "--load" => options.load = Some(value()?),
+            "--tag" => options.tags.push(value()?),
+            "--description" => options.description = value()?,
+            "--from" => options.from = Some(value()?.into()),
+            "--profile" => options.profile = Some(value()?),
+            "--background" => options.background = Some(value()?.into()),
+            "--no-session" => options.no_session = true,
+            "--takeover" => options.takeover = true,
+            "--headless" => options.headless = true,
+            "--preview" => options.preview = true,
+            "--force" => options.force = true,
+            "--nested" => options.nested = true,
+            "--launch" => options.launch = Some(value()?),
+            "--json" => options.json = true,
+            "--since" => options.since = Some(history::parse_age(&value()?)?),
+            "--tolerance" => {
+                let px = value()?;
+                let tolerance = px
+                    .parse()
+                    .ok()
+                    .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+                    .ok_or_else(|| format!("invalid tolerance '{}'", px))?;
+                options.tolerance = Some(tolerance);
+            }
+            "--bar" => options.bar = Some(value()?.parse()?),
+            "--at" => options.at = Some(edit::parse_time(&value()?)?),
+            "--to" => to = Some(value()?),
+            "--event" => options.event = Some(value()?),
+            "--range" => options.range = Some(edit::parse_range(&value()?)?),
+            "--key" => options.key = Some(config::parse_name(&value()?)?),
+            "--by" => options.by = Some(edit::parse_offset(&value()?)?),
+            "--else" => {
+                options.otherwise = Some(edit::parse_time(&value()?)?)
+            }
+            flag if config::VALUE_FLAGS.contains(&flag) => {
+                options.settings.set(flag, Some(&value()?))?
+            }
+            flag if config::SWITCH_FLAGS.contains(&flag) => {
+                options.settings.set(flag, None)?
+            }
+            _ if !arg.starts_with("--") => positional.push(arg.clone()),
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    // A version number for `rollback`, a time for the others.
+    let rollback = positional.first() == Some(&"rollback");
+    if let Some(to) = to.as_deref().filter(|_| !rollback) {
+        options.to = Some(edit::parse_time(to)?);
+    }
+    options.command = match positional[..] {
+        [] => None,
+        ["save", name] => Some(Command::Save(name.to_string())),
+        ["lock", name] => Some(Command::Lock(name.to_string())),
+        ["unlock", name] => Some(Command::Unlock(name.to_string())),
+        ["versions", name] => Some(Command::Versions(name.to_string())),
+        ["rollback", name] => {
+            let version = to.ok_or("rollback needs --to VERSION")?;
+            Some(Command::Rollback {
+                name: name.to_string(),
+                version: version
+                    .parse()
+                    .map_err(|_| format!("invalid version '{}'", version))?,
+            })
+        }
+        ["list"] => Some(Command::List),
+        ["search", query] => Some(Command::Search(query.to_string())),
+        ["import", url] => Some(Command::Import {
+            url: url.to_string(),
+            name: None,
+        }),
+        ["import", url, name] => Some(Command::Import {
+            url: url.to_string(),
+            name: Some(name.to_string()),
+        }),
+        ["export-bundle", out, ref names @ ..] => Some(Command::ExportBundle {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            out: out.into(),
+        }),
+        ["import-bundle", bundle] => {
+            Some(Command::ImportBundle(bundle.into()))
+        }
+        ["import-ahk", script, name] => Some(Command::ImportAhk {
+            script: script.into(),
+            name: name.to_string(),
+        }),
+        ["replace", name, from, to] => Some(Command::Replace {
+            name: name.to_string(),
+            from: edit::parse_time(from)?,
+            to: edit::parse_time(to)?,
+        }),
+        ["edit", op] => Some(Command::Edit {
+            name: None,
+            edit: parse_edit(op, &options)?,
+        }),
+        ["edit", name, op] => Some(Command::Edit {
+            name: Some(name.to_string()),
+            edit: parse_edit(op, &options)?,
+        }),
+        ["inspect"] => Some(Command::Inspect { name: None }),
+        ["inspect", name] => Some(Command::Inspect {
+            name: Some(name.to_string()),
+        }),
+        ["record-trace", out] => Some(Command::RecordTrace {
+            name: None,
+            out: out.into(),
+        }),
+        ["record-trace", name, out] => Some(Command::RecordTrace {
+            name: Some(name.to_string()),
+            out: out.into(),
+        }),
+        ["compare-trace", path] => Some(Command::CompareTrace(path.into())),
+        ["verify"] => Some(Command::Verify(TRACE_DIR.into())),
+        ["verify", dir] => Some(Command::Verify(dir.into())),
+        ["sanitize"] => Some(Command::Sanitize { name: None }),
+        ["sanitize", name] => Some(Command::Sanitize {
+            name: Some(name.to_string()),
+        }),
+        ["simplify"] => Some(Command::Simplify { name: None }),
+        ["simplify", name] => Some(Command::Simplify {
+            name: Some(name.to_string()),
+        }),
+        ["keyboard-nav"] => Some(Command::KeyboardNav { name: None }),
+        ["keyboard-nav", name] => Some(Command::KeyboardNav {
+            name: Some(name.to_string()),
+        }),
+        ["sandbox"] => Some(Command::Sandbox { name: None }),
+        ["sandbox", name] => Some(Command::Sandbox {
+            name: Some(name.to_string()),
+        }),
+        ["calibrate-pointer"] => Some(Command::CalibratePointer),
+        ["status"] => Some(Command::Status),
+        ["history"] => Some(Command::History(None)),
+        ["history", name] => Some(Command::History(Some(name.to_string()))),
+        ["monitor"] => Some(Command::Monitor(None)),
+        ["monitor", out] => Some(Command::Monitor(Some(out.into()))),
+        ["convert", from, to] => Some(Command::Convert {
+            from: from.into(),
+            to: to.into(),
+        }),
+        ["install-service"] => Some(Command::InstallService),
+        ["uninstall-service"] => Some(Command::UninstallService),
+        ["export-gif", out] => Some(Command::ExportGif {
+            name: None,
+            out: out.into(),
+        }),
+        ["export-gif", name, out] => Some(Command::ExportGif {
+            name: Some(name.to_string()),
+            out: out.into(),
+        }),
+        ["export-selenium", out] => Some(Command::ExportSelenium {
+            name: None,
+            out: out.into(),
+        }),
+        ["export-selenium", name, out] => Some(Command::ExportSelenium {
+            name: Some(name.to_string()),
+            out: out.into(),
+        }),
+        ["heatmap", out, ref names @ ..] => Some(Command::Heatmap {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            out: out.into(),
+        }),
+        _ => return Err(format!("unknown command '{}'", positional.join(" "))),
+    };
+    Ok(options)
+}
+
+fn parse_edit(op: &str, options: &Options) -> Result<Edit, String> {
+    let at = options
+        .at
+        .ok_or_else(|| format!("edit {} needs --at TIME", op))?;
+    match op {
+        "insert" => {
+            let spec = options
+                .event
+                .as_deref()
+                .ok_or("edit insert needs --event EVENT")?;
+            let events = edit::parse_events(spec)?;
+            Ok(Edit::Insert { at, events })
+        }
+        "delete" => Ok(Edit::Delete { at, to: options.to }),
+        "move" => {
+            let to = options.to.ok_or("edit move needs --to TIME")?;
+            Ok(Edit::Move { at, to })
+        }
+        "wait" => Ok(Edit::Wait {
+            at,
+            range: options.range,
+        }),
+        "branch" => Ok(Edit::Branch {
+            at,
+            to: options.to.ok_or("edit branch needs --to TIME")?,
+            otherwise: options.otherwise,
+            key: options.key.ok_or("edit branch needs --key KEY")?,
+        }),
+        "cleanup" => Ok(Edit::Cleanup { at }),
+        "offset" => Ok(Edit::Offset {
+            at,
+            to: options.to,
+            by: options.by.ok_or("edit offset needs --by DX,DY")?,
+        }),
+        _ => Err(format!(
+            "unknown edit '{}' \
+             (insert, delete, move, wait, branch, offset, cleanup)",
+            op
+        )),
+    }
+}
+
+fn run_command(
+    command: &Command,
+    options: &Options,
+    config: &Config,
+    library: &Library,
+) -> Result<(), String> {
+    let tagged = |m: &Macro| options.tags.iter().all(|t| m.has_tag(t));
+    match command {
+        Command::Save(name) => {
+            let from = options
+                .from
+                .clone()
+                .unwrap_or_else(|| library.last_recording());
+            let events = library::read_recording(&from)?;
+            if events.is_empty() {
+                return Err(format!("no events in {}", from.display()));
+            }
+            let m = Macro {
+                name: name.clone(),
+                description: options.description.clone(),
+                tags: options.tags.clone(),
+                backend: options.backend,
+                buttons: options.buttons.clone(),
+                params: options.params.clone(),
+                locked: false,
+                checksum: None,
+                events,
+            };
+            let path = library.save(&m)?;
+            println!("Saved {} to {}.", m, path.display());
+        }
+        Command::Lock(name) => {
+            let m = library.set_locked(name, true)?;
+            println!("Locked {}.", m);
+        }
+        Command::Unlock(name) => {
+            let m = library.set_locked(name, false)?;
+            println!("Unlocked {}.", m);
+        }
+        Command::Versions(name) => {
+            let versions = library.versions(name)?;
+            if versions.is_empty() {
+                println!("No earlier versions of '{}'.", name);
+            }
+            for version in versions {
+                let age = SystemTime::now()
+                    .duration_since(version.replaced)
+                    .unwrap_or_default();
+                println!(
+                    "{:>3}  replaced {} ago: {}",
+                    version.number,
+                    history::format_age(age),
+                    version.m
+                );
+            }
+        }
+        Command::Rollback { name, version } => {
+            let m = library.version(name, *version)?;
+            library.save(&m)?;
+            println!("Rolled back to version {}: {}.", version, m);
+        }
+        Command::List => {
+            for m in library.list()?.iter().filter(|m| tagged(m)) {
+                println!("{}", m);
+            }
+        }
+        Command::Search(query) => {
+            let found: Vec<Macro> = library
+                .list()?
+                .into_iter()
+                .filter(|m| tagged(m) && m.matches(query))
+                .collect();
+            if found.is_empty() {
+                println!("No macros match '{}'.", query);
+            }
+            for m in &found {
+                println!("{}", m);
+            }
+        }
+        Command::Import { url, name } => {
+            let mut m = library::fetch(url)?;
+            if let Some(name) = name {
+                m.name = name.clone();
+            }
+            offer_import(library, &m)?;
+        }
+        Command::ExportBundle { names, out } => {
+            let macros = if names.is_empty() {
+                library.list()?.into_iter().filter(|m| tagged(m)).collect()
+            } else {
+                names
+                    .iter()
+                    .map(|name| library.load(name))
+                    .collect::<Result<Vec<_>, String>>()?
+            };
+            if macros.is_empty() {
+                return Err("no macros to bundle".into());
+            }
+            library::write_bundle(out, &macros)?;
+            println!("Bundled {} macros into {}.", macros.len(), out.display());
+        }
+        Command::ImportBundle(bundle) => {
+            for m in library::read_bundle(bundle)? {
+                offer_import(library, &m)?;
+            }
+        }
+        Command::ImportAhk { script, name } => {
+            let text = fs::read_to_string(script).map_err(|e| {
+                format!("cannot read {}: {}", script.display(), e)
+            })?;
+            let script = ahk::parse(&text);
+            for warning in &script.warnings {
+                println!("{}", warning);
+            }
+            let m = Macro {
+                name: name.clone(),
+                description: String::new(),
+                tags: Vec::new(),
+                backend: None,
+                buttons: Vec::new(),
+                params: Vec::new(),
+                locked: false,
+                checksum: None,
+                events: script.events,
+            };
+            m.validate()?;
+            offer_import(library, &m)?;
+        }
+        Command::ExportGif { name, out } => {
+            let events = match name {
+                Some(name) => library.load(name)?.events,
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    library::read_recording(&from)?
+                }
+            };
+            let screen = screen_size(options);
+            let frames = export::gif(
+                &events,
+                out,
+                options.background.as_deref(),
+                screen.unwrap_or((1920, 1080)),
+            )?;
+            println!("Wrote {} frames to {}.", frames, out.display());
+        }
+        Command::ExportSelenium { name, out } => {
+            let (name, events) = match name {
+                Some(name) => (name.clone(), library.load(name)?.events),
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    let events = library::read_recording(&from)?;
+                    (from.display().to_string(), events)
+                }
+            };
+            let script = webtest::selenium(&name, &events);
+            fs::write(out, script).map_err(|e| {
+                format!("cannot write {}: {}", out.display(), e)
+            })?;
+            println!("Wrote a Selenium test to {}.", out.display());
+        }
+        Command::Heatmap { names, out } => {
+            let mut recordings = names
+                .iter()
+                .map(|name| Ok(library.load(name)?.events))
+                .collect::<Result<Vec<_>, String>>()?;
+            if names.is_empty() || options.from.is_some() {
+                let from = options
+                    .from
+                    .clone()
+                    .unwrap_or_else(|| library.last_recording());
+                recordings.push(library::read_recording(&from)?);
+            }
+            let screen = screen_size(options);
+            let heatmap = export::heatmap(
+                &recordings,
+                out,
+                options.background.as_deref(),
+                screen.unwrap_or((1920, 1080)),
+            )?;
+            println!("Wrote {} to {}.", heatmap, out.display());
+        }
+        Command::Replace { name, from, to } => {
+            let mut m = library.load(name)?;
+            let path = options
+                .from
+                .clone()
+                .unwrap_or_else(|| library.last_recording());
+            let segment = library::read_recording(&path)?;
+            if segment.is_empty() {
+                return Err(format!("no events in {}", path.display()));
+            }
+            let count = segment.len();
+            m.events = edit::splice(m.events, *from, *to, segment)?;
+            library.save(&m)?;
+            println!(
+                "Replaced {:.1}-{:.1} s with {} events: {}.",
+                from.as_secs_f64(),
+                to.as_secs_f64(),
+                count,
+                m
+            );
+        }
+        Command::Edit { name, edit } => {
+            match name {
+                Some(name) => {
+                    let mut m = library.load(name)?;
+                    let count = edit.apply(&mut m.events)?;
+                    library.save(&m)?;
+                    println!("Changed {} events: {}.", count, m);
+                }
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    let mut events = library::read_recording(&from)?;
+                    let count = edit.apply(&mut events)?;
+                    library::write_recording(&from, &events)?;
+                    println!(
+                        "Changed {} events in {}.",
+                        count,
+                        from.display()
+                    );
+                }
+            }
+        }
+        Command::Inspect { name } => {
+            let events = match name {
+                Some(name) => library.load(name)?.events,
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    library::read_recording(&from)?
+                }
+            };
+            println!("{}", summary(&events));
+            if options.preview {
+                for step in preview::steps(&events) {
+                    println!("  {}", step);
+                }
+            }
+        }
+        Command::RecordTrace { name, out } => {
+            let events = match name {
+                Some(name) => library.load(name)?.events,
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    library::read_recording(&from)?
+                }
+            };
+            let speed = options.settings.speed.unwrap_or(1.0);
+            let golden = Golden::record(events, speed);
+            golden.save(out)?;
+            println!(
+                "Wrote {} steps to {}.",
+                golden.trace.len(),
+                out.display()
+            );
+        }
+        Command::CompareTrace(path) => {
+            Golden::load(path)?.compare()?;
+            println!("{} matches.", path.display());
+        }
+        Command::Verify(dir) => {
+            let failures = trace::verify(dir)?;
+            for (path, e) in &failures {
+                println!("{}: {}", path.display(), e);
+            }
+            if !failures.is_empty() {
+                return Err(format!("{} traces differ", failures.len()));
+            }
+            println!("All traces in {} match.", dir.display());
+        }
+        Command::Sanitize { name } => {
+            let screen = screen_size(options);
+            let report = |changes: &[String]| {
+                for change in changes {
+                    println!("  {}", change);
+                }
+            };
+            match name {
+                Some(name) => {
+                    let mut m = library.load(name)?;
+                    let changes = sanitize::sanitize(&mut m.events, screen);
+                    report(&changes);
+                    if !changes.is_empty() {
+                        library.save(&m)?;
+                    }
+                    println!("Made {} changes: {}.", changes.len(), m);
+                }
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    let mut events = library::read_recording(&from)?;
+                    let changes = sanitize::sanitize(&mut events, screen);
+                    report(&changes);
+                    if !changes.is_empty() {
+                        library::write_recording(&from, &events)?;
+                    }
+                    println!(
+                        "Made {} changes in {}.",
+                        changes.len(),
+                        from.display()
+                    );
+                }
+            }
+        }
+        Command::Simplify { name } => {
+            let tolerance =
+                options.tolerance.unwrap_or(simplify::DEFAULT_TOLERANCE);
+            let report = |dropped: usize, left: usize| {
+                println!(
+                    "Dropped {} of {} events ({:.0}%).",
+                    dropped,
+                    dropped + left,
+                    dropped as f64 * 100.0 / (dropped + left).max(1) as f64
+                );
+            };
+            match name {
+                Some(name) => {
+                    let mut m = library.load(name)?;
+                    let dropped = simplify::simplify(&mut m.events, tolerance);
+                    if dropped > 0 {
+                        library.save(&m)?;
+                    }
+                    report(dropped, m.events.len());
+                }
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    let mut events = library::read_recording(&from)?;
+                    let dropped = simplify::simplify(&mut events, tolerance);
+                    if dropped > 0 {
+                        library::write_recording(&from, &events)?;
+                    }
+                    report(dropped, events.len());
+                }
+            }
+        }
+        Command::KeyboardNav { name } => {
+            let controls = config.controls.as_deref().unwrap_or_default();
+            if controls.is_empty() {
+                return Err("no controls configured; add them to the config \
+                            or the application's profile"
+                    .into());
+            }
+            match name {
+                Some(name) => {
+                    let mut m = library.load(name)?;
+                    let replaced =
+                        keynav::keyboard_only(&mut m.events, controls);
+                    if replaced > 0 {
+                        library.save(&m)?;
+                    }
+                    println!("Replaced {} clicks in {}.", replaced, m);
+                }
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    let mut events = library::read_recording(&from)?;
+                    let replaced =
+                        keynav::keyboard_only(&mut events, controls);
+                    if replaced > 0 {
+                        library::write_recording(&from, &events)?;
+                    }
+                    println!(
+                        "Replaced {} clicks in {}.",
+                        replaced,
+                        from.display()
+                    );
+                }
+            }
+        }
+        Command::Sandbox { name } => {
+            let (events, macro_backend) = match name {
+                Some(name) => {
+                    let m = library.load(name)?;
+                    (m.events, m.backend)
+                }
+                None => {
+                    let from = options
+                        .from
+                        .clone()
+                        .unwrap_or_else(|| library.last_recording());
+                    (library::read_recording(&from)?, None)
+                }
+            };
+            sandbox(events, macro_backend, options, config)?;
+        }
+        Command::CalibratePointer => {
+            println!("Calibrating; leave the mouse alone for a few seconds.");
+            let curve = accel::calibrate()?;
+            for point in &curve.points {
+                println!(
+                    "  {:>5.2} counts/ms: x{:.2}",
+                    point.speed, point.gain
+                );
+            }
+            let path = library.pointer_curve();
+            curve.save(&path)?;
+            println!("Saved to {}.", path.display());
+        }
+        Command::Status => match options.bar {
+            Some(bar) => status::follow(bar)?,
+            None => {
+                let status = status::query()?;
+                if options.json {
+                    let json = serde_json::to_string(&status)
+                        .map_err(|e| e.to_string())?;
+                    println!("{}", json);
+                } else {
+                    println!("{}", status);
+                }
+            }
+        },
+        Command::Monitor(out) => monitor(out.as_deref())?,
+        Command::Convert { from, to } => {
+            let events = library::read_recording(from)?;
+            library::write_recording(to, &events)?;
+            let size = |path: &Path| fs::metadata(path).map_or(0, |m| m.len());
+            println!(
+                "Wrote {} events to {} ({} bytes, from {}).",
+                events.len(),
+                to.display(),
+                size(to),
+                size(from)
+            );
+        }
+        Command::History(name) => {
+            let query = history::Query {
+                name: name.clone(),
+                since: options.since,
+            };
+            let entries = history::read(&library.history())?;
+            let found: Vec<&Entry> =
+                entries.iter().filter(|e| query.matches(e)).collect();
+            if found.is_empty() {
+                println!("No recordings or playbacks logged.");
+            }
+            for entry in found {
+                if options.json {
+                    let json = serde_json::to_string(entry)
+                        .map_err(|e| e.to_string())?;
+                    println!("{}", json);
+                } else {
+                    println!("{}", entry);
+                }
+            }
+        }
+        Command::InstallService => {
+            let args: Vec<String> = env::args()
+                .skip(1)
+                .filter(|arg| arg != "install-service")
+                .collect();
+            let installed = service::install(&args)?;
+            println!("Installed {}; it starts on your next login.", installed);
+        }
+        Command::UninstallService => {
+            println!("Removed {}.", service::uninstall()?);
+        }
+    }
+    Ok(())
+}
+
+/// How long a recording is and what kinds of input it has.
+fn summary(events: &[RecordedEvent]) -> String {
+    let count = |f: fn(&EventType) -> bool| {
+        events
+            .iter()
+            .filter(|e| matches!(&e.action, MacroAction::Input(t) if f(t)))
+            .count()
+    };
+    let length = events.last().map_or(Duration::ZERO, |e| e.timestamp);
+    format!(
+        "{} events over {:.1} s: {} clicks, {} key presses, {} moves, \
+         {} scrolls, {} other steps",
+        events.len(),
+        length.as_secs_f64(),
+        count(|t| matches!(t, EventType::ButtonPress(_))),
+        count(|t| matches!(t, EventType::KeyPress(_))),
+        count(|t| matches!(t, EventType::MouseMove { .. })),
+        count(|t| matches!(t, EventType::Wheel { .. })),
+        events
+            .iter()
+            .filter(|e| !matches!(e.action, MacroAction::Input(_)))
+            .count()
+    )
+}
+
+/// Rings the terminal bell.
+fn beep() {
+    print!("\x07");
+    let _ = io::stdout().flush();
+}
+
+/// Shows what an imported macro does and saves it if the user agrees.
+fn offer_import(library: &Library, m: &Macro) -> Result<(), String> {
+    println!("{}", m);
+    let steps = preview::steps(&m.events);
+    for step in steps.iter().take(IMPORT_PREVIEW_STEPS) {
+        println!("  {}", step);
+    }
+    if steps.len() > IMPORT_PREVIEW_STEPS {
+        println!(
+            "  ... and {} more steps",
+            steps.len() - IMPORT_PREVIEW_STEPS
+        );
+    }
+    if library.load(&m.name).is_ok() {
+        println!("This replaces '{}' in the library.", m.name);
+    }
+    if !confirm(&format!("Save it as '{}'?", m.name))? {
+        println!("Not saved.");
+        return Ok(());
+    }
+    let path = library.save(m)?;
+    println!("Saved {} to {}.", m, path.display());
+    Ok(())
+}
+
+/// Plays `events` into a new display, through X so nothing reaches the
+/// real session.
+fn sandbox(
+    events: Vec<RecordedEvent>,
+    macro_backend: Option<BackendKind>,
+    options: &Options,
+    config: &Config,
+) -> Result<(), String> {
+    match options.backend.or(macro_backend) {
+        None | Some(BackendKind::Enigo) => {}
+        Some(kind) if options.backend.is_some() => {
+            return Err(format!(
+                "a sandbox is played into through enigo, not the {:?} \
+                 backend",
+                kind
+            ))
+        }
+        Some(kind) => println!(
+            "Playing through enigo instead of the macro's {:?} backend, which \
+             would reach the real session.",
+            kind
+        ),
+    }
+    let size = options.screen.unwrap_or(SANDBOX_SIZE);
+    let mut sandbox = Sandbox::start(size, options.nested)?;
+    println!("Started display {}.", sandbox.display);
+    if let Some(command) = &options.launch {
+        sandbox.launch(command)?;
+        thread::sleep(LAUNCH_SETTLE);
+    }
+    // The backend connects to whatever display these point to.
+    env::set_var("DISPLAY", &sandbox.display);
+    env::remove_var("WAYLAND_DISPLAY");
+    let mut backend = EnigoBackend::new()?;
+    let mut state = SharedState::new();
+    state.speed = config.speed.unwrap_or(1.0);
+    state.set_state(State::Playing);
+    let shared = Arc::new(Mutex::new(state));
+    let mut filter = config.playback.clone().unwrap_or_default();
+    if filter.layout.take().is_some() {
+        println!(
+            "Playing keys as they are: --layout only moves keys played by \
+             position, which a sandbox isn't played with."
+        );
+    }
+    // Branches are played as if no key was held.
+    let source = Filtered::new(Branched::new(events, Vec::new()), filter);
+    let report = player::run(&mut backend, &source, &shared, None);
+    println!("{}", report);
+    if options.nested {
+        print!("Press Enter to close the sandbox. ");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        io::stdin()
+            .read_line(&mut String::new())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Applies the layered settings to the running state. Also used when the
+/// config file changes while running.
+fn apply_config(state: &mut SharedState, config: Config) {
+    state.record_filter = config.record_filter.unwrap_or(RecordFilter::All);
+    state.playback_filter = config.playback.unwrap_or_default();
+    state.device_filter = config.devices.unwrap_or_default();
+    state.speed = config.speed.unwrap_or(1.0);
+    state.hotkeys = match config.hotkeys {
+        Some(hotkeys) => hotkeys.iter().map(|h| (h.key, h.action)).collect(),
+        None => hotkeys::DEFAULT.to_vec(),
+    };
+    state.record_hotkeys = config.record_hotkeys.unwrap_or(false);
+    state.set_slot_keys(
+        config
+            .slot_keys
+            .unwrap_or_else(|| state::DEFAULT_SLOT_KEYS.to_vec()),
+    );
+    state.sequences = config.sequences.unwrap_or_default();
+    state.gestures = config.gestures.unwrap_or_default();
+    state.hot_corners = config.hot_corners.unwrap_or_default();
+    state.idle = config.idle;
+    state.time_windows = config.time_windows.unwrap_or_default();
+    state.snippets = config.snippets.unwrap_or_default();
+    state.record_clipboard = config.record_clipboard.unwrap_or(false);
+    state.demo = config.demo.unwrap_or(false);
+    let relative = config.relative_moves == Some(true);
+    let curve = relative.then(|| {
+        let path = state.library.as_ref().map(Library::pointer_curve);
+        match path.filter(|path| path.exists()) {
+            Some(path) => Curve::load(&path).unwrap_or_else(|e| {
+                println!("Pointer acceleration left as it is: {}", e);
+                Curve::default()
+            }),
+            None => {
+                println!(
+                    "Pointer acceleration left as it is; run \
+                     calibrate-pointer to measure it."
+                );
+                Curve::default()
+            }
+        }
+    });
+    state.pointer_curve = curve;
+    state.thread_priority = config.thread_priority.unwrap_or_default();
+    state.when_busy = config.when_busy.unwrap_or_default();
+    state.priority_slots = config
+        .priority_slots
+        .iter()
+        .flatten()
+        .map(|slot| slot - 1)
+        .collect();
+    state.hold_slots = config
+        .hold_slots
+        .iter()
+        .flatten()
+        .map(|slot| slot - 1)
+        .collect();
+    state.toggle_slots = config
+        .toggle_slots
+        .iter()
+        .flatten()
+        .map(|slot| slot - 1)
+        .collect();
+    state.user_override = config.user_override;
+    state.sequence_timeout = config
+        .sequence_timeout_ms
+        .map_or(DEFAULT_SEQUENCE_TIMEOUT, Duration::from_millis);
+    state.click_delay = config
+        .click_delay_ms
+        .map_or(state::DEFAULT_CLICK_DELAY, Duration::from_millis);
+    let bindings = hotkeys::bindings(&state.hotkeys, &state.slot_keys);
+    let warnings = hotkeys::conflicts(&bindings)
+        .into_iter()
+        .chain(hotkeys::sequence_conflicts(&bindings, &state.sequences))
+        .chain(triggers::gesture_conflicts(&state.gestures));
+    for warning in warnings {
+        println!("Hotkey warning: {}.", warning);
+    }
+}
+
+/// Reports which hotkeys actually reach the recorder.
+fn check_hotkeys(config: Config) -> bool {
+    let mut state = SharedState::new();
+    apply_config(&mut state, config);
+    let bindings = hotkeys::bindings(&state.hotkeys, &state.slot_keys);
+
+    let screen = rdev::display_size()
+        .map(|(w, h)| (w as i32, h as i32))
+        .unwrap_or((1920, 1080));
+    let (tx, rx) = mpsc::channel();
+    capture::spawn(screen, false, move |event, _| {
+        if let EventType::KeyPress(key) = event.event_type {
+            let _ = tx.send(key);
+        }
+    });
+    hotkeys::check(&bindings, rx, HOTKEY_CHECK_TIMEOUT)
+}
+
+/// Shows live input statistics until Enter is pressed, then a summary.
+fn monitor(out: Option<&Path>) -> Result<(), String> {
+    let screen = rdev::display_size()
+        .map(|(w, h)| (w as i32, h as i32))
+        .unwrap_or((1920, 1080));
+    let stats = Arc::new(Mutex::new(Stats::new(Instant::now())));
+    let counting = Arc::clone(&stats);
+    let captured = capture::spawn(screen, false, move |event, _| {
+        let mut stats = counting.lock().unwrap();
+        stats.input(&event.event_type, Instant::now());
+    });
+    if !captured {
+        return Err("input can't be captured in this session".into());
+    }
+    println!("Monitoring input, nothing is recorded. Press Enter to stop.");
+    let (done, stopped) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = io::stdin().read_line(&mut String::new());
+        let _ = done.send(());
+    });
+    while let Err(RecvTimeoutError::Timeout) =
+        stopped.recv_timeout(MONITOR_REFRESH)
+    {
+        let mut stats = stats.lock().unwrap();
+        let now = Instant::now();
+        let apm = stats.apm(now);
+        let summary = stats.summary(now);
+        print!(
+            "\r{:.0} APM, {} actions, mouse moved {:.0} px   ",
+            apm, summary.actions, summary.mouse_distance
+        );
+        let _ = io::stdout().flush();
+    }
+    let summary = stats.lock().unwrap().summary(Instant::now());
+    print!("{}", summary);
+    if let Some(out) = out {
+        let json = serde_json::to_string_pretty(&summary)
+            .map_err(|e| e.to_string())?;
+        fs::write(out, json)
+            .map_err(|e| format!("cannot write {}: {}", out.display(), e))?;
+        println!("Saved the summary to {}.", out.display());
+    }
+    Ok(())
+}
+
+/// The `--screen` size, or the display's unless running headless.
+fn screen_size(options: &Options) -> Option<(i32, i32)> {
+    if options.screen.is_some() || options.headless {
+        return options.screen;
+    }
+    rdev::display_size()
+        .ok()
+        .map(|(w, h)| (w as i32, h as i32))
+}
+
+fn parse_size(s: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("invalid screen size '{}', expected WxH", s);
+    let (w, h) = s.split_once('x').ok_or_else(invalid)?;
+    match (w.parse(), h.parse()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(invalid()),
+    }
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "usage: realtime-macro [--backend enigo|uinput|scancode|mock] \
+                 [--screen WxH] [--stream FILE [--start-at TIME]] \
+                 [--bench FILE] [--report FILE] \
+                 [--check-hotkeys] \
+                 [--record-filter all|keyboard|mouse|no-move|clicks] \
+                 [--config FILE] [--device NAME]... [--ignore-device NAME]... \
+                 [--library DIR] [--load NAME] [--profile NAME] \
+                 [--no-session] [--takeover] [--headless] \
+                 [--speed FACTOR] [--slot-keys KEY,...] [--skip-mouse-moves] \
+                 [--hotkeys KEY=ACTION,...] [--record-hotkeys] \
+                 [--skip-scroll] [--ease-scroll] [--record-clipboard] [--demo] \
+                 [--relative-moves] \
+                 [--remap-key FROM=TO,...] \
+                 [--remap-button FROM=TO,...] [--typing-wpm WPM] \
+                 [--delay-before KIND=[xFACTOR][+MS],...] \
+                 [--randomize-delays PCT] [--randomize] [--csv FILE] \
+                 [--env-file FILE] [--loop-from CHECKPOINT] \
+                 [--layout qwerty|azerty|qwertz] \
+                 [--click-delay-ms MS] \
+                 [--trigger-port PORT] \
+                 [--thread-priority normal|high|realtime] \
+                 [--drop-privileges] [--run-as USER] \
+                 [--inject-group GROUP] \
+                 [--when-busy queue|reject|interrupt] \
+                 [--priority-slots N,...] [--hold-slots N,...] \
+                 [--toggle-slots N,...] [--user-override pause|stop] \
+                 [--sequence KEY+KEY=ACTION,...] [--sequence-timeout-ms MS] \
+                 [--gesture BUTTON:STROKES=ACTION,...] \
+                 [--hot-corner CORNER[:MS]=ACTION,...] \
+                 [--idle MINUTES=NAME[@WHEN]] [--time-window ACTION=WHEN,...] \
+                 [--snippet ABBR=TEXT|ABBR=@NAME,...]\n       \
+                 realtime-macro save NAME [--from FILE] [--tag TAG]... \
+                 [--description TEXT] [--backend KIND] \
+                 [--button-map FROM=TO,...] \
+                 [--param NAME[=DEFAULT]]...\n       \
+                 realtime-macro lock NAME\n       \
+                 realtime-macro unlock NAME\n       \
+                 realtime-macro versions NAME\n       \
+                 realtime-macro rollback NAME --to VERSION\n       \
+                 realtime-macro list [--tag TAG]...\n       \
+                 realtime-macro search TEXT [--tag TAG]...\n       \
+                 realtime-macro import URL [NAME]\n       \
+                 realtime-macro import-ahk SCRIPT.ahk NAME\n       \
+                 realtime-macro export-bundle OUT.json [NAME...] \
+                 [--tag TAG]...\n       \
+                 realtime-macro import-bundle BUNDLE.json\n       \
+                 realtime-macro export-gif [NAME] OUT.gif [--from FILE] \
+                 [--background SCREENSHOT.png]\n       \
+                 realtime-macro export-selenium [NAME] OUT.py \
+                 [--from FILE]\n       \
+                 realtime-macro heatmap OUT.png [NAME...] [--from FILE] \
+                 [--background SCREENSHOT.png]\n       \
+                 realtime-macro replace NAME FROM TO [--from FILE]\n       \
+                 realtime-macro edit [NAME] insert --at TIME --event EVENT \
+                 [--from FILE]\n       \
+                 realtime-macro edit [NAME] delete --at TIME [--to TIME] \
+                 [--from FILE]\n       \
+                 realtime-macro edit [NAME] move --at TIME --to TIME \
+                 [--from FILE]\n       \
+                 realtime-macro edit [NAME] wait --at TIME [--range MIN-MAX] \
+                 [--from FILE]\n       \
+                 realtime-macro edit [NAME] branch --at TIME --to TIME \
+                 --key KEY [--else TIME] [--from FILE]\n       \
+                 realtime-macro edit [NAME] offset --at TIME [--to TIME] \
+                 --by DX,DY [--from FILE]\n       \
+                 realtime-macro edit [NAME] cleanup --at TIME \
+                 [--from FILE]\n       \
+                 realtime-macro inspect [NAME] [--from FILE] \
+                 [--preview]\n       \
+                 realtime-macro record-trace [NAME] OUT.trace [--from FILE] \
+                 [--speed FACTOR]\n       \
+                 realtime-macro compare-trace FILE.trace\n       \
+                 realtime-macro verify [DIR]\n       \
+                 realtime-macro sanitize [NAME] [--from FILE] \
+                 [--screen WxH]\n       \
+                 realtime-macro simplify [NAME] [--from FILE] \
+                 [--tolerance PX]\n       \
+                 realtime-macro keyboard-nav [NAME] [--from FILE] \
+                 [--profile NAME]\n       \
+                 realtime-macro sandbox [NAME] [--from FILE] [--screen WxH] \
+                 [--nested] [--launch COMMAND]\n       \
+                 realtime-macro calibrate-pointer\n       \
+                 realtime-macro status [--json] \
+                 [--bar waybar|polybar|i3bar]\n       \
+                 realtime-macro history [NAME] [--since AGE] [--json]\n       \
+                 realtime-macro monitor [OUT.json]\n       \
+                 realtime-macro convert FROM TO[.rmb]\n       \
+                 realtime-macro install-service [FLAGS...]\n       \
+                 realtime-macro uninstall-service\n\
+                 Commands saving over a locked macro need --force.\n\
+                 Settings flags can also be given as MACRO_RECORDER_* \
+                 environment variables (e.g. MACRO_RECORDER_SPEED=2); flags \
+                 win over the environment, which wins over the config file."
+            );
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(path) = options.bench {
+        match bench::run_file(&path) {
+            Ok(report) => print!("{}", report),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let config =
+        Config::load(options.config.as_deref(), options.profile.as_deref());
+    let config = match config {
+        Ok(config) => config.overlay(options.settings.clone()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if options.check_hotkeys {
+        let received = check_hotkeys(config);
+        std::process::exit(if received { 0 } else { 1 });
+    }
+
+    let trigger_port = config.trigger_port;
+
+    let library_dir = config.library.clone();
+    if let Some(command) = &options.command {
+        let result = Library::open(library_dir)
+            .map(|library| library.with_force(options.force))
+            .and_then(|library| {
+                run_command(command, &options, &config, &library)
+            });
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Looked up before any threads start, which reading the user database
+    // isn't safe with.
+    let drop = config.drop_privileges == Some(true) || config.run_as.is_some();
+    let target = Target {
+        user: config.run_as.clone(),
+        inject_group: config.inject_group.clone(),
+    };
+    let switch = match drop.then(|| privileges::prepare(&target)) {
+        Some(Ok(switch)) => switch,
+        Some(Err(e)) => {
+            eprintln!("Cannot drop privileges: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let instance = match instance::claim(options.takeover) {
+        Ok(instance) => instance,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if !options.headless {
+        permissions::request(&permissions::missing(permissions::RECORD));
+    }
+
+    let mut state = SharedState::new();
+    match Library::open(library_dir) {
+        Ok(library) => state.library = Some(library),
+        Err(e) => println!("Macro library unavailable: {}", e),
+    }
+    let config_speed = config.speed;
+    state.backend = if options.headless {
+        BackendKind::Mock
+    } else {
+        options.backend.unwrap_or(BackendKind::Enigo)
+    };
+    state.stream_path = options.stream.clone();
+    state.stream_start = options.start_at;
+    state.report_path = options.report.clone();
+    state.history_path = state.library.as_ref().map(Library::history);
+    apply_config(&mut state, config);
+    if let (false, Some(library)) = (options.no_session, &state.library) {
+        let path = library.session();
+        match Session::load(&path) {
+            Ok(Some(session)) => {
+                state.restore(session);
+                println!("Restored the session from {}.", path.display());
+            }
+            Ok(None) => {}
+            Err(e) => println!("Could not restore the session: {}", e),
+        }
+        if let Some(speed) = config_speed {
+            state.speed = speed;
+        }
+        state.session_path = Some(path);
+    }
+    if let Some(name) = &options.load {
+        let loaded = state
+            .library
+            .as_ref()
+            .ok_or("no macro library".to_string())
+            .and_then(|library| library.load(name));
+        match loaded {
+            Ok(m) => {
+                println!("Loaded {}.", m);
+                state.recorded_events = m.events;
+                state.recording_backend = m.backend;
+                state.recording_buttons = m.buttons;
+                state.recording_params = m.params;
+                state.last_macro = Some(m.name);
+                save_session(&state);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    state.screen_size = screen_size(&options);
+    if state.backend == BackendKind::Uinput && state.screen_size.is_none() {
+        println!("Could not detect the screen size, pass --screen WxH.");
+        state.screen_size = Some((1920, 1080));
+    }
+
+    let shared = Arc::new(Mutex::new(state));
+    let s = Arc::clone(&shared);
+
+    instance.serve(
+        {
+            let s = Arc::clone(&shared);
+            move || shut_down(&s)
+        },
+        status::reporter(Arc::clone(&shared)),
+    );
+    if let Some(port) = trigger_port {
+        let s = Arc::clone(&shared);
+        if let Err(e) = remote::listen(port, move |msg| triggered(&s, msg)) {
+            println!("Triggers unavailable: {}", e);
+        }
+    }
+
+    if let Some(path) = options.config.clone().or_else(config::default_path) {
+        let s = Arc::clone(&shared);
+        let profile = options.profile.clone();
+        let flags = options.settings.clone();
+        config::watch(path, profile, move |config| {
+            apply_config(&mut s.lock().unwrap(), config.overlay(flags.clone()));
+        });
+    }
+
+    let (screen, per_device) = {
+        let shared = shared.lock().unwrap();
+        (
+            shared.screen_size.unwrap_or((1920, 1080)),
+            !shared.device_filter.is_empty(),
+        )
+    };
+    // The capture callback runs inside the OS input hook, so it only queues
+    // events; all state handling happens on a dedicated thread.
+    let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE_CAPACITY);
+    // Headless nothing is captured, but the queue stays open so triggers
+    // and timers are still served.
+    let _open = options.headless.then(|| tx.clone());
+    let recording_available = !options.headless
+        && capture::spawn(screen, per_device, move |event, device| {
+            if tx.try_send((event, device)).is_err() {
+                DROPPED_EVENTS.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    shared.lock().unwrap().recording_available = recording_available;
+    if let Some(switch) = switch {
+        // Files written as root so far, which the user has to write to.
+        let mut owned = Vec::new();
+        {
+            let shared = shared.lock().unwrap();
+            owned.extend(shared.history_path.clone());
+            if let Some(session) = &shared.session_path {
+                owned.extend(session.parent().map(Path::to_path_buf));
+                owned.push(session.clone());
+            }
+        }
+        match switch.apply(&owned) {
+            Ok(()) => println!("Dropped root privileges."),
+            Err(e) => {
+                eprintln!("Cannot drop privileges: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if !options.headless {
+        watch_clipboard(Arc::clone(&shared));
+    }
+
+    // The main thread is the state thread; it sleeps until input arrives,
+    // the pointer has rested in a hot corner long enough or the user has
+    // been idle long enough.
+    loop {
+        let deadline = {
+            let shared = s.lock().unwrap();
+            let corner = shared.corner_tracker.deadline(&shared.hot_corners);
+            let idle = shared.idle_tracker.deadline(shared.idle.as_ref());
+            corner.into_iter().chain(idle).min()
+        };
+        let received = match deadline {
+            Some(deadline) => rx.recv_timeout(
+                deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            ),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok((event, device)) => handle_event(&s, event, device.as_deref()),
+            Err(RecvTimeoutError::Timeout) => check_timers(&s),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Work that has to wait until the state lock is released.
+#[derive(Default)]
+struct Pending {
+    stop_playback: bool,
+    stop_recording: bool,
+    play: Option<Playback>,
+    show_pick: bool,
+}
+
+fn handle_event(
+    s: &Arc<Mutex<SharedState>>,
+    event: Event,
+    device: Option<&str>,
+) {
+    let mut pending = Pending::default();
+    let injected = synthetic::is_injected(&event.event_type);
+
+    {
+        let mut shared = s.lock().unwrap();
+        let shared = &mut *shared;
+
+        if let EventType::MouseMove { x, y } = event.event_type {
+            shared.last_mouse_pos = (x, y);
+        }
+        // What playback injects must not trigger anything or end up in a
+        // recording, e.g. the releases sent when recording interrupts it.
+        if injected {
+            return;
+        }
+
+        shared.idle_tracker.input(event.time);
+        if shared.idle_playing && is_user_input(&event) {
+            shared.idle_playing = false;
+            if shared.state == State::Playing || shared.state == State::Paused
+            {
+                println!("User input, stopping the idle macro.");
+                pending.stop_playback = true;
+            }
+        }
+
+        match event.event_type {
+            EventType::KeyPress(RdevKey::ShiftLeft | RdevKey::ShiftRight) => {
+                shared.shift_held = true
+            }
+            EventType::KeyRelease(RdevKey::ShiftLeft | RdevKey::ShiftRight) => {
+                shared.shift_held = false
+            }
+            _ => {}
+        }
+        // Held keys repeat their presses.
+        let repeated = matches!(
+            event.event_type,
+            EventType::KeyPress(key) if shared.keys_held.contains(&key)
+        );
+        match event.event_type {
+            EventType::KeyPress(key) if !shared.keys_held.contains(&key) => {
+                shared.keys_held.push(key)
+            }
+            EventType::KeyRelease(key) => {
+                shared.keys_held.retain(|k| *k != key)
+            }
+            _ => {}
+        }
+
+        let mut triggered = false;
+        match event.event_type {
+            EventType::KeyPress(key) => {
+                let action = shared.hotkey(key).or_else(|| {
+                    let slot = shared.slot_for_key(key)?;
+                    Some(if shared.shift_held {
+                        Action::RecordSlot(slot)
+                    } else {
+                        Action::PlaySlot(slot)
+                    })
+                });
+                let action = action
+                    .filter(|action| allowed_now(shared, *action))
+                    .and_then(|action| repeat_slot(shared, action, repeated));
+                if let Some(action) = action {
+                    perform(shared, action, event.time, &mut pending);
+                }
+
+                let matched = shared.sequence_matcher.press(
+                    key,
+                    event.time,
+                    &shared.sequences,
+                    shared.sequence_timeout,
+                );
+                if let Some(matched) = matched {
+                    if shared.state == State::Recording {
+                        drop_trigger_keys(shared, &matched);
+                        triggered = true;
+                    }
+                    perform(shared, matched.action, event.time, &mut pending);
+                }
+            }
+            EventType::KeyRelease(key) => {
+                shared.sequence_matcher.release(key);
+                let slot = match shared.hotkey(key) {
+                    Some(Action::PlaySlot(slot)) => Some(slot),
+                    Some(_) => None,
+                    None => shared.slot_for_key(key),
+                };
+                // Held slots stop as soon as their key is let go.
+                let held = slot.filter(|slot| {
+                    shared.hold_slots.contains(slot)
+                        && shared.repeating.contains(slot)
+                });
+                if let Some(slot) = held {
+                    shared.repeating.retain(|s| *s != slot);
+                    // Other slots playing alongside keep going.
+                    let domain = shared.domain(&Playback::Slot(slot));
+                    shared.stop_domain(domain);
+                }
+            }
+            EventType::ButtonPress(button) => {
+                let at = shared.last_mouse_pos;
+                triggered =
+                    shared.gesture_tracker.press(button, at, &shared.gestures);
+            }
+            EventType::ButtonRelease(button) => {
+                if let Some(drawn) = shared.gesture_tracker.release(button) {
+                    triggered = true;
+                    match drawn.action(&shared.gestures) {
+                        Some(action) => {
+                            perform(shared, action, event.time, &mut pending)
+                        }
+                        None => println!("No action for gesture {}.", drawn),
+                    }
+                }
+            }
+            EventType::MouseMove { x, y } => {
+                triggered = shared.gesture_tracker.moved((x, y));
+                let screen = shared.screen_size.unwrap_or((1920, 1080));
+                let corner = Corner::at((x, y), screen);
+                shared.corner_tracker.moved(corner, event.time);
+            }
+            _ => {}
+        }
+
+        if shared.state == State::Playing
+            && is_user_input(&event)
+            && !is_hotkey_event(&event, shared)
+        {
+            match shared.user_override {
+                Some(UserOverride::Pause) => {
+                    println!("User input, pausing playback.");
+                    shared.set_state(State::Paused);
+                }
+                Some(UserOverride::Stop) => {
+                    println!("User input, stopping playback.");
+                    pending.stop_playback = true;
+                }
+                None => {}
+            }
+        }
+
+        if shared.state == State::Recording
+            && !triggered
+            && (shared.record_hotkeys || !is_hotkey_event(&event, shared))
+            && shared.record_filter.allows(&event.event_type)
+            && shared.device_filter.allows(device)
+        {
+            record_input_event(shared, &event, device);
+        }
+
+        if shared.state == State::Idle {
+            let snippet = shared
+                .snippet_matcher
+                .input(&event.event_type, &shared.snippets)
+                .cloned();
+            if let Some(snippet) = snippet {
+                pending.play = expand(shared, &snippet).map(Playback::Snippet);
+            }
+        }
+
+        let corner =
+            shared.corner_tracker.poll(event.time, &shared.hot_corners);
+        if let Some(action) = corner {
+            perform(shared, action, event.time, &mut pending);
+        }
+    }
+
+    run_pending(s, pending);
+}
+
+fn check_timers(s: &Arc<Mutex<SharedState>>) {
+    let mut pending = Pending::default();
+    {
+        let mut shared = s.lock().unwrap();
+        let shared = &mut *shared;
+        let now = SystemTime::now();
+        if let Some(action) =
+            shared.corner_tracker.poll(now, &shared.hot_corners)
+        {
+            perform(shared, action, now, &mut pending);
+        }
+        if shared.idle_tracker.poll(now, shared.idle.as_ref()) {
+            play_idle_macro(shared, &mut pending);
+        }
+    }
+    run_pending(s, pending);
+}
+
+/// Acts on a message sent by another program.
+fn triggered(s: &Arc<Mutex<SharedState>>, message: Message) {
+    let mut pending = Pending::default();
+    {
+        let mut shared = s.lock().unwrap();
+        let shared = &mut *shared;
+        match message {
+            Message::Action(action) => {
+                perform(shared, action, SystemTime::now(), &mut pending)
+            }
+            Message::Play(name) => {
+                let loaded = shared
+                    .library
+                    .as_ref()
+                    .ok_or("no macro library".to_string())
+                    .and_then(|library| library.load(&name));
+                match loaded {
+                    Ok(m) => {
+                        println!("Triggered {}.", m.name);
+                        let playback = Playback::Snippet(m);
+                        request_playback(shared, playback, &mut pending);
+                    }
+                    Err(e) => println!("Cannot play {}: {}", name, e),
+                }
+            }
+        }
+    }
+    run_pending(s, pending);
+}
+
+/// Plays the idle macro unless something else is going on.
+fn play_idle_macro(shared: &mut SharedState, pending: &mut Pending) {
+    let (Some(idle), Some(library)) = (&shared.idle, &shared.library) else {
+        return;
+    };
+    if shared.state != State::Idle
+        || idle.when.is_some_and(|when| !when.is_open())
+    {
+        return;
+    }
+    match library.load(&idle.macro_name) {
+        Ok(m) => {
+            println!("Idle for {} minutes, playing {}.", idle.minutes, m.name);
+            shared.idle_playing = true;
+            pending.play = Some(Playback::Snippet(m));
+        }
+        Err(e) => println!("Cannot play the idle macro: {}", e),
+    }
 }
 
-#[derive(Debug, Clone)]
-struct RecordedEvent {
-    event_type: EventType,
-    timestamp: Duration, // time since start of recording
+fn run_pending(s: &Arc<Mutex<SharedState>>, pending: Pending) {
+    if pending.stop_playback {
+        stop_playback(s);
+    }
+
+    if pending.stop_recording {
+        stop_recording(s);
+    }
+
+    if let Some(playback) = pending.play {
+        start_playback(Arc::clone(s), playback);
+    }
+
+    if pending.show_pick {
+        pick::spawn_display(Arc::clone(s));
+    }
 }
 
-struct SharedState {
-    state: State,
-    recorded_events: Vec<RecordedEvent>,
-    start_record_time: Option<Instant>,
-    playback_thread: Option<thread::JoinHandle<()>>,
-    looping: bool,
+/// A macro erasing a snippet's abbreviation and the key that ended it,
+/// then typing or playing its expansion.
+fn expand(shared: &SharedState, snippet: &Snippet) -> Option<Macro> {
+    let erase = snippet.abbreviation.chars().count() + 1;
+    let mut macro_backend = None;
+    let mut macro_buttons = Vec::new();
+    let mut macro_params = Vec::new();
+    let events = if let Some(text) = &snippet.text {
+        let (events, untypable) = snippets::typing(erase, text);
+        if !untypable.is_empty() {
+            println!(
+                "Snippet '{}' skips characters a US layout can't type: {:?}",
+                snippet.abbreviation, untypable
+            );
+        }
+        events
+    } else {
+        let name = snippet.macro_name.as_deref()?;
+        let Some(library) = &shared.library else {
+            println!("No library to play '{}' from.", name);
+            return None;
+        };
+        let m = match library.load(name) {
+            Ok(m) => m,
+            Err(e) => {
+                println!("Cannot expand '{}': {}", snippet.abbreviation, e);
+                return None;
+            }
+        };
+        macro_backend = m.backend;
+        macro_buttons = m.buttons;
+        macro_params = m.params;
+        let (mut events, _) = snippets::typing(erase, "");
+        let offset = events.last().map_or(Duration::ZERO, |e| e.timestamp);
+        events.extend(m.events.into_iter().map(|mut evt| {
+            evt.timestamp += offset;
+            evt
+        }));
+        events
+    };
+    Some(Macro {
+        name: format!("'{}'", snippet.abbreviation),
+        description: String::new(),
+        tags: Vec::new(),
+        backend: macro_backend,
+        buttons: macro_buttons,
+        params: macro_params,
+        locked: false,
+        checksum: None,
+        events,
+    })
 }
 
-impl SharedState {
-    fn new() -> Self {
-        Self {
-            state: State::Idle,
-            recorded_events: Vec::new(),
-            start_record_time: None,
-            playback_thread: None,
-            looping: false,
+/// Whether `action` isn't restricted to a time window that is closed now.
+fn allowed_now(shared: &SharedState, action: Action) -> bool {
+    match triggers::closed_window(&shared.time_windows, action) {
+        Some(when) => {
+            println!("{} only works {}.", action, when);
+            false
         }
+        None => true,
     }
 }
 
-fn main() {
-    let shared = Arc::new(Mutex::new(SharedState::new()));
-    let s = Arc::clone(&shared);
-
-    thread::spawn(move || {
-        listen(move |event: Event| {
-            let mut start_playback_flag = false;
-            let mut stop_playback_flag = false;
-            let mut stop_recording_flag = false;
-
+fn perform(
+    shared: &mut SharedState,
+    action: Action,
+    at: SystemTime,
+    pending: &mut Pending,
+) {
+    if !allowed_now(shared, action) {
+        return;
+    }
+    match action {
+        Action::PlayPause => match (shared.state, shared.play_pressed) {
+            (State::Playing, Some((first, presses)))
+                if at
+                    .duration_since(first)
+                    .is_ok_and(|d| d < MULTI_PRESS_WINDOW) =>
             {
-                let mut shared = s.lock().unwrap();
-
-                if let EventType::KeyPress(key) = event.event_type {
-                    match key {
-                        RdevKey::F1 => match shared.state {
-                            State::Playing => {
-                                shared.state = State::Paused;
-                                println!("Paused.");
-                            }
-                            State::Paused => {
-                                shared.state = State::Playing;
-                                println!("Resumed.");
-                            }
-                            State::Recording => {
-                                stop_recording_flag = true;
-                            }
-                            State::Idle => {
-                                if !shared.recorded_events.is_empty() {
-                                    start_playback_flag = true;
-                                }
-                            }
-                        },
-                        RdevKey::F2 => {
-                            if shared.state == State::Playing
-                                || shared.state == State::Paused
-                            {
-                                stop_playback_flag = true;
-                            }
-                            if shared.state == State::Recording {
-                                stop_recording_flag = true;
-                            }
-                        }
-                        RdevKey::F3 => {
-                            shared.looping = !shared.looping;
-                            println!(
-                                "Looping {}",
-                                if shared.looping {
-                                    "enabled"
-                                } else {
-                                    "disabled"
-                                }
-                            );
-                        }
-                        RdevKey::F4 => {
-                            start_recording(&mut shared);
-                        }
-                        _ => {}
-                    }
+                shared.play_pressed = Some((first, presses + 1));
+                shared.passes = Some(presses + 1);
+                beep();
+                println!("Playing {} times.", presses + 1);
+            }
+            (State::Playing, _) => {
+                shared.set_state(State::Paused);
+                println!("Paused.");
+            }
+            (State::Paused, _) => {
+                shared.set_state(State::Playing);
+                println!("Resumed.");
+            }
+            (State::Recording, _) => {
+                pending.stop_recording = true;
+            }
+            (State::Idle, _) => {
+                if shared.has_recording() {
+                    shared.play_pressed = Some((at, 1));
+                    pending.play = Some(Playback::Recording);
                 }
-
-                if shared.state == State::Recording {
-                    if should_record_event(&event) {
-                        record_input_event(&mut shared, &event);
+            }
+        },
+        Action::Stop | Action::SoftStop
+            if shared.state == State::Playing
+                && (action == Action::SoftStop || shared.shift_held) =>
+        {
+            shared.stop_after_pass = true;
+            shared.repeating.clear();
+            println!("Stopping after this pass.");
+            if !shared.queue.is_empty() {
+                println!("Dropped {} queued playbacks.", shared.queue.len());
+                shared.queue.clear();
+            }
+        }
+        Action::Stop | Action::SoftStop => {
+            if shared.state == State::Playing
+                || shared.state == State::Paused
+                || shared.prompt.is_some()
+            {
+                pending.stop_playback = true;
+            }
+            shared.repeating.clear();
+            if !shared.queue.is_empty() {
+                println!("Dropped {} queued playbacks.", shared.queue.len());
+                shared.queue.clear();
+            }
+            if shared.state == State::Recording {
+                pending.stop_recording = true;
+            }
+        }
+        Action::ToggleLoop => {
+            shared.looping = !shared.looping;
+            save_session(shared);
+            println!(
+                "Looping {}",
+                if shared.looping {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+        Action::Record => {
+            start_recording(shared, at, None);
+        }
+        Action::ToggleElements => {
+            shared.target_elements = !shared.target_elements;
+            println!(
+                "Element targeting {}",
+                if shared.target_elements {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+        Action::ToggleWindow => {
+            toggle_target_window(shared);
+        }
+        Action::CycleRecordFilter => {
+            shared.record_filter = shared.record_filter.next();
+            println!("Recording {} input.", shared.record_filter.name());
+        }
+        Action::PlaySlot(slot) | Action::RecordSlot(slot)
+            if slot >= shared.slots.len() =>
+        {
+            println!("There is no slot {}.", slot + 1);
+        }
+        Action::PlaySlot(slot) => {
+            if shared.state == State::Recording {
+                return;
+            }
+            if shared.slots[slot].is_empty() {
+                println!("Slot {} is empty.", slot + 1);
+            } else {
+                request_playback(shared, Playback::Slot(slot), pending);
+            }
+        }
+        Action::RecordSlot(slot) => {
+            if shared.state == State::Recording {
+                pending.stop_recording = true;
+            } else {
+                start_recording(shared, at, Some(slot));
+            }
+        }
+        Action::Status => print_status(shared),
+        Action::Pick if !shared.picking => {
+            shared.picking = true;
+            pending.show_pick = true;
+            println!("Pick mode on, press it again to take the position.");
+        }
+        Action::Pick => {
+            shared.picking = false;
+            println!();
+            let pos = shared.last_mouse_pos;
+            if shared.shift_held {
+                add_click(shared, pos);
+                return;
+            }
+            let (x, y) = (pos.0.round(), pos.1.round());
+            match clipboard::set_text(&format!("{}, {}", x, y)) {
+                Ok(()) => println!("Copied {}.", pick::describe(pos)),
+                Err(e) => println!("Could not copy the position: {}", e),
+            }
+        }
+        Action::AddClick => add_click(shared, shared.last_mouse_pos),
+        Action::LoopStart | Action::LoopEnd if shared.shift_held => {
+            for running in &mut shared.playing {
+                running.loop_marks = LoopMarks::default();
+            }
+            println!("Loop marks cleared.");
+        }
+        Action::LoopStart | Action::LoopEnd
+            if shared.state != State::Paused =>
+        {
+            println!("Pause playback to mark the loop there.");
+        }
+        // Each running playback is marked where it is.
+        Action::LoopStart => {
+            let several = shared.playing.len() > 1;
+            for running in &mut shared.playing {
+                running.loop_marks = LoopMarks {
+                    start: Some(running.position),
+                    end: None,
+                };
+                println!(
+                    "The loop{} starts at {:.1} s.",
+                    playback_of(running, several),
+                    running.position.as_secs_f64()
+                );
+            }
+        }
+        Action::LoopEnd => {
+            let several = shared.playing.len() > 1;
+            for running in &mut shared.playing {
+                let of = playback_of(running, several);
+                match running.loop_marks.start {
+                    Some(start) if running.position > start => {
+                        running.loop_marks.end = Some(running.position);
+                        println!(
+                            "The loop{} ends at {:.1} s; resume to repeat it.",
+                            of,
+                            running.position.as_secs_f64()
+                        );
                     }
+                    Some(_) => {
+                        println!("The loop{} has to end after it starts.", of)
+                    }
+                    None => println!("Mark where the loop{} starts first.", of),
                 }
             }
+        }
+    }
+}
 
-            if stop_playback_flag {
-                stop_playback(&s);
-            }
+/// Arms a hold or toggle slot pressed to play over and over, or disarms a
+/// toggle slot, returning the action left to perform.
+fn repeat_slot(
+    shared: &mut SharedState,
+    action: Action,
+    repeated: bool,
+) -> Option<Action> {
+    let Action::PlaySlot(slot) = action else {
+        return Some(action);
+    };
+    let hold = shared.hold_slots.contains(&slot);
+    let toggle = shared.toggle_slots.contains(&slot);
+    if !hold && !toggle {
+        return Some(action);
+    }
+    // Played again as they finish, not on each repeated press.
+    if repeated {
+        return None;
+    }
+    if toggle && shared.repeating.contains(&slot) {
+        shared.repeating.retain(|s| *s != slot);
+        shared
+            .queue
+            .retain(|p| !matches!(p, Playback::Slot(s) if *s == slot));
+        println!("Slot {} stops repeating after this pass.", slot + 1);
+        return None;
+    }
+    shared.repeating.push(slot);
+    if toggle {
+        println!("Slot {} repeats until pressed again.", slot + 1);
+    }
+    Some(action)
+}
 
-            if stop_recording_flag {
-                stop_recording(&s);
-            }
+/// Adds a click at `pos` to the end of the in-memory recording, so simple
+/// macros can be put together without recording them.
+fn add_click(shared: &mut SharedState, pos: (f64, f64)) {
+    if shared.state != State::Idle {
+        println!("Can't add a click while playing or recording.");
+        return;
+    }
+    if shared.stream_path.is_some() {
+        println!("Can't add a click to a --stream recording.");
+        return;
+    }
+    let at = match shared.recorded_events.last() {
+        Some(last) => last.timestamp + shared.click_delay,
+        None => Duration::ZERO,
+    };
+    shared.recorded_events.extend(pick::click_at(at, pos));
+    save_session(shared);
+    println!("Added a click at {}.", pick::describe(pos));
+}
 
-            if start_playback_flag {
-                start_playback(Arc::clone(&s));
-            }
-        })
-        .unwrap();
-    });
+/// Plays `playback`, or deals with it according to `--when-busy` if
+/// something is playing already.
+fn request_playback(
+    shared: &mut SharedState,
+    playback: Playback,
+    pending: &mut Pending,
+) {
+    if shared.state == State::Idle || shared.can_play_alongside(&playback) {
+        pending.play = Some(playback);
+        return;
+    }
+    let priority = matches!(
+        playback,
+        Playback::Slot(slot) if shared.priority_slots.contains(&slot)
+    );
+    let when_busy = if priority {
+        WhenBusy::Interrupt
+    } else {
+        shared.when_busy
+    };
+    match when_busy {
+        WhenBusy::Queue => {
+            println!(
+                "Queued {} ({} waiting).",
+                playback,
+                shared.queue.len() + 1
+            );
+            shared.queue.push_back(playback);
+        }
+        WhenBusy::Reject => {
+            println!("Already playing, {} was not started.", playback)
+        }
+        WhenBusy::Interrupt => {
+            pending.stop_playback = true;
+            pending.play = Some(playback);
+        }
+    }
+}
 
-    loop {
-        thread::sleep(Duration::from_secs(1));
+fn print_status(shared: &SharedState) {
+    println!(
+        "{:?}, looping {}, speed {}x.",
+        shared.state,
+        if shared.looping { "on" } else { "off" },
+        shared.speed
+    );
+    if shared.queue.is_empty() {
+        println!("Nothing queued.");
     }
+    for (i, playback) in shared.queue.iter().enumerate() {
+        println!("  {}. {}", i + 1, playback);
+    }
+}
+
+/// Removes the keys of a trigger sequence from the recording in progress.
+fn drop_trigger_keys(shared: &mut SharedState, matched: &Matched) {
+    let Some(start) = shared.start_record_time else {
+        return;
+    };
+    let since = matched.started.duration_since(start).unwrap_or_default();
+    shared.recording_mut().retain(|evt| match evt.action {
+        MacroAction::Input(
+            EventType::KeyPress(key) | EventType::KeyRelease(key),
+        ) => evt.timestamp < since || !matched.keys.contains(&key),
+        _ => true,
+    });
 }
 
-fn should_record_event(event: &Event) -> bool {
+/// Whether `event` shows the user acting, as opposed to letting go of
+/// something pressed before playback started.
+fn is_user_input(event: &Event) -> bool {
+    !matches!(
+        event.event_type,
+        EventType::KeyRelease(_) | EventType::ButtonRelease(_)
+    )
+}
+
+fn is_hotkey_event(event: &Event, shared: &SharedState) -> bool {
     match event.event_type {
         EventType::KeyPress(key) | EventType::KeyRelease(key) => {
-            !matches!(
-                key,
-                RdevKey::F1 | RdevKey::F2 | RdevKey::F3 | RdevKey::F4
-            )
+            shared.is_hotkey(key)
         }
-        _ => true,
+        _ => false,
+    }
+}
+
+fn toggle_target_window(shared: &mut SharedState) {
+    if shared.target_window.take().is_some() {
+        println!("Background playback disabled.");
+        return;
+    }
+    match WindowTarget::foreground() {
+        Some(window) => {
+            println!("Background playback into \"{}\".", window.title());
+            shared.target_window = Some(window);
+        }
+        None => println!("Background playback is not available."),
     }
 }
 
-fn start_recording(shared: &mut SharedState) {
+/// Starts a new recording, into `slot` when given. Slot recordings are kept
+/// in memory even when streaming to a file.
+fn start_recording(
+    shared: &mut SharedState,
+    at: SystemTime,
+    slot: Option<usize>,
+) {
+    if !shared.recording_available {
+        println!("Recording is not available in this session.");
+        return;
+    }
+
+    if !permissions::ensure(permissions::RECORD) {
+        return;
+    }
+
     if shared.state == State::Playing || shared.state == State::Paused {
-        shared.state = State::Idle;
+        shared.set_state(State::Idle);
+    }
+
+    if let (Some(path), None) = (&shared.stream_path, slot) {
+        match EventLogWriter::create(path) {
+            Ok(stream) => shared.stream = Some(stream),
+            Err(e) => {
+                println!("Cannot record: {}", e);
+                return;
+            }
+        }
     }
 
-    shared.recorded_events.clear();
-    shared.start_record_time = Some(Instant::now());
-    shared.state = State::Recording;
+    // A slot is recorded apart, leaving the main recording as it is.
+    shared.recording_slot = slot;
+    shared.recording_mut().clear();
+    if slot.is_none() {
+        shared.recording_backend = None;
+        shared.recording_buttons.clear();
+        shared.recording_params.clear();
+    }
+    shared.swallow_release = None;
+    shared.swallow_key = None;
+    shared.capture_lag_total = Duration::ZERO;
+    shared.capture_lag_max = Duration::ZERO;
+    shared.start_record_time = Some(at);
+    let name = slot.map_or("the recording".to_string(), |slot| {
+        format!("slot {}", slot + 1)
+    });
+    shared.recording_entry = Some(Entry::start(Kind::Recording, name));
+    shared.clipboard_seen = None;
+    if shared.record_clipboard {
+        // What is on the clipboard already may get pasted.
+        shared.clipboard_seen = clipboard::text();
+        if let Some(text) = shared.clipboard_seen.clone() {
+            shared.store_event(RecordedEvent {
+                action: MacroAction::Clipboard(text),
+                timestamp: Duration::ZERO,
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            });
+        }
+    }
+    DROPPED_EVENTS.store(0, Ordering::Relaxed);
+    shared.set_state(State::Recording);
 
     println!("Recording started.");
 }
 
+/// Records clipboard text as it changes while recording with
+/// `--record-clipboard`. There is no portable change notification, so the
+/// clipboard is polled.
+fn watch_clipboard(s: Arc<Mutex<SharedState>>) {
+    thread::spawn(move || loop {
+        thread::sleep(CLIPBOARD_POLL_INTERVAL);
+        let watching = {
+            let shared = s.lock().unwrap();
+            shared.state == State::Recording && shared.record_clipboard
+        };
+        if !watching {
+            continue;
+        }
+        let Some(text) = clipboard::text() else {
+            continue;
+        };
+        let mut shared = s.lock().unwrap();
+        let Some(start) = shared.start_record_time else {
+            continue;
+        };
+        if shared.state != State::Recording
+            || shared.clipboard_seen.as_ref() == Some(&text)
+        {
+            continue;
+        }
+        let mut elapsed = start.elapsed().unwrap_or_default();
+        if let Some(last) = shared.last_recorded_timestamp() {
+            elapsed = elapsed.max(last);
+        }
+        shared.clipboard_seen = Some(text.clone());
+        shared.store_event(RecordedEvent {
+            action: MacroAction::Clipboard(text),
+            timestamp: elapsed,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        });
+    });
+}
+
 fn stop_recording(s: &Arc<Mutex<SharedState>>) {
     let mut shared = s.lock().unwrap();
     if shared.state == State::Recording {
-        shared.state = State::Idle;
-        shared.start_record_time = None;
-        println!(
-            "Recording stopped. {} events recorded.",
-            shared.recorded_events.len()
-        );
+        shared.set_state(State::Idle);
+        let started = shared.start_record_time.take();
+        let streamed = shared.stream.is_some();
+        let recorded = match shared.stream.take() {
+            Some(mut stream) => {
+                if let Err(e) = stream.flush() {
+                    println!("Failed to write events: {}", e);
+                }
+                stream.count
+            }
+            None => shared.recording().len(),
+        };
+        println!("Recording stopped. {} events recorded.", recorded);
+        if let Some(mut entry) = shared.recording_entry.take() {
+            entry.duration = started
+                .and_then(|t| t.elapsed().ok())
+                .unwrap_or_default();
+            entry.outcome = "Stopped".to_string();
+            entry.events = recorded;
+            log_history(&shared, &entry);
+        }
+        let slot = shared.recording_slot.take();
+        if let Some(slot) = slot {
+            // Shift was held to stop the recording with the slot hotkey.
+            while let Some(RecordedEvent {
+                action:
+                    MacroAction::Input(EventType::KeyPress(
+                        RdevKey::ShiftLeft | RdevKey::ShiftRight,
+                    )),
+                ..
+            }) = shared.slot_events.last()
+            {
+                shared.slot_events.pop();
+            }
+            shared.slots[slot] = std::mem::take(&mut shared.slot_events);
+            println!("Saved to slot {}.", slot + 1);
+        }
+        if let (false, None, Some(library)) = (streamed, slot, &shared.library)
+        {
+            let events = &shared.recorded_events;
+            if let Err(e) = library.store_last_recording(events) {
+                println!("Could not keep the recording for `save`: {}", e);
+            }
+        }
+        save_session(&shared);
+        let dropped = DROPPED_EVENTS.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            println!("{} events were dropped, the event queue was full.", dropped);
+        }
+        if let Ok(count) = u32::try_from(recorded) {
+            if count > 0 {
+                println!(
+                    "Capture lag excluded from timestamps: avg {:.2} ms, \
+                     max {:.2} ms.",
+                    (shared.capture_lag_total / count).as_secs_f64() * 1000.0,
+                    shared.capture_lag_max.as_secs_f64() * 1000.0
+                );
+            }
+        }
+    }
+}
+
+/// Ends a recording or playback in progress, keeping what it can, and
+/// exits.
+fn shut_down(s: &Arc<Mutex<SharedState>>) {
+    println!("Another recorder is taking over, shutting down.");
+    stop_recording(s);
+    stop_playback(s);
+    save_session(&s.lock().unwrap());
+    std::process::exit(0);
+}
+
+fn log_history(shared: &SharedState, entry: &Entry) {
+    if let Some(path) = &shared.history_path {
+        if let Err(e) = history::append(path, entry) {
+            println!("Could not log to the history: {}", e);
+        }
+    }
+}
+
+/// Saves the working set so a restart picks up where this one left off.
+fn save_session(shared: &SharedState) {
+    if let Some(path) = &shared.session_path {
+        if let Err(e) = shared.session().save(path) {
+            println!("Could not save the session: {}", e);
+        }
     }
 }
 
-fn record_input_event(shared: &mut SharedState, event: &Event) {
+fn record_input_event(
+    shared: &mut SharedState,
+    event: &Event,
+    device: Option<&str>,
+) {
     if let Some(start) = shared.start_record_time {
-        let elapsed = Instant::now().duration_since(start);
-        shared.recorded_events.push(RecordedEvent {
-            event_type: event.event_type.clone(),
+        // Timestamp from the event itself: the time we got here also
+        // includes waiting for the lock.
+        let mut elapsed = event.time.duration_since(start).unwrap_or_default();
+        if let Some(last) = shared.last_recorded_timestamp() {
+            // Different capture threads or clock adjustments can make
+            // event times step backwards; keep the recording monotonic.
+            elapsed = elapsed.max(last);
+        }
+        let lag = SystemTime::now()
+            .duration_since(event.time)
+            .unwrap_or_default();
+        shared.capture_lag_total += lag;
+        shared.capture_lag_max = shared.capture_lag_max.max(lag);
+
+        let action = match event.event_type {
+            EventType::ButtonPress(button) if shared.target_elements => {
+                let (x, y) = shared.last_mouse_pos;
+                match accessibility::element_at(x, y) {
+                    Ok(target) => {
+                        println!("Recorded click on {}.", target);
+                        shared.swallow_release = Some(button);
+                        MacroAction::ClickElement { target, button }
+                    }
+                    Err(e) => {
+                        println!(
+                            "Element lookup failed ({}), recording raw click.",
+                            e
+                        );
+                        MacroAction::Input(event.event_type)
+                    }
+                }
+            }
+            EventType::ButtonRelease(button)
+                if shared.swallow_release == Some(button) =>
+            {
+                shared.swallow_release = None;
+                return;
+            }
+            EventType::KeyPress(key) => {
+                match ime::committed(event.name.as_deref()) {
+                    Some(text) => {
+                        shared.swallow_key = Some(key);
+                        MacroAction::Type(text.to_string())
+                    }
+                    None => MacroAction::Input(event.event_type),
+                }
+            }
+            EventType::KeyRelease(key) if shared.swallow_key == Some(key) => {
+                shared.swallow_key = None;
+                return;
+            }
+            event_type => MacroAction::Input(event_type),
+        };
+        // Played by scan code, keys are recorded by where they are.
+        let by_position = shared.backend == BackendKind::ScanCode;
+        let action = match action {
+            MacroAction::Input(input) if by_position => {
+                MacroAction::Input(scancode::positional(input))
+            }
+            action => action,
+        };
+        if let MacroAction::Input(EventType::ButtonPress(_)) = action {
+            if !shared.record_filter.records_moves() {
+                // Anchor the click where it happened since the moves that
+                // led there aren't recorded.
+                let (x, y) = shared.last_mouse_pos;
+                shared.store_event(RecordedEvent {
+                    action: MacroAction::Input(EventType::MouseMove { x, y }),
+                    timestamp: elapsed,
+                    device: device.map(str::to_string),
+                    wait: None,
+                    per_pass: None,
+                    text: None,
+                });
+            }
+        }
+        // What the key typed, so the recording can be played with another
+        // layout; capture methods that don't say leave it to the key.
+        let text = match action {
+            MacroAction::Input(EventType::KeyPress(_)) => {
+                event.name.clone().filter(|name| !name.is_empty())
+            }
+            _ => None,
+        };
+        shared.store_event(RecordedEvent {
+            action,
             timestamp: elapsed,
+            device: device.map(str::to_string),
+            wait: None,
+            per_pass: None,
+            text,
         });
     }
 }
 
-fn start_playback(s: Arc<Mutex<SharedState>>) {
-    let events = {
+/// " of the keyboard playback" when `several` are running, telling which
+/// one a message is about.
+fn playback_of(running: &Running, several: bool) -> String {
+    if several {
+        format!(" of the {} playback", running.domain.name())
+    } else {
+        String::new()
+    }
+}
+
+/// Counts a playback driving `domain` as running, unless one driving the
+/// same devices already runs or playback is paused.
+fn begin_playback(shared: &mut SharedState, domain: Domain) -> bool {
+    if !shared.playing.is_empty() && !shared.can_play_in(domain) {
+        return false;
+    }
+    if shared.playing.is_empty() {
+        shared.stop_after_pass = false;
+        shared.passes = None;
+    }
+    shared.set_state(State::Playing);
+    shared.playing.push(Running::new(domain));
+    true
+}
+
+fn start_playback(s: Arc<Mutex<SharedState>>, playback: Playback) {
+    let (source, target, logs, run, domain, slot) = {
         let mut shared = s.lock().unwrap();
 
-        if shared.recorded_events.is_empty() {
+        let has_recording = match &playback {
+            Playback::Recording => shared.has_recording(),
+            Playback::Slot(slot) => !shared.slots[*slot].is_empty(),
+            Playback::Snippet(m) => !m.events.is_empty(),
+        };
+        if !has_recording {
             println!("No events recorded.");
             return;
         }
 
-        if shared.state == State::Playing {
+        if !shared.playing.is_empty()
+            && !shared.can_play_alongside(&playback)
+        {
+            return;
+        }
+
+        if shared.backend != BackendKind::Mock
+            && !permissions::ensure(permissions::PLAY)
+        {
             return;
         }
 
-        shared.state = State::Playing;
+        let domain = shared.domain(&playback);
+        let (backend, buttons, params) = match &playback {
+            Playback::Snippet(m) => {
+                (m.backend, m.buttons.clone(), m.params.clone())
+            }
+            Playback::Recording => (
+                shared.recording_backend,
+                shared.recording_buttons.clone(),
+                shared.recording_params.clone(),
+            ),
+            Playback::Slot(_) => (None, Vec::new(), Vec::new()),
+        };
+        if let Some(name) = &shared.playback_filter.loop_from {
+            if !shared.has_checkpoint(&playback, name) {
+                println!("No checkpoint named '{}' to loop from.", name);
+                return;
+            }
+        }
+        // Playbacks asking for values start once they are answered.
+        if params.is_empty() {
+            if !begin_playback(&mut shared, domain) {
+                return;
+            }
+        } else if shared.prompt.is_some() {
+            println!("Not playing {}: already asking for values.", playback);
+            return;
+        } else {
+            shared.prompt = Some(Prompt::default());
+        }
+        let slot = match playback {
+            Playback::Slot(slot) => Some(slot),
+            _ => None,
+        };
+        let name = match &playback {
+            Playback::Recording => shared
+                .last_macro
+                .clone()
+                .unwrap_or_else(|| "the recording".to_string()),
+            Playback::Slot(slot) => format!("slot {}", slot + 1),
+            Playback::Snippet(m) => m.name.clone(),
+        };
+        let entry = Entry::start(Kind::Playback, name);
 
-        println!(
-            "Starting playback ({} events).",
-            shared.recorded_events.len()
-        );
+        // The macro's own button mapping comes before the configured one.
+        let mut filter = shared.playback_filter.clone().overlay(PlaybackFilter {
+            remap_buttons: buttons,
+            ..Default::default()
+        });
+        // Headless, nothing may reach a real device.
+        let backend = match shared.backend {
+            BackendKind::Mock => BackendKind::Mock,
+            kind => backend.unwrap_or(kind),
+        };
+        let positional = shared.target_window.is_none()
+            && layout::is_positional(backend);
+        if filter.layout.is_some() && !positional {
+            println!(
+                "Playing keys as they are: --layout only moves keys played \
+                 by position, through the uinput or scancode backend."
+            );
+            filter.layout = None;
+        }
+        let source: Box<dyn EventSource + Send> = match (
+            playback,
+            &shared.stream_path,
+        ) {
+            (Playback::Snippet(m), _) => {
+                println!("Expanding {}.", m.name);
+                Box::new(m.events)
+            }
+            (Playback::Slot(slot), _) => {
+                println!(
+                    "Playing slot {} ({} events).",
+                    slot + 1,
+                    shared.slots[slot].len()
+                );
+                Box::new(shared.slots[slot].clone())
+            }
+            (Playback::Recording, Some(path)) => {
+                println!(
+                    "Starting playback from {} at {:.1} s.",
+                    path.display(),
+                    shared.stream_start.as_secs_f64()
+                );
+                let log = EventLog::new(path).starting_at(shared.stream_start);
+                // Read ahead of playback, skipping and remapping with it.
+                match filter.take_remaps() {
+                    Some(plan) => Box::new(log.with_plan(plan)),
+                    None => Box::new(log),
+                }
+            }
+            (Playback::Recording, None) => {
+                println!(
+                    "Starting playback ({} events).",
+                    shared.recorded_events.len()
+                );
+                Box::new(shared.recorded_events.clone())
+            }
+        };
+        // Branches follow the keys held as playback is triggered.
+        let source = Branched::new(source, shared.keys_held.clone());
+        let source = (source, filter, params);
 
-        shared.recorded_events.clone()
+        let target = (
+            shared.target_window.clone(),
+            backend,
+            shared.screen_size.unwrap_or((1920, 1080)),
+        );
+        let logs = (
+            shared.report_path.clone(),
+            shared.history_path.clone(),
+            entry,
+        );
+        let run = (
+            shared.demo,
+            shared.thread_priority,
+            shared.pointer_curve.clone(),
+        );
+        (source, target, logs, run, domain, slot)
     };
+    let (window, backend, screen) = target;
+    let (demo, priority, curve) = run;
+    let (report_path, history_path, mut entry) = logs;
+    let (source, filter, params) = source;
 
     let s_for_thread = Arc::clone(&s);
 
     let handle = thread::spawn(move || {
-        let mut enigo = Enigo::new(&Settings::default()).unwrap();
-
-        loop {
-            let mut last_timestamp = Duration::ZERO;
-
-            for evt in &events {
-                let delta = evt
-                    .timestamp
-                    .checked_sub(last_timestamp)
-                    .unwrap_or(Duration::ZERO);
-                last_timestamp = evt.timestamp;
-
-                let mut remaining = delta;
-
-                while remaining > Duration::ZERO {
-                    let state = {
-                        let shared = s_for_thread.lock().unwrap();
-                        shared.state
-                    };
-
-                    match state {
-                        State::Idle | State::Recording => {
-                            println!("Playback stopped.");
-                            return;
-                        }
-                        State::Paused => {
-                            thread::sleep(Duration::from_millis(10));
-                            continue;
-                        }
-                        State::Playing => {
-                            let sleep_chunk =
-                                remaining.min(Duration::from_millis(10));
-                            thread::sleep(sleep_chunk);
-                            remaining -= sleep_chunk;
-                        }
-                    }
+        let log = |entry: &Entry| {
+            if let Some(path) = &history_path {
+                if let Err(e) = history::append(path, entry) {
+                    println!("Could not log to the history: {}", e);
                 }
-
-                perform_event(&mut enigo, &evt.event_type);
             }
-
-            let looping = {
-                let mut sh = s_for_thread.lock().unwrap();
-                if sh.looping && sh.state == State::Playing {
-                    true
-                } else {
-                    if sh.state == State::Playing {
-                        sh.state = State::Idle;
+        };
+        let not_playing = |entry: &mut Entry, why: String| {
+            println!("Not playing {}: {}.", entry.name, why);
+            entry.outcome = format!("Failed: {}", why);
+            log(entry);
+        };
+        if !params.is_empty() {
+            let mut shared = s_for_thread.lock().unwrap();
+            let prompt = shared.prompt.get_or_insert_with(Prompt::default);
+            if prompt.cancelled {
+                shared.prompt = None;
+                drop(shared);
+                not_playing(&mut entry, "stopped".to_string());
+                return;
+            }
+            prompt.thread = Some(thread::current().id());
+        }
+        // Asked here, as the dialog waits for the user, and before the
+        // playback runs, so pausing or stopping others leaves it be.
+        let answers = params::ask(&entry.name, &params).and_then(|answers| {
+            answers.ok_or_else(|| "cancelled".to_string())
+        });
+        if !params.is_empty() {
+            let began = {
+                let mut shared = s_for_thread.lock().unwrap();
+                let cancelled =
+                    shared.prompt.take().is_some_and(|prompt| prompt.cancelled);
+                match &answers {
+                    _ if cancelled => Err("stopped".to_string()),
+                    Err(e) => Err(e.clone()),
+                    Ok(_) if !begin_playback(&mut shared, domain) => {
+                        Err("its devices are in use".to_string())
                     }
-                    false
+                    Ok(_) => Ok(()),
                 }
             };
-
-            if !looping {
-                println!("Playback finished.");
-                break;
+            if let Err(why) = began {
+                not_playing(&mut entry, why);
+                return;
             }
-
-            // println!("Looping playback...");
+        }
+        let source = answers.map(|answers| {
+            Filtered::new(source, filter).with_params(answers)
+        });
+        let (boost, error) = timing::boost(priority);
+        if let Some(e) = error {
+            println!("Cannot raise the playback priority: {}", e);
+        }
+        let result = source.and_then(|source| match (window, backend) {
+            (Some(mut window), _) => {
+                Ok(play(&mut window, &source, &s_for_thread, domain, demo))
+            }
+            (None, BackendKind::Enigo) => match curve {
+                Some(curve) => RelativeMoves::new(curve).map(|mut relative| {
+                    play(&mut relative, &source, &s_for_thread, domain, demo)
+                }),
+                None => EnigoBackend::new().map(|mut enigo| {
+                    play(&mut enigo, &source, &s_for_thread, domain, demo)
+                }),
+            },
+            (None, BackendKind::Uinput) => {
+                play_uinput(&source, &s_for_thread, screen, domain, demo)
+            }
+            (None, BackendKind::ScanCode) => {
+                play_scan_codes(&source, &s_for_thread, screen, domain, demo)
+            }
+            // Nothing to show the mock's input on.
+            (None, BackendKind::Mock) => {
+                let mock = MockBackend::new();
+                Ok(play(mock, &source, &s_for_thread, domain, false))
+            }
+        });
+        drop(boost);
+        let finished = match result {
+            Ok(report) => {
+                println!("{}", report);
+                if let Some(path) = &report_path {
+                    if let Err(e) = append_report(path, &report) {
+                        println!("Could not write playback report: {}", e);
+                    }
+                }
+                entry.duration = report.wall;
+                entry.outcome = format!("{:?}", report.outcome);
+                entry.events = report.events_injected;
+                entry.passes = report.loops_completed;
+                report.outcome == Outcome::Finished
+            }
+            Err(e) => {
+                println!("Could not start playback: {}", e);
+                entry.outcome = format!("Failed: {}", e);
+                false
+            }
+        };
+        log(&entry);
+        let next = {
+            let mut shared = s_for_thread.lock().unwrap();
+            let i = shared.playing.iter().position(|r| r.domain == domain);
+            if let Some(i) = i {
+                shared.playing.remove(i);
+            }
+            let again = slot.filter(|slot| {
+                finished
+                    && !shared.stop_after_pass
+                    && shared.repeating.contains(slot)
+            });
+            if let Some(slot) = again {
+                // Repeating slots take turns with whatever is queued.
+                if shared.playing.is_empty() && !shared.queue.is_empty() {
+                    shared.queue.push_back(Playback::Slot(slot));
+                    shared.queue.pop_front()
+                } else {
+                    Some(Playback::Slot(slot))
+                }
+            } else if !shared.playing.is_empty() {
+                None
+            } else {
+                if shared.state == State::Playing {
+                    shared.set_state(State::Idle);
+                }
+                shared.idle_playing = false;
+                // A stopped or failed playback leaves the queue for the
+                // user to deal with.
+                finished.then(|| shared.queue.pop_front()).flatten()
+            }
+        };
+        if let Some(next) = next {
+            start_playback(s_for_thread, next);
         }
     });
 
     let mut shared = s.lock().unwrap();
-    shared.playback_thread = Some(handle);
+    shared.playback_threads.retain(|h| !h.is_finished());
+    shared.playback_threads.push(handle);
+}
+
+/// Plays `source` into `backend`, showing it on screen in demo mode.
+fn play<B: InputBackend>(
+    mut backend: B,
+    source: &(dyn EventSource + Send),
+    s: &Arc<Mutex<SharedState>>,
+    domain: Domain,
+    demo: bool,
+) -> PlaybackReport {
+    let overlay = match demo.then(Overlay::start) {
+        Some(Ok(overlay)) => Some(overlay),
+        Some(Err(e)) => {
+            println!("Demo mode is unavailable: {}", e);
+            None
+        }
+        None => None,
+    };
+    match overlay {
+        Some(overlay) => {
+            let mut demo = Demo::new(backend, overlay);
+            player::run(&mut demo, source, s, Some(domain))
+        }
+        None => player::run(&mut backend, source, s, Some(domain)),
+    }
+}
+
+fn append_report(path: &Path, report: &PlaybackReport) -> Result<(), String> {
+    let line = serde_json::to_string(report).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(target_os = "linux")]
+fn play_uinput(
+    source: &(dyn EventSource + Send),
+    s: &Arc<Mutex<SharedState>>,
+    screen: (i32, i32),
+    domain: Domain,
+    demo: bool,
+) -> Result<PlaybackReport, String> {
+    let device = realtime_macro::uinput::UinputBackend::new(screen)?;
+    Ok(play(device, source, s, domain, demo))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn play_uinput(
+    _source: &(dyn EventSource + Send),
+    _s: &Arc<Mutex<SharedState>>,
+    _screen: (i32, i32),
+    _domain: Domain,
+    _demo: bool,
+) -> Result<PlaybackReport, String> {
+    Err("the uinput backend is only available on Linux".into())
+}
+
+#[cfg(target_os = "windows")]
+fn play_scan_codes(
+    source: &(dyn EventSource + Send),
+    s: &Arc<Mutex<SharedState>>,
+    _screen: (i32, i32),
+    domain: Domain,
+    demo: bool,
+) -> Result<PlaybackReport, String> {
+    let backend = realtime_macro::backend::ScanCodeBackend::new()?;
+    Ok(play(backend, source, s, domain, demo))
+}
+
+/// Input from uinput looks like a real keyboard's to everything reading it.
+#[cfg(target_os = "linux")]
+fn play_scan_codes(
+    source: &(dyn EventSource + Send),
+    s: &Arc<Mutex<SharedState>>,
+    screen: (i32, i32),
+    domain: Domain,
+    demo: bool,
+) -> Result<PlaybackReport, String> {
+    play_uinput(source, s, screen, domain, demo)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn play_scan_codes(
+    _source: &(dyn EventSource + Send),
+    _s: &Arc<Mutex<SharedState>>,
+    _screen: (i32, i32),
+    _domain: Domain,
+    _demo: bool,
+) -> Result<PlaybackReport, String> {
+    Err("the scancode backend is only available on Windows and Linux".into())
 }
 
 fn stop_playback(s: &Arc<Mutex<SharedState>>) {
-    let handle = {
+    let handles = {
         let mut shared = s.lock().unwrap();
 
         if shared.state == State::Playing
             || shared.state == State::Paused
         {
-            shared.state = State::Idle;
+            shared.set_state(State::Idle);
             println!("Stopping playback...");
         }
 
-        shared.playback_thread.take()
+        // A playback still asking for values waits for the user, so it is
+        // only told not to start.
+        let asking = shared.prompt.as_mut().and_then(|prompt| {
+            prompt.cancelled = true;
+            prompt.thread
+        });
+        let (asking, handles) = std::mem::take(&mut shared.playback_threads)
+            .into_iter()
+            .partition(|h| Some(h.thread().id()) == asking);
+        shared.playback_threads = asking;
+        handles
     };
 
-    if let Some(h) = handle {
+    for h in handles {
         let _ = h.join();
     }
 }
-
-fn perform_event(enigo: &mut Enigo, evt: &EventType) {
-    match evt {
-        EventType::MouseMove { x, y } => {
-            enigo.move_mouse(*x as i32, *y as i32, Coordinate::Abs).unwrap();
-        }
-        EventType::ButtonPress(button) => match button {
-            rdev::Button::Left => {
-                enigo.button(Button::Left, Direction::Press).unwrap()
-            }
-            rdev::Button::Right => {
-                enigo.button(Button::Right, Direction::Press).unwrap()
-            }
-            rdev::Button::Middle => {
-                enigo.button(Button::Middle, Direction::Press).unwrap()
-            }
-            _ => {}
-        },
-        EventType::ButtonRelease(button) => match button {
-            rdev::Button::Left => {
-                enigo.button(Button::Left, Direction::Release).unwrap()
-            }
-            rdev::Button::Right => {
-                enigo.button(Button::Right, Direction::Release).unwrap()
-            }
-            rdev::Button::Middle => {
-                enigo.button(Button::Middle, Direction::Release).unwrap()
-            }
-            _ => {}
-        },
-        EventType::Wheel { delta_x, delta_y } => {
-            if *delta_y != 0 {
-                enigo.scroll(*delta_y as i32, Axis::Vertical).unwrap();
-            }
-            if *delta_x != 0 {
-                enigo.scroll(*delta_x as i32, Axis::Horizontal).unwrap();
-            }
-        }
-        EventType::KeyPress(key) => {
-            if let Some(k) = rdev_key_to_enigo_key(*key) {
-                enigo.key(k, Direction::Press).unwrap();
-            }
-        }
-        EventType::KeyRelease(key) => {
-            if let Some(k) = rdev_key_to_enigo_key(*key) {
-                enigo.key(k, Direction::Release).unwrap();
-            }
-        }
-    }
-}
-
-fn rdev_key_to_enigo_key(rkey: RdevKey) -> Option<Key> {
-    use RdevKey::*;
-    match rkey {
-        Num0 => Some(Key::Num0),
-        Num1 => Some(Key::Num1),
-        Num2 => Some(Key::Num2),
-        Num3 => Some(Key::Num3),
-        Num4 => Some(Key::Num4),
-        Num5 => Some(Key::Num5),
-        Num6 => Some(Key::Num6),
-        Num7 => Some(Key::Num7),
-        Num8 => Some(Key::Num8),
-        Num9 => Some(Key::Num9),
-        KeyA => Some(Key::A),
-        KeyB => Some(Key::B),
-        KeyC => Some(Key::C),
-        KeyD => Some(Key::D),
-        KeyE => Some(Key::E),
-        KeyF => Some(Key::F),
-        KeyG => Some(Key::G),
-        KeyH => Some(Key::H),
-        KeyI => Some(Key::I),
-        KeyJ => Some(Key::J),
-        KeyK => Some(Key::K),
-        KeyL => Some(Key::L),
-        KeyM => Some(Key::M),
-        KeyN => Some(Key::N),
-        KeyO => Some(Key::O),
-        KeyP => Some(Key::P),
-        KeyQ => Some(Key::Q),
-        KeyR => Some(Key::R),
-        KeyS => Some(Key::S),
-        KeyT => Some(Key::T),
-        KeyU => Some(Key::U),
-        KeyV => Some(Key::V),
-        KeyW => Some(Key::W),
-        KeyX => Some(Key::X),
-        KeyY => Some(Key::Y),
-        KeyZ => Some(Key::Z),
-        ShiftLeft => Some(Key::LShift),
-        ShiftRight => Some(Key::RShift),
-        ControlLeft => Some(Key::LControl),
-        ControlRight => Some(Key::RControl),
-        Space => Some(Key::Space),
-        Return => Some(Key::Return),
-        Backspace => Some(Key::Backspace),
-        Tab => Some(Key::Tab),
-        Escape => Some(Key::Escape),
-        _ => None,
-    }
-}
\ No newline at end of file
@@ -0,0 +1,168 @@
+//! Live input statistics: actions per minute, the keys pressed most and
+//! how far the mouse travelled. Nothing is recorded.
+
+use rdev::{EventType, Key};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Window the live actions per minute are counted over.
+const APM_WINDOW: Duration = Duration::from_secs(60);
+
+/// Keys listed in the summary.
+const TOP_KEYS: usize = 10;
+
+/// Counts input as it arrives. Key and button presses are actions; held
+/// keys repeating don't count again.
+pub struct Stats {
+    started: Instant,
+    // actions within the last APM_WINDOW
+    recent: VecDeque<Instant>,
+    peak_apm: f64,
+    key_presses: usize,
+    clicks: usize,
+    scrolls: usize,
+    keys: Vec<(Key, usize)>,
+    held: Vec<Key>,
+    distance: f64,
+    pointer: Option<(f64, f64)>,
+}
+
+impl Stats {
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            recent: VecDeque::new(),
+            peak_apm: 0.0,
+            key_presses: 0,
+            clicks: 0,
+            scrolls: 0,
+            keys: Vec::new(),
+            held: Vec::new(),
+            distance: 0.0,
+            pointer: None,
+        }
+    }
+
+    pub fn input(&mut self, event: &EventType, at: Instant) {
+        match *event {
+            EventType::KeyPress(key) if !self.held.contains(&key) => {
+                self.held.push(key);
+                self.key_presses += 1;
+                match self.keys.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, count)) => *count += 1,
+                    None => self.keys.push((key, 1)),
+                }
+                self.action(at);
+            }
+            EventType::KeyRelease(key) => self.held.retain(|k| *k != key),
+            EventType::ButtonPress(_) => {
+                self.clicks += 1;
+                self.action(at);
+            }
+            EventType::Wheel { .. } => self.scrolls += 1,
+            EventType::MouseMove { x, y } => {
+                if let Some((px, py)) = self.pointer {
+                    self.distance += (x - px).hypot(y - py);
+                }
+                self.pointer = Some((x, y));
+            }
+            _ => {}
+        }
+    }
+
+    fn action(&mut self, at: Instant) {
+        self.recent.push_back(at);
+        self.apm(at);
+    }
+
+    /// Actions over the last minute, or extrapolated to a minute early in
+    /// the session.
+    pub fn apm(&mut self, now: Instant) -> f64 {
+        while self
+            .recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > APM_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        let window = now.duration_since(self.started).min(APM_WINDOW);
+        // Too short a time to extrapolate from.
+        if window < Duration::from_secs(5) {
+            return self.recent.len() as f64;
+        }
+        let apm = self.recent.len() as f64 * 60.0 / window.as_secs_f64();
+        self.peak_apm = self.peak_apm.max(apm);
+        apm
+    }
+
+    pub fn summary(&mut self, now: Instant) -> Summary {
+        self.apm(now);
+        let elapsed = now.duration_since(self.started);
+        let actions = self.key_presses + self.clicks;
+        let minutes = elapsed.as_secs_f64() / 60.0;
+        let mut keys = self.keys.clone();
+        keys.sort_by_key(|(_, count)| Reverse(*count));
+        Summary {
+            seconds: elapsed.as_secs_f64(),
+            actions,
+            average_apm: if minutes > 0.0 {
+                actions as f64 / minutes
+            } else {
+                0.0
+            },
+            peak_apm: self.peak_apm,
+            key_presses: self.key_presses,
+            clicks: self.clicks,
+            scrolls: self.scrolls,
+            mouse_distance: self.distance,
+            top_keys: keys
+                .into_iter()
+                .take(TOP_KEYS)
+                .map(|(key, count)| (format!("{:?}", key), count))
+                .collect(),
+        }
+    }
+}
+
+/// Statistics over a whole monitoring session.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub seconds: f64,
+    pub actions: usize,
+    pub average_apm: f64,
+    pub peak_apm: f64,
+    pub key_presses: usize,
+    pub clicks: usize,
+    pub scrolls: usize,
+    /// In pixels.
+    pub mouse_distance: f64,
+    /// The keys pressed most, most pressed first.
+    pub top_keys: Vec<(String, usize)>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} actions in {:.0} s: {:.0} APM on average, {:.0} at peak",
+            self.actions, self.seconds, self.average_apm, self.peak_apm
+        )?;
+        writeln!(
+            f,
+            "{} key presses, {} clicks, {} scrolls, mouse moved {:.0} px",
+            self.key_presses, self.clicks, self.scrolls, self.mouse_distance
+        )?;
+        if !self.top_keys.is_empty() {
+            let keys: Vec<String> = self
+                .top_keys
+                .iter()
+                .map(|(key, count)| format!("{} {}", key, count))
+                .collect();
+            writeln!(f, "Most pressed: {}", keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
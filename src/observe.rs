@@ -0,0 +1,70 @@
+//! Callbacks for front-ends and plugins reacting to what the recorder does,
+//! instead of polling the shared state.
+//!
+//! Callbacks run on the thread doing the work, some with the shared state
+//! locked, so they must return quickly and must not lock it themselves.
+//! [`channel`] hands the values to another thread instead.
+
+use crate::event::RecordedEvent;
+use crate::state::State;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+type Callback<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+/// The callbacks registered, kept in [`SharedState::observers`].
+///
+/// [`SharedState::observers`]: crate::state::SharedState::observers
+#[derive(Default, Clone)]
+pub struct Observers {
+    recorded: Vec<Callback<RecordedEvent>>,
+    played: Vec<Callback<RecordedEvent>>,
+    state_changed: Vec<Callback<(State, State)>>,
+}
+
+impl Observers {
+    /// Calls `f` with each event stored in a recording.
+    pub fn on_event_recorded(
+        &mut self,
+        f: impl Fn(&RecordedEvent) + Send + Sync + 'static,
+    ) {
+        self.recorded.push(Arc::new(f));
+    }
+
+    /// Calls `f` with each event once it has been played.
+    pub fn on_event_played(
+        &mut self,
+        f: impl Fn(&RecordedEvent) + Send + Sync + 'static,
+    ) {
+        self.played.push(Arc::new(f));
+    }
+
+    /// Calls `f` with the old and new state on every state change.
+    pub fn on_state_change(
+        &mut self,
+        f: impl Fn(&(State, State)) + Send + Sync + 'static,
+    ) {
+        self.state_changed.push(Arc::new(f));
+    }
+
+    pub(crate) fn event_recorded(&self, evt: &RecordedEvent) {
+        self.recorded.iter().for_each(|f| f(evt));
+    }
+
+    pub(crate) fn event_played(&self, evt: &RecordedEvent) {
+        self.played.iter().for_each(|f| f(evt));
+    }
+
+    pub(crate) fn state_changed(&self, from: State, to: State) {
+        self.state_changed.iter().for_each(|f| f(&(from, to)));
+    }
+}
+
+/// A callback sending what it is called with to the receiver returned, e.g.
+/// `let (f, rx) = channel(); observers.on_event_played(f);`.
+pub fn channel<T: Clone + Send + 'static>(
+) -> (impl Fn(&T) + Send + Sync + 'static, Receiver<T>) {
+    let (tx, rx) = mpsc::channel();
+    // Once the receiver is dropped the values just go nowhere.
+    (move |value: &T| drop(tx.send(value.clone())), rx)
+}
@@ -0,0 +1,218 @@
+//! Values a macro asks for before it plays, filling in its placeholders,
+//! e.g. `{customer}` in typed text.
+//!
+//! They are asked for in the terminal when there is one, and otherwise in
+//! a small dialog, so macros started from a hotkey or a trigger can ask
+//! too.
+
+use crate::vars::Vars;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::str::FromStr;
+
+/// A value asked for before playing, filling the placeholder `{name}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    /// Shown when asking; the name if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Filled in to start with, and used when the answer is left empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// `NAME` or `NAME=DEFAULT`.
+impl FromStr for Param {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, default) = match s.split_once('=') {
+            Some((name, default)) => (name, Some(default.to_string())),
+            None => (s, None),
+        };
+        let name = name.trim();
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("invalid parameter name '{}'", name));
+        }
+        Ok(Param {
+            name: name.to_string(),
+            prompt: None,
+            default,
+        })
+    }
+}
+
+/// Asks for the values of `params` before playing the macro `name`.
+/// Returns `None` if the user cancels.
+pub fn ask(name: &str, params: &[Param]) -> Result<Option<Vars>, String> {
+    let terminal = io::stdin().is_terminal();
+    if terminal && !params.is_empty() {
+        println!("{} needs some values:", name);
+    }
+    let mut values = Vars::new();
+    for param in params {
+        let question = param.prompt.as_deref().unwrap_or(&param.name);
+        let default = param.default.as_deref().unwrap_or("");
+        let answer = if terminal {
+            ask_terminal(question, default)?
+        } else {
+            platform::dialog(&format!("Playing {}", name), question, default)?
+        };
+        let Some(answer) = answer else {
+            return Ok(None);
+        };
+        let answer = match answer.is_empty() {
+            true => default.to_string(),
+            false => answer,
+        };
+        values.insert(param.name.clone(), answer);
+    }
+    Ok(Some(values))
+}
+
+/// Reads a line, or `None` at the end of input.
+fn ask_terminal(
+    question: &str,
+    default: &str,
+) -> Result<Option<String>, String> {
+    match default {
+        "" => print!("  {}: ", question),
+        _ => print!("  {} [{}]: ", question, default),
+    }
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    Ok((read > 0).then(|| answer.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Through a small Windows Forms dialog shown from PowerShell, which,
+/// unlike Visual Basic's input box, tells cancelling from an empty answer.
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::process::Command;
+
+    /// The dialog's exit code when cancelled or closed.
+    const CANCELLED: i32 = 1;
+
+    pub fn dialog(
+        title: &str,
+        question: &str,
+        default: &str,
+    ) -> Result<Option<String>, String> {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+        let script = format!(
+            "[Console]::OutputEncoding = [Text.Encoding]::UTF8; \
+             Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+             $form = New-Object Windows.Forms.Form; \
+             $form.Text = {}; \
+             $form.ClientSize = New-Object Drawing.Size(340, 100); \
+             $form.FormBorderStyle = 'FixedDialog'; \
+             $form.StartPosition = 'CenterScreen'; \
+             $form.TopMost = $true; \
+             $label = New-Object Windows.Forms.Label; \
+             $label.Text = {}; \
+             $label.SetBounds(10, 10, 320, 20); \
+             $box = New-Object Windows.Forms.TextBox; \
+             $box.Text = {}; \
+             $box.SetBounds(10, 35, 320, 20); \
+             $ok = New-Object Windows.Forms.Button; \
+             $ok.Text = 'OK'; \
+             $ok.DialogResult = 'OK'; \
+             $ok.SetBounds(170, 65, 75, 25); \
+             $cancel = New-Object Windows.Forms.Button; \
+             $cancel.Text = 'Cancel'; \
+             $cancel.DialogResult = 'Cancel'; \
+             $cancel.SetBounds(255, 65, 75, 25); \
+             $form.Controls.AddRange(@($label, $box, $ok, $cancel)); \
+             $form.AcceptButton = $ok; \
+             $form.CancelButton = $cancel; \
+             if ($form.ShowDialog() -ne 'OK') {{ exit {} }}; \
+             [Console]::Out.Write($box.Text)",
+            quote(title),
+            quote(question),
+            quote(default),
+            CANCELLED
+        );
+        let output = Command::new("powershell")
+            .args(["-NoProfile", "-Command", &script])
+            .output()
+            .map_err(|e| format!("cannot run powershell: {}", e))?;
+        match output.status.code() {
+            Some(0) => {}
+            Some(CANCELLED) => return Ok(None),
+            _ => return Err(format!("powershell failed ({})", output.status)),
+        }
+        let answer = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(answer.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    /// AppleScript's code for the user cancelling.
+    const USER_CANCELED: &str = "-128";
+
+    pub fn dialog(
+        title: &str,
+        question: &str,
+        default: &str,
+    ) -> Result<Option<String>, String> {
+        let quote = |s: &str| {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        };
+        let script = format!(
+            "text returned of (display dialog {} default answer {} \
+             with title {})",
+            quote(question),
+            quote(default),
+            quote(title)
+        );
+        let output = Command::new("osascript")
+            .args(["-e", &script])
+            .output()
+            .map_err(|e| format!("cannot run osascript: {}", e))?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            if error.contains(USER_CANCELED) {
+                return Ok(None);
+            }
+            return Err(format!("cannot show a dialog: {}", error.trim()));
+        }
+        let answer = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(answer.trim_end_matches('\n').to_string()))
+    }
+}
+
+/// Through `zenity`, which most desktops have or can install.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::process::Command;
+
+    pub fn dialog(
+        title: &str,
+        question: &str,
+        default: &str,
+    ) -> Result<Option<String>, String> {
+        let output = Command::new("zenity")
+            .args(["--entry", "--title", title, "--text", question])
+            .args(["--entry-text", default])
+            .output()
+            .map_err(|e| format!("cannot run zenity: {}", e))?;
+        match output.status.code() {
+            Some(0) => {}
+            // Cancelled or closed.
+            Some(1) => return Ok(None),
+            _ => return Err(format!("zenity failed ({})", output.status)),
+        }
+        let answer = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(answer.trim_end_matches('\n').to_string()))
+    }
+}
@@ -0,0 +1,166 @@
+//! OS permissions needed for capture and injection.
+//!
+//! Only macOS gates input behind explicit user consent; without it capture
+//! and playback fail silently, so missing permissions are detected up front
+//! and re-checked before each record/play.
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Permission {
+    /// Needed to inject input and to read the accessibility tree.
+    Accessibility,
+    /// Needed to observe global keyboard and mouse input.
+    InputMonitoring,
+}
+
+impl Permission {
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "Accessibility",
+            Permission::InputMonitoring => "Input Monitoring",
+        }
+    }
+}
+
+pub const RECORD: &[Permission] =
+    &[Permission::Accessibility, Permission::InputMonitoring];
+pub const PLAY: &[Permission] = &[Permission::Accessibility];
+
+/// Permissions out of `required` that haven't been granted.
+pub fn missing(required: &[Permission]) -> Vec<Permission> {
+    required
+        .iter()
+        .copied()
+        .filter(|p| !platform::is_granted(*p))
+        .collect()
+}
+
+/// Prompts for each missing permission and opens its System Settings pane.
+pub fn request(missing: &[Permission]) {
+    for permission in missing {
+        println!("Missing {} permission.", permission.name());
+        platform::request(*permission);
+    }
+    if !missing.is_empty() {
+        println!(
+            "Grant access to this program in System Settings > Privacy & \
+             Security, then restart it if hotkeys still don't respond."
+        );
+    }
+}
+
+/// Re-checks `required` right before an action, explaining what's missing.
+pub fn ensure(required: &[Permission]) -> bool {
+    let missing = missing(required);
+    for permission in &missing {
+        println!(
+            "{} permission is still missing, see System Settings > Privacy & \
+             Security.",
+            permission.name()
+        );
+    }
+    missing.is_empty()
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Permission;
+    use std::ffi::c_void;
+    use std::process::Command;
+
+    type CFTypeRef = *const c_void;
+
+    const K_IOHID_REQUEST_TYPE_LISTEN_EVENT: u32 = 1;
+    const K_IOHID_ACCESS_TYPE_GRANTED: u32 = 0;
+
+    #[repr(C)]
+    struct CFDictionaryCallBacks {
+        _private: [u8; 0],
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        static kAXTrustedCheckOptionPrompt: CFTypeRef;
+        fn AXIsProcessTrusted() -> bool;
+        fn AXIsProcessTrustedWithOptions(options: CFTypeRef) -> bool;
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOHIDCheckAccess(request_type: u32) -> u32;
+        fn IOHIDRequestAccess(request_type: u32) -> bool;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFBooleanTrue: CFTypeRef;
+        static kCFTypeDictionaryKeyCallBacks: CFDictionaryCallBacks;
+        static kCFTypeDictionaryValueCallBacks: CFDictionaryCallBacks;
+        fn CFDictionaryCreate(
+            allocator: CFTypeRef,
+            keys: *const CFTypeRef,
+            values: *const CFTypeRef,
+            count: isize,
+            key_callbacks: *const CFDictionaryCallBacks,
+            value_callbacks: *const CFDictionaryCallBacks,
+        ) -> CFTypeRef;
+        fn CFRelease(cf: CFTypeRef);
+    }
+
+    pub fn is_granted(permission: Permission) -> bool {
+        unsafe {
+            match permission {
+                Permission::Accessibility => AXIsProcessTrusted(),
+                Permission::InputMonitoring => {
+                    IOHIDCheckAccess(K_IOHID_REQUEST_TYPE_LISTEN_EVENT)
+                        == K_IOHID_ACCESS_TYPE_GRANTED
+                }
+            }
+        }
+    }
+
+    pub fn request(permission: Permission) {
+        let pane = match permission {
+            Permission::Accessibility => {
+                unsafe {
+                    let keys = [kAXTrustedCheckOptionPrompt];
+                    let values = [kCFBooleanTrue];
+                    let options = CFDictionaryCreate(
+                        std::ptr::null(),
+                        keys.as_ptr(),
+                        values.as_ptr(),
+                        1,
+                        &kCFTypeDictionaryKeyCallBacks,
+                        &kCFTypeDictionaryValueCallBacks,
+                    );
+                    AXIsProcessTrustedWithOptions(options);
+                    CFRelease(options);
+                }
+                "Privacy_Accessibility"
+            }
+            Permission::InputMonitoring => {
+                unsafe {
+                    IOHIDRequestAccess(K_IOHID_REQUEST_TYPE_LISTEN_EVENT);
+                }
+                "Privacy_ListenEvent"
+            }
+        };
+        let url = format!(
+            "x-apple.systempreferences:com.apple.preference.security?{}",
+            pane
+        );
+        if let Err(e) = Command::new("open").arg(&url).status() {
+            println!("Could not open System Settings: {}", e);
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod platform {
+    use super::Permission;
+
+    pub fn is_granted(_permission: Permission) -> bool {
+        true
+    }
+
+    pub fn request(_permission: Permission) {}
+}
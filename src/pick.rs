@@ -0,0 +1,149 @@
+//! Pick mode, showing the pointer position and the color under it for
+//! writing macros by hand.
+
+use crate::event::{MacroAction, RecordedEvent};
+use crate::state::SharedState;
+use rdev::{Button, EventType};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often the shown position is brought up to date.
+const REFRESH: Duration = Duration::from_millis(100);
+
+/// `#rrggbb` for a color.
+pub fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The position and, where it can be read, the color under the pointer.
+pub fn describe((x, y): (f64, f64)) -> String {
+    let (x, y) = (x.round() as i32, y.round() as i32);
+    match platform::color_at(x, y) {
+        Ok(color) => format!("({}, {}) {}", x, y, hex(color)),
+        Err(_) => format!("({}, {})", x, y),
+    }
+}
+
+/// A left click at `pos`, moving there first.
+pub fn click_at(at: Duration, (x, y): (f64, f64)) -> Vec<RecordedEvent> {
+    [
+        EventType::MouseMove { x, y },
+        EventType::ButtonPress(Button::Left),
+        EventType::ButtonRelease(Button::Left),
+    ]
+    .into_iter()
+    .map(|event_type| RecordedEvent {
+        action: MacroAction::Input(event_type),
+        timestamp: at,
+        device: None,
+        wait: None,
+        per_pass: None,
+        text: None,
+    })
+    .collect()
+}
+
+/// Shows the pointer position on one line, as it moves, until pick mode
+/// is turned off.
+pub fn spawn_display(shared: Arc<Mutex<SharedState>>) {
+    thread::spawn(move || {
+        let mut shown = None;
+        loop {
+            let pos = {
+                let shared = shared.lock().unwrap();
+                if !shared.picking {
+                    break;
+                }
+                shared.last_mouse_pos
+            };
+            if shown != Some(pos) {
+                shown = Some(pos);
+                let described = describe(pos);
+                // Pick mode may have ended meanwhile; turning it off ends
+                // the line.
+                let guard = shared.lock().unwrap();
+                if guard.picking {
+                    print!("\rPointer at {}    ", described);
+                    let _ = io::stdout().flush();
+                }
+            }
+            thread::sleep(REFRESH);
+        }
+    });
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::Graphics::Gdi::{GetDC, GetPixel, ReleaseDC};
+
+    /// Returned by `GetPixel` for points off the screen.
+    const CLR_INVALID: u32 = 0xffff_ffff;
+
+    pub fn color_at(x: i32, y: i32) -> Result<[u8; 3], String> {
+        let color = unsafe {
+            let dc = GetDC(HWND::default());
+            let color = GetPixel(dc, x, y);
+            ReleaseDC(HWND::default(), dc);
+            color.0
+        };
+        if color == CLR_INVALID {
+            return Err("no pixel there".into());
+        }
+        Ok([color as u8, (color >> 8) as u8, (color >> 16) as u8])
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::env;
+    use std::fs::{self, File};
+    use std::process::Command;
+
+    pub fn color_at(x: i32, y: i32) -> Result<[u8; 3], String> {
+        let path = env::temp_dir().join("realtime-macro-pick.png");
+        let status = Command::new("screencapture")
+            .args(["-x", "-t", "png", &format!("-R{},{},1,1", x, y)])
+            .arg(&path)
+            .status()
+            .map_err(|e| format!("cannot run screencapture: {}", e))?;
+        if !status.success() {
+            return Err(format!("screencapture failed ({})", status));
+        }
+        let file = File::open(&path).map_err(|e| e.to_string())?;
+        let mut decoder = png::Decoder::new(file);
+        decoder
+            .set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+        let _ = fs::remove_file(&path);
+        match info.color_type {
+            png::ColorType::Rgb | png::ColorType::Rgba => {
+                Ok([buffer[0], buffer[1], buffer[2]])
+            }
+            _ => Ok([buffer[0]; 3]),
+        }
+    }
+}
+
+/// Through ImageMagick's `import`, on X11 only.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::process::Command;
+
+    pub fn color_at(x: i32, y: i32) -> Result<[u8; 3], String> {
+        let output = Command::new("import")
+            .args(["-window", "root", "-crop"])
+            .arg(format!("1x1+{}+{}", x, y))
+            .args(["-depth", "8", "rgb:-"])
+            .output()
+            .map_err(|e| format!("cannot run import: {}", e))?;
+        match output.stdout[..] {
+            [r, g, b, ..] if output.status.success() => Ok([r, g, b]),
+            _ => Err(format!("import failed ({})", output.status)),
+        }
+    }
+}
@@ -0,0 +1,933 @@
+//! The replay engine, generic over the backend that receives the input.
+
+use crate::accessibility;
+use crate::backend::InputBackend;
+use crate::clipboard;
+use crate::screenshot;
+use crate::event::{MacroAction, RecordedEvent};
+use crate::state::{SharedState, State};
+use crate::synthetic::Tagged;
+use crate::timing::{self, Clock, Scheduler};
+use crate::wait::Condition;
+use rdev::{Button, EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Where the player reads events from; opened again for every loop pass.
+pub trait EventSource {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String>;
+
+    /// How many passes to play, whether looping or not, for sources that
+    /// have a set number.
+    fn passes(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl EventSource for [RecordedEvent] {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        Ok(Box::new(self.iter().cloned()))
+    }
+}
+
+impl EventSource for Vec<RecordedEvent> {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        self.as_slice().open()
+    }
+}
+
+impl<T: EventSource + ?Sized> EventSource for Box<T> {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        (**self).open()
+    }
+
+    fn passes(&self) -> Option<usize> {
+        (**self).passes()
+    }
+}
+
+/// What to do with a playback requested while another one is running.
+#[derive(Debug, PartialEq, Copy, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum WhenBusy {
+    /// Play it once the running ones have finished.
+    #[default]
+    Queue,
+    Reject,
+    /// Stop the running playback and play it instead.
+    Interrupt,
+}
+
+impl WhenBusy {
+    const ALL: [WhenBusy; 3] =
+        [WhenBusy::Queue, WhenBusy::Reject, WhenBusy::Interrupt];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WhenBusy::Queue => "queue",
+            WhenBusy::Reject => "reject",
+            WhenBusy::Interrupt => "interrupt",
+        }
+    }
+}
+
+impl FromStr for WhenBusy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|w| w.name() == s).ok_or_else(|| {
+            format!("unknown busy policy '{}' (queue, reject, interrupt)", s)
+        })
+    }
+}
+
+impl TryFrom<String> for WhenBusy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Which devices a macro drives. Macros driving different ones can play
+/// at the same time.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Domain {
+    Keyboard,
+    Mouse,
+    Both,
+}
+
+impl Domain {
+    pub fn of<'a>(events: impl IntoIterator<Item = &'a RecordedEvent>) -> Self {
+        let mut domain = None;
+        for evt in events {
+            let this = match &evt.action {
+                MacroAction::Input(
+                    EventType::KeyPress(_) | EventType::KeyRelease(_),
+                )
+                | MacroAction::Clipboard(_)
+                | MacroAction::Type(_) => Domain::Keyboard,
+                MacroAction::Input(_) | MacroAction::ClickElement { .. } => {
+                    Domain::Mouse
+                }
+                // Waiting and screenshots don't drive anything.
+                MacroAction::Wait { .. }
+                | MacroAction::Cleanup
+                | MacroAction::Screenshot { .. }
+                | MacroAction::Checkpoint(_) => continue,
+                MacroAction::IfKeyHeld {
+                    then, otherwise, ..
+                } => {
+                    if then.is_empty() && otherwise.is_empty() {
+                        continue;
+                    }
+                    Domain::of(then.iter().chain(otherwise))
+                }
+            };
+            match domain {
+                None => domain = Some(this),
+                Some(d) if d != this => return Domain::Both,
+                Some(_) => {}
+            }
+        }
+        domain.unwrap_or(Domain::Both)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Domain::Keyboard => "keyboard",
+            Domain::Mouse => "mouse",
+            Domain::Both => "keyboard and mouse",
+        }
+    }
+
+    pub fn overlaps(self, other: Domain) -> bool {
+        self == Domain::Both || other == Domain::Both || self == other
+    }
+}
+
+/// What playback does when the user uses the mouse or keyboard meanwhile.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum UserOverride {
+    Pause,
+    Stop,
+}
+
+impl FromStr for UserOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(UserOverride::Pause),
+            "stop" => Ok(UserOverride::Stop),
+            _ => Err(format!("unknown user override '{}' (pause, stop)", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for UserOverride {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// How a playback run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum Outcome {
+    #[default]
+    Finished,
+    Stopped,
+    TargetLost,
+    ReadError,
+    /// A wait step timed out or couldn't check its condition.
+    WaitFailed,
+}
+
+/// How far a playback has got, sent to [`SharedState::progress`] after
+/// each event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Loop passes completed.
+    pub passes: usize,
+    /// Recording time of the last event played in this pass.
+    pub at: Duration,
+    pub events_injected: usize,
+}
+
+/// A section of the pass marked while paused to play over and over, e.g.
+/// to watch a step that goes wrong.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoopMarks {
+    pub start: Option<Duration>,
+    pub end: Option<Duration>,
+}
+
+/// Summary of a playback run, for logs of unattended runs.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlaybackReport {
+    pub outcome: Outcome,
+    pub events_injected: usize,
+    pub injection_errors: usize,
+    pub loops_completed: usize,
+    /// Recording time covered by the events that were played.
+    pub recorded: Duration,
+    pub wall: Duration,
+    /// How late the last event was injected relative to its schedule.
+    pub final_drift: Duration,
+    pub max_drift: Duration,
+}
+
+impl PlaybackReport {
+    pub(crate) fn injected(
+        &mut self,
+        result: Result<(), String>,
+        count: usize,
+    ) {
+        match result {
+            Ok(()) => self.events_injected += count,
+            Err(e) => {
+                println!("Injection failed: {}", e);
+                self.injection_errors += count;
+            }
+        }
+    }
+}
+
+/// Keys and buttons the player has pressed but not released yet.
+#[derive(Default)]
+pub(crate) struct Held {
+    keys: Vec<Key>,
+    buttons: Vec<Button>,
+}
+
+impl Held {
+    pub(crate) fn update(&mut self, evt: &EventType) {
+        match *evt {
+            EventType::KeyPress(key) if !self.keys.contains(&key) => {
+                self.keys.push(key)
+            }
+            EventType::KeyRelease(key) => self.keys.retain(|k| *k != key),
+            EventType::ButtonPress(b) if !self.buttons.contains(&b) => {
+                self.buttons.push(b)
+            }
+            EventType::ButtonRelease(b) => self.buttons.retain(|h| *h != b),
+            _ => {}
+        }
+    }
+
+    /// Releases everything still held, so a playback that is stopped or
+    /// recorded without the release doesn't leave a key stuck down.
+    pub(crate) fn release<B: InputBackend>(
+        &mut self,
+        backend: &mut B,
+        report: &mut PlaybackReport,
+    ) {
+        let keys = self.keys.drain(..).map(EventType::KeyRelease);
+        let buttons = self.buttons.drain(..).map(EventType::ButtonRelease);
+        for evt in keys.chain(buttons) {
+            let result = backend
+                .send(&evt)
+                .map_err(|e| format!("{:?}: {}", evt, e));
+            report.injected(result, 1);
+        }
+    }
+}
+
+impl fmt::Display for PlaybackReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Playback report: {:?} after {} loops, {} events injected \
+             ({} errors), {:.3} s wall vs {:.3} s recorded, drift {:.2} ms \
+             (max {:.2} ms)",
+            self.outcome,
+            self.loops_completed,
+            self.events_injected,
+            self.injection_errors,
+            self.wall.as_secs_f64(),
+            self.recorded.as_secs_f64(),
+            self.final_drift.as_secs_f64() * 1000.0,
+            self.max_drift.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Plays the events from `source` into `backend`, honouring pause/stop and
+/// looping from the shared state. Returns once playback finishes or is
+/// stopped; going back to idle after it finishes is up to the caller, as
+/// other playbacks may still be running.
+///
+/// `domain` is what it drives among the playbacks in
+/// [`SharedState::playing`], for ones started there, so it can be stopped
+/// without stopping the others.
+pub fn run<B, S>(
+    backend: &mut B,
+    source: &S,
+    shared: &Arc<Mutex<SharedState>>,
+    domain: Option<Domain>,
+) -> PlaybackReport
+where
+    B: InputBackend,
+    S: EventSource + ?Sized,
+{
+    block_on(play(backend, source, shared, domain, &Blocking))
+}
+
+/// How a playback waits: [`run`] blocks its thread, while the async
+/// player yields to its runtime.
+pub(crate) trait Wait {
+    /// Sleeps until `deadline` by `clock`.
+    async fn sleep_until(&self, clock: &Clock, deadline: Instant);
+
+    /// Waits while `waiting` holds for the shared state, which it checks
+    /// again on every change, or until `deadline` if there is one.
+    async fn wait_while<F>(
+        &self,
+        shared: &Arc<Mutex<SharedState>>,
+        deadline: Option<Instant>,
+        waiting: F,
+    ) where
+        F: FnMut(&SharedState) -> bool;
+}
+
+/// Waits by blocking the thread, sleeping on [`SharedState::changed`] so a
+/// pause or stop wakes it at once.
+struct Blocking;
+
+impl Wait for Blocking {
+    async fn sleep_until(&self, clock: &Clock, deadline: Instant) {
+        clock.sleep_until(deadline);
+    }
+
+    async fn wait_while<F>(
+        &self,
+        shared: &Arc<Mutex<SharedState>>,
+        deadline: Option<Instant>,
+        mut waiting: F,
+    ) where
+        F: FnMut(&SharedState) -> bool,
+    {
+        let guard = shared.lock().unwrap();
+        let changed = Arc::clone(&guard.changed);
+        let waiting = |s: &mut SharedState| waiting(s);
+        match deadline {
+            Some(deadline) => {
+                let timeout =
+                    deadline.saturating_duration_since(Instant::now());
+                drop(changed.wait_timeout_while(guard, timeout, waiting));
+            }
+            None => drop(changed.wait_while(guard, waiting)),
+        }
+    }
+}
+
+/// Runs a future whose waits all block, as [`Blocking`]'s do, so it is
+/// ready the first time it is polled.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Plays like [`run`], waiting through `wait`.
+pub(crate) async fn play<B, S, W>(
+    backend: &mut B,
+    source: &S,
+    shared: &Arc<Mutex<SharedState>>,
+    domain: Option<Domain>,
+    wait: &W,
+) -> PlaybackReport
+where
+    B: InputBackend,
+    S: EventSource + ?Sized,
+    W: Wait,
+{
+    let (clock, changes) = {
+        let shared = shared.lock().unwrap();
+        (shared.clock.clone(), Arc::clone(&shared.changes))
+    };
+    let run = Run {
+        shared,
+        clock: &clock,
+        domain,
+        wait,
+        changes: &changes,
+        seen: Cell::new(None),
+        marks: Cell::new(LoopMarks::default()),
+    };
+    let started = clock.now();
+    let mut report = PlaybackReport::default();
+    let mut held = Held::default();
+    let mut backend = Tagged(backend);
+    report.outcome =
+        play_passes(&mut backend, source, &run, &mut report, &mut held).await;
+    held.release(&mut backend, &mut report);
+    report.wall = clock.now() - started;
+    report
+}
+
+/// What a playback runs with, handed down to the steps of playing it.
+struct Run<'a, W> {
+    shared: &'a Arc<Mutex<SharedState>>,
+    clock: &'a Clock,
+    domain: Option<Domain>,
+    wait: &'a W,
+    /// [`SharedState::changes`].
+    changes: &'a AtomicU64,
+    // the changes counted when the state was last seen playing, and the
+    // loop marks then
+    seen: Cell<Option<u64>>,
+    marks: Cell<LoopMarks>,
+}
+
+async fn play_passes<B, S, W>(
+    backend: &mut B,
+    source: &S,
+    run: &Run<'_, W>,
+    report: &mut PlaybackReport,
+    held: &mut Held,
+) -> Outcome
+where
+    B: InputBackend,
+    S: EventSource + ?Sized,
+    W: Wait,
+{
+    let Run { shared, clock, .. } = *run;
+    loop {
+        let mut events = match source.open() {
+            Ok(events) => events.peekable(),
+            Err(e) => {
+                println!("Cannot read events: {}", e);
+                shared.lock().unwrap().set_state(State::Idle);
+                return Outcome::ReadError;
+            }
+        };
+        let (speed, progress, observers) = {
+            let shared = shared.lock().unwrap();
+            let observers = shared.observers.clone();
+            (shared.speed, shared.progress.clone(), observers)
+        };
+        let played = |report: &PlaybackReport, evt: &RecordedEvent| {
+            observers.event_played(evt);
+            if let Some(progress) = &progress {
+                // Nobody listening any more is fine.
+                let _ = progress.send(Progress {
+                    passes: report.loops_completed,
+                    at: evt.timestamp,
+                    events_injected: report.events_injected,
+                });
+            }
+        };
+        let mut scheduler = Scheduler::start_at(clock.now(), speed);
+        let mut pass_length = Duration::ZERO;
+        let mut cleaning = false;
+        // events played since the loop start mark, and where it was
+        let mut section = Vec::new();
+        let mut section_start = None;
+
+        while let Some(evt) = events.next() {
+            let at = evt.timestamp;
+            let Some(marks) = wait_for(at, at, &mut scheduler, run).await else {
+                println!("Playback stopped.");
+                report.recorded += pass_length;
+                let rest = std::iter::once(evt).chain(events);
+                clean_up(backend, rest, cleaning, speed, run, report, held)
+                    .await;
+                return Outcome::Stopped;
+            };
+            if marks.start != section_start {
+                section.clear();
+                section_start = marks.start;
+            }
+            let end_reached = marks.end.is_some_and(|end| evt.timestamp >= end);
+            if end_reached && !section.is_empty() {
+                let started = clock.now();
+                let ended = repeat_section(
+                    backend, &section, run, speed, report, held, &played,
+                )
+                .await;
+                if let Some(outcome) = ended {
+                    report.recorded += pass_length;
+                    let rest = std::iter::once(evt).chain(events);
+                    clean_up(backend, rest, cleaning, speed, run, report, held)
+                        .await;
+                    return outcome;
+                }
+                scheduler.delay(clock.now() - started);
+            }
+            let in_section = |evt: &RecordedEvent| {
+                section_start.is_some_and(|start| evt.timestamp >= start)
+                    && !end_reached
+            };
+            if in_section(&evt) {
+                section.push(evt.clone());
+            }
+
+            if !backend.is_available() {
+                println!("Input target is gone, stopping playback.");
+                shared.lock().unwrap().set_state(State::Idle);
+                report.recorded += pass_length;
+                let rest = std::iter::once(evt).chain(events);
+                clean_up(backend, rest, cleaning, speed, run, report, held)
+                    .await;
+                return Outcome::TargetLost;
+            }
+
+            let due = scheduler.deadline(evt.timestamp);
+            let drift = clock.now().saturating_duration_since(due);
+            report.final_drift = drift;
+            report.max_drift = report.max_drift.max(drift);
+            pass_length = evt.timestamp;
+
+            if let Some(first) = batchable_move(&evt, evt.timestamp) {
+                let mut moves = vec![first];
+                let mut dues = vec![due];
+                let mut batched = Vec::new();
+                while let Some(next) = events
+                    .next_if(|e| batchable_move(e, evt.timestamp).is_some())
+                {
+                    pass_length = next.timestamp;
+                    moves.extend(batchable_move(&next, evt.timestamp));
+                    dues.push(scheduler.deadline(next.timestamp));
+                    if in_section(&next) {
+                        section.push(next.clone());
+                    }
+                    batched.push(next);
+                }
+                if moves.len() > 1 {
+                    let result = backend.send_batch(&moves, &dues);
+                    report.injected(result, moves.len());
+                    for evt in std::iter::once(&evt).chain(&batched) {
+                        played(report, evt);
+                    }
+                    continue;
+                }
+            }
+
+            if let MacroAction::Cleanup = evt.action {
+                cleaning = true;
+                continue;
+            }
+            if let MacroAction::Wait { until, timeout_ms } = &evt.action {
+                let timeout = Duration::from_millis(*timeout_ms);
+                if let Some(outcome) =
+                    wait_until(until, timeout, &mut scheduler, run).await
+                {
+                    report.recorded += pass_length;
+                    clean_up(
+                        backend, events, cleaning, speed, run, report, held,
+                    )
+                    .await;
+                    return outcome;
+                }
+                continue;
+            }
+            if let MacroAction::Input(input) = &evt.action {
+                held.update(input);
+            }
+            perform_action(backend, &evt.action, due, report);
+            played(report, &evt);
+        }
+
+        report.loops_completed += 1;
+        report.recorded += pass_length;
+
+        let looping = {
+            let sh = shared.lock().unwrap();
+            let more = match source.passes() {
+                Some(passes) => report.loops_completed < passes,
+                None => sh
+                    .passes
+                    .map_or(sh.looping, |n| report.loops_completed < n),
+            };
+            more && !sh.stop_after_pass && sh.state == State::Playing
+        };
+
+        if !looping {
+            println!("Playback finished.");
+            return Outcome::Finished;
+        }
+
+        // println!("Looping playback...");
+    }
+}
+
+/// Plays the events of the marked loop section over and over until the end
+/// mark is cleared, as the pass plays them. Returns how playback ended if
+/// it can't go on.
+async fn repeat_section<B: InputBackend, W: Wait>(
+    backend: &mut B,
+    section: &[RecordedEvent],
+    run: &Run<'_, W>,
+    speed: f64,
+    report: &mut PlaybackReport,
+    held: &mut Held,
+    played: &impl Fn(&PlaybackReport, &RecordedEvent),
+) -> Option<Outcome> {
+    let Run {
+        shared,
+        clock,
+        domain,
+        ..
+    } = *run;
+    let start = section[0].timestamp;
+    println!(
+        "Repeating {:.1}-{:.1} s until the loop marks are cleared.",
+        start.as_secs_f64(),
+        section[section.len() - 1].timestamp.as_secs_f64()
+    );
+    loop {
+        let mut scheduler = Scheduler::start_at(clock.now(), speed);
+        for evt in section {
+            let offset = evt.timestamp.saturating_sub(start);
+            let waited = wait_for(offset, evt.timestamp, &mut scheduler, run);
+            if waited.await.is_none() {
+                println!("Playback stopped.");
+                return Some(Outcome::Stopped);
+            }
+            if !backend.is_available() {
+                println!("Input target is gone, stopping playback.");
+                shared.lock().unwrap().set_state(State::Idle);
+                return Some(Outcome::TargetLost);
+            }
+            let due = scheduler.deadline(offset);
+            match &evt.action {
+                MacroAction::Cleanup => continue,
+                MacroAction::Wait { until, timeout_ms } => {
+                    let timeout = Duration::from_millis(*timeout_ms);
+                    let outcome =
+                        wait_until(until, timeout, &mut scheduler, run).await;
+                    if outcome.is_some() {
+                        return outcome;
+                    }
+                    continue;
+                }
+                MacroAction::Input(input) => held.update(input),
+                _ => {}
+            }
+            perform_action(backend, &evt.action, due, report);
+            played(report, evt);
+        }
+        let mut shared = shared.lock().unwrap();
+        let marks = shared.running_mut(domain).map(|r| r.loop_marks);
+        if marks.is_none_or(|marks| marks.end.is_none()) {
+            return None;
+        }
+    }
+}
+
+/// Longest a cleanup section is played for.
+const CLEANUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Plays the cleanup section of a playback that stopped early, from the
+/// start of it or, if it had `started`, on from where it stopped. It
+/// ignores pause and stop, so the target is left in a sane state, but
+/// gives up after [`CLEANUP_TIMEOUT`], as stopping waits for it.
+async fn clean_up<B: InputBackend, W: Wait>(
+    backend: &mut B,
+    mut events: impl Iterator<Item = RecordedEvent>,
+    started: bool,
+    speed: f64,
+    run: &Run<'_, W>,
+    report: &mut PlaybackReport,
+    held: &mut Held,
+) {
+    if !started
+        && !events.any(|evt| matches!(evt.action, MacroAction::Cleanup))
+    {
+        return;
+    }
+    let mut events = events.peekable();
+    let Some(start) = events.peek().map(|evt| evt.timestamp) else {
+        return;
+    };
+    println!("Running cleanup.");
+    let scheduler = Scheduler::start_at(run.clock.now(), speed);
+    let give_up = run.clock.now() + CLEANUP_TIMEOUT;
+    for evt in events {
+        let offset = evt.timestamp.saturating_sub(start);
+        let due = scheduler.deadline(offset);
+        if due > give_up {
+            println!(
+                "Cleanup took over {} s, skipping the rest of it.",
+                CLEANUP_TIMEOUT.as_secs()
+            );
+            return;
+        }
+        run.wait.sleep_until(run.clock, due).await;
+        match &evt.action {
+            // Nothing left to wait for.
+            MacroAction::Wait { .. } => continue,
+            MacroAction::Input(input) => held.update(input),
+            _ => {}
+        }
+        perform_action(backend, &evt.action, due, report);
+    }
+}
+
+/// Waits until the event at `offset` is due, honouring pause and stop,
+/// showing `at` as where the playback is meanwhile. Time spent paused is
+/// added to the schedule. Returns the playback's loop marks, or `None` if
+/// it was stopped.
+///
+/// The shared state is only locked once it has changed, or to sleep.
+async fn wait_for<W: Wait>(
+    offset: Duration,
+    at: Duration,
+    scheduler: &mut Scheduler,
+    run: &Run<'_, W>,
+) -> Option<LoopMarks> {
+    let Run {
+        shared,
+        clock,
+        domain,
+        wait,
+        ..
+    } = *run;
+    loop {
+        let changes = run.changes.load(Ordering::Acquire);
+        let (state, marks) = if run.seen.get() == Some(changes) {
+            (State::Playing, run.marks.get())
+        } else {
+            let mut guard = shared.lock().unwrap();
+            if guard.is_stopped(domain) {
+                return None;
+            }
+            let marks = match guard.running_mut(domain) {
+                Some(running) => {
+                    running.position = at;
+                    running.loop_marks
+                }
+                None => LoopMarks::default(),
+            };
+            let playing = guard.state == State::Playing;
+            run.seen.set(playing.then_some(changes));
+            run.marks.set(marks);
+            (guard.state, marks)
+        };
+        match state {
+            State::Idle | State::Recording => return None,
+            State::Paused => {
+                let paused_at = clock.now();
+                let paused = |s: &SharedState| {
+                    s.state == State::Paused && !s.is_stopped(domain)
+                };
+                wait.wait_while(shared, None, paused).await;
+                scheduler.delay(clock.now() - paused_at);
+            }
+            State::Playing => {
+                let deadline = scheduler.deadline(offset);
+                let remaining =
+                    deadline.saturating_duration_since(Instant::now());
+                if clock.is_simulated() || remaining <= timing::SPIN_THRESHOLD
+                {
+                    wait.sleep_until(clock, deadline).await;
+                    return Some(marks);
+                }
+                // Woken at once by a pause or stop.
+                let playing = |s: &SharedState| {
+                    s.state == State::Playing && !s.is_stopped(domain)
+                };
+                let wake = deadline - timing::SPIN_THRESHOLD;
+                wait.wait_while(shared, Some(wake), playing).await;
+            }
+        }
+    }
+}
+
+/// How often a wait step checks its condition.
+pub(crate) const WAIT_POLL: Duration = Duration::from_millis(250);
+
+/// Waits until `until` holds, honouring pause and stop; the time waited is
+/// added to the schedule. Returns how playback ended if it can't go on.
+async fn wait_until<W: Wait>(
+    until: &Condition,
+    timeout: Duration,
+    scheduler: &mut Scheduler,
+    run: &Run<'_, W>,
+) -> Option<Outcome> {
+    let Run {
+        shared,
+        clock,
+        domain,
+        wait,
+        ..
+    } = *run;
+    let started = clock.now();
+    let outcome = loop {
+        let state = {
+            let guard = shared.lock().unwrap();
+            if guard.is_stopped(domain) {
+                println!("Playback stopped.");
+                break Some(Outcome::Stopped);
+            }
+            guard.state
+        };
+        match state {
+            State::Idle | State::Recording => {
+                println!("Playback stopped.");
+                break Some(Outcome::Stopped);
+            }
+            State::Paused => {
+                let paused = |s: &SharedState| {
+                    s.state == State::Paused && !s.is_stopped(domain)
+                };
+                let wake = Instant::now() + WAIT_POLL;
+                wait.wait_while(shared, Some(wake), paused).await;
+                continue;
+            }
+            State::Playing => {}
+        }
+        match until.check() {
+            Ok(true) => break None,
+            Ok(false) => {}
+            Err(e) => {
+                println!("Cannot wait for {}: {}", until, e);
+                break Some(Outcome::WaitFailed);
+            }
+        }
+        if clock.now() - started >= timeout {
+            println!("Gave up waiting for {}.", until);
+            break Some(Outcome::WaitFailed);
+        }
+        if clock.is_simulated() {
+            wait.sleep_until(clock, clock.now() + WAIT_POLL).await;
+            continue;
+        }
+        // Woken early by a pause or stop.
+        let playing = |s: &SharedState| {
+            s.state == State::Playing && !s.is_stopped(domain)
+        };
+        let wake = Instant::now() + WAIT_POLL;
+        wait.wait_while(shared, Some(wake), playing).await;
+    };
+    if outcome == Some(Outcome::WaitFailed) {
+        shared.lock().unwrap().set_state(State::Idle);
+    }
+    scheduler.delay(clock.now() - started);
+    outcome
+}
+
+/// Consecutive mouse moves due within this window of the first one are
+/// injected together, trading a little timing precision for far fewer
+/// injection calls on dense recordings.
+const MOVE_BATCH_WINDOW: Duration = Duration::from_millis(2);
+
+/// The mouse move in `evt`, if it can join a batch that started at `start`.
+fn batchable_move(evt: &RecordedEvent, start: Duration) -> Option<EventType> {
+    match evt.action {
+        MacroAction::Input(m @ EventType::MouseMove { .. })
+            if evt.timestamp.saturating_sub(start) <= MOVE_BATCH_WINDOW =>
+        {
+            Some(m)
+        }
+        _ => None,
+    }
+}
+
+/// Performs `action`, due at `due` by the recording's timing.
+pub(crate) fn perform_action<B: InputBackend>(
+    backend: &mut B,
+    action: &MacroAction,
+    due: Instant,
+    report: &mut PlaybackReport,
+) {
+    let mut perform = |evt: &EventType| {
+        let result = backend
+            .send_due(evt, due)
+            .map_err(|e| format!("{:?}: {}", evt, e));
+        report.injected(result, 1);
+    };
+    match action {
+        MacroAction::Input(evt) => perform(evt),
+        MacroAction::ClickElement { target, button } => {
+            match accessibility::locate(target) {
+                Ok((x, y)) => {
+                    perform(&EventType::MouseMove { x, y });
+                    perform(&EventType::ButtonPress(*button));
+                    perform(&EventType::ButtonRelease(*button));
+                }
+                Err(e) => println!("Skipping click on {}: {}", target, e),
+            }
+        }
+        MacroAction::Clipboard(text) => {
+            report.injected(clipboard::set_text(text), 1);
+        }
+        MacroAction::Screenshot { region, path } => {
+            if let Err(e) = screenshot::take(region, path) {
+                println!("Screenshot failed: {}", e);
+            }
+        }
+        // Handled by the caller, which can follow pause and stop.
+        MacroAction::Wait { .. } | MacroAction::Cleanup => {}
+        // Only read by `Filtered`.
+        MacroAction::Checkpoint(_) => {}
+        // Sources are wrapped in `Branched` and `Filtered`, which leave
+        // none of these.
+        MacroAction::Type(_) | MacroAction::IfKeyHeld { .. } => {}
+    }
+}
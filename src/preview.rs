@@ -0,0 +1,332 @@
+//! Human-readable description of what a recording does, shown before a
+//! macro from elsewhere is trusted with the keyboard and mouse and by
+//! `inspect --preview`.
+
+use crate::event::{MacroAction, RecordedEvent};
+use rdev::{Button, EventType, Key};
+use std::time::Duration;
+
+/// Pauses at least this long are listed as their own step.
+const SHOWN_PAUSE: Duration = Duration::from_secs(1);
+
+/// Characters typed by keys on a US layout, without and with Shift.
+const CHARS: &[(Key, char, char)] = &[
+    (Key::KeyA, 'a', 'A'),
+    (Key::KeyB, 'b', 'B'),
+    (Key::KeyC, 'c', 'C'),
+    (Key::KeyD, 'd', 'D'),
+    (Key::KeyE, 'e', 'E'),
+    (Key::KeyF, 'f', 'F'),
+    (Key::KeyG, 'g', 'G'),
+    (Key::KeyH, 'h', 'H'),
+    (Key::KeyI, 'i', 'I'),
+    (Key::KeyJ, 'j', 'J'),
+    (Key::KeyK, 'k', 'K'),
+    (Key::KeyL, 'l', 'L'),
+    (Key::KeyM, 'm', 'M'),
+    (Key::KeyN, 'n', 'N'),
+    (Key::KeyO, 'o', 'O'),
+    (Key::KeyP, 'p', 'P'),
+    (Key::KeyQ, 'q', 'Q'),
+    (Key::KeyR, 'r', 'R'),
+    (Key::KeyS, 's', 'S'),
+    (Key::KeyT, 't', 'T'),
+    (Key::KeyU, 'u', 'U'),
+    (Key::KeyV, 'v', 'V'),
+    (Key::KeyW, 'w', 'W'),
+    (Key::KeyX, 'x', 'X'),
+    (Key::KeyY, 'y', 'Y'),
+    (Key::KeyZ, 'z', 'Z'),
+    (Key::Num1, '1', '!'),
+    (Key::Num2, '2', '@'),
+    (Key::Num3, '3', '#'),
+    (Key::Num4, '4', '$'),
+    (Key::Num5, '5', '%'),
+    (Key::Num6, '6', '^'),
+    (Key::Num7, '7', '&'),
+    (Key::Num8, '8', '*'),
+    (Key::Num9, '9', '('),
+    (Key::Num0, '0', ')'),
+    (Key::Space, ' ', ' '),
+    (Key::Minus, '-', '_'),
+    (Key::Equal, '=', '+'),
+    (Key::LeftBracket, '[', '{'),
+    (Key::RightBracket, ']', '}'),
+    (Key::SemiColon, ';', ':'),
+    (Key::Quote, '\'', '"'),
+    (Key::BackSlash, '\\', '|'),
+    (Key::Comma, ',', '<'),
+    (Key::Dot, '.', '>'),
+    (Key::Slash, '/', '?'),
+    (Key::BackQuote, '`', '~'),
+];
+
+pub(crate) fn key_char(key: Key, shift: bool) -> Option<char> {
+    CHARS
+        .iter()
+        .find(|(k, _, _)| *k == key)
+        .map(|(_, c, shifted)| if shift { *shifted } else { *c })
+}
+
+/// The key typing `c` on a US layout and whether it needs Shift.
+pub(crate) fn char_key(c: char) -> Option<(Key, bool)> {
+    CHARS.iter().find_map(|(key, plain, shifted)| {
+        if c == *plain {
+            Some((*key, false))
+        } else if c == *shifted {
+            Some((*key, true))
+        } else {
+            None
+        }
+    })
+}
+
+fn key_name(key: Key) -> String {
+    match key_char(key, true) {
+        Some(' ') => "Space".into(),
+        Some(c) if c.is_ascii_alphabetic() => c.to_string(),
+        _ => match key_char(key, false) {
+            Some(c) => c.to_string(),
+            None => format!("{:?}", key),
+        },
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Modifiers {
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+    meta: bool,
+}
+
+impl Modifiers {
+    /// Updates the held modifiers, returning false if `key` isn't one.
+    pub(crate) fn update(&mut self, key: Key, down: bool) -> bool {
+        let held = match key {
+            Key::ShiftLeft | Key::ShiftRight => &mut self.shift,
+            Key::ControlLeft | Key::ControlRight => &mut self.ctrl,
+            Key::Alt | Key::AltGr => &mut self.alt,
+            Key::MetaLeft | Key::MetaRight => &mut self.meta,
+            _ => return false,
+        };
+        *held = down;
+        true
+    }
+
+    pub(crate) fn combo(&self, key: Key) -> String {
+        let mut combo = String::new();
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.meta, "Meta+"),
+            (self.shift, "Shift+"),
+        ] {
+            if held {
+                combo.push_str(name);
+            }
+        }
+        combo + &key_name(key)
+    }
+}
+
+/// Describes the recording as a list of steps, merging typed text,
+/// clicks, scrolling and runs of moves into one step each.
+pub fn steps(events: &[RecordedEvent]) -> Vec<String> {
+    let mut steps = Vec::new();
+    let mut merged = Merged::default();
+    let mut modifiers = Modifiers::default();
+    let mut pointer = (0.0, 0.0);
+    let mut pressed_at: Option<(Button, (f64, f64))> = None;
+    let mut last = Duration::ZERO;
+
+    for evt in events {
+        let pause = evt.timestamp.saturating_sub(last);
+        last = evt.timestamp;
+        if pause >= SHOWN_PAUSE {
+            merged.flush(&mut steps);
+            steps.push(format!("wait {:.1} s", pause.as_secs_f64()));
+        }
+
+        let event_type = match &evt.action {
+            MacroAction::Input(event_type) => *event_type,
+            MacroAction::ClickElement { target, button } => {
+                merged.flush(&mut steps);
+                steps.push(format!(
+                    "{} click on {}",
+                    button_name(*button),
+                    target
+                ));
+                continue;
+            }
+            MacroAction::Clipboard(text) => {
+                merged.flush(&mut steps);
+                steps.push(format!("put {:?} on the clipboard", text));
+                continue;
+            }
+            MacroAction::Type(text) => {
+                merged.flush(&mut steps);
+                steps.push(format!("type {:?}", text));
+                continue;
+            }
+            MacroAction::Screenshot { region, path } => {
+                merged.flush(&mut steps);
+                steps.push(format!(
+                    "save a screenshot of {} to {}",
+                    region, path
+                ));
+                continue;
+            }
+            MacroAction::Checkpoint(name) => {
+                merged.flush(&mut steps);
+                steps.push(format!("checkpoint {:?}", name));
+                continue;
+            }
+            MacroAction::Cleanup => {
+                merged.flush(&mut steps);
+                steps.push("cleanup, also run when stopped early:".into());
+                continue;
+            }
+            MacroAction::Wait { until, timeout_ms } => {
+                merged.flush(&mut steps);
+                steps.push(format!(
+                    "wait up to {:.1} s for {}",
+                    *timeout_ms as f64 / 1000.0,
+                    until
+                ));
+                continue;
+            }
+            MacroAction::IfKeyHeld {
+                key,
+                then,
+                otherwise,
+            } => {
+                let nested = |events: &[RecordedEvent]| {
+                    self::steps(events)
+                        .into_iter()
+                        .map(|step| format!("  {}", step))
+                };
+                merged.flush(&mut steps);
+                steps.push(format!("if {} is held:", key_name(*key)));
+                steps.extend(nested(then));
+                if !otherwise.is_empty() {
+                    steps.push("otherwise:".to_string());
+                    steps.extend(nested(otherwise));
+                }
+                continue;
+            }
+        };
+        match event_type {
+            EventType::MouseMove { x, y } => {
+                pointer = (x, y);
+                merged.moved_to = Some(pointer);
+            }
+            EventType::Wheel { delta_x, delta_y } => {
+                if !merged.typed.is_empty() || merged.moved_to.is_some() {
+                    merged.flush(&mut steps);
+                }
+                merged.scroll.0 += delta_x;
+                merged.scroll.1 += delta_y;
+            }
+            EventType::ButtonPress(button) => {
+                // The click says where it is.
+                merged.moved_to = None;
+                merged.flush(&mut steps);
+                pressed_at = Some((button, pointer));
+            }
+            EventType::ButtonRelease(button) => {
+                merged.moved_to = None;
+                match pressed_at.take() {
+                    Some((pressed, from))
+                        if pressed == button && from != pointer =>
+                    {
+                        steps.push(format!(
+                            "{} drag from ({:.0}, {:.0}) to ({:.0}, {:.0})",
+                            button_name(button),
+                            from.0,
+                            from.1,
+                            pointer.0,
+                            pointer.1
+                        ));
+                    }
+                    _ => steps.push(format!(
+                        "{} click at ({:.0}, {:.0})",
+                        button_name(button),
+                        pointer.0,
+                        pointer.1
+                    )),
+                }
+            }
+            EventType::KeyPress(key) => {
+                if modifiers.update(key, true) {
+                    continue;
+                }
+                let plain =
+                    !(modifiers.ctrl || modifiers.alt || modifiers.meta);
+                match key_char(key, modifiers.shift) {
+                    Some(c) if plain => {
+                        if merged.scroll != (0, 0)
+                            || merged.moved_to.is_some()
+                        {
+                            merged.flush(&mut steps);
+                        }
+                        merged.typed.push(c);
+                    }
+                    _ => {
+                        merged.flush(&mut steps);
+                        steps.push(format!("press {}", modifiers.combo(key)));
+                    }
+                }
+            }
+            EventType::KeyRelease(key) => {
+                modifiers.update(key, false);
+            }
+        }
+    }
+    merged.flush(&mut steps);
+    steps
+}
+
+/// Steps built up over several events.
+#[derive(Default)]
+struct Merged {
+    typed: String,
+    scroll: (i64, i64),
+    // where a run of moves ended, unless a click followed
+    moved_to: Option<(f64, f64)>,
+}
+
+impl Merged {
+    /// Ends the pending steps.
+    fn flush(&mut self, steps: &mut Vec<String>) {
+        if !self.typed.is_empty() {
+            steps.push(format!("type {:?}", self.typed));
+            self.typed.clear();
+        }
+        if self.scroll != (0, 0) {
+            steps.push(scroll_step(self.scroll));
+            self.scroll = (0, 0);
+        }
+        if let Some((x, y)) = self.moved_to.take() {
+            steps.push(format!("move to ({:.0}, {:.0})", x, y));
+        }
+    }
+}
+
+/// E.g. `scroll down 3`, or `scroll up 1 and right 2`.
+fn scroll_step((dx, dy): (i64, i64)) -> String {
+    let mut parts = Vec::new();
+    if dy != 0 {
+        let direction = if dy > 0 { "up" } else { "down" };
+        parts.push(format!("{} {}", direction, dy.abs()));
+    }
+    if dx != 0 {
+        let direction = if dx > 0 { "right" } else { "left" };
+        parts.push(format!("{} {}", direction, dx.abs()));
+    }
+    format!("scroll {}", parts.join(" and "))
+}
+
+fn button_name(button: Button) -> String {
+    format!("{:?}", button).to_lowercase()
+}
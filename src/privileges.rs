@@ -0,0 +1,176 @@
+//! Giving up root once the input devices are open.
+//!
+//! Reading `/dev/input` may need root, but once the devices are open their
+//! file descriptors keep working, so the rest of the run can be done as an
+//! ordinary user. Injection through `/dev/uinput` opens the device again
+//! for every playback; giving it to a dedicated group and keeping just that
+//! group means nothing else the user can't already do is left.
+//!
+//! The user is looked up with [`prepare`] before any threads start, as the
+//! user and group databases aren't safe to read from several at once, and
+//! switched to later with [`Switch::apply`].
+
+use std::path::PathBuf;
+
+/// Who to run as after dropping root.
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    /// A user name or id; the user who ran `sudo` if not given.
+    pub user: Option<String>,
+    /// A group to keep for injection, e.g. the group `/dev/uinput` belongs
+    /// to.
+    pub inject_group: Option<String>,
+}
+
+/// The ids to switch to, looked up ahead of switching.
+pub struct Switch(platform::Ids);
+
+/// Looks up who `target` names. `None` when not running as root, as then
+/// there is nothing to drop.
+pub fn prepare(target: &Target) -> Result<Option<Switch>, String> {
+    Ok(platform::resolve(target)?.map(Switch))
+}
+
+impl Switch {
+    /// Hands those of `files` that root owns, e.g. the session written
+    /// while starting up, to the user, and switches the whole process to
+    /// them.
+    pub fn apply(self, files: &[PathBuf]) -> Result<(), String> {
+        platform::switch(self.0, files)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Target;
+    use std::env;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{chown, MetadataExt};
+    use std::path::{Path, PathBuf};
+
+    pub struct Ids {
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+        groups: Vec<libc::gid_t>,
+    }
+
+    pub fn resolve(target: &Target) -> Result<Option<Ids>, String> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(None);
+        }
+        let (uid, gid) = match &target.user {
+            Some(user) => user_ids(user)?,
+            None => sudo_ids().ok_or(
+                "no user to switch to, as this wasn't run through sudo; \
+                 name one with --run-as",
+            )?,
+        };
+        if uid == 0 {
+            return Err("refusing to switch to root".into());
+        }
+        let groups = match &target.inject_group {
+            Some(group) => vec![group_id(group)?],
+            None => Vec::new(),
+        };
+        Ok(Some(Ids { uid, gid, groups }))
+    }
+
+    pub fn switch(ids: Ids, files: &[PathBuf]) -> Result<(), String> {
+        let Ids { uid, gid, groups } = ids;
+        for file in files {
+            hand_over(file, uid, gid)?;
+        }
+        let failed = |what: &str| {
+            format!("cannot {}: {}", what, io::Error::last_os_error())
+        };
+        // Groups first, as changing them needs root. glibc applies these
+        // to every thread, not just the calling one.
+        unsafe {
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 {
+                return Err(failed("set the supplementary groups"));
+            }
+            if libc::setresgid(gid, gid, gid) != 0 {
+                return Err(failed("switch group"));
+            }
+            if libc::setresuid(uid, uid, uid) != 0 {
+                return Err(failed("switch user"));
+            }
+            // Had anything been left over, root could be regained.
+            if libc::setuid(0) == 0 {
+                return Err("root could be regained after switching".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives `path` to the user if root owns it; a missing one is left
+    /// alone.
+    fn hand_over(
+        path: &Path,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+    ) -> Result<(), String> {
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(());
+        };
+        if metadata.uid() == 0 {
+            chown(path, Some(uid), Some(gid)).map_err(|e| {
+                format!("cannot hand {} over: {}", path.display(), e)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn sudo_ids() -> Option<(libc::uid_t, libc::gid_t)> {
+        let uid = env::var("SUDO_UID").ok()?.parse().ok()?;
+        let gid = env::var("SUDO_GID").ok()?.parse().ok()?;
+        Some((uid, gid))
+    }
+
+    /// The ids of `user`, a name or a numeric id.
+    fn user_ids(user: &str) -> Result<(libc::uid_t, libc::gid_t), String> {
+        let name = CString::new(user).map_err(|e| e.to_string())?;
+        let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+        if !entry.is_null() {
+            return Ok(unsafe { ((*entry).pw_uid, (*entry).pw_gid) });
+        }
+        let uid: libc::uid_t = user
+            .parse()
+            .map_err(|_| format!("no user named '{}'", user))?;
+        let entry = unsafe { libc::getpwuid(uid) };
+        if entry.is_null() {
+            return Err(format!("no user with id {}", uid));
+        }
+        Ok((uid, unsafe { (*entry).pw_gid }))
+    }
+
+    /// The id of `group`, a name or a numeric id.
+    fn group_id(group: &str) -> Result<libc::gid_t, String> {
+        let name = CString::new(group).map_err(|e| e.to_string())?;
+        let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+        if !entry.is_null() {
+            return Ok(unsafe { (*entry).gr_gid });
+        }
+        group
+            .parse()
+            .map_err(|_| format!("no group named '{}'", group))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::Target;
+    use std::path::PathBuf;
+
+    pub enum Ids {}
+
+    pub fn resolve(_target: &Target) -> Result<Option<Ids>, String> {
+        Err("dropping privileges is only supported on Linux".into())
+    }
+
+    pub fn switch(ids: Ids, _files: &[PathBuf]) -> Result<(), String> {
+        match ids {}
+    }
+}
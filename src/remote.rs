@@ -0,0 +1,66 @@
+//! Fire-and-forget triggers for scripts, cron and other automation tools:
+//! one-line UDP messages on the loopback interface, such as
+//! `echo play:deploy | nc -uw0 127.0.0.1 PORT` with `--trigger-port PORT`.
+//!
+//! Nothing is sent back; `status` tells what came of it. Any local program
+//! can send triggers, so they are off unless a port is given.
+
+use crate::hotkeys::Action;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::str::FromStr;
+use std::thread;
+
+/// Largest message taken; longer ones are cut off.
+const MAX_MESSAGE: usize = 4096;
+
+/// What a trigger message asks for.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Message {
+    /// `play:NAME`, playing a library macro.
+    Play(String),
+    /// `action:ACTION` or just `ACTION`, as a hotkey would.
+    Action(Action),
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(name) = s.strip_prefix("play:") {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("play: needs a macro name".into());
+            }
+            return Ok(Message::Play(name.to_string()));
+        }
+        let action = s.strip_prefix("action:").unwrap_or(s);
+        Ok(Message::Action(action.trim().parse()?))
+    }
+}
+
+/// Takes trigger messages on `port` until the process exits, passing them
+/// to `handle` one at a time. A datagram can hold one message per line.
+pub fn listen<F>(port: u16, mut handle: F) -> Result<(), String>
+where
+    F: FnMut(Message) + Send + 'static,
+{
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| format!("cannot listen on port {}: {}", port, e))?;
+    thread::spawn(move || {
+        let mut buffer = [0u8; MAX_MESSAGE];
+        loop {
+            let Ok((len, _)) = socket.recv_from(&mut buffer) else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&buffer[..len]);
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                match line.parse() {
+                    Ok(message) => handle(message),
+                    Err(e) => println!("Ignoring trigger '{}': {}", line, e),
+                }
+            }
+        }
+    });
+    Ok(())
+}
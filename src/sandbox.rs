@@ -0,0 +1,107 @@
+//! A throwaway X display to play macros into, so they can be tried end to
+//! end without touching the real session.
+//!
+//! The display is served by Xvfb, which shows nothing, or by Xephyr, which
+//! shows it nested in a window of the real session. Input has to reach it
+//! through X itself: the uinput backends inject below the display server,
+//! where the real session would get it.
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the server gets to come up.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Display numbers tried, from the first after the usual ones.
+const DISPLAYS: std::ops::Range<u32> = 90..200;
+
+/// A running display server, stopped when dropped along with whatever was
+/// launched on it.
+pub struct Sandbox {
+    /// The display's name, e.g. `:90`.
+    pub display: String,
+    server: Child,
+    launched: Vec<Child>,
+}
+
+impl Sandbox {
+    /// Starts a display of `size`, nested in a window when `nested`.
+    pub fn start(size: (i32, i32), nested: bool) -> Result<Sandbox, String> {
+        if cfg!(any(target_os = "windows", target_os = "macos")) {
+            return Err("a sandbox display needs X11".into());
+        }
+        let n = free_display()?;
+        let display = format!(":{}", n);
+        let program = if nested { "Xephyr" } else { "Xvfb" };
+        let mut command = Command::new(program);
+        command.arg(&display);
+        if nested {
+            command.args(["-screen", &format!("{}x{}", size.0, size.1)]);
+        } else {
+            command.args([
+                "-screen",
+                "0",
+                &format!("{}x{}x24", size.0, size.1),
+            ]);
+        }
+        let server = command
+            .args(["-nolisten", "tcp"])
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", program, e))?;
+        let mut sandbox = Sandbox {
+            display,
+            server,
+            launched: Vec::new(),
+        };
+        let started = Instant::now();
+        while !socket(n).exists() {
+            if let Ok(Some(status)) = sandbox.server.try_wait() {
+                return Err(format!("{} exited ({})", program, status));
+            }
+            if started.elapsed() > START_TIMEOUT {
+                return Err(format!("{} didn't start in time", program));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(sandbox)
+    }
+
+    /// Runs `command` through the shell on the display, e.g. the
+    /// application the macro drives.
+    pub fn launch(&mut self, command: &str) -> Result<(), String> {
+        let child = Command::new("sh")
+            .args(["-c", command])
+            .env("DISPLAY", &self.display)
+            .env_remove("WAYLAND_DISPLAY")
+            .spawn()
+            .map_err(|e| format!("cannot run '{}': {}", command, e))?;
+        self.launched.push(child);
+        Ok(())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        for child in self.launched.iter_mut().chain([&mut self.server]) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn socket(n: u32) -> PathBuf {
+    format!("/tmp/.X11-unix/X{}", n).into()
+}
+
+/// A display number no server is using.
+fn free_display() -> Result<u32, String> {
+    DISPLAYS
+        .clone()
+        .find(|n| {
+            !socket(*n).exists()
+                && !Path::new(&format!("/tmp/.X{}-lock", n)).exists()
+        })
+        .ok_or_else(|| "no free display number".to_string())
+}
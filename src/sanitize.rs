@@ -0,0 +1,149 @@
+//! Fixing defects in recordings, e.g. from capture dropping events or from
+//! editing files by hand.
+
+use crate::event::{MacroAction, RecordedEvent};
+use rdev::{Button, EventType, Key};
+use std::time::Duration;
+
+/// How long a press is held at least, as some applications miss a press
+/// released at the same instant.
+pub const MIN_HOLD: Duration = Duration::from_millis(10);
+
+/// A key or button that is pressed and released.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Control {
+    Key(Key),
+    Button(Button),
+}
+
+impl Control {
+    /// The control `evt` presses, or releases if `false`.
+    fn of(evt: &RecordedEvent) -> Option<(Control, bool)> {
+        match evt.action {
+            MacroAction::Input(EventType::KeyPress(k)) => {
+                Some((Control::Key(k), true))
+            }
+            MacroAction::Input(EventType::KeyRelease(k)) => {
+                Some((Control::Key(k), false))
+            }
+            MacroAction::Input(EventType::ButtonPress(b)) => {
+                Some((Control::Button(b), true))
+            }
+            MacroAction::Input(EventType::ButtonRelease(b)) => {
+                Some((Control::Button(b), false))
+            }
+            _ => None,
+        }
+    }
+
+    fn release(self) -> EventType {
+        match self {
+            Control::Key(k) => EventType::KeyRelease(k),
+            Control::Button(b) => EventType::ButtonRelease(b),
+        }
+    }
+}
+
+/// Fixes out of order timestamps, releases without a press, presses never
+/// released, presses released at once and, if the `screen` size is known,
+/// pointer moves off it. Returns a description of each change.
+pub fn sanitize(
+    events: &mut Vec<RecordedEvent>,
+    screen: Option<(i32, i32)>,
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut note = |at: Duration, change: String| {
+        changes.push(format!("{:.3} s: {}", at.as_secs_f64(), change));
+    };
+
+    let mut last = Duration::ZERO;
+    for evt in events.iter_mut() {
+        if evt.timestamp < last {
+            note(
+                evt.timestamp,
+                format!("moved to {:.3} s", last.as_secs_f64()),
+            );
+            evt.timestamp = last;
+        }
+        last = evt.timestamp;
+    }
+
+    if let Some((width, height)) = screen {
+        let max = ((width - 1) as f64, (height - 1) as f64);
+        for evt in events.iter_mut() {
+            if let MacroAction::Input(EventType::MouseMove { x, y }) =
+                &mut evt.action
+            {
+                let (cx, cy) = (x.clamp(0.0, max.0), y.clamp(0.0, max.1));
+                if (cx, cy) != (*x, *y) {
+                    note(
+                        evt.timestamp,
+                        format!("moved ({}, {}) onto the screen", x, y),
+                    );
+                    (*x, *y) = (cx, cy);
+                }
+            }
+        }
+    }
+
+    // Keys repeat while held, so only buttons pressed twice are dropped.
+    let mut held: Vec<(Control, usize)> = Vec::new();
+    let mut i = 0;
+    while i < events.len() {
+        let Some((control, press)) = Control::of(&events[i]) else {
+            i += 1;
+            continue;
+        };
+        let at = events[i].timestamp;
+        let pressed = held.iter().position(|(c, _)| *c == control);
+        match (press, pressed) {
+            (true, None) => held.push((control, i)),
+            (true, Some(_)) if matches!(control, Control::Button(_)) => {
+                note(at, format!("dropped repeated press of {:?}", control));
+                events.remove(i);
+                continue;
+            }
+            (true, Some(_)) => {}
+            (false, None) => {
+                note(
+                    at,
+                    format!("dropped release of {:?} without a press", control),
+                );
+                events.remove(i);
+                continue;
+            }
+            (false, Some(h)) => {
+                let (_, pressed_at) = held.remove(h);
+                if events[pressed_at].timestamp == at {
+                    note(
+                        at,
+                        format!(
+                            "held {:?} for {} ms",
+                            control,
+                            MIN_HOLD.as_millis()
+                        ),
+                    );
+                    for evt in &mut events[i..] {
+                        evt.timestamp += MIN_HOLD;
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+
+    let end = events.last().map_or(Duration::ZERO, |e| e.timestamp);
+    for (control, pressed_at) in held {
+        let at = end.max(events[pressed_at].timestamp + MIN_HOLD);
+        note(at, format!("added the missing release of {:?}", control));
+        events.push(RecordedEvent {
+            action: MacroAction::Input(control.release()),
+            timestamp: at,
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        });
+    }
+    changes
+}
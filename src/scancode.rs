@@ -0,0 +1,196 @@
+//! Keys by where they are on the keyboard, as the hardware scan codes
+//! (set 1) a US keyboard sends for them, which games reading DirectInput or
+//! raw input go by.
+//!
+//! rdev reports keys by position everywhere but on Windows, where it goes
+//! by the virtual key the layout gives them. Recording with the scan-code
+//! backend turns those back into positions, so a macro presses the same
+//! keys whatever layout it is played with.
+
+use rdev::{EventType, Key};
+
+/// Scan codes of the keys rdev knows, with `0xe0` in front of extended
+/// ones.
+const SCAN_CODES: &[(Key, u16)] = &[
+    (Key::Escape, 0x01),
+    (Key::Num1, 0x02),
+    (Key::Num2, 0x03),
+    (Key::Num3, 0x04),
+    (Key::Num4, 0x05),
+    (Key::Num5, 0x06),
+    (Key::Num6, 0x07),
+    (Key::Num7, 0x08),
+    (Key::Num8, 0x09),
+    (Key::Num9, 0x0a),
+    (Key::Num0, 0x0b),
+    (Key::Minus, 0x0c),
+    (Key::Equal, 0x0d),
+    (Key::Backspace, 0x0e),
+    (Key::Tab, 0x0f),
+    (Key::KeyQ, 0x10),
+    (Key::KeyW, 0x11),
+    (Key::KeyE, 0x12),
+    (Key::KeyR, 0x13),
+    (Key::KeyT, 0x14),
+    (Key::KeyY, 0x15),
+    (Key::KeyU, 0x16),
+    (Key::KeyI, 0x17),
+    (Key::KeyO, 0x18),
+    (Key::KeyP, 0x19),
+    (Key::LeftBracket, 0x1a),
+    (Key::RightBracket, 0x1b),
+    (Key::Return, 0x1c),
+    (Key::ControlLeft, 0x1d),
+    (Key::KeyA, 0x1e),
+    (Key::KeyS, 0x1f),
+    (Key::KeyD, 0x20),
+    (Key::KeyF, 0x21),
+    (Key::KeyG, 0x22),
+    (Key::KeyH, 0x23),
+    (Key::KeyJ, 0x24),
+    (Key::KeyK, 0x25),
+    (Key::KeyL, 0x26),
+    (Key::SemiColon, 0x27),
+    (Key::Quote, 0x28),
+    (Key::BackQuote, 0x29),
+    (Key::ShiftLeft, 0x2a),
+    (Key::BackSlash, 0x2b),
+    (Key::KeyZ, 0x2c),
+    (Key::KeyX, 0x2d),
+    (Key::KeyC, 0x2e),
+    (Key::KeyV, 0x2f),
+    (Key::KeyB, 0x30),
+    (Key::KeyN, 0x31),
+    (Key::KeyM, 0x32),
+    (Key::Comma, 0x33),
+    (Key::Dot, 0x34),
+    (Key::Slash, 0x35),
+    (Key::ShiftRight, 0x36),
+    (Key::KpMultiply, 0x37),
+    (Key::Alt, 0x38),
+    (Key::Space, 0x39),
+    (Key::CapsLock, 0x3a),
+    (Key::F1, 0x3b),
+    (Key::F2, 0x3c),
+    (Key::F3, 0x3d),
+    (Key::F4, 0x3e),
+    (Key::F5, 0x3f),
+    (Key::F6, 0x40),
+    (Key::F7, 0x41),
+    (Key::F8, 0x42),
+    (Key::F9, 0x43),
+    (Key::F10, 0x44),
+    (Key::NumLock, 0x45),
+    (Key::ScrollLock, 0x46),
+    (Key::Kp7, 0x47),
+    (Key::Kp8, 0x48),
+    (Key::Kp9, 0x49),
+    (Key::KpMinus, 0x4a),
+    (Key::Kp4, 0x4b),
+    (Key::Kp5, 0x4c),
+    (Key::Kp6, 0x4d),
+    (Key::KpPlus, 0x4e),
+    (Key::Kp1, 0x4f),
+    (Key::Kp2, 0x50),
+    (Key::Kp3, 0x51),
+    (Key::Kp0, 0x52),
+    (Key::KpDelete, 0x53),
+    (Key::IntlBackslash, 0x56),
+    (Key::F11, 0x57),
+    (Key::F12, 0x58),
+    (Key::KpReturn, 0xe01c),
+    (Key::ControlRight, 0xe01d),
+    (Key::KpDivide, 0xe035),
+    (Key::PrintScreen, 0xe037),
+    (Key::AltGr, 0xe038),
+    (Key::Home, 0xe047),
+    (Key::UpArrow, 0xe048),
+    (Key::PageUp, 0xe049),
+    (Key::LeftArrow, 0xe04b),
+    (Key::RightArrow, 0xe04d),
+    (Key::End, 0xe04f),
+    (Key::DownArrow, 0xe050),
+    (Key::PageDown, 0xe051),
+    (Key::Insert, 0xe052),
+    (Key::Delete, 0xe053),
+    (Key::MetaLeft, 0xe05b),
+    (Key::MetaRight, 0xe05c),
+];
+
+/// The scan code of the key at `key`'s place on a US keyboard.
+pub fn code(key: Key) -> Option<u16> {
+    SCAN_CODES.iter().find(|(k, _)| *k == key).map(|(_, code)| *code)
+}
+
+/// The key sending scan code `code`.
+pub fn key(code: u16) -> Option<Key> {
+    SCAN_CODES.iter().find(|(_, c)| *c == code).map(|(key, _)| *key)
+}
+
+/// A key event as the capture hook reports it, with the key turned into
+/// the one at its place on the keyboard.
+pub fn positional(event_type: EventType) -> EventType {
+    let by_position =
+        |key| platform::scan_code(key).and_then(self::key).unwrap_or(key);
+    match event_type {
+        EventType::KeyPress(key) => EventType::KeyPress(by_position(key)),
+        EventType::KeyRelease(key) => EventType::KeyRelease(by_position(key)),
+        other => other,
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use rdev::Key;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        MapVirtualKeyW, MAPVK_VK_TO_VSC_EX,
+    };
+
+    /// The scan code the current layout sends `key`'s virtual key with.
+    pub fn scan_code(key: Key) -> Option<u16> {
+        let vk = crate::background::rdev_key_to_vk(key).or(match key {
+            Key::Unknown(code) => u16::try_from(code).ok(),
+            _ => None,
+        })?;
+        let scan = unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC_EX) };
+        u16::try_from(scan).ok().filter(|scan| *scan != 0)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use rdev::Key;
+
+    // Keys are reported by position already.
+    pub fn scan_code(_key: Key) -> Option<u16> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_and_keys_match_both_ways() {
+        for (key, code) in SCAN_CODES {
+            assert_eq!(self::key(*code), Some(*key), "{:#x}", code);
+            assert_eq!(self::code(*key), Some(*code), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn extended_keys_have_the_prefix() {
+        assert_eq!(code(Key::Return), Some(0x1c));
+        assert_eq!(code(Key::KpReturn), Some(0xe01c));
+        assert_eq!(code(Key::Function), None);
+    }
+
+    #[test]
+    fn keys_reported_by_position_stay() {
+        if cfg!(not(target_os = "windows")) {
+            let press = EventType::KeyPress(Key::KeyQ);
+            assert_eq!(positional(press), press);
+        }
+    }
+}
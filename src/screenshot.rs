@@ -0,0 +1,316 @@
+//! Saving part of the screen to a PNG file as a macro plays, e.g. to
+//! archive a dashboard every hour.
+//!
+//! The file name is a template: besides the placeholders typed text has,
+//! `{date}` and `{time}` are filled with when the screenshot is taken.
+//! It names a file inside the screenshots folder, `screenshots` in the
+//! program's data folder, and can't climb out of it.
+//!
+//! Only the capture holds up playback; the file is written in the
+//! background.
+
+use crate::calendar::{self, LocalTime};
+use crate::library;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+
+/// Writes a captured screenshot to its file.
+type Finish = Box<dyn FnOnce() -> Result<(), String> + Send>;
+
+/// A rectangle of the screen, in screen coordinates.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// `X,Y,WIDTHxHEIGHT`, e.g. `0,0,1920x1080`.
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid region '{}', expected X,Y,WxH", s);
+        let mut parts = s.trim().splitn(3, ',');
+        let x = parts.next().and_then(|p| p.trim().parse().ok());
+        let y = parts.next().and_then(|p| p.trim().parse().ok());
+        let size = parts.next().and_then(|p| p.trim().split_once('x'));
+        let (Some(x), Some(y), Some((width, height))) = (x, y, size) else {
+            return Err(invalid());
+        };
+        let width: u32 = width.parse().map_err(|_| invalid())?;
+        let height: u32 = height.parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(format!("region '{}' is empty", s));
+        }
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// `template` with `{date}` and `{time}` filled from `now`. The time is
+/// written with dashes, as Windows doesn't allow colons in file names.
+pub fn stamp(template: &str, now: LocalTime) -> String {
+    let date = format!("{}-{:02}-{:02}", now.year, now.month, now.day);
+    let time = format!(
+        "{:02}-{:02}-{:02}",
+        now.minute / 60,
+        now.minute % 60,
+        now.second
+    );
+    template.replace("{date}", &date).replace("{time}", &time)
+}
+
+/// The folder screenshots are saved in.
+pub fn dir() -> PathBuf {
+    library::data_dir()
+        .map(|d| d.join("screenshots"))
+        .unwrap_or_else(|| PathBuf::from("screenshots"))
+}
+
+/// `name` inside `dir`. Absolute names and ones climbing out with `..`
+/// are refused, as placeholders can fill them from outside the macro.
+fn confine(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let name = Path::new(name);
+    let inside = name
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !inside || name.file_name().is_none() {
+        return Err(format!(
+            "'{}' is not a file name inside {}",
+            name.display(),
+            dir.display()
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+/// Captures `region` for the file `template` names at the current time,
+/// creating its folder if needed, and writes it in the background, which
+/// says when it's saved. Returns the path being written.
+pub fn take(region: &Region, template: &str) -> Result<PathBuf, String> {
+    let path = confine(&dir(), &stamp(template, calendar::now()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let finish = platform::capture(region, &path)?;
+    let saved = path.clone();
+    thread::spawn(move || match finish() {
+        Ok(()) => println!("Saved a screenshot to {}", saved.display()),
+        Err(e) => println!("Screenshot failed: {}", e),
+    });
+    Ok(path)
+}
+
+/// Through GDI, copying the screen into a bitmap and encoding it here.
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{Finish, Region};
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::Graphics::Gdi::{
+        BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC,
+        DeleteObject, GetDC, GetDIBits, ReleaseDC, SelectObject, BITMAPINFO,
+        BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, SRCCOPY,
+    };
+
+    pub fn capture(region: &Region, path: &Path) -> Result<Finish, String> {
+        let pixels = grab(region)?;
+        let (region, path) = (*region, path.to_path_buf());
+        Ok(Box::new(move || save(&region, &path, &pixels)))
+    }
+
+    fn save(
+        region: &Region,
+        path: &Path,
+        pixels: &[u8],
+    ) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+        let encode =
+            |e: png::EncodingError| format!("{}: {}", path.display(), e);
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            region.width,
+            region.height,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(encode)?;
+        writer.write_image_data(pixels).map_err(encode)
+    }
+
+    /// The region's pixels as rows of RGB, top to bottom.
+    fn grab(region: &Region) -> Result<Vec<u8>, String> {
+        let (width, height) = (region.width as i32, region.height as i32);
+        let mut info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                // Negative for rows top to bottom.
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut bgra =
+            vec![0u8; region.width as usize * region.height as usize * 4];
+        let copied = unsafe {
+            let screen = GetDC(HWND::default());
+            let memory = CreateCompatibleDC(screen);
+            let bitmap = CreateCompatibleBitmap(screen, width, height);
+            let previous = SelectObject(memory, bitmap);
+            let copied = BitBlt(
+                memory, 0, 0, width, height, screen, region.x, region.y,
+                SRCCOPY,
+            )
+            .map_err(|e| format!("cannot copy the screen: {}", e));
+            // The bitmap can't be read while it is selected.
+            SelectObject(memory, previous);
+            let copied = copied.and_then(|()| {
+                let rows = GetDIBits(
+                    memory,
+                    bitmap,
+                    0,
+                    region.height,
+                    Some(bgra.as_mut_ptr().cast()),
+                    &mut info,
+                    DIB_RGB_COLORS,
+                );
+                if rows == height {
+                    Ok(())
+                } else {
+                    Err("cannot read the copied screen".to_string())
+                }
+            });
+            let _ = DeleteObject(bitmap);
+            let _ = DeleteDC(memory);
+            ReleaseDC(HWND::default(), screen);
+            copied
+        };
+        copied?;
+        Ok(bgra
+            .chunks_exact(4)
+            .flat_map(|px| [px[2], px[1], px[0]])
+            .collect())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{Finish, Region};
+    use std::path::Path;
+    use std::process::Command;
+
+    pub fn capture(region: &Region, path: &Path) -> Result<Finish, String> {
+        let mut child = Command::new("screencapture")
+            .args(["-x", "-t", "png"])
+            .arg(format!(
+                "-R{},{},{},{}",
+                region.x, region.y, region.width, region.height
+            ))
+            .arg(path)
+            .spawn()
+            .map_err(|e| format!("cannot run screencapture: {}", e))?;
+        Ok(Box::new(move || {
+            let status = child
+                .wait()
+                .map_err(|e| format!("screencapture failed: {}", e))?;
+            if !status.success() {
+                return Err(format!("screencapture failed ({})", status));
+            }
+            Ok(())
+        }))
+    }
+}
+
+/// Through `grim` on Wayland and ImageMagick's `import` on X11.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::{Finish, Region};
+    use std::env;
+    use std::path::Path;
+    use std::process::Command;
+
+    pub fn capture(region: &Region, path: &Path) -> Result<Finish, String> {
+        let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+        let (program, mut command) = if wayland {
+            let mut command = Command::new("grim");
+            command.arg("-g").arg(format!(
+                "{},{} {}x{}",
+                region.x, region.y, region.width, region.height
+            ));
+            command.arg(path);
+            ("grim", command)
+        } else {
+            let mut command = Command::new("import");
+            command.args(["-window", "root", "-crop"]).arg(format!(
+                "{}x{}+{}+{}",
+                region.width, region.height, region.x, region.y
+            ));
+            // Whatever the file is called, it is a PNG.
+            let mut target = std::ffi::OsString::from("png:");
+            target.push(path);
+            command.arg(target);
+            ("import", command)
+        };
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", program, e))?;
+        Ok(Box::new(move || {
+            let status = child
+                .wait()
+                .map_err(|e| format!("{} failed: {}", program, e))?;
+            if !status.success() {
+                return Err(format!("{} failed ({})", program, status));
+            }
+            Ok(())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_files_inside_the_folder() {
+        let dir = Path::new("shots");
+        assert_eq!(
+            confine(dir, "daily/2024-05-01.png"),
+            Ok(dir.join("daily/2024-05-01.png"))
+        );
+        assert_eq!(confine(dir, "./a.png"), Ok(dir.join("./a.png")));
+    }
+
+    #[test]
+    fn refuses_names_outside_the_folder() {
+        let dir = Path::new("shots");
+        assert!(confine(dir, "../a.png").is_err());
+        assert!(confine(dir, "daily/../../a.png").is_err());
+        assert!(confine(dir, "/tmp/a.png").is_err());
+        assert!(confine(dir, "").is_err());
+        assert!(confine(dir, "daily/..").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Starting the recorder with the user's session: a systemd user unit on
+//! Linux, a LaunchAgent on macOS and a Run registry value on Windows.
+
+use std::env;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process::Command;
+
+const NAME: &str = "realtime-macro";
+
+/// Sets the recorder up to start on login with `args`, replacing an
+/// earlier installation. Returns where it was installed.
+pub fn install(args: &[String]) -> Result<String, String> {
+    let exe = env::current_exe()
+        .map_err(|e| format!("cannot find the executable: {}", e))?;
+    platform::install(&exe, args)
+}
+
+/// Undoes [`install`]. Returns what was removed.
+pub fn uninstall() -> Result<String, String> {
+    platform::uninstall()
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if !status.success() {
+        let command = format!("{} {}", program, args.join(" "));
+        return Err(format!("{} failed ({})", command, status));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn home() -> Result<PathBuf, String> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| "HOME is not set".to_string())
+}
+
+#[cfg(unix)]
+fn create_parent(path: &std::path::Path) -> Result<(), String> {
+    match path.parent() {
+        Some(dir) => std::fs::create_dir_all(dir)
+            .map_err(|e| format!("cannot create {}: {}", dir.display(), e)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{create_parent, home, run, NAME};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn unit_path() -> Result<PathBuf, String> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .map_or_else(|| home().map(|h| h.join(".config")), Ok)?;
+        Ok(config.join("systemd/user").join(format!("{}.service", NAME)))
+    }
+
+    /// Quotes an argument for a unit's `ExecStart`.
+    fn quote(arg: &str) -> String {
+        let escaped = arg
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%");
+        format!("\"{}\"", escaped)
+    }
+
+    pub fn install(exe: &Path, args: &[String]) -> Result<String, String> {
+        let path = unit_path()?;
+        let command: Vec<String> = std::iter::once(exe.to_string_lossy())
+            .chain(args.iter().map(|a| a.as_str().into()))
+            .map(|arg| quote(&arg))
+            .collect();
+        // Input capture needs the graphical session, so the unit is tied
+        // to it rather than to the login.
+        let unit = format!(
+            "[Unit]\n\
+             Description=Realtime macro recorder\n\
+             PartOf=graphical-session.target\n\
+             After=graphical-session.target\n\
+             \n\
+             [Service]\n\
+             ExecStart={}\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=graphical-session.target\n",
+            command.join(" ")
+        );
+        create_parent(&path)?;
+        fs::write(&path, unit)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        run("systemctl", &["--user", "daemon-reload"])?;
+        let unit = format!("{}.service", NAME);
+        run("systemctl", &["--user", "enable", &unit])?;
+        Ok(path.display().to_string())
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        let path = unit_path()?;
+        if !path.exists() {
+            return Err(format!("{} is not installed", path.display()));
+        }
+        let unit = format!("{}.service", NAME);
+        run("systemctl", &["--user", "disable", "--now", &unit])?;
+        fs::remove_file(&path)
+            .map_err(|e| format!("cannot remove {}: {}", path.display(), e))?;
+        run("systemctl", &["--user", "daemon-reload"])?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{create_parent, home, run, NAME};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn label() -> String {
+        format!("com.{}", NAME)
+    }
+
+    fn agent_path() -> Result<PathBuf, String> {
+        Ok(home()?
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label())))
+    }
+
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    pub fn install(exe: &Path, args: &[String]) -> Result<String, String> {
+        let path = agent_path()?;
+        let arguments: String = std::iter::once(exe.to_string_lossy())
+            .chain(args.iter().map(|a| a.as_str().into()))
+            .map(|arg| format!("        <string>{}</string>\n", escape(&arg)))
+            .collect();
+        let agent = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>{}</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {}\
+             \x20   </array>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             </dict>\n\
+             </plist>\n",
+            label(),
+            arguments
+        );
+        create_parent(&path)?;
+        // Unload an earlier version first; it's fine if there is none.
+        let agent_arg = path.to_string_lossy();
+        let _ = run("launchctl", &["unload", &agent_arg]);
+        fs::write(&path, agent)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        run("launchctl", &["load", "-w", &agent_arg])?;
+        Ok(path.display().to_string())
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        let path = agent_path()?;
+        if !path.exists() {
+            return Err(format!("{} is not installed", path.display()));
+        }
+        run("launchctl", &["unload", "-w", &path.to_string_lossy()])?;
+        fs::remove_file(&path)
+            .map_err(|e| format!("cannot remove {}: {}", path.display(), e))?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{run, NAME};
+    use std::path::Path;
+
+    const RUN_KEY: &str =
+        r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+    /// Quotes an argument the way the C runtime splits command lines.
+    fn quote(arg: &str) -> String {
+        if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+            return arg.to_string();
+        }
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    }
+
+    pub fn install(exe: &Path, args: &[String]) -> Result<String, String> {
+        let command: Vec<String> = std::iter::once(exe.to_string_lossy())
+            .chain(args.iter().map(|a| a.as_str().into()))
+            .map(|arg| quote(&arg))
+            .collect();
+        let command = command.join(" ");
+        let args = [
+            "add", RUN_KEY, "/v", NAME, "/t", "REG_SZ", "/d", &command, "/f",
+        ];
+        run("reg", &args)?;
+        Ok(format!("{}\\{}", RUN_KEY, NAME))
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        run("reg", &["delete", RUN_KEY, "/v", NAME, "/f"])?;
+        Ok(format!("{}\\{}", RUN_KEY, NAME))
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "windows"
+)))]
+mod platform {
+    use std::path::Path;
+
+    pub fn install(_exe: &Path, _args: &[String]) -> Result<String, String> {
+        Err("starting on login is not supported on this platform".into())
+    }
+
+    pub fn uninstall() -> Result<String, String> {
+        Err("starting on login is not supported on this platform".into())
+    }
+}
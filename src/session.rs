@@ -0,0 +1,62 @@
+//! The interactive mode's working set, kept across restarts: in-memory
+//! recordings and the settings changed while running.
+
+use crate::event::RecordedEvent;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub recording: Vec<RecordedEvent>,
+    pub slots: Vec<Vec<RecordedEvent>>,
+    pub looping: bool,
+    pub speed: f64,
+    /// The library macro last loaded with `--load`.
+    pub last_macro: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            recording: Vec::new(),
+            slots: Vec::new(),
+            looping: false,
+            speed: 1.0,
+            last_macro: None,
+        }
+    }
+}
+
+impl Session {
+    /// Reads the session saved at `path`, or `None` if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(e) => {
+                return Err(format!("cannot open {}: {}", path.display(), e))
+            }
+        };
+        serde_json::from_reader(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+    }
+
+    /// Writes the session to `path`, replacing the old one only once the
+    /// new one is complete.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let partial = path.with_extension("partial");
+        let write = || -> std::io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&partial)?);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+            fs::rename(&partial, path)
+        };
+        write().map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+}
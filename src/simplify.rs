@@ -0,0 +1,75 @@
+//! Shrinking recordings by dropping pointer moves that hardly change the
+//! path, Douglas-Peucker style.
+//!
+//! Each run of moves between other events is simplified on its own and
+//! keeps its first and last move, so the pointer is exactly where it was
+//! recorded whenever a click, key or anything else happens.
+
+use crate::event::{MacroAction, RecordedEvent};
+use rdev::EventType;
+
+/// Farthest in pixels the simplified path strays from the recorded one,
+/// unless configured.
+pub const DEFAULT_TOLERANCE: f64 = 2.0;
+
+/// Drops moves whose removal changes the pointer's path by at most
+/// `tolerance` pixels. Returns how many were dropped.
+pub fn simplify(events: &mut Vec<RecordedEvent>, tolerance: f64) -> usize {
+    let mut keep = vec![true; events.len()];
+    let mut run: Vec<(usize, (f64, f64))> = Vec::new();
+    for (i, evt) in events.iter().enumerate() {
+        match (&evt.action, evt.wait, evt.per_pass) {
+            // Moves that wait or shift on each pass have to stay.
+            (MacroAction::Input(EventType::MouseMove { x, y }), None, None) => {
+                run.push((i, (*x, *y)))
+            }
+            _ => {
+                mark(&run, tolerance, &mut keep);
+                run.clear();
+            }
+        }
+    }
+    mark(&run, tolerance, &mut keep);
+
+    let before = events.len();
+    let mut flags = keep.into_iter();
+    events.retain(|_| flags.next().unwrap_or(true));
+    before - events.len()
+}
+
+/// Clears `keep` for the points of `run` between its ends that can go.
+fn mark(run: &[(usize, (f64, f64))], tolerance: f64, keep: &mut [bool]) {
+    if run.len() < 3 {
+        return;
+    }
+    // Ranges of the run still to be simplified, by their end points.
+    let mut spans = vec![(0, run.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let (a, b) = (run[first].1, run[last].1);
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance(run[i].1, a, b)))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        match farthest {
+            Some((i, d)) if d > tolerance => {
+                spans.push((first, i));
+                spans.push((i, last));
+            }
+            _ => {
+                for (index, _) in &run[first + 1..last] {
+                    keep[*index] = false;
+                }
+            }
+        }
+    }
+}
+
+/// Distance of `p` from the segment from `a` to `b`.
+fn distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    if length == 0.0 {
+        return (p.0 - a.0).hypot(p.1 - a.1);
+    }
+    let t = (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0);
+    (p.0 - a.0 - t * dx).hypot(p.1 - a.1 - t * dy)
+}
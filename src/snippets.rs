@@ -0,0 +1,171 @@
+//! Text expansion: typing an abbreviation followed by a space, Tab or Enter
+//! replaces it with a longer text or a macro from the library.
+//!
+//! Typed text is reconstructed from key presses with a US layout, the same
+//! way previews are, so other layouts only work for the keys they share.
+
+use crate::event::{MacroAction, RecordedEvent};
+use crate::preview::{char_key, key_char};
+use rdev::{EventType, Key};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Longest abbreviation that can be matched.
+const MAX_TYPED: usize = 64;
+/// Time between the keys of a typed expansion.
+const KEY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// An abbreviation and what replaces it, either `text` or the library
+/// macro named by `macro`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snippet {
+    pub abbreviation: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default, rename = "macro")]
+    pub macro_name: Option<String>,
+}
+
+impl Snippet {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.abbreviation.is_empty()
+            || self.abbreviation.chars().any(char::is_whitespace)
+        {
+            return Err(format!(
+                "invalid abbreviation '{}', it can't be empty or contain \
+                 spaces",
+                self.abbreviation
+            ));
+        }
+        match (&self.text, &self.macro_name) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(format!(
+                "snippet '{}' needs either a text or a macro",
+                self.abbreviation
+            )),
+        }
+    }
+}
+
+/// Follows typed text and spots abbreviations.
+#[derive(Debug, Default)]
+pub struct SnippetMatcher {
+    typed: String,
+    shift: bool,
+    // Ctrl, Alt or Meta, which turn keys into shortcuts rather than text
+    modifiers: u8,
+}
+
+impl SnippetMatcher {
+    /// Feeds an input event, returning the snippet whose abbreviation was
+    /// just completed.
+    pub fn input<'a>(
+        &mut self,
+        event_type: &EventType,
+        snippets: &'a [Snippet],
+    ) -> Option<&'a Snippet> {
+        let (key, down) = match *event_type {
+            EventType::KeyPress(key) => (key, true),
+            EventType::KeyRelease(key) => (key, false),
+            EventType::ButtonPress(_) => {
+                // The text cursor has probably moved.
+                self.typed.clear();
+                return None;
+            }
+            _ => return None,
+        };
+        match key {
+            Key::ShiftLeft | Key::ShiftRight => {
+                self.shift = down;
+                return None;
+            }
+            Key::ControlLeft
+            | Key::ControlRight
+            | Key::Alt
+            | Key::AltGr
+            | Key::MetaLeft
+            | Key::MetaRight => {
+                self.modifiers = if down {
+                    self.modifiers.saturating_add(1)
+                } else {
+                    self.modifiers.saturating_sub(1)
+                };
+                return None;
+            }
+            _ if !down => return None,
+            _ => {}
+        }
+        if self.modifiers > 0 {
+            self.typed.clear();
+            return None;
+        }
+
+        let ends_word = matches!(key, Key::Space | Key::Tab | Key::Return);
+        match key_char(key, self.shift) {
+            _ if ends_word => {
+                let typed = std::mem::take(&mut self.typed);
+                return snippets.iter().find(|s| {
+                    typed.strip_suffix(&s.abbreviation).is_some_and(|before| {
+                        !before.ends_with(|c: char| c.is_alphanumeric())
+                    })
+                });
+            }
+            Some(c) => {
+                self.typed.push(c);
+                if self.typed.len() > MAX_TYPED {
+                    self.typed.remove(0);
+                }
+            }
+            None if key == Key::Backspace => {
+                self.typed.pop();
+            }
+            None => self.typed.clear(),
+        }
+        None
+    }
+}
+
+/// Events erasing `erase` characters and then typing `text`. Characters
+/// that can't be typed with a US layout are returned separately.
+pub fn typing(erase: usize, text: &str) -> (Vec<RecordedEvent>, Vec<char>) {
+    let mut events = Vec::new();
+    let mut untypable = Vec::new();
+    let mut tap = |key: Key, shift: bool| {
+        let mut types = Vec::new();
+        if shift {
+            types.push(EventType::KeyPress(Key::ShiftLeft));
+        }
+        types.push(EventType::KeyPress(key));
+        types.push(EventType::KeyRelease(key));
+        if shift {
+            types.push(EventType::KeyRelease(Key::ShiftLeft));
+        }
+        for event_type in types {
+            events.push(RecordedEvent {
+                action: MacroAction::Input(event_type),
+                timestamp: KEY_INTERVAL * events.len() as u32,
+                device: None,
+                wait: None,
+                per_pass: None,
+                text: None,
+            });
+        }
+    };
+
+    for _ in 0..erase {
+        tap(Key::Backspace, false);
+    }
+    for c in text.chars() {
+        let key = match c {
+            '\n' => Some((Key::Return, false)),
+            '\t' => Some((Key::Tab, false)),
+            c => char_key(c),
+        };
+        match key {
+            Some((key, shift)) => tap(key, shift),
+            None => untypable.push(c),
+        }
+    }
+    (events, untypable)
+}
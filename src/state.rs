@@ -0,0 +1,499 @@
+use crate::accel::Curve;
+use crate::backend::BackendKind;
+use crate::background::WindowTarget;
+use crate::event::RecordedEvent;
+use crate::filter::{
+    self, ButtonTarget, DeviceFilter, PlaybackFilter, RecordFilter, Remap,
+};
+use crate::history::Entry;
+use crate::hotkeys::{self, Action};
+use crate::library::{Library, Macro};
+use crate::observe::Observers;
+use crate::params::Param;
+use crate::player::{
+    Domain, EventSource, LoopMarks, Progress, UserOverride, WhenBusy,
+};
+use crate::session::Session;
+use crate::snippets::{Snippet, SnippetMatcher};
+use crate::stream::{EventLog, EventLogWriter};
+use crate::timing::{Clock, Priority};
+use crate::triggers::{
+    CornerTracker, Gesture, GestureTracker, HotCorner, IdleTracker,
+    IdleTrigger, Sequence, SequenceMatcher, TimeRestriction,
+    DEFAULT_SEQUENCE_TIMEOUT,
+};
+use rdev::{Button, Key};
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar};
+use std::task::Waker;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_SLOT_KEYS: [Key; 4] = [Key::F5, Key::F6, Key::F7, Key::F8];
+
+/// Time between the end of the recording and a click added to it.
+pub const DEFAULT_CLICK_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum State {
+    Idle,
+    Recording,
+    Playing,
+    Paused,
+}
+
+/// Something to play.
+pub enum Playback {
+    /// The in-memory recording, or the `--stream` file.
+    Recording,
+    Slot(usize),
+    /// The expansion of a snippet.
+    Snippet(Macro),
+}
+
+impl fmt::Display for Playback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Playback::Recording => f.write_str("the recording"),
+            Playback::Slot(slot) => write!(f, "slot {}", slot + 1),
+            Playback::Snippet(m) => write!(f, "snippet {}", m.name),
+        }
+    }
+}
+
+/// A playback asking for its parameters before it starts.
+#[derive(Debug, Default)]
+pub struct Prompt {
+    /// The thread asking, which stopping doesn't wait for.
+    pub thread: Option<thread::ThreadId>,
+    /// Stopped while asking, so it doesn't start once answered.
+    pub cancelled: bool,
+}
+
+/// A playback running, maybe next to others.
+#[derive(Debug, Clone, Copy)]
+pub struct Running {
+    /// Devices it drives, which none of the others do.
+    pub domain: Domain,
+    /// Set to stop it without stopping the others.
+    pub stop: bool,
+    /// Recording time of the event it was waiting for at the last state
+    /// change, such as a pause.
+    pub position: Duration,
+    /// Section marked while paused to play over and over.
+    pub loop_marks: LoopMarks,
+}
+
+impl Running {
+    pub fn new(domain: Domain) -> Self {
+        Self {
+            domain,
+            stop: false,
+            position: Duration::ZERO,
+            loop_marks: LoopMarks::default(),
+        }
+    }
+}
+
+pub struct SharedState {
+    // change through `set_state` so waiters are woken up
+    pub state: State,
+    /// Notified on every state change; wait on it with this state's mutex.
+    pub changed: Arc<Condvar>,
+    /// Async playbacks to wake on the next state change, as they can't
+    /// wait on `changed`.
+    pub wakers: Vec<Waker>,
+    /// Counts state changes, so playbacks can tell nothing changed without
+    /// taking this state's mutex.
+    pub changes: Arc<AtomicU64>,
+    pub recorded_events: Vec<RecordedEvent>,
+    // capture time of the hotkey that started the recording
+    pub start_record_time: Option<SystemTime>,
+    pub playback_threads: Vec<thread::JoinHandle<()>>,
+    /// The playback asking for its parameters; only one asks at a time.
+    pub prompt: Option<Prompt>,
+    /// The running playbacks.
+    pub playing: Vec<Running>,
+    pub looping: bool,
+    /// Where playback reports its progress, if anywhere.
+    pub progress: Option<Sender<Progress>>,
+    /// Callbacks for recorded and played events and state changes.
+    pub observers: Observers,
+    /// What playback times itself by.
+    pub clock: Clock,
+    /// Set by a soft stop: playbacks end after the pass they are on.
+    pub stop_after_pass: bool,
+    /// Passes to play instead of following `looping`, set by pressing play
+    /// more than once.
+    pub passes: Option<usize>,
+    /// When play was pressed to start playback and how often since.
+    pub play_pressed: Option<(SystemTime, usize)>,
+    // playback pace relative to the recording
+    pub speed: f64,
+    pub target_elements: bool,
+    pub last_mouse_pos: (f64, f64),
+    /// Whether pick mode is showing the pointer position.
+    pub picking: bool,
+    /// Wait before clicks added by hotkey.
+    pub click_delay: Duration,
+    // release of a button whose press was recorded as an element click
+    pub swallow_release: Option<rdev::Button>,
+    // release of a key whose press was recorded as the text it committed
+    pub swallow_key: Option<rdev::Key>,
+    // when set, playback posts messages to this window instead
+    pub target_window: Option<WindowTarget>,
+    pub backend: BackendKind,
+    /// Backend the macro loaded into the recording asks for.
+    pub recording_backend: Option<BackendKind>,
+    /// Button mapping of the macro loaded into the recording.
+    pub recording_buttons: Vec<Remap<Button, ButtonTarget>>,
+    /// Parameters of the macro loaded into the recording.
+    pub recording_params: Vec<Param>,
+    // desktop size, needed by backends that position the pointer themselves
+    pub screen_size: Option<(i32, i32)>,
+    // false when global capture doesn't work in this session
+    pub recording_available: bool,
+    // delay between capture and handling of recorded events, which used to
+    // be baked into their timestamps
+    pub capture_lag_total: Duration,
+    pub capture_lag_max: Duration,
+    // when set, recordings are streamed to this file instead of memory
+    pub stream_path: Option<PathBuf>,
+    // where in the stream file playback starts
+    pub stream_start: Duration,
+    pub stream: Option<EventLogWriter>,
+    // each playback report is appended here as a JSON line
+    pub report_path: Option<PathBuf>,
+    pub record_filter: RecordFilter,
+    pub device_filter: DeviceFilter,
+    pub playback_filter: PlaybackFilter,
+    pub hotkeys: Vec<(Key, Action)>,
+    /// Whether hotkey presses are recorded along with other input.
+    pub record_hotkeys: bool,
+    // hotkeys playing a slot, or recording into it with Shift held
+    pub slot_keys: Vec<Key>,
+    pub slots: Vec<Vec<RecordedEvent>>,
+    pub recording_slot: Option<usize>,
+    // recorded into `recording_slot`, apart from the main recording until
+    // the recording stops
+    pub slot_events: Vec<RecordedEvent>,
+    /// The history entry of the recording in progress.
+    pub recording_entry: Option<Entry>,
+    pub shift_held: bool,
+    /// Keys the user holds down, for branches in playback.
+    pub keys_held: Vec<Key>,
+    pub sequences: Vec<Sequence>,
+    pub sequence_timeout: Duration,
+    pub sequence_matcher: SequenceMatcher,
+    pub gestures: Vec<Gesture>,
+    pub gesture_tracker: GestureTracker,
+    pub hot_corners: Vec<HotCorner>,
+    pub corner_tracker: CornerTracker,
+    pub idle: Option<IdleTrigger>,
+    pub idle_tracker: IdleTracker,
+    /// Whether the idle macro is playing, to be stopped by real input.
+    pub idle_playing: bool,
+    /// Actions only performed at certain times.
+    pub time_windows: Vec<TimeRestriction>,
+    pub snippets: Vec<Snippet>,
+    pub snippet_matcher: SnippetMatcher,
+    pub record_clipboard: bool,
+    /// Clipboard text last recorded.
+    pub clipboard_seen: Option<String>,
+    pub demo: bool,
+    /// Set when pointer moves are played as relative motion, undoing this
+    /// acceleration.
+    pub pointer_curve: Option<Curve>,
+    pub thread_priority: Priority,
+    /// Where the session is saved, unless disabled.
+    pub session_path: Option<PathBuf>,
+    /// Where recordings and playbacks are logged, if anywhere.
+    pub history_path: Option<PathBuf>,
+    pub last_macro: Option<String>,
+    pub when_busy: WhenBusy,
+    /// Slots whose playback interrupts any other, whatever `when_busy`
+    /// says.
+    pub priority_slots: Vec<usize>,
+    /// Slots played again each time they finish while their key is held.
+    pub hold_slots: Vec<usize>,
+    /// Slots whose key arms and disarms playing them over and over.
+    pub toggle_slots: Vec<usize>,
+    /// Slots to play again when they finish, taking turns with anything
+    /// queued.
+    pub repeating: Vec<usize>,
+    pub user_override: Option<UserOverride>,
+    /// Playbacks waiting for the running one to finish.
+    pub queue: VecDeque<Playback>,
+    // keeps a copy of the latest in-memory recording
+    pub library: Option<Library>,
+}
+
+impl SharedState {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            changed: Arc::new(Condvar::new()),
+            wakers: Vec::new(),
+            changes: Arc::new(AtomicU64::new(0)),
+            recorded_events: Vec::new(),
+            start_record_time: None,
+            playback_threads: Vec::new(),
+            prompt: None,
+            playing: Vec::new(),
+            looping: false,
+            progress: None,
+            observers: Observers::default(),
+            clock: Clock::Real,
+            stop_after_pass: false,
+            passes: None,
+            play_pressed: None,
+            speed: 1.0,
+            target_elements: false,
+            last_mouse_pos: (0.0, 0.0),
+            picking: false,
+            click_delay: DEFAULT_CLICK_DELAY,
+            swallow_release: None,
+            swallow_key: None,
+            target_window: None,
+            backend: BackendKind::Enigo,
+            recording_backend: None,
+            recording_buttons: Vec::new(),
+            recording_params: Vec::new(),
+            screen_size: None,
+            recording_available: true,
+            capture_lag_total: Duration::ZERO,
+            capture_lag_max: Duration::ZERO,
+            stream_path: None,
+            stream_start: Duration::ZERO,
+            stream: None,
+            report_path: None,
+            record_filter: RecordFilter::All,
+            device_filter: DeviceFilter::default(),
+            playback_filter: PlaybackFilter::default(),
+            hotkeys: hotkeys::DEFAULT.to_vec(),
+            record_hotkeys: false,
+            slot_keys: DEFAULT_SLOT_KEYS.to_vec(),
+            slots: vec![Vec::new(); DEFAULT_SLOT_KEYS.len()],
+            recording_slot: None,
+            slot_events: Vec::new(),
+            recording_entry: None,
+            shift_held: false,
+            keys_held: Vec::new(),
+            sequences: Vec::new(),
+            sequence_timeout: DEFAULT_SEQUENCE_TIMEOUT,
+            sequence_matcher: SequenceMatcher::default(),
+            gestures: Vec::new(),
+            gesture_tracker: GestureTracker::default(),
+            hot_corners: Vec::new(),
+            corner_tracker: CornerTracker::default(),
+            idle: None,
+            idle_tracker: IdleTracker::default(),
+            idle_playing: false,
+            time_windows: Vec::new(),
+            snippets: Vec::new(),
+            snippet_matcher: SnippetMatcher::default(),
+            record_clipboard: false,
+            clipboard_seen: None,
+            demo: false,
+            pointer_curve: None,
+            thread_priority: Priority::default(),
+            session_path: None,
+            history_path: None,
+            last_macro: None,
+            when_busy: WhenBusy::default(),
+            priority_slots: Vec::new(),
+            hold_slots: Vec::new(),
+            toggle_slots: Vec::new(),
+            repeating: Vec::new(),
+            user_override: None,
+            queue: VecDeque::new(),
+            library: None,
+        }
+    }
+}
+
+impl SharedState {
+    pub fn set_state(&mut self, state: State) {
+        let from = std::mem::replace(&mut self.state, state);
+        self.notify();
+        self.observers.state_changed(from, state);
+    }
+
+    /// Whether there is anything to play back.
+    pub fn has_recording(&self) -> bool {
+        match &self.stream_path {
+            Some(path) => path.metadata().is_ok_and(|m| m.len() > 0),
+            None => !self.recorded_events.is_empty(),
+        }
+    }
+
+    /// The devices `playback` drives. A stream file isn't read for this and
+    /// counts as driving both.
+    pub fn domain(&self, playback: &Playback) -> Domain {
+        match playback {
+            Playback::Recording if self.stream_path.is_some() => Domain::Both,
+            Playback::Recording => Domain::of(&self.recorded_events),
+            Playback::Slot(slot) => Domain::of(&self.slots[*slot]),
+            Playback::Snippet(m) => Domain::of(&m.events),
+        }
+    }
+
+    /// Whether `playback` has the checkpoint `name`. A stream file that
+    /// can't be read is left for playback to report.
+    pub fn has_checkpoint(&self, playback: &Playback, name: &str) -> bool {
+        match playback {
+            Playback::Recording => match &self.stream_path {
+                Some(path) => {
+                    let log =
+                        EventLog::new(path).starting_at(self.stream_start);
+                    let found = match log.open() {
+                        Ok(events) => filter::has_checkpoint(events, name),
+                        Err(_) => true,
+                    };
+                    found
+                }
+                None => filter::has_checkpoint(&self.recorded_events, name),
+            },
+            Playback::Slot(slot) => {
+                filter::has_checkpoint(&self.slots[*slot], name)
+            }
+            Playback::Snippet(m) => filter::has_checkpoint(&m.events, name),
+        }
+    }
+
+    /// Whether `playback` can start next to the running playbacks, which
+    /// it can when they drive other devices and aren't paused.
+    pub fn can_play_alongside(&self, playback: &Playback) -> bool {
+        self.can_play_in(self.domain(playback))
+    }
+
+    /// Whether a playback driving `domain` can start next to the running
+    /// playbacks.
+    pub fn can_play_in(&self, domain: Domain) -> bool {
+        self.state == State::Playing
+            && self.playing.iter().all(|r| !r.domain.overlaps(domain))
+    }
+
+    /// Stops the playback driving `domain`, leaving the others running.
+    pub fn stop_domain(&mut self, domain: Domain) {
+        for running in &mut self.playing {
+            running.stop |= running.domain == domain;
+        }
+        self.notify();
+    }
+
+    /// Wakes everything waiting for the state to change.
+    fn notify(&mut self) {
+        self.changes.fetch_add(1, Ordering::Release);
+        self.changed.notify_all();
+        self.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    /// The running playback driving `domain`, for ones started in
+    /// `playing`.
+    pub fn running_mut(
+        &mut self,
+        domain: Option<Domain>,
+    ) -> Option<&mut Running> {
+        let domain = domain?;
+        self.playing.iter_mut().find(|r| r.domain == domain)
+    }
+
+    /// Whether the playback driving `domain` was stopped on its own.
+    pub fn is_stopped(&self, domain: Option<Domain>) -> bool {
+        domain.is_some_and(|domain| {
+            self.playing.iter().any(|r| r.domain == domain && r.stop)
+        })
+    }
+
+    /// Replaces the slot hotkeys, keeping recordings of slots that remain.
+    pub fn set_slot_keys(&mut self, keys: Vec<Key>) {
+        self.slots.resize(keys.len(), Vec::new());
+        self.slot_keys = keys;
+    }
+
+    /// The working set to keep across restarts.
+    pub fn session(&self) -> Session {
+        Session {
+            recording: self.recorded_events.clone(),
+            slots: self.slots.clone(),
+            looping: self.looping,
+            speed: self.speed,
+            last_macro: self.last_macro.clone(),
+        }
+    }
+
+    /// Restores a saved session. Slots beyond the configured slot keys
+    /// are dropped.
+    pub fn restore(&mut self, session: Session) {
+        self.recorded_events = session.recording;
+        for (slot, events) in self.slots.iter_mut().zip(session.slots) {
+            *slot = events;
+        }
+        self.looping = session.looping;
+        self.speed = session.speed;
+        self.last_macro = session.last_macro;
+    }
+
+    pub fn hotkey(&self, key: Key) -> Option<Action> {
+        self.hotkeys
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, action)| *action)
+    }
+
+    /// Whether `key` is one of the hotkeys, slot hotkeys included.
+    pub fn is_hotkey(&self, key: Key) -> bool {
+        self.hotkey(key).is_some() || self.slot_keys.contains(&key)
+    }
+
+    pub fn slot_for_key(&self, key: Key) -> Option<usize> {
+        self.slot_keys.iter().position(|k| *k == key)
+    }
+
+    /// The events of the recording in progress: those of the slot being
+    /// recorded into, or else the main recording.
+    pub fn recording(&self) -> &[RecordedEvent] {
+        match self.recording_slot {
+            Some(_) => &self.slot_events,
+            None => &self.recorded_events,
+        }
+    }
+
+    pub fn recording_mut(&mut self) -> &mut Vec<RecordedEvent> {
+        match self.recording_slot {
+            Some(_) => &mut self.slot_events,
+            None => &mut self.recorded_events,
+        }
+    }
+
+    pub fn last_recorded_timestamp(&self) -> Option<Duration> {
+        match &self.stream {
+            Some(stream) => stream.last_timestamp,
+            None => self.recording().last().map(|e| e.timestamp),
+        }
+    }
+
+    /// Stores a recorded event in memory or in the stream file.
+    pub fn store_event(&mut self, evt: RecordedEvent) {
+        self.observers.event_recorded(&evt);
+        match self.stream.as_mut() {
+            Some(stream) => {
+                if let Err(e) = stream.append(&evt) {
+                    println!("Failed to write event: {}", e);
+                }
+            }
+            None => self.recording_mut().push(evt),
+        }
+    }
+}
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
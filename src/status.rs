@@ -0,0 +1,213 @@
+//! What the running recorder is doing, answered on the port it holds as
+//! the running instance, for the `status` command, scripts and status bars.
+//!
+//! A status request gets one JSON line; nothing can be changed through it.
+
+use crate::instance;
+use crate::player::Progress;
+use crate::state::{SharedState, State};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a bar's status is brought up to date.
+const BAR_REFRESH: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    /// `idle`, `recording`, `playing` or `paused`.
+    pub state: String,
+    /// The macro last loaded or saved, if any.
+    #[serde(rename = "macro")]
+    pub macro_name: Option<String>,
+    /// Loop passes completed by the running playback.
+    pub passes: Option<usize>,
+    /// Recording time reached in the current pass, in seconds.
+    pub position: Option<f64>,
+    pub looping: bool,
+    pub speed: f64,
+    pub queued: usize,
+    pub uptime: f64,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.state)?;
+        if let Some(name) = &self.macro_name {
+            write!(f, ", macro {}", name)?;
+        }
+        if let (Some(passes), Some(position)) = (self.passes, self.position) {
+            write!(f, " at {:.1} s of pass {}", position, passes + 1)?;
+        }
+        write!(
+            f,
+            ", looping {}, speed {}x, {} queued, up {:.0} s",
+            if self.looping { "on" } else { "off" },
+            self.speed,
+            self.queued,
+            self.uptime
+        )
+    }
+}
+
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::Idle => "idle",
+        State::Recording => "recording",
+        State::Playing => "playing",
+        State::Paused => "paused",
+    }
+}
+
+/// What status requests are answered with, taking over the progress
+/// playback reports.
+pub fn reporter(
+    shared: Arc<Mutex<SharedState>>,
+) -> impl Fn() -> String + Send + 'static {
+    let started = Instant::now();
+    let latest = Arc::new(Mutex::new(None::<Progress>));
+
+    let (tx, rx) = mpsc::channel();
+    shared.lock().unwrap().progress = Some(tx);
+    thread::spawn({
+        let latest = Arc::clone(&latest);
+        move || {
+            for progress in rx {
+                *latest.lock().unwrap() = Some(progress);
+            }
+        }
+    });
+
+    move || {
+        let status = {
+            let shared = shared.lock().unwrap();
+            let busy = matches!(shared.state, State::Playing | State::Paused);
+            let progress = latest.lock().unwrap().filter(|_| busy);
+            Status {
+                state: state_name(shared.state).to_string(),
+                macro_name: shared.last_macro.clone(),
+                passes: progress.map(|p| p.passes),
+                position: progress.map(|p| p.at.as_secs_f64()),
+                looping: shared.looping,
+                speed: shared.speed,
+                queued: shared.queue.len(),
+                uptime: started.elapsed().as_secs_f64(),
+            }
+        };
+        serde_json::to_string(&status).unwrap_or_default()
+    }
+}
+
+/// Asks the running recorder for its status.
+pub fn query() -> Result<Status, String> {
+    let line = instance::status()?;
+    serde_json::from_str(&line).map_err(|e| format!("invalid status: {}", e))
+}
+
+/// Status bars `follow` can write for.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Bar {
+    /// JSON lines for a waybar custom module with `return-type` json.
+    Waybar,
+    /// Plain lines, for polybar's `tail = true` and i3blocks.
+    Polybar,
+    /// The i3bar protocol, for i3 and sway.
+    I3bar,
+}
+
+impl FromStr for Bar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "waybar" => Ok(Bar::Waybar),
+            "polybar" => Ok(Bar::Polybar),
+            "i3bar" => Ok(Bar::I3bar),
+            _ => Err(format!("unknown bar '{}' (waybar, polybar, i3bar)", s)),
+        }
+    }
+}
+
+/// The short text shown in a bar: the state and macro, or `off` when no
+/// recorder is running.
+fn bar_text(status: Option<&Status>) -> String {
+    let Some(status) = status else {
+        return "off".into();
+    };
+    let symbol = match status.state.as_str() {
+        "recording" => "\u{25cf}",
+        "playing" => "\u{25b6}",
+        "paused" => "\u{23f8}",
+        _ => "\u{25a0}",
+    };
+    match (&status.macro_name, status.state.as_str()) {
+        (Some(name), "playing" | "paused") => format!("{} {}", symbol, name),
+        _ => format!("{} {}", symbol, status.state),
+    }
+}
+
+#[derive(Serialize)]
+struct WaybarLine<'a> {
+    text: &'a str,
+    tooltip: &'a str,
+    class: &'a str,
+}
+
+#[derive(Serialize)]
+struct I3barBlock<'a> {
+    name: &'a str,
+    full_text: &'a str,
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+/// Writes a line for `bar` whenever the status of the running recorder
+/// changes, until the bar closes the output.
+pub fn follow(bar: Bar) -> Result<(), String> {
+    let mut out = io::stdout().lock();
+    let mut write = |line: String| {
+        writeln!(out, "{}", line)
+            .and_then(|()| out.flush())
+            .map_err(|e| e.to_string())
+    };
+    if bar == Bar::I3bar {
+        write("{\"version\":1}\n[".into())?;
+    }
+    let mut shown = None;
+    loop {
+        let status = query().ok();
+        let text = bar_text(status.as_ref());
+        let tooltip = status.as_ref().map_or(text.clone(), Status::to_string);
+        let class = status.as_ref().map_or("off", |s| s.state.as_str());
+        // The tooltip counts up the uptime, so it alone changing doesn't
+        // need a new line.
+        let showing = (text.clone(), class.to_string());
+        if shown.as_ref() != Some(&showing) {
+            let line = match bar {
+                Bar::Waybar => to_json(&WaybarLine {
+                    text: &text,
+                    tooltip: &tooltip,
+                    class,
+                })?,
+                Bar::Polybar => text,
+                Bar::I3bar => format!(
+                    "[{}],",
+                    to_json(&I3barBlock {
+                        name: "realtime-macro",
+                        full_text: &text,
+                    })?
+                ),
+            };
+            write(line)?;
+            shown = Some(showing);
+        }
+        thread::sleep(BAR_REFRESH);
+    }
+}
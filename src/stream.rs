@@ -0,0 +1,286 @@
+//! Append-only on-disk event log, for recordings too long to keep in memory.
+//!
+//! The log is JSON Lines: one recorded event per line. A crash loses at most
+//! the events since the last periodic flush, and a torn final line is
+//! ignored when reading. Logs are read a chunk at a time ahead of playback,
+//! which can also start part way in without reading what comes before. The
+//! reading also skips and remaps events for the playback filter, so playing
+//! them costs no more than playing ones kept in memory.
+//!
+//! Only logs are played this way: a library macro is one JSON document and
+//! is read whole, so recordings too long to keep in memory stay logs, e.g.
+//! recorded with `--stream`.
+
+use crate::event::RecordedEvent;
+use crate::filter::Plan;
+use crate::player::EventSource;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events between the entries of a log's index.
+const INDEX_STRIDE: usize = 1024;
+
+/// Events read ahead of playback at a time, and how many such chunks may
+/// wait for it, which bounds the memory a log takes however long it is.
+const CHUNK_SIZE: usize = 512;
+const CHUNKS_AHEAD: usize = 4;
+
+pub struct EventLogWriter {
+    writer: BufWriter<File>,
+    last_flush: Instant,
+    pub count: usize,
+    pub last_timestamp: Option<Duration>,
+}
+
+impl EventLogWriter {
+    /// Creates (or truncates) the log at `path`.
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+        // The old log's index would point into the new one.
+        let _ = fs::remove_file(index_path(path));
+        Ok(Self {
+            writer: BufWriter::new(file),
+            last_flush: Instant::now(),
+            count: 0,
+            last_timestamp: None,
+        })
+    }
+
+    pub fn append(&mut self, evt: &RecordedEvent) -> Result<(), String> {
+        serde_json::to_writer(&mut self.writer, evt)
+            .map_err(|e| e.to_string())?;
+        self.writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        self.count += 1;
+        self.last_timestamp = Some(evt.timestamp);
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.last_flush = Instant::now();
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// A recording on disk, streamed event by event during playback.
+pub struct EventLog {
+    path: PathBuf,
+    start: Duration,
+    plan: Option<Plan>,
+    index: OnceLock<Index>,
+}
+
+impl EventLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            start: Duration::ZERO,
+            plan: None,
+            index: OnceLock::new(),
+        }
+    }
+
+    /// Plays only the events from `start` on, as if recorded that much
+    /// later.
+    pub fn starting_at(mut self, start: Duration) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Skips and remaps events by `plan` as they are read, which takes
+    /// that work off the playback thread.
+    pub fn with_plan(mut self, plan: Plan) -> Self {
+        self.plan = Some(plan);
+        self
+    }
+
+    fn index(&self) -> Result<&Index, String> {
+        if let Some(index) = self.index.get() {
+            return Ok(index);
+        }
+        let index = Index::open(&self.path)?;
+        Ok(self.index.get_or_init(|| index))
+    }
+}
+
+impl EventSource for EventLog {
+    fn open(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = RecordedEvent> + '_>, String> {
+        let mut file = File::open(&self.path)
+            .map_err(|e| format!("cannot open {}: {}", self.path.display(), e))?;
+        let mut first_line = 0;
+        if !self.start.is_zero() {
+            let (offset, line) = self.index()?.before(self.start);
+            file.seek(SeekFrom::Start(offset))
+                .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+            first_line = line;
+        }
+        // Parsing and checking run ahead on their own thread, so playback
+        // starts right away and doesn't wait on the disk.
+        let (chunks, prepared) = mpsc::sync_channel(CHUNKS_AHEAD);
+        let plan = self.plan.clone();
+        thread::spawn(move || prepare(file, first_line, plan, chunks));
+        let start = self.start;
+        let events = prepared
+            .into_iter()
+            .flatten()
+            .skip_while(move |evt: &RecordedEvent| evt.timestamp < start)
+            .map(move |mut evt| {
+                // Events going back in time after `start` play right away.
+                evt.timestamp = evt.timestamp.saturating_sub(start);
+                evt
+            });
+        Ok(Box::new(events))
+    }
+}
+
+/// Reads and checks the events in `file`, starting at `first_line`, applies
+/// `plan` to them and sends them on in chunks until the receiver goes away.
+fn prepare(
+    file: File,
+    first_line: usize,
+    plan: Option<Plan>,
+    chunks: SyncSender<Vec<RecordedEvent>>,
+) {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut last = Duration::ZERO;
+    let mut out_of_order = false;
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let n = first_line + n + 1;
+        let parsed = line.map_err(|e| e.to_string()).and_then(|l| {
+            serde_json::from_str::<RecordedEvent>(&l)
+                .map_err(|e| e.to_string())
+        });
+        let evt = match parsed {
+            Ok(evt) => evt,
+            Err(e) => {
+                println!("Stopping at line {} of the log: {}", n, e);
+                break;
+            }
+        };
+        if evt.timestamp < last && !out_of_order {
+            out_of_order = true;
+            println!(
+                "Line {} of the log goes back in time; `sanitize` fixes \
+                 this.",
+                n
+            );
+        }
+        last = last.max(evt.timestamp);
+        let evt = match &plan {
+            Some(plan) => plan.apply_event(evt),
+            None => Some(evt),
+        };
+        chunk.extend(evt);
+        if chunk.len() == CHUNK_SIZE {
+            let full = std::mem::replace(
+                &mut chunk,
+                Vec::with_capacity(CHUNK_SIZE),
+            );
+            // Playback stopped.
+            if chunks.send(full).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = chunks.send(chunk);
+}
+
+/// Where every [`INDEX_STRIDE`]th event of a log starts, so playback can
+/// seek close to a time and read on from there. It is kept next to the log
+/// and only extended as the log grows, so each playback reads at most what
+/// was appended since the last.
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    /// How much of the log is indexed, in bytes and lines.
+    length: u64,
+    lines: usize,
+    /// The event's timestamp, byte offset and line, counting from 0.
+    entries: Vec<(Duration, u64, usize)>,
+}
+
+impl Index {
+    /// The index of the log at `path`, read from beside it and brought up
+    /// to date.
+    fn open(path: &Path) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let length = fs::metadata(path).map_err(error)?.len();
+        let saved = fs::read(index_path(path))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Index>(&data).ok())
+            // A shorter log was written over the one indexed.
+            .filter(|index| index.length <= length);
+        let mut index = saved.unwrap_or_default();
+        if index.length < length {
+            index.extend(path)?;
+            // Without it, the next playback indexes the log again.
+            if let Ok(data) = serde_json::to_vec(&index) {
+                let _ = fs::write(index_path(path), data);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Reads the log at `path` on from where the index ends, parsing only
+    /// the indexed events.
+    fn extend(&mut self, path: &Path) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut file = File::open(path)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+        file.seek(SeekFrom::Start(self.length)).map_err(error)?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).map_err(error)?;
+            // A torn line is indexed once it is finished.
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            if self.lines.is_multiple_of(INDEX_STRIDE) {
+                match serde_json::from_str::<RecordedEvent>(&line) {
+                    Ok(evt) => self.entries.push((
+                        evt.timestamp,
+                        self.length,
+                        self.lines,
+                    )),
+                    // Read as the end of the log.
+                    Err(_) => break,
+                }
+            }
+            self.length += read as u64;
+            self.lines += 1;
+        }
+        Ok(())
+    }
+
+    /// The offset and line of the last indexed event before `at`, or of
+    /// the start of the log.
+    fn before(&self, at: Duration) -> (u64, usize) {
+        let i = self.entries.partition_point(|(t, _, _)| *t < at);
+        match i.checked_sub(1) {
+            Some(i) => (self.entries[i].1, self.entries[i].2),
+            None => (0, 0),
+        }
+    }
+}
+
+/// Where the index of the log at `path` is kept.
+fn index_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".idx");
+    PathBuf::from(name)
+}
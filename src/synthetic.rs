@@ -0,0 +1,93 @@
+//! Telling input the player injected apart from the user's own.
+//!
+//! Most capture methods can't see where an event came from, so injected
+//! events are noted as they are sent and captured ones are matched against
+//! those sent shortly before.
+
+use crate::backend::InputBackend;
+use rdev::EventType;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long after injection an event can still come back through capture.
+const WINDOW: Duration = Duration::from_millis(250);
+/// Captured pointer positions may be rounded or scaled slightly.
+const MOVE_TOLERANCE: f64 = 2.0;
+
+type Recent = VecDeque<(Instant, EventType)>;
+
+static RECENT: Mutex<Recent> = Mutex::new(VecDeque::new());
+
+fn same(injected: &EventType, captured: &EventType) -> bool {
+    match (injected, captured) {
+        (
+            EventType::MouseMove { x, y },
+            EventType::MouseMove { x: cx, y: cy },
+        ) => {
+            (x - cx).abs() <= MOVE_TOLERANCE
+                && (y - cy).abs() <= MOVE_TOLERANCE
+        }
+        _ => injected == captured,
+    }
+}
+
+/// The injections still within [`WINDOW`].
+fn recent() -> MutexGuard<'static, Recent> {
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    while recent.front().is_some_and(|(at, _)| now - *at > WINDOW) {
+        recent.pop_front();
+    }
+    recent
+}
+
+/// Notes that `evt` is about to be injected.
+pub fn injecting(evt: &EventType) {
+    recent().push_back((Instant::now(), *evt));
+}
+
+/// Whether the captured `evt` is one we injected. Each injection matches
+/// only one captured event.
+pub fn is_injected(evt: &EventType) -> bool {
+    let mut recent = recent();
+    match recent.iter().position(|(_, injected)| same(injected, evt)) {
+        Some(i) => {
+            recent.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Wraps a backend to note everything it injects.
+pub struct Tagged<B>(pub B);
+
+impl<B: InputBackend> InputBackend for Tagged<B> {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        injecting(evt);
+        self.0.send(evt)
+    }
+
+    fn send_due(
+        &mut self,
+        evt: &EventType,
+        due: Instant,
+    ) -> Result<(), String> {
+        injecting(evt);
+        self.0.send_due(evt, due)
+    }
+
+    fn send_batch(
+        &mut self,
+        evts: &[EventType],
+        due: &[Instant],
+    ) -> Result<(), String> {
+        evts.iter().for_each(injecting);
+        self.0.send_batch(evts, due)
+    }
+
+    fn is_available(&self) -> bool {
+        self.0.is_available()
+    }
+}
@@ -0,0 +1,262 @@
+//! High-resolution scheduling for playback.
+//!
+//! Events are scheduled against absolute deadlines measured from the start
+//! of a pass, so sleep overshoot on one event doesn't push every later event
+//! back. OS sleeps are only accurate to a millisecond or worse, so the last
+//! [`SPIN_THRESHOLD`] before a deadline is spun instead of slept.
+
+use serde::Deserialize;
+use std::hint;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How close to a deadline we stop sleeping and start spinning.
+pub const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// Maps recording offsets to wall-clock deadlines.
+#[derive(Debug, Clone, Copy)]
+pub struct Scheduler {
+    origin: Instant,
+    // played time per recorded time, worked out once from the speed
+    scale: f64,
+}
+
+impl Scheduler {
+    /// Starts a pass played at `speed` times the recorded pace.
+    pub fn start(speed: f64) -> Self {
+        Self::start_at(Instant::now(), speed)
+    }
+
+    pub fn start_at(origin: Instant, speed: f64) -> Self {
+        Self {
+            origin,
+            scale: 1.0 / speed,
+        }
+    }
+
+    pub fn deadline(&self, offset: Duration) -> Instant {
+        self.origin + offset.mul_f64(self.scale)
+    }
+
+    /// Pushes every later deadline back, e.g. by the time spent paused.
+    pub fn delay(&mut self, by: Duration) {
+        self.origin += by;
+    }
+}
+
+/// Time as the player sees it.
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    Real,
+    /// Jumps ahead to each deadline instead of sleeping, so playback takes
+    /// no time and the times events are played at are exactly the same on
+    /// every run.
+    Simulated(Arc<Mutex<Instant>>),
+}
+
+impl Clock {
+    pub fn simulated() -> Self {
+        Clock::Simulated(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        matches!(self, Clock::Simulated(_))
+    }
+
+    pub fn now(&self) -> Instant {
+        match self {
+            Clock::Real => Instant::now(),
+            Clock::Simulated(now) => *now.lock().unwrap(),
+        }
+    }
+
+    pub fn sleep_until(&self, deadline: Instant) {
+        match self {
+            Clock::Real => sleep_until(deadline),
+            Clock::Simulated(now) => {
+                let mut now = now.lock().unwrap();
+                *now = (*now).max(deadline);
+            }
+        }
+    }
+}
+
+/// Sleeps until `deadline`, spinning for the last stretch.
+pub fn sleep_until(deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining > SPIN_THRESHOLD {
+        thread::sleep(remaining - SPIN_THRESHOLD);
+    }
+    spin_until(deadline);
+}
+
+/// Busy-waits until `deadline`, yielding so other threads still run.
+pub fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        hint::spin_loop();
+        thread::yield_now();
+    }
+}
+
+/// How much CPU time the playback thread asks for. Above normal, Windows
+/// timers also tick every millisecond for as long as it plays.
+#[derive(Debug, PartialEq, Copy, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Priority {
+    #[default]
+    Normal,
+    High,
+    /// Real-time scheduling, which may need extra rights.
+    Realtime,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] =
+        [Priority::Normal, Priority::High, Priority::Realtime];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Realtime => "realtime",
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|p| p.name() == s).ok_or_else(|| {
+            format!("unknown priority '{}' (normal, high, realtime)", s)
+        })
+    }
+}
+
+impl TryFrom<String> for Priority {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Raised timer resolution, for as long as it is kept.
+pub struct Boost {
+    timer: bool,
+}
+
+impl Drop for Boost {
+    fn drop(&mut self) {
+        if self.timer {
+            platform::end_timer_period();
+        }
+    }
+}
+
+/// Gives the calling thread `priority` until it ends. The thread stays at
+/// its priority if it can't be raised, e.g. for lack of rights, but the
+/// error is returned along with the boost so it can be reported.
+pub fn boost(priority: Priority) -> (Boost, Option<String>) {
+    if priority == Priority::Normal {
+        return (Boost { timer: false }, None);
+    }
+    let boost = Boost {
+        timer: platform::begin_timer_period(),
+    };
+    (boost, platform::raise_thread(priority).err())
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::Priority;
+    use windows::Win32::Media::{timeBeginPeriod, timeEndPeriod};
+    use windows::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
+        THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    const TIMER_PERIOD_MS: u32 = 1;
+
+    pub fn begin_timer_period() -> bool {
+        // TIMERR_NOERROR
+        unsafe { timeBeginPeriod(TIMER_PERIOD_MS) == 0 }
+    }
+
+    pub fn end_timer_period() {
+        unsafe { timeEndPeriod(TIMER_PERIOD_MS) };
+    }
+
+    pub fn raise_thread(priority: Priority) -> Result<(), String> {
+        let level = match priority {
+            Priority::Realtime => THREAD_PRIORITY_TIME_CRITICAL,
+            _ => THREAD_PRIORITY_HIGHEST,
+        };
+        unsafe { SetThreadPriority(GetCurrentThread(), level) }
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Priority;
+    use std::io;
+
+    /// Nice value of a high priority thread.
+    const HIGH_NICE: libc::c_int = -10;
+    /// SCHED_FIFO priority of a real-time thread, middling so it doesn't
+    /// starve the kernel's own threads.
+    const REALTIME_PRIORITY: libc::c_int = 50;
+
+    // Linux timers are fine-grained already.
+    pub fn begin_timer_period() -> bool {
+        false
+    }
+
+    pub fn end_timer_period() {}
+
+    pub fn raise_thread(priority: Priority) -> Result<(), String> {
+        let failed = |e: io::Error| {
+            format!("{} (needs CAP_SYS_NICE or a raised rtprio limit)", e)
+        };
+        if priority == Priority::Realtime {
+            let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+            param.sched_priority = REALTIME_PRIORITY;
+            let err = unsafe {
+                libc::pthread_setschedparam(
+                    libc::pthread_self(),
+                    libc::SCHED_FIFO,
+                    &param,
+                )
+            };
+            return match err {
+                0 => Ok(()),
+                err => Err(failed(io::Error::from_raw_os_error(err))),
+            };
+        }
+        // Nice values are per thread on Linux.
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+        match unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, HIGH_NICE) } {
+            0 => Ok(()),
+            _ => Err(failed(io::Error::last_os_error())),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::Priority;
+
+    pub fn begin_timer_period() -> bool {
+        false
+    }
+
+    pub fn end_timer_period() {}
+
+    pub fn raise_thread(_priority: Priority) -> Result<(), String> {
+        Err("not supported on this platform".into())
+    }
+}
@@ -0,0 +1,240 @@
+//! Deterministic playback on a simulated clock, for testing the player's
+//! scheduling without waiting for it or depending on the machine's load.
+//!
+//! A golden trace keeps a macro together with what playing it injected, so
+//! `verify` can tell when a change to the player plays it differently.
+
+use crate::backend::MockBackend;
+use crate::branch::Branched;
+use crate::event::{MacroAction, RecordedEvent};
+use crate::filter::{Filtered, PlaybackFilter};
+use crate::player::{self, EventSource, PlaybackReport};
+use crate::state::{SharedState, State};
+use crate::timing::Clock;
+use crate::vars::Vars;
+use rdev::EventType;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// File extension of golden traces.
+pub const EXTENSION: &str = "trace";
+
+/// The events a playback injected, with when it injected them relative to
+/// its start.
+pub type Trace = Vec<(Duration, EventType)>;
+
+/// Plays `source` once, or as many passes as it has, at `speed` into a
+/// [`MockBackend`] on a [`Clock::Simulated`]. The same source always gives
+/// the same trace.
+pub fn simulate<S: EventSource + ?Sized>(
+    source: &S,
+    speed: f64,
+) -> (Trace, PlaybackReport) {
+    let clock = Clock::simulated();
+    let start = clock.now();
+    let mut state = SharedState::new();
+    state.speed = speed;
+    state.clock = clock.clone();
+    state.set_state(State::Playing);
+    let shared = Arc::new(Mutex::new(state));
+    let mut mock = MockBackend::with_clock(clock);
+
+    let report = player::run(&mut mock, source, &shared, None);
+    let trace = mock
+        .injected
+        .into_iter()
+        .map(|(at, evt)| (at - start, evt))
+        .collect();
+    (trace, report)
+}
+
+/// An event injected at some time into a playback.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub at: Duration,
+    pub event: EventType,
+}
+
+/// A macro with the trace playing it is expected to give.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Golden {
+    pub speed: f64,
+    pub events: Vec<RecordedEvent>,
+    pub trace: Vec<Step>,
+}
+
+impl Golden {
+    /// Plays `events` at `speed` and keeps the trace as the expected one.
+    /// Element clicks, clipboard text, waits and screenshots depend on the
+    /// desktop and are left out.
+    pub fn record(events: Vec<RecordedEvent>, speed: f64) -> Self {
+        let events: Vec<RecordedEvent> = events
+            .into_iter()
+            .filter(|evt| {
+                !matches!(
+                    evt.action,
+                    MacroAction::ClickElement { .. }
+                        | MacroAction::Clipboard(_)
+                        | MacroAction::Wait { .. }
+                        | MacroAction::Screenshot { .. }
+                )
+            })
+            .collect();
+        let trace = play(&events, speed);
+        Self {
+            speed,
+            events,
+            trace,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("invalid trace {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    /// Plays the macro again and describes the first difference from the
+    /// expected trace, if any.
+    pub fn compare(&self) -> Result<(), String> {
+        let played = play(&self.events, self.speed);
+        let steps = self.trace.iter().zip(&played).enumerate();
+        for (i, (expected, got)) in steps {
+            if expected != got {
+                return Err(format!(
+                    "step {}: expected {:?} at {:.3} s, got {:?} at {:.3} s",
+                    i + 1,
+                    expected.event,
+                    expected.at.as_secs_f64(),
+                    got.event,
+                    got.at.as_secs_f64()
+                ));
+            }
+        }
+        if played.len() != self.trace.len() {
+            return Err(format!(
+                "expected {} steps, got {}",
+                self.trace.len(),
+                played.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The trace of `events` as the recorder would play them, branches and
+/// typed text included. Placeholders are left as they are rather than
+/// filled from the environment, which differs between machines.
+fn play(events: &[RecordedEvent], speed: f64) -> Vec<Step> {
+    let source = Branched::new(events.to_vec(), Vec::new());
+    let source = Filtered::new(source, PlaybackFilter::default())
+        .with_env(Vars::new());
+    let (trace, _) = simulate(&source, speed);
+    trace
+        .into_iter()
+        .map(|(at, event)| Step { at, event })
+        .collect()
+}
+
+/// Compares every golden trace in `dir`, returning the failures by file.
+pub fn verify(dir: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+        .collect();
+    if paths.is_empty() {
+        return Err(format!("no .{} files in {}", EXTENSION, dir.display()));
+    }
+    paths.sort();
+    let mut failures = Vec::new();
+    for path in paths {
+        let result = Golden::load(&path).and_then(|golden| golden.compare());
+        if let Err(e) = result {
+            failures.push((path, e));
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::{Button, Key};
+
+    fn input(ms: u64, input: EventType) -> RecordedEvent {
+        RecordedEvent {
+            action: MacroAction::Input(input),
+            timestamp: Duration::from_millis(ms),
+            device: None,
+            wait: None,
+            per_pass: None,
+            text: None,
+        }
+    }
+
+    fn clicks_and_keys() -> Vec<RecordedEvent> {
+        vec![
+            input(0, EventType::MouseMove { x: 10.0, y: 20.0 }),
+            input(100, EventType::ButtonPress(Button::Left)),
+            input(150, EventType::ButtonRelease(Button::Left)),
+            input(400, EventType::KeyPress(Key::KeyA)),
+            input(450, EventType::KeyRelease(Key::KeyA)),
+        ]
+    }
+
+    #[test]
+    fn simulated_playback_keeps_the_scaled_times() {
+        let events = clicks_and_keys();
+        let (trace, report) = simulate(&events, 2.0);
+        let times: Vec<u64> =
+            trace.iter().map(|(at, _)| at.as_millis() as u64).collect();
+        assert_eq!(times, [0, 50, 75, 200, 225]);
+        assert_eq!(trace[3].1, EventType::KeyPress(Key::KeyA));
+        assert_eq!(report.events_injected, 5);
+    }
+
+    #[test]
+    fn compare_reports_the_first_difference() {
+        let mut golden = Golden::record(clicks_and_keys(), 1.0);
+        assert_eq!(golden.compare(), Ok(()));
+
+        golden.trace[1].at += Duration::from_millis(1);
+        let error = golden.compare().unwrap_err();
+        assert!(error.starts_with("step 2:"), "{}", error);
+
+        golden.trace.pop();
+        golden.trace[1].at -= Duration::from_millis(1);
+        assert_eq!(golden.compare(), Err("expected 4 steps, got 5".into()));
+    }
+
+    #[test]
+    fn placeholders_are_not_filled_from_the_environment() {
+        let typed = RecordedEvent {
+            action: MacroAction::Type("{HOME}".into()),
+            ..input(0, EventType::KeyPress(Key::KeyA))
+        };
+        let golden = Golden::record(vec![typed], 1.0);
+        // Shift, press, release, shift for each of the six characters.
+        assert_eq!(golden.trace.len(), 24);
+        assert_eq!(golden.trace[5].event, EventType::KeyPress(Key::KeyH));
+    }
+
+    #[test]
+    fn the_golden_traces_match() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("traces");
+        assert_eq!(verify(&dir), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,471 @@
+//! Triggers other than single hotkeys, for people who have no free keys
+//! left.
+
+use crate::calendar::TimeWindow;
+use crate::hotkeys::Action;
+use rdev::{Button, Key};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Longest gap between the keys of a sequence, unless configured.
+pub const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Distance in pixels the pointer has to travel to draw a stroke.
+const STROKE_LENGTH: f64 = 40.0;
+
+/// How long the pointer has to stay in a hot corner, unless configured.
+pub const DEFAULT_DWELL_MS: u64 = 500;
+
+/// Distance in pixels from the screen edges that still counts as a corner.
+const CORNER_SIZE: f64 = 2.0;
+
+/// Keys pressed one after another, e.g. Right Ctrl twice or F9 then P.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sequence {
+    pub keys: Vec<Key>,
+    pub action: Action,
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> =
+            self.keys.iter().map(|k| format!("{:?}", k)).collect();
+        write!(f, "{} -> {}", keys.join(" "), self.action)
+    }
+}
+
+/// A completed sequence and when its first key was pressed.
+pub struct Matched {
+    pub action: Action,
+    pub keys: Vec<Key>,
+    pub started: SystemTime,
+}
+
+/// Matches key presses against the configured sequences.
+#[derive(Debug, Default)]
+pub struct SequenceMatcher {
+    presses: Vec<(Key, SystemTime)>,
+    // held keys, so autorepeat doesn't count as another press
+    held: Vec<Key>,
+}
+
+impl SequenceMatcher {
+    pub fn press(
+        &mut self,
+        key: Key,
+        at: SystemTime,
+        sequences: &[Sequence],
+        timeout: Duration,
+    ) -> Option<Matched> {
+        if self.held.contains(&key) {
+            return None;
+        }
+        self.held.push(key);
+
+        if let Some((_, last)) = self.presses.last() {
+            if at.duration_since(*last).unwrap_or_default() > timeout {
+                self.presses.clear();
+            }
+        }
+        self.presses.push((key, at));
+        let longest = sequences.iter().map(|s| s.keys.len()).max()?;
+        if self.presses.len() > longest {
+            self.presses.drain(..self.presses.len() - longest);
+        }
+
+        let sequence = sequences.iter().find(|s| {
+            !s.keys.is_empty()
+                && self.presses.len() >= s.keys.len()
+                && self.presses[self.presses.len() - s.keys.len()..]
+                    .iter()
+                    .map(|(k, _)| k)
+                    .eq(s.keys.iter())
+        })?;
+        let started = self.presses[self.presses.len() - sequence.keys.len()].1;
+        self.presses.clear();
+        Some(Matched {
+            action: sequence.action,
+            keys: sequence.keys.clone(),
+            started,
+        })
+    }
+
+    pub fn release(&mut self, key: Key) {
+        self.held.retain(|k| *k != key);
+    }
+}
+
+/// Direction of one straight stroke of a gesture.
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] =
+        [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    /// The main direction of a movement, with y growing downwards.
+    fn of(dx: f64, dy: f64) -> Self {
+        if dx.abs() >= dy.abs() {
+            if dx < 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            }
+        } else if dy < 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|d| d.name() == s)
+            .ok_or_else(|| {
+                format!("unknown direction '{}' (up, down, left, right)", s)
+            })
+    }
+}
+
+/// Strokes drawn while holding a mouse button, e.g. down then right for an
+/// L shape. The button still reaches the focused window, so a side button
+/// that does nothing there works best.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gesture {
+    pub button: Button,
+    pub strokes: Vec<Direction>,
+    pub action: Action,
+}
+
+impl fmt::Display for Gesture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} -> {}",
+            self.button,
+            strokes_name(&self.strokes),
+            self.action
+        )
+    }
+}
+
+fn strokes_name(strokes: &[Direction]) -> String {
+    if strokes.is_empty() {
+        return "click".into();
+    }
+    let names: Vec<&str> = strokes.iter().map(|d| d.name()).collect();
+    names.join("-")
+}
+
+/// Warnings about gestures that get in the way of recording.
+pub fn gesture_conflicts(gestures: &[Gesture]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for button in [Button::Left, Button::Right] {
+        if gestures.iter().any(|g| g.button == button) {
+            warnings.push(format!(
+                "{:?} clicks are used for gestures and can't be recorded",
+                button
+            ));
+        }
+    }
+    warnings
+}
+
+/// A gesture as drawn, which may or may not be configured.
+pub struct Drawn {
+    pub button: Button,
+    pub strokes: Vec<Direction>,
+}
+
+impl Drawn {
+    pub fn action(&self, gestures: &[Gesture]) -> Option<Action> {
+        gestures
+            .iter()
+            .find(|g| g.button == self.button && g.strokes == self.strokes)
+            .map(|g| g.action)
+    }
+}
+
+impl fmt::Display for Drawn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.button, strokes_name(&self.strokes))
+    }
+}
+
+/// Follows the pointer while a gesture button is held.
+#[derive(Debug, Default)]
+pub struct GestureTracker {
+    drawing: Option<(Button, Vec<Direction>)>,
+    // where the stroke being drawn started
+    anchor: (f64, f64),
+}
+
+impl GestureTracker {
+    /// Starts a gesture if `button` is used by one, returning whether it
+    /// did or a gesture is already being drawn.
+    pub fn press(
+        &mut self,
+        button: Button,
+        at: (f64, f64),
+        gestures: &[Gesture],
+    ) -> bool {
+        if self.drawing.is_none() && gestures.iter().any(|g| g.button == button)
+        {
+            self.drawing = Some((button, Vec::new()));
+            self.anchor = at;
+        }
+        self.drawing.is_some()
+    }
+
+    /// Follows the pointer, returning whether a gesture is being drawn.
+    pub fn moved(&mut self, to: (f64, f64)) -> bool {
+        let Some((_, strokes)) = &mut self.drawing else {
+            return false;
+        };
+        let (dx, dy) = (to.0 - self.anchor.0, to.1 - self.anchor.1);
+        if dx.hypot(dy) >= STROKE_LENGTH {
+            let direction = Direction::of(dx, dy);
+            if strokes.last() != Some(&direction) {
+                strokes.push(direction);
+            }
+            self.anchor = to;
+        }
+        true
+    }
+
+    /// Ends the gesture if `button` is the one it is drawn with.
+    pub fn release(&mut self, button: Button) -> Option<Drawn> {
+        match self.drawing.take() {
+            Some((held, strokes)) if held == button => {
+                Some(Drawn { button, strokes })
+            }
+            drawing => {
+                self.drawing = drawing;
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    const ALL: [Corner; 4] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        }
+    }
+
+    /// The corner the pointer is in, if any.
+    pub fn at(pos: (f64, f64), screen: (i32, i32)) -> Option<Self> {
+        let left = pos.0 <= CORNER_SIZE;
+        let right = pos.0 >= (screen.0 - 1) as f64 - CORNER_SIZE;
+        let top = pos.1 <= CORNER_SIZE;
+        let bottom = pos.1 >= (screen.1 - 1) as f64 - CORNER_SIZE;
+        match (left, right, top, bottom) {
+            (true, _, true, _) => Some(Corner::TopLeft),
+            (_, true, true, _) => Some(Corner::TopRight),
+            (true, _, _, true) => Some(Corner::BottomLeft),
+            (_, true, _, true) => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|c| c.name() == s).ok_or_else(|| {
+            format!(
+                "unknown corner '{}' (top-left, top-right, bottom-left, \
+                 bottom-right)",
+                s
+            )
+        })
+    }
+}
+
+fn default_dwell_ms() -> u64 {
+    DEFAULT_DWELL_MS
+}
+
+/// An action triggered by resting the pointer in a screen corner.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HotCorner {
+    pub corner: Corner,
+    #[serde(default = "default_dwell_ms")]
+    pub dwell_ms: u64,
+    pub action: Action,
+}
+
+/// Times how long the pointer has been in a corner. Each visit to a
+/// corner triggers its action at most once.
+#[derive(Debug, Default)]
+pub struct CornerTracker {
+    entered: Option<(Corner, SystemTime)>,
+    fired: bool,
+}
+
+impl CornerTracker {
+    pub fn moved(&mut self, corner: Option<Corner>, at: SystemTime) {
+        if self.entered.map(|(c, _)| c) != corner {
+            self.entered = corner.map(|c| (c, at));
+            self.fired = false;
+        }
+    }
+
+    /// When the pointer will have dwelt long enough in its corner.
+    pub fn deadline(&self, corners: &[HotCorner]) -> Option<SystemTime> {
+        let (corner, entered) = self.entered?;
+        if self.fired {
+            return None;
+        }
+        let hot = corners.iter().find(|h| h.corner == corner)?;
+        Some(entered + Duration::from_millis(hot.dwell_ms))
+    }
+
+    /// Returns the corner's action once the pointer has dwelt long enough.
+    pub fn poll(
+        &mut self,
+        now: SystemTime,
+        corners: &[HotCorner],
+    ) -> Option<Action> {
+        let deadline = self.deadline(corners)?;
+        if now < deadline {
+            return None;
+        }
+        self.fired = true;
+        let (corner, _) = self.entered?;
+        corners.iter().find(|h| h.corner == corner).map(|h| h.action)
+    }
+}
+
+/// A library macro played once the user has been idle for a while, and
+/// again every `minutes` as long as they stay away.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleTrigger {
+    pub minutes: f64,
+    #[serde(rename = "macro")]
+    pub macro_name: String,
+    /// Only played within this window, if given.
+    #[serde(default)]
+    pub when: Option<TimeWindow>,
+}
+
+impl IdleTrigger {
+    pub fn after(&self) -> Duration {
+        Duration::from_secs_f64(self.minutes * 60.0)
+    }
+}
+
+/// Times how long there has been no real input.
+#[derive(Debug)]
+pub struct IdleTracker {
+    last_input: SystemTime,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self {
+            last_input: SystemTime::now(),
+        }
+    }
+}
+
+impl IdleTracker {
+    pub fn input(&mut self, at: SystemTime) {
+        self.last_input = at;
+    }
+
+    pub fn deadline(
+        &self,
+        trigger: Option<&IdleTrigger>,
+    ) -> Option<SystemTime> {
+        Some(self.last_input + trigger?.after())
+    }
+
+    /// Whether the idle macro is due, counting the time until it is due
+    /// again from now if so.
+    pub fn poll(
+        &mut self,
+        now: SystemTime,
+        trigger: Option<&IdleTrigger>,
+    ) -> bool {
+        match self.deadline(trigger) {
+            Some(deadline) if now >= deadline => {
+                self.last_input = now;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An action that hotkeys and other triggers only perform within a time
+/// window.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeRestriction {
+    pub action: Action,
+    pub when: TimeWindow,
+}
+
+/// The window `action` is restricted to and isn't open now, if any.
+pub fn closed_window(
+    restrictions: &[TimeRestriction],
+    action: Action,
+) -> Option<TimeWindow> {
+    let mut windows = restrictions
+        .iter()
+        .filter(|r| r.action == action)
+        .map(|r| r.when)
+        .peekable();
+    let first = *windows.peek()?;
+    // Several windows for one action add up.
+    let open = windows.any(|w| w.is_open());
+    (!open).then_some(first)
+}
@@ -0,0 +1,364 @@
+//! Direct injection through a Linux uinput virtual device.
+//!
+//! Unlike enigo's X11 path this works under Wayland compositors and on
+//! virtual terminals, as long as the user can write to `/dev/uinput`.
+
+use crate::backend::InputBackend;
+use rdev::{Button, EventType, Key};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+const UI_DEV_CREATE: u32 = 0x5501;
+const UI_DEV_DESTROY: u32 = 0x5502;
+const UI_DEV_SETUP: u32 = 0x405c_5503;
+const UI_ABS_SETUP: u32 = 0x401c_5504;
+const UI_SET_EVBIT: u32 = 0x4004_5564;
+const UI_SET_KEYBIT: u32 = 0x4004_5565;
+const UI_SET_RELBIT: u32 = 0x4004_5566;
+const UI_SET_ABSBIT: u32 = 0x4004_5567;
+
+pub(crate) const EV_SYN: u16 = 0x00;
+pub(crate) const EV_KEY: u16 = 0x01;
+pub(crate) const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+pub(crate) const SYN_REPORT: u16 = 0;
+pub(crate) const REL_HWHEEL: u16 = 0x06;
+pub(crate) const REL_WHEEL: u16 = 0x08;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+pub(crate) const BTN_LEFT: u16 = 0x110;
+pub(crate) const BTN_RIGHT: u16 = 0x111;
+pub(crate) const BTN_MIDDLE: u16 = 0x112;
+const BUS_USB: u16 = 0x03;
+// highest KEY_* code produced by `key_code`
+const KEY_CODE_MAX: u16 = 127;
+
+/// Name of the virtual device, so capture can tell our own input apart.
+pub(crate) const DEVICE_NAME: &str = "realtime-macro virtual input";
+
+// Time for udev and the compositor to pick up a freshly created device;
+// events written before that are silently lost.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+#[repr(C)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+#[repr(C)]
+struct UinputSetup {
+    id: InputId,
+    name: [u8; 80],
+    ff_effects_max: u32,
+}
+
+#[repr(C)]
+struct InputAbsinfo {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
+#[repr(C)]
+struct UinputAbsSetup {
+    code: u16,
+    absinfo: InputAbsinfo,
+}
+
+#[repr(C)]
+pub(crate) struct InputEvent {
+    pub time: libc::timeval,
+    pub type_: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// A virtual keyboard + absolute pointer, destroyed again on drop.
+pub struct UinputBackend {
+    device: File,
+}
+
+impl UinputBackend {
+    /// Creates the virtual device. `screen` is the size of the desktop, used
+    /// as the range of the absolute pointer axes.
+    pub fn new(screen: (i32, i32)) -> Result<Self, String> {
+        let device = OpenOptions::new()
+            .write(true)
+            .open("/dev/uinput")
+            .map_err(|e| format!("cannot open /dev/uinput: {e}"))?;
+        let backend = Self { device };
+        backend.setup(screen)?;
+        thread::sleep(SETTLE_TIME);
+        Ok(backend)
+    }
+
+    fn ioctl(&self, request: u32, arg: u16) -> Result<(), String> {
+        let fd = self.device.as_raw_fd();
+        if unsafe { libc::ioctl(fd, request as _, arg as libc::c_ulong) } < 0 {
+            return Err(format!(
+                "uinput ioctl {request:#x} failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn ioctl_ptr<T>(&self, request: u32, arg: &T) -> Result<(), String> {
+        let fd = self.device.as_raw_fd();
+        if unsafe { libc::ioctl(fd, request as _, arg as *const T) } < 0 {
+            return Err(format!(
+                "uinput ioctl {request:#x} failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn setup(&self, (width, height): (i32, i32)) -> Result<(), String> {
+        for ev in [EV_SYN, EV_KEY, EV_REL, EV_ABS] {
+            self.ioctl(UI_SET_EVBIT, ev)?;
+        }
+        // Enable every code the key table can produce, plus mouse buttons.
+        for code in 1..=KEY_CODE_MAX {
+            self.ioctl(UI_SET_KEYBIT, code)?;
+        }
+        for code in [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE] {
+            self.ioctl(UI_SET_KEYBIT, code)?;
+        }
+        for code in [REL_WHEEL, REL_HWHEEL] {
+            self.ioctl(UI_SET_RELBIT, code)?;
+        }
+        for (code, max) in [(ABS_X, width - 1), (ABS_Y, height - 1)] {
+            self.ioctl(UI_SET_ABSBIT, code)?;
+            let abs = UinputAbsSetup {
+                code,
+                absinfo: InputAbsinfo {
+                    value: 0,
+                    minimum: 0,
+                    maximum: max,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            };
+            self.ioctl_ptr(UI_ABS_SETUP, &abs)?;
+        }
+
+        let mut name = [0u8; 80];
+        let label = DEVICE_NAME.as_bytes();
+        name[..label.len()].copy_from_slice(label);
+        let setup = UinputSetup {
+            id: InputId {
+                bustype: BUS_USB,
+                vendor: 0x1234,
+                product: 0x5678,
+                version: 1,
+            },
+            name,
+            ff_effects_max: 0,
+        };
+        self.ioctl_ptr(UI_DEV_SETUP, &setup)?;
+        self.ioctl(UI_DEV_CREATE, 0)
+    }
+
+    fn emit(&mut self, type_: u16, code: u16, value: i32) -> Result<(), String> {
+        let event = InputEvent {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const InputEvent as *const u8,
+                std::mem::size_of::<InputEvent>(),
+            )
+        };
+        self.device
+            .write_all(bytes)
+            .map_err(|e| format!("uinput write failed: {e}"))
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl InputBackend for UinputBackend {
+    fn send(&mut self, evt: &EventType) -> Result<(), String> {
+        match evt {
+            EventType::MouseMove { x, y } => {
+                self.emit(EV_ABS, ABS_X, *x as i32)?;
+                self.emit(EV_ABS, ABS_Y, *y as i32)?;
+            }
+            EventType::ButtonPress(button) => match button_code(*button) {
+                Some(code) => self.emit(EV_KEY, code, 1)?,
+                None => return Ok(()),
+            },
+            EventType::ButtonRelease(button) => match button_code(*button) {
+                Some(code) => self.emit(EV_KEY, code, 0)?,
+                None => return Ok(()),
+            },
+            EventType::Wheel { delta_x, delta_y } => {
+                if *delta_y != 0 {
+                    self.emit(EV_REL, REL_WHEEL, *delta_y as i32)?;
+                }
+                if *delta_x != 0 {
+                    self.emit(EV_REL, REL_HWHEEL, *delta_x as i32)?;
+                }
+            }
+            EventType::KeyPress(key) => match key_code(*key) {
+                Some(code) => self.emit(EV_KEY, code, 1)?,
+                None => return Ok(()),
+            },
+            EventType::KeyRelease(key) => match key_code(*key) {
+                Some(code) => self.emit(EV_KEY, code, 0)?,
+                None => return Ok(()),
+            },
+        }
+        self.sync()
+    }
+}
+
+impl Drop for UinputBackend {
+    fn drop(&mut self) {
+        let _ = self.ioctl(UI_DEV_DESTROY, 0);
+    }
+}
+
+pub(crate) fn button_code(button: Button) -> Option<u16> {
+    match button {
+        Button::Left => Some(BTN_LEFT),
+        Button::Right => Some(BTN_RIGHT),
+        Button::Middle => Some(BTN_MIDDLE),
+        Button::Unknown(_) => None,
+    }
+}
+
+/// rdev keys and their Linux input event codes (`KEY_*`).
+const KEY_CODES: &[(Key, u16)] = &[
+    (Key::Escape, 1),
+    (Key::Num1, 2),
+    (Key::Num2, 3),
+    (Key::Num3, 4),
+    (Key::Num4, 5),
+    (Key::Num5, 6),
+    (Key::Num6, 7),
+    (Key::Num7, 8),
+    (Key::Num8, 9),
+    (Key::Num9, 10),
+    (Key::Num0, 11),
+    (Key::Minus, 12),
+    (Key::Equal, 13),
+    (Key::Backspace, 14),
+    (Key::Tab, 15),
+    (Key::KeyQ, 16),
+    (Key::KeyW, 17),
+    (Key::KeyE, 18),
+    (Key::KeyR, 19),
+    (Key::KeyT, 20),
+    (Key::KeyY, 21),
+    (Key::KeyU, 22),
+    (Key::KeyI, 23),
+    (Key::KeyO, 24),
+    (Key::KeyP, 25),
+    (Key::LeftBracket, 26),
+    (Key::RightBracket, 27),
+    (Key::Return, 28),
+    (Key::ControlLeft, 29),
+    (Key::KeyA, 30),
+    (Key::KeyS, 31),
+    (Key::KeyD, 32),
+    (Key::KeyF, 33),
+    (Key::KeyG, 34),
+    (Key::KeyH, 35),
+    (Key::KeyJ, 36),
+    (Key::KeyK, 37),
+    (Key::KeyL, 38),
+    (Key::SemiColon, 39),
+    (Key::Quote, 40),
+    (Key::BackQuote, 41),
+    (Key::ShiftLeft, 42),
+    (Key::BackSlash, 43),
+    (Key::KeyZ, 44),
+    (Key::KeyX, 45),
+    (Key::KeyC, 46),
+    (Key::KeyV, 47),
+    (Key::KeyB, 48),
+    (Key::KeyN, 49),
+    (Key::KeyM, 50),
+    (Key::Comma, 51),
+    (Key::Dot, 52),
+    (Key::Slash, 53),
+    (Key::ShiftRight, 54),
+    (Key::KpMultiply, 55),
+    (Key::Alt, 56),
+    (Key::Space, 57),
+    (Key::CapsLock, 58),
+    (Key::F1, 59),
+    (Key::F2, 60),
+    (Key::F3, 61),
+    (Key::F4, 62),
+    (Key::F5, 63),
+    (Key::F6, 64),
+    (Key::F7, 65),
+    (Key::F8, 66),
+    (Key::F9, 67),
+    (Key::F10, 68),
+    (Key::NumLock, 69),
+    (Key::ScrollLock, 70),
+    (Key::Kp7, 71),
+    (Key::Kp8, 72),
+    (Key::Kp9, 73),
+    (Key::KpMinus, 74),
+    (Key::Kp4, 75),
+    (Key::Kp5, 76),
+    (Key::Kp6, 77),
+    (Key::KpPlus, 78),
+    (Key::Kp1, 79),
+    (Key::Kp2, 80),
+    (Key::Kp3, 81),
+    (Key::Kp0, 82),
+    (Key::KpDelete, 83),
+    (Key::IntlBackslash, 86),
+    (Key::F11, 87),
+    (Key::F12, 88),
+    (Key::KpReturn, 96),
+    (Key::ControlRight, 97),
+    (Key::KpDivide, 98),
+    (Key::PrintScreen, 99),
+    (Key::AltGr, 100),
+    (Key::Home, 102),
+    (Key::UpArrow, 103),
+    (Key::PageUp, 104),
+    (Key::LeftArrow, 105),
+    (Key::RightArrow, 106),
+    (Key::End, 107),
+    (Key::DownArrow, 108),
+    (Key::PageDown, 109),
+    (Key::Insert, 110),
+    (Key::Delete, 111),
+    (Key::Pause, 119),
+    (Key::MetaLeft, 125),
+    (Key::MetaRight, 126),
+];
+
+pub(crate) fn key_code(key: Key) -> Option<u16> {
+    KEY_CODES.iter().find(|(k, _)| *k == key).map(|(_, code)| *code)
+}
+
+pub(crate) fn key_from_code(code: u16) -> Option<Key> {
+    KEY_CODES.iter().find(|(_, c)| *c == code).map(|(key, _)| *key)
+}
@@ -0,0 +1,184 @@
+//! Placeholders like `{name}` in typed text, filled in as a macro plays.
+//!
+//! Values come from, in increasing priority: a `.env` file, environment
+//! variables, the macro's parameters as answered before it plays, the
+//! `--csv` row and the loop pass as `{i}`. Keeping secrets and paths in the
+//! first two means they never end up in a recording. They are only read
+//! when a placeholder isn't filled otherwise, or a file is given.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Read from the working directory unless another file is given.
+const DEFAULT_ENV_FILE: &str = ".env";
+
+/// Values of the placeholders, by name.
+pub type Vars = HashMap<String, String>;
+
+/// The placeholder names in `text`, in order.
+pub fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{').skip(1).filter_map(|part| {
+        let name = part.split_once('}')?.0;
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        valid.then_some(name)
+    })
+}
+
+/// `text` with the placeholders `vars` has a value for filled in. Others
+/// are left as they are, so text that happens to contain braces is typed
+/// as written.
+pub fn fill(text: &str, vars: &Vars) -> String {
+    let mut filled = text.to_string();
+    for name in placeholders(text) {
+        if let Some(value) = vars.get(name) {
+            filled = filled.replace(&format!("{{{}}}", name), value);
+        }
+    }
+    filled
+}
+
+/// Values from `env_file`, or a `.env` file in the working directory if
+/// there is one, overridden by environment variables. Only a given file
+/// that can't be read is an error.
+pub fn from_env(env_file: Option<&Path>) -> Result<Vars, String> {
+    let mut vars = match env_file {
+        Some(path) => read_env_file(path)?,
+        None if Path::new(DEFAULT_ENV_FILE).is_file() => {
+            read_env_file(Path::new(DEFAULT_ENV_FILE)).unwrap_or_else(|e| {
+                println!("Skipping {}: {}", DEFAULT_ENV_FILE, e);
+                Vars::new()
+            })
+        }
+        None => Vars::new(),
+    };
+    vars.extend(env::vars());
+    Ok(vars)
+}
+
+/// Reads `NAME=VALUE` lines, see [`parse_env`].
+pub fn read_env_file(path: &Path) -> Result<Vars, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let (vars, skipped) = parse_env(&text);
+    for n in skipped {
+        let path = path.display();
+        println!("Skipping {} line {}: expected NAME=VALUE", path, n);
+    }
+    Ok(vars)
+}
+
+/// The values of `NAME=VALUE` lines, ignoring blank lines and `#`
+/// comments, and the numbers of other lines, which are skipped. An
+/// `export ` in front and quotes around the value are dropped.
+fn parse_env(text: &str) -> (Vars, Vec<usize>) {
+    let mut vars = Vars::new();
+    let mut skipped = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            skipped.push(n + 1);
+            continue;
+        };
+        let value = value.trim();
+        let unquoted = ['"', '\''].iter().find_map(|q| {
+            value.strip_prefix(*q)?.strip_suffix(*q)
+        });
+        vars.insert(
+            name.trim().to_string(),
+            unquoted.unwrap_or(value).to_string(),
+        );
+    }
+    (vars, skipped)
+}
+
+/// Reads a CSV file into one set of values per row, named by the header
+/// row. Fields may be quoted, with `""` for a quote inside.
+pub fn read_csv(path: &Path) -> Result<Vec<Vars>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let mut records = parse_csv(&text).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| format!("{} is empty", path.display()))?;
+    let rows = records
+        .enumerate()
+        .map(|(n, record)| {
+            if record.len() != header.len() {
+                return Err(format!(
+                    "{} row {} has {} fields, the header {}",
+                    path.display(),
+                    n + 1,
+                    record.len(),
+                    header.len()
+                ));
+            }
+            Ok(header.iter().cloned().zip(record).collect())
+        })
+        .collect::<Result<Vec<Vars>, String>>()?;
+    if rows.is_empty() {
+        return Err(format!("{} has no rows", path.display()));
+    }
+    Ok(rows)
+}
+
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // Blank lines, e.g. at the end of the file.
+    records.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_env_lines() {
+        let text =
+            "# secrets\n\nexport USER=ann\nPASS = \"p w\"\nDIR='/tmp'\n";
+        let (vars, skipped) = parse_env(text);
+        assert_eq!(vars["USER"], "ann");
+        assert_eq!(vars["PASS"], "p w");
+        assert_eq!(vars["DIR"], "/tmp");
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn skips_lines_without_a_value() {
+        let (vars, skipped) = parse_env("USER=ann\noops\nPASS=pw\n");
+        assert_eq!(vars.len(), 2);
+        assert_eq!(skipped, [2]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Conditions a macro can wait for before going on, so it doesn't race
+//! applications that take a while to start.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Something playback can wait for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Condition {
+    /// A window whose title matches the pattern exists, or has focus.
+    Window {
+        title_pattern: String,
+        #[serde(default)]
+        focused: bool,
+    },
+    /// A file exists, e.g. once a download completes.
+    File { path: PathBuf },
+    /// A process with this executable name is running, or isn't.
+    Process { name: String, running: bool },
+}
+
+impl Condition {
+    pub fn window(title_pattern: &str, focused: bool) -> Result<Self, String> {
+        Regex::new(title_pattern)
+            .map_err(|e| format!("invalid title pattern: {}", e))?;
+        Ok(Condition::Window {
+            title_pattern: title_pattern.to_string(),
+            focused,
+        })
+    }
+
+    /// Whether the condition holds now.
+    pub fn check(&self) -> Result<bool, String> {
+        match self {
+            Condition::Window {
+                title_pattern,
+                focused,
+            } => {
+                let pattern = Regex::new(title_pattern)
+                    .map_err(|e| format!("invalid title pattern: {}", e))?;
+                let titles = if *focused {
+                    platform::focused_title()?.into_iter().collect()
+                } else {
+                    platform::window_titles()?
+                };
+                Ok(titles.iter().any(|title| pattern.is_match(title)))
+            }
+            Condition::File { path } => Ok(path.exists()),
+            Condition::Process { name, running } => {
+                let found = process_names()?
+                    .iter()
+                    .any(|process| same_program(process, name));
+                Ok(found == *running)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Window {
+                title_pattern,
+                focused: false,
+            } => write!(f, "a window matching /{}/", title_pattern),
+            Condition::Window {
+                title_pattern,
+                focused: true,
+            } => write!(f, "focus on a window matching /{}/", title_pattern),
+            Condition::File { path } => write!(f, "{}", path.display()),
+            Condition::Process {
+                name,
+                running: true,
+            } => write!(f, "process {} to start", name),
+            Condition::Process {
+                name,
+                running: false,
+            } => write!(f, "process {} to exit", name),
+        }
+    }
+}
+
+/// Whether `process`, a name or path as the system lists it, is the
+/// program `name`. The `.exe` and case don't matter on Windows.
+fn same_program(process: &str, name: &str) -> bool {
+    let process = process.rsplit(['/', '\\']).next().unwrap_or(process);
+    if cfg!(target_os = "windows") {
+        let strip = |s: &str| {
+            let s = s.to_lowercase();
+            s.strip_suffix(".exe").map(str::to_string).unwrap_or(s)
+        };
+        strip(process) == strip(name)
+    } else {
+        // Linux cuts names short in the process list.
+        process == name || (process.len() == 15 && name.starts_with(process))
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn process_names() -> Result<Vec<String>, String> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW,
+        PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .map_err(|e| format!("cannot list processes: {}", e))?;
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut names = Vec::new();
+    let mut more = unsafe { Process32FirstW(snapshot, &mut entry) }.is_ok();
+    while more {
+        let len = entry
+            .szExeFile
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(entry.szExeFile.len());
+        names.push(String::from_utf16_lossy(&entry.szExeFile[..len]));
+        more = unsafe { Process32NextW(snapshot, &mut entry) }.is_ok();
+    }
+    let _ = unsafe { CloseHandle(snapshot) };
+    Ok(names)
+}
+
+/// Through `ps`, which lists the executable's path on macOS and its name,
+/// cut to 15 characters, on Linux.
+#[cfg(not(target_os = "windows"))]
+fn process_names() -> Result<Vec<String>, String> {
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "comm="])
+        .output()
+        .map_err(|e| format!("cannot run ps: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("ps failed ({})", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetForegroundWindow, GetWindowTextW, IsWindowVisible,
+    };
+
+    fn title(hwnd: HWND) -> String {
+        let mut buffer = [0u16; 512];
+        let len = unsafe { GetWindowTextW(hwnd, &mut buffer) };
+        String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+    }
+
+    unsafe extern "system" fn collect(hwnd: HWND, titles: LPARAM) -> BOOL {
+        let titles = &mut *(titles.0 as *mut Vec<String>);
+        if IsWindowVisible(hwnd).as_bool() {
+            let title = title(hwnd);
+            if !title.is_empty() {
+                titles.push(title);
+            }
+        }
+        BOOL(1)
+    }
+
+    pub fn window_titles() -> Result<Vec<String>, String> {
+        let mut titles: Vec<String> = Vec::new();
+        unsafe {
+            EnumWindows(
+                Some(collect),
+                LPARAM(&mut titles as *mut Vec<String> as isize),
+            )
+        }
+        .map_err(|e| format!("cannot list windows: {}", e))?;
+        Ok(titles)
+    }
+
+    pub fn focused_title() -> Result<Option<String>, String> {
+        let hwnd = unsafe { GetForegroundWindow() };
+        if hwnd.0.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(title(hwnd)))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    fn osascript(script: &str) -> Result<String, String> {
+        let output = Command::new("osascript")
+            .args(["-e", script])
+            .output()
+            .map_err(|e| format!("cannot run osascript: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "cannot list windows: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn window_titles() -> Result<Vec<String>, String> {
+        let names = osascript(
+            "set text item delimiters to linefeed\n\
+             tell application \"System Events\" to \
+             get (name of every window of every process) as text",
+        )?;
+        Ok(names.lines().map(str::to_string).collect())
+    }
+
+    pub fn focused_title() -> Result<Option<String>, String> {
+        let name = osascript(
+            "tell application \"System Events\" to get name of front window \
+             of (first process whose frontmost is true)",
+        );
+        // No front window is an error as far as AppleScript is concerned.
+        Ok(name.ok().map(|name| name.trim_end().to_string()))
+    }
+}
+
+/// X11 through `wmctrl` and `xdotool`, which most desktops have or can
+/// install; Wayland doesn't let other programs see windows at all.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use std::process::Command;
+
+    fn run(program: &str, args: &[&str]) -> Result<String, String> {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|e| format!("cannot run {}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!("{} failed ({})", program, output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn window_titles() -> Result<Vec<String>, String> {
+        let listing = run("wmctrl", &["-l"])?;
+        Ok(listing.lines().filter_map(title).collect())
+    }
+
+    /// The title in a `wmctrl -l` line, after the ID, desktop and host.
+    fn title(line: &str) -> Option<String> {
+        let mut rest = line;
+        for _ in 0..3 {
+            rest = rest.trim_start().split_once(char::is_whitespace)?.1;
+        }
+        Some(rest.trim().to_string())
+    }
+
+    pub fn focused_title() -> Result<Option<String>, String> {
+        let output = Command::new("xdotool")
+            .args(["getactivewindow", "getwindowname"])
+            .output()
+            .map_err(|e| format!("cannot run xdotool: {}", e))?;
+        // Fails when no window has focus, e.g. on an empty desktop.
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let title = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(title.trim_end().to_string()))
+    }
+}
@@ -0,0 +1,394 @@
+//! Turns a recording into the skeleton of a Selenium test in Python, as a
+//! start on replacing a screen macro with a proper web test.
+//!
+//! Clicks, drags, scrolling, typed text and shortcuts become actions at
+//! the recorded screen positions, with the pauses between them. Finding
+//! the elements those positions hit, and what to wait for instead of the
+//! pauses, is left to whoever finishes the test; the script says where.
+
+use crate::event::{MacroAction, RecordedEvent};
+use crate::preview::key_char;
+use rdev::{Button, EventType, Key};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Pauses at least this long are kept as sleeps.
+const PAUSE: Duration = Duration::from_millis(500);
+/// Clicks this close together at the same place are a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+/// The pointer moving further than this while pressed is a drag.
+const DRAG_DISTANCE: f64 = 5.0;
+/// Pixels scrolled by a notch of the wheel.
+const SCROLL_STEP: i64 = 100;
+
+const HEADER: &str = r#"from selenium import webdriver
+from selenium.webdriver.common.action_chains import ActionChains
+from selenium.webdriver.common.actions.action_builder import ActionBuilder
+from selenium.webdriver.common.keys import Keys
+import time
+
+# Where the page's top left corner is on screen when recorded. The
+# positions below are screen coordinates.
+OFFSET_X, OFFSET_Y = 0, 0
+
+
+def at(x, y):
+    builder = ActionBuilder(driver)
+    builder.pointer_action.move_to_location(x - OFFSET_X, y - OFFSET_Y)
+    return builder
+
+
+def click_at(x, y, button="left", double=False):
+    builder = at(x, y)
+    pointer = builder.pointer_action
+    clicks = {"left": pointer.click, "right": pointer.context_click}
+    clicks.get(button, pointer.click)()
+    if double:
+        pointer.click()
+    builder.perform()
+
+
+def drag(x1, y1, x2, y2):
+    builder = at(x1, y1)
+    builder.pointer_action.click_and_hold()
+    builder.pointer_action.move_to_location(x2 - OFFSET_X, y2 - OFFSET_Y)
+    builder.pointer_action.release()
+    builder.perform()
+
+
+driver = webdriver.Chrome()
+driver.get("https://example.com")  # TODO: the page under test
+"#;
+
+/// One line of the test.
+enum Step {
+    Click {
+        at: (f64, f64),
+        button: Button,
+        double: bool,
+    },
+    Drag((f64, f64), (f64, f64)),
+    Scroll(i64, i64),
+    /// Text and special keys, sent together.
+    Keys(Vec<Part>),
+    /// A key pressed with modifiers held.
+    Shortcut(Vec<&'static str>, Part),
+    Sleep(Duration),
+    Comment(String),
+}
+
+enum Part {
+    Text(String),
+    Key(&'static str),
+}
+
+impl Part {
+    fn python(&self) -> String {
+        match self {
+            Part::Text(text) => python_string(text),
+            Part::Key(name) => format!("Keys.{}", name),
+        }
+    }
+}
+
+/// The Python script for `events`, a recording called `name`.
+pub fn selenium(name: &str, events: &[RecordedEvent]) -> String {
+    let mut script = format!(
+        "# A start on a web test, from the recording {}.\n{}\n",
+        name, HEADER
+    );
+    for step in steps(events) {
+        let line = match step {
+            Step::Click { at, button, double } => {
+                let button = match button {
+                    Button::Right => ", button=\"right\"",
+                    Button::Middle => ", button=\"middle\"",
+                    _ => "",
+                };
+                let double = if double { ", double=True" } else { "" };
+                format!(
+                    "# TODO: find the element at ({0}, {1}) instead.\n\
+                     click_at({0}, {1}{2}{3})",
+                    at.0.round(),
+                    at.1.round(),
+                    button,
+                    double
+                )
+            }
+            Step::Drag(from, to) => format!(
+                "drag({}, {}, {}, {})",
+                from.0.round(),
+                from.1.round(),
+                to.0.round(),
+                to.1.round()
+            ),
+            Step::Scroll(dx, dy) => format!(
+                "ActionChains(driver).scroll_by_amount({}, {}).perform()",
+                dx * SCROLL_STEP,
+                -dy * SCROLL_STEP
+            ),
+            Step::Keys(parts) => {
+                let parts: Vec<String> =
+                    parts.iter().map(Part::python).collect();
+                format!(
+                    "ActionChains(driver).send_keys({}).perform()",
+                    parts.join(", ")
+                )
+            }
+            Step::Shortcut(modifiers, key) => {
+                let mut line = "ActionChains(driver)".to_string();
+                for m in &modifiers {
+                    let _ = write!(line, ".key_down(Keys.{})", m);
+                }
+                let _ = write!(line, ".send_keys({})", key.python());
+                for m in modifiers.iter().rev() {
+                    let _ = write!(line, ".key_up(Keys.{})", m);
+                }
+                line + ".perform()"
+            }
+            Step::Sleep(pause) => format!(
+                "time.sleep({:.1})  # TODO: wait for the page instead",
+                pause.as_secs_f64()
+            ),
+            Step::Comment(text) => format!("# TODO: {}", text),
+        };
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script.push_str("\ndriver.quit()\n");
+    script
+}
+
+fn steps(events: &[RecordedEvent]) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut pointer = (0.0, 0.0);
+    let mut pressed: Option<(Button, (f64, f64))> = None;
+    let mut held: Vec<Key> = Vec::new();
+    let mut typed: Vec<Part> = Vec::new();
+    let mut last = None;
+    let mut last_click: Option<Duration> = None;
+    for evt in events {
+        let flush = |typed: &mut Vec<Part>, steps: &mut Vec<Step>| {
+            if !typed.is_empty() {
+                steps.push(Step::Keys(std::mem::take(typed)));
+            }
+        };
+        // Key releases and moves don't make steps, so don't end pauses.
+        let makes_step = match &evt.action {
+            MacroAction::Input(EventType::KeyPress(key)) => !is_modifier(*key),
+            MacroAction::Input(
+                EventType::KeyRelease(_)
+                | EventType::MouseMove { .. }
+                | EventType::ButtonPress(_),
+            ) => false,
+            _ => true,
+        };
+        if makes_step {
+            if let Some(last) = last {
+                let pause = evt.timestamp.saturating_sub(last);
+                if pause >= PAUSE {
+                    flush(&mut typed, &mut steps);
+                    steps.push(Step::Sleep(pause));
+                }
+            }
+            last = Some(evt.timestamp);
+        }
+        match &evt.action {
+            MacroAction::Input(EventType::MouseMove { x, y }) => {
+                pointer = (*x, *y)
+            }
+            MacroAction::Input(EventType::ButtonPress(button)) => {
+                pressed = Some((*button, pointer))
+            }
+            MacroAction::Input(EventType::ButtonRelease(button)) => {
+                let Some((_, from)) = pressed.take() else {
+                    continue;
+                };
+                flush(&mut typed, &mut steps);
+                let moved = (pointer.0 - from.0).hypot(pointer.1 - from.1);
+                if moved > DRAG_DISTANCE {
+                    steps.push(Step::Drag(from, pointer));
+                    continue;
+                }
+                let double = last_click.is_some_and(|at| {
+                    evt.timestamp.saturating_sub(at) < DOUBLE_CLICK
+                }) && matches!(
+                    steps.last(),
+                    Some(Step::Click { at, button: b, double: false })
+                        if *at == from && b == button
+                );
+                if double {
+                    steps.pop();
+                    last_click = None;
+                } else {
+                    last_click = Some(evt.timestamp);
+                }
+                steps.push(Step::Click {
+                    at: from,
+                    button: *button,
+                    double,
+                });
+            }
+            MacroAction::Input(EventType::Wheel { delta_x, delta_y }) => {
+                flush(&mut typed, &mut steps);
+                match steps.last_mut() {
+                    Some(Step::Scroll(dx, dy)) => {
+                        *dx += delta_x;
+                        *dy += delta_y;
+                    }
+                    _ => steps.push(Step::Scroll(*delta_x, *delta_y)),
+                }
+            }
+            MacroAction::Input(EventType::KeyPress(key)) => {
+                if is_modifier(*key) {
+                    if !held.contains(key) {
+                        held.push(*key);
+                    }
+                    continue;
+                }
+                let shift = held.iter().any(|k| is_shift(*k));
+                let shortcut: Vec<&'static str> = held
+                    .iter()
+                    .filter(|k| !is_shift(**k))
+                    .filter_map(|k| modifier_name(*k))
+                    .collect();
+                let part = match key_char(*key, shift && shortcut.is_empty()) {
+                    Some(c) => Part::Text(c.to_string()),
+                    None => match key_name(*key) {
+                        Some(name) => Part::Key(name),
+                        None => continue,
+                    },
+                };
+                if shortcut.is_empty() {
+                    match (&part, typed.last_mut()) {
+                        (Part::Text(c), Some(Part::Text(text))) => {
+                            text.push_str(c)
+                        }
+                        _ => typed.push(part),
+                    }
+                    continue;
+                }
+                flush(&mut typed, &mut steps);
+                let mut modifiers = shortcut;
+                if shift {
+                    modifiers.push("SHIFT");
+                }
+                steps.push(Step::Shortcut(modifiers, part));
+            }
+            MacroAction::Input(EventType::KeyRelease(key)) => {
+                held.retain(|k| k != key)
+            }
+            MacroAction::Type(text) => {
+                typed.push(Part::Text(text.clone()));
+            }
+            MacroAction::ClickElement { target, .. } => {
+                flush(&mut typed, &mut steps);
+                steps.push(Step::Comment(format!("click {}", target)));
+            }
+            MacroAction::Clipboard(text) => {
+                flush(&mut typed, &mut steps);
+                steps.push(Step::Comment(format!(
+                    "the clipboard held {}",
+                    python_string(text)
+                )));
+            }
+            MacroAction::Wait { until, .. } => {
+                flush(&mut typed, &mut steps);
+                steps.push(Step::Comment(format!("wait for {}", until)));
+            }
+            MacroAction::Screenshot { path, .. } => {
+                flush(&mut typed, &mut steps);
+                steps.push(Step::Comment(format!(
+                    "driver.save_screenshot({})",
+                    python_string(path)
+                )));
+            }
+            MacroAction::IfKeyHeld { key, .. } => {
+                flush(&mut typed, &mut steps);
+                steps.push(Step::Comment(format!(
+                    "the recording branches on {:?} here",
+                    key
+                )));
+            }
+            MacroAction::Cleanup | MacroAction::Checkpoint(_) => {}
+        }
+    }
+    if !typed.is_empty() {
+        steps.push(Step::Keys(typed));
+    }
+    steps
+}
+
+/// `text` as a Python string literal.
+fn python_string(text: &str) -> String {
+    let mut literal = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn is_modifier(key: Key) -> bool {
+    modifier_name(key).is_some()
+}
+
+fn is_shift(key: Key) -> bool {
+    matches!(key, Key::ShiftLeft | Key::ShiftRight)
+}
+
+fn modifier_name(key: Key) -> Option<&'static str> {
+    let name = match key {
+        Key::ShiftLeft | Key::ShiftRight => "SHIFT",
+        Key::ControlLeft | Key::ControlRight => "CONTROL",
+        Key::Alt | Key::AltGr => "ALT",
+        Key::MetaLeft | Key::MetaRight => "META",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Selenium's name for a key that doesn't type a character.
+fn key_name(key: Key) -> Option<&'static str> {
+    let name = match key {
+        Key::Return | Key::KpReturn => "ENTER",
+        Key::Tab => "TAB",
+        Key::Escape => "ESCAPE",
+        Key::Backspace => "BACKSPACE",
+        Key::Delete => "DELETE",
+        Key::Insert => "INSERT",
+        Key::Home => "HOME",
+        Key::End => "END",
+        Key::PageUp => "PAGE_UP",
+        Key::PageDown => "PAGE_DOWN",
+        Key::UpArrow => "ARROW_UP",
+        Key::DownArrow => "ARROW_DOWN",
+        Key::LeftArrow => "ARROW_LEFT",
+        Key::RightArrow => "ARROW_RIGHT",
+        Key::F1 => "F1",
+        Key::F2 => "F2",
+        Key::F3 => "F3",
+        Key::F4 => "F4",
+        Key::F5 => "F5",
+        Key::F6 => "F6",
+        Key::F7 => "F7",
+        Key::F8 => "F8",
+        Key::F9 => "F9",
+        Key::F10 => "F10",
+        Key::F11 => "F11",
+        Key::F12 => "F12",
+        _ => return None,
+    };
+    Some(name)
+}